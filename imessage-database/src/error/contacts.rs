@@ -0,0 +1,35 @@
+/*!
+ Errors that can happen when reading contact data.
+*/
+
+use std::fmt::{Display, Formatter, Result};
+
+use rusqlite::Error;
+
+/// Errors that can happen when reading contact names from an address book
+#[derive(Debug)]
+pub enum ContactsError {
+    CannotConnect(String),
+    UnknownSchema(String),
+    AddressBook(Error),
+    CannotRead(std::io::Error),
+    InvalidCsv(usize),
+}
+
+impl Display for ContactsError {
+    fn fmt(&self, fmt: &mut Formatter<'_>) -> Result {
+        match self {
+            ContactsError::CannotConnect(why) => write!(fmt, "{why}"),
+            ContactsError::UnknownSchema(path) => write!(
+                fmt,
+                "{path} is not a contacts database! Must be an `AddressBook-v22.abcddb` file from macOS, an `AddressBook.sqlitedb` file from an iOS backup, a `.vcf` file, or a `.csv` file"
+            ),
+            ContactsError::AddressBook(why) => write!(fmt, "Failed to read contacts: {why}"),
+            ContactsError::CannotRead(why) => write!(fmt, "Unable to read contacts file: {why}"),
+            ContactsError::InvalidCsv(line) => write!(
+                fmt,
+                "Invalid contacts CSV at line {line}! Each row must be a `handle,name` pair"
+            ),
+        }
+    }
+}
@@ -3,6 +3,7 @@
 */
 
 pub mod attachment;
+pub mod contacts;
 pub mod message;
 pub mod plist;
 pub mod query_context;
@@ -8,6 +8,7 @@ use std::fmt::{Display, Formatter, Result};
 #[derive(Debug)]
 pub enum QueryContextError {
     InvalidDate(String),
+    InvalidRegex(String, String),
 }
 
 impl Display for QueryContextError {
@@ -15,8 +16,11 @@ impl Display for QueryContextError {
         match self {
             QueryContextError::InvalidDate(date) => write!(
                 fmt,
-                "Invalid date provided: {date}! Must be in format YYYY-MM-DD."
+                "Invalid date provided: {date}! Must be a day like 2023-01-01, an RFC 3339 timestamp like 2023-01-01T09:30:00-08:00, or a time before now like 24h, 7d, 2 weeks, 3 months ago, today, or yesterday."
             ),
+            QueryContextError::InvalidRegex(pattern, why) => {
+                write!(fmt, "Invalid regular expression provided: {pattern}! {why}")
+            }
         }
     }
 }
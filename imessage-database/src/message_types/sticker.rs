@@ -12,9 +12,10 @@ const STICKER_EFFECT_PREFIX: [u8; 20] = [
 const STICKER_EFFECT_SUFFIX: [u8; 3] = [34, 47, 62];
 
 /// Represents different types of [sticker effects](https://www.macrumors.com/how-to/add-effects-to-stickers-in-messages/) that can be applied to sticker iMessage balloons.
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Default)]
 pub enum StickerEffect {
     /// Sticker sent with no effect
+    #[default]
     Normal,
     /// Internally referred to as `stroke`
     Outline,
@@ -51,12 +52,6 @@ impl Display for StickerEffect {
     }
 }
 
-impl Default for StickerEffect {
-    fn default() -> Self {
-        Self::Normal
-    }
-}
-
 /// Parse the sticker effect type from the EXIF data of a HEIC blob
 pub fn get_sticker_effect(mut heic_data: Vec<u8>) -> StickerEffect {
     // Find the start index and drain
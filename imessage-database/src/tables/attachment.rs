@@ -15,7 +15,7 @@ use crate::{
     message_types::sticker::{get_sticker_effect, StickerEffect},
     tables::{
         messages::Message,
-        table::{Table, ATTACHMENT},
+        table::{Table, ATTACHMENT, CHAT_MESSAGE_JOIN, MESSAGE_ATTACHMENT_JOIN},
     },
     util::{
         dates::TIMESTAMP_FACTOR,
@@ -80,7 +80,7 @@ impl Table for Attachment {
         })
     }
 
-    fn get(db: &Connection) -> Result<Statement, TableError> {
+    fn get(db: &Connection) -> Result<Statement<'_>, TableError> {
         db.prepare(&format!("SELECT * from {ATTACHMENT}"))
             .map_err(TableError::Attachment)
     }
@@ -237,24 +237,40 @@ impl Attachment {
         context: &QueryContext,
     ) -> Result<u64, TableError> {
         let mut bytes_query = if context.has_filters() {
-            let mut statement = format!("SELECT SUM(total_bytes) FROM {ATTACHMENT} a");
-
-            if context.has_filters() {
-                statement.push_str(" WHERE ");
-                if let Some(start) = context.start {
-                    statement.push_str(&format!(
-                        "    a.created_date >= {}",
-                        start / TIMESTAMP_FACTOR
-                    ));
-                }
-                if let Some(end) = context.end {
-                    if !statement.is_empty() {
-                        statement.push_str(" AND ");
-                    }
-                    statement
-                        .push_str(&format!("    a.created_date <= {}", end / TIMESTAMP_FACTOR));
-                }
+            // Filters can exclude every attachment, so an empty sum counts as zero
+            let mut statement = format!("SELECT IFNULL(SUM(total_bytes), 0) FROM {ATTACHMENT} a");
+
+            let mut filters = vec![];
+            if let Some(start) = context.start {
+                filters.push(format!(
+                    "    a.created_date >= {}",
+                    start / TIMESTAMP_FACTOR
+                ));
+            }
+            if let Some(end) = context.end {
+                filters.push(format!("    a.created_date <= {}", end / TIMESTAMP_FACTOR));
+            }
+            if let Some(last_message) = context.last_message {
+                let chats = context
+                    .refresh_chats
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                filters.push(format!(
+                    "    a.ROWID IN (SELECT attachment_id FROM {MESSAGE_ATTACHMENT_JOIN} WHERE message_id > {last_message} OR message_id IN (SELECT message_id FROM {CHAT_MESSAGE_JOIN} WHERE chat_id IN ({chats})))"
+                ));
+            }
+            let mut message_filters = context.generate_chat_filters("message_id");
+            message_filters.extend(context.generate_handle_filters("message_id"));
+            if !message_filters.is_empty() {
+                filters.push(format!(
+                    "    a.ROWID IN (SELECT attachment_id FROM {MESSAGE_ATTACHMENT_JOIN} WHERE {})",
+                    message_filters.join(" AND ")
+                ));
             }
+            statement.push_str(" WHERE ");
+            statement.push_str(&filters.join(" AND "));
 
             db.prepare(&statement).map_err(TableError::Attachment)?
         } else {
@@ -32,7 +32,7 @@ impl Table for Chat {
         })
     }
 
-    fn get(db: &Connection) -> Result<Statement, TableError> {
+    fn get(db: &Connection) -> Result<Statement<'_>, TableError> {
         db.prepare(&format!("SELECT * from {CHAT}"))
             .map_err(TableError::Chat)
     }
@@ -27,7 +27,7 @@ impl Table for ChatToHandle {
         })
     }
 
-    fn get(db: &Connection) -> Result<Statement, TableError> {
+    fn get(db: &Connection) -> Result<Statement<'_>, TableError> {
         db.prepare(&format!("SELECT * FROM {CHAT_HANDLE_JOIN}"))
             .map_err(TableError::ChatToHandle)
     }
@@ -90,9 +90,13 @@ impl Deduplicate for ChatToHandle {
         let mut deduplicated_chats: HashMap<i32, i32> = HashMap::new();
         let mut participants_to_unique_chat_id: HashMap<Self::T, i32> = HashMap::new();
 
+        // Visit chats in order so each one gets the same identifier every time the database is read
+        let mut chats: Vec<(&i32, &Self::T)> = duplicated_data.iter().collect();
+        chats.sort_by_key(|(chat_id, _)| **chat_id);
+
         // Build cache of each unique set of participants to a new identifier:
         let mut unique_chat_identifier = 0;
-        for (chat_id, participants) in duplicated_data {
+        for (chat_id, participants) in chats {
             if let Some(id) = participants_to_unique_chat_id.get(participants) {
                 deduplicated_chats.insert(chat_id.to_owned(), id.to_owned());
             } else {
@@ -106,6 +110,49 @@ impl Deduplicate for ChatToHandle {
     }
 }
 
+impl ChatToHandle {
+    /// Replace the handles in each chat with the deduplicated participants they belong to
+    ///
+    /// Deduplicating the result merges chats with the same people even if they used different handles,
+    /// like a conversation that is split between SMS and iMessage.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// use std::collections::{BTreeSet, HashMap};
+    /// use imessage_database::tables::{chat_handle::ChatToHandle, table::Deduplicate};
+    ///
+    /// let chatroom_participants = HashMap::from([(1, BTreeSet::from([10])), (2, BTreeSet::from([20]))]);
+    /// let real_participants = HashMap::from([(10, 0), (20, 0)]);
+    /// let real_chatrooms = ChatToHandle::dedupe(&ChatToHandle::with_participants(
+    ///     &chatroom_participants,
+    ///     &real_participants,
+    /// ));
+    /// assert_eq!(real_chatrooms[&1], real_chatrooms[&2]);
+    /// ```
+    pub fn with_participants(
+        chatroom_participants: &HashMap<i32, BTreeSet<i32>>,
+        real_participants: &HashMap<i32, i32>,
+    ) -> HashMap<i32, BTreeSet<i32>> {
+        chatroom_participants
+            .iter()
+            .map(|(chat_id, handles)| {
+                let participants = handles
+                    .iter()
+                    // Handles missing from the handle table keep a negative ID, so they never match a participant
+                    .map(|handle_id| {
+                        real_participants
+                            .get(handle_id)
+                            .copied()
+                            .unwrap_or(-handle_id - 1)
+                    })
+                    .collect();
+                (*chat_id, participants)
+            })
+            .collect()
+    }
+}
+
 impl Diagnostic for ChatToHandle {
     /// Emit diagnostic data for the Chat to Handle join table
     ///
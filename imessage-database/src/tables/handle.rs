@@ -3,12 +3,15 @@
 */
 
 use rusqlite::{Connection, Error, Result, Row, Statement};
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeSet, HashMap, HashSet};
 
 use crate::{
     error::table::TableError,
     tables::table::{Cacheable, Deduplicate, Diagnostic, Table, HANDLE, ME},
-    util::output::{done_processing, processing},
+    util::{
+        output::{done_processing, processing},
+        phone_number::Region,
+    },
 };
 
 /// Represents a single row in the `handle` table.
@@ -28,7 +31,7 @@ impl Table for Handle {
         })
     }
 
-    fn get(db: &Connection) -> Result<Statement, TableError> {
+    fn get(db: &Connection) -> Result<Statement<'_>, TableError> {
         db.prepare(&format!("SELECT * from {HANDLE}"))
             .map_err(TableError::Handle)
     }
@@ -63,20 +66,7 @@ impl Cacheable for Handle {
         let mut map = HashMap::new();
         // Handle ID 0 is self in group chats
         map.insert(0, ME.to_string());
-
-        // Create query
-        let mut statement = Handle::get(db)?;
-
-        // Execute query to build the Handles
-        let handles = statement
-            .query_map([], |row| Ok(Handle::from_row(row)))
-            .map_err(TableError::Handle)?;
-
-        // Iterate over the handles and update the map
-        for handle in handles {
-            let contact = Handle::extract(handle)?;
-            map.insert(contact.rowid, contact.id);
-        }
+        map.extend(Handle::ids(db)?);
 
         // Condense contacts that share person_centric_id so their IDs map to the same strings
         let dupe_contacts = Handle::get_person_id_map(db)?;
@@ -166,6 +156,131 @@ impl Diagnostic for Handle {
 }
 
 impl Handle {
+    /// Get the `ROWID` of every handle that belongs to the same person as any of the given IDs
+    ///
+    /// Handles are matched by their `id`, like a phone number or email address, then expanded to
+    /// every handle that shares a `person_centric_id` with a match.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// use std::collections::BTreeSet;
+    /// use imessage_database::util::dirs::default_db_path;
+    /// use imessage_database::tables::table::get_connection;
+    /// use imessage_database::tables::handle::Handle;
+    ///
+    /// let db_path = default_db_path();
+    /// let conn = get_connection(&db_path).unwrap();
+    /// let ids = BTreeSet::from(["+15558675309".to_string()]);
+    /// let handles = Handle::get_person_handles(&conn, &ids);
+    /// ```
+    pub fn get_person_handles(
+        db: &Connection,
+        ids: &BTreeSet<String>,
+    ) -> Result<BTreeSet<i32>, TableError> {
+        let person_ids = Handle::get_person_id_map(db)?;
+
+        // Create query
+        let mut statement = Handle::get(db)?;
+
+        // Execute query to build the Handles
+        let handles = statement
+            .query_map([], |row| Ok(Handle::from_row(row)))
+            .map_err(TableError::Handle)?;
+
+        // Pair each handle with the string that represents its person, which is its own ID if it has no duplicates
+        let mut people: Vec<(i32, String)> = vec![];
+        let mut matched: HashSet<String> = HashSet::new();
+        for handle in handles {
+            let contact = Handle::extract(handle)?;
+            let person = person_ids
+                .get(&contact.rowid)
+                .cloned()
+                .unwrap_or_else(|| contact.id.clone());
+            if ids.contains(&contact.id) {
+                matched.insert(person.clone());
+            }
+            people.push((contact.rowid, person));
+        }
+
+        Ok(people
+            .into_iter()
+            .filter(|(_, person)| matched.contains(person))
+            .map(|(rowid, _)| rowid)
+            .collect())
+    }
+
+    /// Generate a `HashMap` of each handle's ID to its own phone number or email address
+    ///
+    /// Unlike [`Handle::cache()`], handles that share a `person_centric_id` are not condensed.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// use imessage_database::util::dirs::default_db_path;
+    /// use imessage_database::tables::table::get_connection;
+    /// use imessage_database::tables::handle::Handle;
+    ///
+    /// let db_path = default_db_path();
+    /// let conn = get_connection(&db_path).unwrap();
+    /// let addresses = Handle::ids(&conn);
+    /// ```
+    pub fn ids(db: &Connection) -> Result<HashMap<i32, String>, TableError> {
+        let mut map = HashMap::new();
+
+        // Create query
+        let mut statement = Handle::get(db)?;
+
+        // Execute query to build the Handles
+        let handles = statement
+            .query_map([], |row| Ok(Handle::from_row(row)))
+            .map_err(TableError::Handle)?;
+
+        // Iterate over the handles and update the map
+        for handle in handles {
+            let contact = Handle::extract(handle)?;
+            map.insert(contact.rowid, contact.id);
+        }
+
+        Ok(map)
+    }
+
+    /// Normalize the handle strings in a participants cache so they can be deduplicated
+    ///
+    /// Each phone number and email address is normalized with [`Region::normalize()`], so handles like
+    /// `+15558675309`, `15558675309`, and `5558675309` deduplicate together. Handles that share a
+    /// `person_centric_id` are cached as a space-separated list of IDs, which is sorted after normalizing.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    /// use imessage_database::tables::{handle::Handle, table::Deduplicate};
+    /// use imessage_database::util::phone_number::Region;
+    ///
+    /// let participants = HashMap::from([
+    ///     (1, "+15558675309".to_string()),
+    ///     (2, "5558675309".to_string()),
+    /// ]);
+    /// let real_participants = Handle::dedupe(&Handle::normalize(&participants, &Region::default()));
+    /// assert_eq!(real_participants[&1], real_participants[&2]);
+    /// ```
+    pub fn normalize(participants: &HashMap<i32, String>, region: &Region) -> HashMap<i32, String> {
+        participants
+            .iter()
+            .map(|(handle_id, participant)| {
+                let ids: BTreeSet<String> = participant
+                    .split(' ')
+                    .map(|id| region.normalize(id))
+                    .collect();
+                (
+                    *handle_id,
+                    ids.into_iter().collect::<Vec<String>>().join(" "),
+                )
+            })
+            .collect()
+    }
+
     /// The handles table does not have a lot of information and can have many duplicate values.
     ///
     /// This method generates a hashmap of each separate item in this table to a combined string
@@ -237,7 +352,10 @@ impl Handle {
 
 #[cfg(test)]
 mod tests {
-    use crate::tables::{handle::Handle, table::Deduplicate};
+    use crate::{
+        tables::{handle::Handle, table::Deduplicate},
+        util::phone_number::Region,
+    };
     use std::collections::{HashMap, HashSet};
 
     #[test]
@@ -254,4 +372,21 @@ mod tests {
         let expected_deduped_ids: HashSet<i32> = output.values().copied().collect();
         assert_eq!(expected_deduped_ids.len(), 3);
     }
+
+    #[test]
+    fn test_can_dedupe_normalized() {
+        let mut input: HashMap<i32, String> = HashMap::new();
+        input.insert(1, String::from("+15551234567"));
+        input.insert(2, String::from("5551234567"));
+        input.insert(3, String::from("e:Jane@Example.com"));
+        input.insert(4, String::from("jane@example.com +15550000000"));
+        input.insert(5, String::from("5550000000 e:jane@example.com"));
+        input.insert(6, String::from("+15559999999"));
+
+        let output = Handle::dedupe(&Handle::normalize(&input, &Region::default()));
+        assert_eq!(output[&1], output[&2]);
+        assert_eq!(output[&4], output[&5]);
+        assert_ne!(output[&3], output[&4]);
+        assert_ne!(output[&1], output[&6]);
+    }
 }
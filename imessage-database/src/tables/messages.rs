@@ -2,11 +2,14 @@
  This module represents common (but not all) columns in the `message` table.
 */
 
-use std::{collections::HashMap, io::Read};
+use std::{
+    collections::{BTreeSet, HashMap},
+    io::Read,
+};
 
 use chrono::{offset::Local, DateTime};
 use plist::Value;
-use rusqlite::{blob::Blob, Connection, Error, Result, Row, Statement};
+use rusqlite::{blob::Blob, params, Connection, Error, Result, Row, Statement};
 use serde::Serialize;
 
 use crate::{
@@ -72,7 +75,6 @@ pub enum Service<'a> {
 
 /// Represents a single row in the `message` table.
 #[derive(Debug)]
-
 #[allow(non_snake_case)]
 #[derive(Serialize)]
 pub struct Message {
@@ -159,7 +161,7 @@ impl Table for Message {
         })
     }
 
-    fn get(db: &Connection) -> Result<Statement, TableError> {
+    fn get(db: &Connection) -> Result<Statement<'_>, TableError> {
         // If the database has `chat_recoverable_message_join`, we can restore some deleted messages.
         // If database has `thread_originator_guid`, we can parse replies, otherwise default to 0
         Ok(db.prepare(&format!(
@@ -403,7 +405,7 @@ impl Message {
     /// Will have a `body()` of:
     ///
     /// `[BubbleType::Attachment, BubbleType::Text("Check out this photo!")]`
-    pub fn body(&self) -> Vec<BubbleType> {
+    pub fn body(&self) -> Vec<BubbleType<'_>> {
         let mut out_v = vec![];
 
         // If the message is an app, it will be rendered differently, so just escape there
@@ -561,7 +563,7 @@ impl Message {
     }
 
     /// Get the index of the part of a message a reply is pointing to
-    fn get_reply_index(&self) -> usize {
+    pub fn get_reply_index(&self) -> usize {
         if let Some(parts) = &self.thread_originator_part {
             return match parts.split(':').next() {
                 Some(part) => str::parse::<usize>(part).unwrap_or(0),
@@ -660,8 +662,156 @@ impl Message {
             )).map_err(TableError::Messages)?))
     }
 
+    /// Get the largest message `ROWID` and the most recent time any message was sent or edited
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// use imessage_database::util::dirs::default_db_path;
+    /// use imessage_database::tables::table::get_connection;
+    /// use imessage_database::tables::messages::Message;
+    ///
+    /// let db_path = default_db_path();
+    /// let conn = get_connection(&db_path).unwrap();
+    /// let (last_message, last_change) = Message::get_latest(&conn).unwrap();
+    /// ```
+    pub fn get_latest(db: &Connection) -> Result<(i32, i64), TableError> {
+        // Older databases do not have `date_edited`
+        let mut statement = db
+            .prepare(&format!(
+                "SELECT IFNULL(MAX(ROWID), 0), MAX(IFNULL(MAX(date), 0), IFNULL(MAX(date_edited), 0)) FROM {MESSAGE}"
+            ))
+            .or_else(|_| {
+                db.prepare(&format!(
+                    "SELECT IFNULL(MAX(ROWID), 0), IFNULL(MAX(date), 0) FROM {MESSAGE}"
+                ))
+            })
+            .map_err(TableError::Messages)?;
+        statement
+            .query_row([], |r| Ok((r.get(0)?, r.get(1)?)))
+            .map_err(TableError::Messages)
+    }
+
+    /// Get the IDs of chats that received messages after `last_message`, with the date of the earliest one
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// use imessage_database::util::dirs::default_db_path;
+    /// use imessage_database::tables::table::get_connection;
+    /// use imessage_database::tables::messages::Message;
+    ///
+    /// let db_path = default_db_path();
+    /// let conn = get_connection(&db_path).unwrap();
+    /// let chats = Message::get_new_chats(&conn, 1000).unwrap();
+    /// ```
+    pub fn get_new_chats(
+        db: &Connection,
+        last_message: i32,
+    ) -> Result<HashMap<i32, i64>, TableError> {
+        let mut statement = db
+            .prepare(&format!(
+                "SELECT c.chat_id, MIN(m.date)
+                 FROM {MESSAGE} as m
+                     JOIN {CHAT_MESSAGE_JOIN} as c ON m.ROWID = c.message_id
+                 WHERE m.ROWID > ?1
+                 GROUP BY c.chat_id"
+            ))
+            .map_err(TableError::Messages)?;
+        let chats = statement
+            .query_map([last_message], |r| Ok((r.get(0)?, r.get(1)?)))
+            .map_err(TableError::Messages)?;
+        chats
+            .collect::<Result<HashMap<i32, i64>>>()
+            .map_err(TableError::Messages)
+    }
+
+    /// Get the IDs of chats where a message up to `last_message` changed after `since`
+    ///
+    /// A message changes when it is edited or unsent, or when a newer message reacts or replies to it.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// use imessage_database::util::dirs::default_db_path;
+    /// use imessage_database::tables::table::get_connection;
+    /// use imessage_database::tables::messages::Message;
+    ///
+    /// let db_path = default_db_path();
+    /// let conn = get_connection(&db_path).unwrap();
+    /// let chats = Message::get_changed_chats(&conn, 1000, 0).unwrap();
+    /// ```
+    pub fn get_changed_chats(
+        db: &Connection,
+        last_message: i32,
+        since: i64,
+    ) -> Result<BTreeSet<i32>, TableError> {
+        let mut chats = BTreeSet::new();
+
+        // Edited and unsent messages; older databases do not have `date_edited`
+        if let Ok(mut statement) = db.prepare(&format!(
+            "SELECT DISTINCT c.chat_id
+             FROM {MESSAGE} as m
+                 JOIN {CHAT_MESSAGE_JOIN} as c ON m.ROWID = c.message_id
+             WHERE m.ROWID <= ?1 AND m.date_edited > ?2"
+        )) {
+            let rows = statement
+                .query_map(params![last_message, since], |r| r.get(0))
+                .map_err(TableError::Messages)?;
+            for chat_id in rows {
+                chats.insert(chat_id.map_err(TableError::Messages)?);
+            }
+        }
+
+        // Messages that newer reactions, stickers, or replies point to
+        // Only the GUIDs matter here, so the counts are not queried
+        let mut targets = BTreeSet::new();
+        let mut statement = db
+            .prepare(&format!(
+                "SELECT *, 0 as num_attachments, 0 as num_replies FROM {MESSAGE} WHERE ROWID > ?1 AND (associated_message_guid NOT NULL OR thread_originator_guid NOT NULL)"
+            ))
+            .or_else(|_| {
+                db.prepare(&format!(
+                    "SELECT *, 0 as num_attachments, 0 as num_replies FROM {MESSAGE} WHERE ROWID > ?1 AND associated_message_guid NOT NULL"
+                ))
+            })
+            .map_err(TableError::Messages)?;
+        let messages = statement
+            .query_map([last_message], |row| Ok(Message::from_row(row)))
+            .map_err(TableError::Messages)?;
+        for message in messages {
+            let message = Self::extract(message)?;
+            if let Some((_, guid)) = message.clean_associated_guid() {
+                targets.insert(guid.to_string());
+            }
+            if let Some(guid) = message.thread_originator_guid {
+                targets.insert(guid);
+            }
+        }
+
+        let mut statement = db
+            .prepare(&format!(
+                "SELECT c.chat_id
+                 FROM {MESSAGE} as m
+                     JOIN {CHAT_MESSAGE_JOIN} as c ON m.ROWID = c.message_id
+                 WHERE m.ROWID <= ?1 AND m.guid = ?2"
+            ))
+            .map_err(TableError::Messages)?;
+        for guid in &targets {
+            let rows = statement
+                .query_map(params![last_message, guid], |r| r.get(0))
+                .map_err(TableError::Messages)?;
+            for chat_id in rows {
+                chats.insert(chat_id.map_err(TableError::Messages)?);
+            }
+        }
+        Ok(chats)
+    }
+
+    /// Get the body part index and GUID of the message a reaction or sticker targets
+    ///
     /// See [Reaction](crate::message_types::variants::Reaction) for details on this data.
-    fn clean_associated_guid(&self) -> Option<(usize, &str)> {
+    pub fn clean_associated_guid(&self) -> Option<(usize, &str)> {
         if let Some(guid) = &self.associated_message_guid {
             if guid.starts_with("p:") {
                 let mut split = guid.split('/');
@@ -794,7 +944,7 @@ impl Message {
     }
 
     /// Get the variant of a message, see [`variants`](crate::message_types::variants) for detail.
-    pub fn variant(&self) -> Variant {
+    pub fn variant(&self) -> Variant<'_> {
         // Check if a message was edited first as those have special properties
         if self.is_edited() {
             return Variant::Edited;
@@ -860,20 +1010,20 @@ impl Message {
     }
 
     /// Determine the type of announcement a message contains, if it contains one
-    pub fn get_announcement(&self) -> Option<Announcement> {
+    pub fn get_announcement(&self) -> Option<Announcement<'_>> {
         if let Some(name) = &self.group_title {
             return Some(Announcement::NameChange(name));
         }
 
-        return match &self.group_action_type {
+        match &self.group_action_type {
             0 => None,
             1 => Some(Announcement::PhotoChange),
             other => Some(Announcement::Unknown(other)),
-        };
+        }
     }
 
     /// Determine the service the message was sent from, i.e. iMessage, SMS, IRC, etc.
-    pub fn service(&self) -> Service {
+    pub fn service(&self) -> Service<'_> {
         match self.service.as_deref() {
             Some("iMessage") => Service::iMessage,
             Some("SMS") => Service::SMS,
@@ -884,16 +1034,14 @@ impl Message {
 
     /// Extract a blob of data that belongs to a single message from a given column
     fn get_blob<'a>(&self, db: &'a Connection, column: &str) -> Option<Blob<'a>> {
-        match db.blob_open(
+        db.blob_open(
             rusqlite::DatabaseName::Main,
             MESSAGE,
             column,
             self.rowid as i64,
             true,
-        ) {
-            Ok(blob) => Some(blob),
-            Err(_) => None,
-        }
+        )
+        .ok()
     }
 
     /// Get a message's plist from the `payload_data` BLOB column
@@ -931,7 +1079,7 @@ impl Message {
     }
 
     /// Determine which expressive the message was sent with
-    pub fn get_expressive(&self) -> Expressive {
+    pub fn get_expressive(&self) -> Expressive<'_> {
         match &self.expressive_send_style_id {
             Some(content) => match content.as_str() {
                 "com.apple.MobileSMS.expressivesend.gentle" => {
@@ -0,0 +1,762 @@
+/*!
+ Contains logic for resolving handles to contact names using a macOS or iOS address book, a vCard file, or a CSV file.
+*/
+
+use std::{collections::HashMap, fs::read_to_string, path::Path};
+
+use rusqlite::{Connection, OpenFlags, Row};
+
+use crate::{error::contacts::ContactsError, util::phone_number::Region};
+
+/// Query for every person in a macOS `AddressBook-v22.abcddb` database
+const MACOS_PEOPLE: &str =
+    "SELECT Z_PK, ZFIRSTNAME, ZLASTNAME, ZORGANIZATION, ZNICKNAME FROM ZABCDRECORD";
+/// Query for every phone number and email address in a macOS address book, with the person it belongs to
+const MACOS_IDENTIFIERS: &str = concat!(
+    "SELECT ZOWNER, ZFULLNUMBER FROM ZABCDPHONENUMBER ",
+    "UNION ALL ",
+    "SELECT ZOWNER, ZADDRESS FROM ZABCDEMAILADDRESS"
+);
+/// Query for every person in an iOS `AddressBook.sqlitedb` database
+const IOS_PEOPLE: &str = "SELECT ROWID, First, Last, Organization, Nickname FROM ABPerson";
+/// Query for every phone number (property `3`) and email address (property `4`) in an iOS address book
+const IOS_IDENTIFIERS: &str = "SELECT record_id, value FROM ABMultiValue WHERE property IN (3, 4)";
+
+/// Prefix for the keys used to group handles by the contact card they belong to
+const CARD_KEY: &str = "card:";
+
+/// Represents the contact cards that handles can resolve to
+#[derive(Debug, Default)]
+pub struct Contacts {
+    /// Name of each contact card
+    names: Vec<String>,
+    /// Map of normalized phone numbers and email addresses to the index of their card in `names`
+    cards: HashMap<String, usize>,
+    /// Region used to normalize phone numbers that do not include a country code
+    region: Region,
+}
+
+impl Contacts {
+    /// Read contact cards from a file, choosing the parser by its extension
+    ///
+    /// - `.vcf` files are read with [`Contacts::from_vcard()`]
+    /// - `.csv` files are read with [`Contacts::from_csv()`]
+    /// - Anything else is read with [`Contacts::from_address_book()`]
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// use std::path::PathBuf;
+    /// use imessage_database::util::{contacts::Contacts, phone_number::Region};
+    ///
+    /// let path = PathBuf::from("contacts.vcf");
+    /// let contacts = Contacts::from_path(&path, &Region::default());
+    /// ```
+    pub fn from_path(path: &Path, region: &Region) -> Result<Self, ContactsError> {
+        let extension = path
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase());
+        match extension.as_deref() {
+            Some("vcf") => Self::from_vcard(path, region),
+            Some("csv") => Self::from_csv(path, region),
+            _ => Self::from_address_book(path, region),
+        }
+    }
+
+    /// Read every contact card with a name and at least one phone number or email address from a vCard file
+    ///
+    /// Names are read from the `FN` property, falling back to `N`, `ORG`, and `NICKNAME`.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// use std::path::PathBuf;
+    /// use imessage_database::util::{contacts::Contacts, phone_number::Region};
+    ///
+    /// let path = PathBuf::from("contacts.vcf");
+    /// let contacts = Contacts::from_vcard(&path, &Region::default());
+    /// ```
+    pub fn from_vcard(path: &Path, region: &Region) -> Result<Self, ContactsError> {
+        Ok(Self::parse_vcard(
+            &read_to_string(path).map_err(ContactsError::CannotRead)?,
+            region,
+        ))
+    }
+
+    /// Read contacts from a CSV file where each row is a `handle,name` pair
+    ///
+    /// A `handle,name` header row is optional. Rows that share a name belong to the same contact card.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// use std::path::PathBuf;
+    /// use imessage_database::util::{contacts::Contacts, phone_number::Region};
+    ///
+    /// let path = PathBuf::from("contacts.csv");
+    /// let contacts = Contacts::from_csv(&path, &Region::default());
+    /// ```
+    pub fn from_csv(path: &Path, region: &Region) -> Result<Self, ContactsError> {
+        Self::parse_csv(
+            &read_to_string(path).map_err(ContactsError::CannotRead)?,
+            region,
+        )
+    }
+
+    /// Read every contact card with a name and at least one phone number or email address from an address book
+    ///
+    /// Supports `AddressBook-v22.abcddb` files from macOS and `AddressBook.sqlitedb` files from iOS backups.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// use std::path::PathBuf;
+    /// use imessage_database::util::{contacts::Contacts, phone_number::Region};
+    ///
+    /// let path = PathBuf::from("AddressBook-v22.abcddb");
+    /// let contacts = Contacts::from_address_book(&path, &Region::default());
+    /// ```
+    pub fn from_address_book(path: &Path, region: &Region) -> Result<Self, ContactsError> {
+        if !path.is_file() {
+            return Err(ContactsError::CannotConnect(format!(
+                "Contacts database not found at {}",
+                path.display()
+            )));
+        }
+        let db =
+            Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY).map_err(|why| {
+                ContactsError::CannotConnect(format!(
+                    "Unable to read from contacts database: {why}"
+                ))
+            })?;
+
+        let (people_query, identifiers_query) = if Self::has_table(&db, "ZABCDRECORD") {
+            (MACOS_PEOPLE, MACOS_IDENTIFIERS)
+        } else if Self::has_table(&db, "ABPerson") {
+            (IOS_PEOPLE, IOS_IDENTIFIERS)
+        } else {
+            return Err(ContactsError::UnknownSchema(path.display().to_string()));
+        };
+
+        // Cache the name of each person
+        let mut statement = db
+            .prepare(people_query)
+            .map_err(ContactsError::AddressBook)?;
+        let people = statement
+            .query_map([], |row| Ok((row.get::<_, i64>(0)?, Self::full_name(row)?)))
+            .map_err(ContactsError::AddressBook)?;
+        let mut names: HashMap<i64, String> = HashMap::new();
+        for person in people {
+            if let (id, Some(name)) = person.map_err(ContactsError::AddressBook)? {
+                names.insert(id, name);
+            }
+        }
+
+        // Group the phone numbers and email addresses by the person they belong to
+        let mut statement = db
+            .prepare(identifiers_query)
+            .map_err(ContactsError::AddressBook)?;
+        let identifiers = statement
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, Option<i64>>(0)?,
+                    row.get::<_, Option<String>>(1)?,
+                ))
+            })
+            .map_err(ContactsError::AddressBook)?;
+        let mut owners: HashMap<i64, Vec<String>> = HashMap::new();
+        for identifier in identifiers {
+            if let (Some(owner), Some(identifier)) =
+                identifier.map_err(ContactsError::AddressBook)?
+            {
+                owners.entry(owner).or_default().push(identifier);
+            }
+        }
+
+        // Add cards in a stable order, so the first card to claim an identifier always wins
+        let mut owners: Vec<(i64, Vec<String>)> = owners.into_iter().collect();
+        owners.sort_unstable_by_key(|(owner, _)| *owner);
+
+        let mut contacts = Contacts::new(region);
+        for (owner, identifiers) in owners {
+            if let Some(name) = names.remove(&owner) {
+                contacts.add(name, identifiers);
+            }
+        }
+        Ok(contacts)
+    }
+
+    /// Create an empty set of contact cards that reads phone numbers without a country code in a region
+    pub fn new(region: &Region) -> Self {
+        Self {
+            region: *region,
+            ..Default::default()
+        }
+    }
+
+    /// Add a contact card with a name and the phone numbers and email addresses that belong to it
+    ///
+    /// Identifiers that already belong to another card are ignored.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// use imessage_database::util::contacts::Contacts;
+    ///
+    /// let mut contacts = Contacts::default();
+    /// contacts.add("Jane Doe".to_string(), ["+1 (555) 123-4567", "jane@example.com"]);
+    /// assert_eq!(contacts.name("5551234567"), Some("Jane Doe"));
+    /// ```
+    pub fn add<I, S>(&mut self, name: String, identifiers: I)
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let card = self.names.len();
+        let mut added = false;
+        for identifier in identifiers {
+            let identifier = self.region.normalize(identifier.as_ref());
+            if is_handle(&identifier) {
+                self.cards.entry(identifier).or_insert(card);
+                added = true;
+            }
+        }
+        if added {
+            self.names.push(name);
+        }
+    }
+
+    /// Get the name of the contact a phone number or email address belongs to
+    pub fn name(&self, id: &str) -> Option<&str> {
+        self.card(id).map(|card| self.names[card].as_str())
+    }
+
+    /// Get the index of the contact card a phone number or email address belongs to
+    fn card(&self, id: &str) -> Option<usize> {
+        self.cards.get(&self.region.normalize(id)).copied()
+    }
+
+    /// Map each participant to a key for the contact card it belongs to, or its own handle string if it has none
+    ///
+    /// Deduplicating the result merges handles that share a contact card, the same way handles that
+    /// share a `person_centric_id` are merged, without merging different contacts that share a name.
+    pub fn group(&self, participants: &HashMap<i32, String>) -> HashMap<i32, String> {
+        participants
+            .iter()
+            .map(|(handle_id, participant)| {
+                let key = match participant.split(' ').find_map(|id| self.card(id)) {
+                    Some(card) => format!("{CARD_KEY}{card}"),
+                    None => participant.clone(),
+                };
+                (*handle_id, key)
+            })
+            .collect()
+    }
+
+    /// Map each participant in a participants cache to the name of the contact it belongs to
+    ///
+    /// Handles that share a `person_centric_id` are cached as a space-separated list of their IDs,
+    /// so the first ID that belongs to a contact names the whole group. Participants that do not
+    /// belong to a contact are left out.
+    pub fn resolve(&self, participants: &HashMap<i32, String>) -> HashMap<i32, String> {
+        participants
+            .iter()
+            .filter_map(|(handle_id, participant)| {
+                let name = participant.split(' ').find_map(|id| self.name(id))?;
+                Some((*handle_id, name.to_string()))
+            })
+            .collect()
+    }
+
+    /// Get the number of contact cards
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    /// Determine if there are no contact cards
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    /// Determine if a database contains a table
+    fn has_table(db: &Connection, table: &str) -> bool {
+        db.query_row(
+            "SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?1",
+            [table],
+            |_| Ok(()),
+        )
+        .is_ok()
+    }
+
+    /// Build a readable name from a row of first name, last name, organization, and nickname columns
+    fn full_name(row: &Row) -> rusqlite::Result<Option<String>> {
+        Ok(display_name(
+            row.get::<_, Option<String>>(1)?.as_deref(),
+            row.get::<_, Option<String>>(2)?.as_deref(),
+            row.get::<_, Option<String>>(3)?.as_deref(),
+            row.get::<_, Option<String>>(4)?.as_deref(),
+        ))
+    }
+
+    /// Parse the contact cards in the contents of a vCard file
+    fn parse_vcard(contents: &str, region: &Region) -> Self {
+        // Unfold properties that continue onto lines starting with whitespace
+        let mut lines: Vec<String> = vec![];
+        for line in contents.lines() {
+            match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+                (Some(rest), Some(last)) => last.push_str(rest),
+                _ => lines.push(line.to_string()),
+            }
+        }
+
+        let mut contacts = Contacts::new(region);
+        let mut card = VCard::default();
+        for line in &lines {
+            let Some((property, value)) = line.split_once(':') else {
+                continue;
+            };
+            // Drop parameters like `;type=CELL` and groups like `item1.`
+            let property = property.split(';').next().unwrap_or_default();
+            let property = property.rsplit('.').next().unwrap_or_default();
+
+            match property.to_uppercase().as_str() {
+                "BEGIN" => card = VCard::default(),
+                "FN" => card.formatted_name = Some(unescape(value)),
+                "N" => {
+                    let mut parts = value.split(';').map(unescape);
+                    card.last_name = parts.next();
+                    card.first_name = parts.next();
+                }
+                "ORG" => card.organization = value.split(';').next().map(unescape),
+                "NICKNAME" => card.nickname = value.split(',').next().map(unescape),
+                "TEL" => card
+                    .identifiers
+                    .push(value.trim_start_matches("tel:").to_string()),
+                "EMAIL" => card
+                    .identifiers
+                    .push(value.trim_start_matches("mailto:").to_string()),
+                "END" => {
+                    let card = std::mem::take(&mut card);
+                    if let Some(name) = card.name() {
+                        contacts.add(name, card.identifiers);
+                    }
+                }
+                _ => {}
+            }
+        }
+        contacts
+    }
+
+    /// Parse the `handle,name` rows in the contents of a CSV file
+    fn parse_csv(contents: &str, region: &Region) -> Result<Self, ContactsError> {
+        // Rows that share a name belong to the same card
+        let mut cards: Vec<(String, Vec<String>)> = vec![];
+        let mut card_names: HashMap<String, usize> = HashMap::new();
+
+        for (idx, line) in contents.trim_start_matches('\u{feff}').lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            match &split_csv_row(line)[..] {
+                [handle, _] if idx == 0 && handle.eq_ignore_ascii_case("handle") => {}
+                [handle, name] if !handle.is_empty() && !name.is_empty() => {
+                    let card = *card_names.entry(name.clone()).or_insert_with(|| {
+                        cards.push((name.clone(), vec![]));
+                        cards.len() - 1
+                    });
+                    cards[card].1.push(handle.clone());
+                }
+                _ => return Err(ContactsError::InvalidCsv(idx + 1)),
+            }
+        }
+
+        let mut contacts = Contacts::new(region);
+        for (name, handles) in cards {
+            contacts.add(name, handles);
+        }
+        Ok(contacts)
+    }
+}
+
+/// Represents the properties of a vCard used to build a contact card
+#[derive(Debug, Default)]
+struct VCard {
+    formatted_name: Option<String>,
+    first_name: Option<String>,
+    last_name: Option<String>,
+    organization: Option<String>,
+    nickname: Option<String>,
+    identifiers: Vec<String>,
+}
+
+impl VCard {
+    /// Get the name to show for the card, preferring the formatted name
+    fn name(&self) -> Option<String> {
+        self.formatted_name
+            .as_deref()
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(str::to_string)
+            .or_else(|| {
+                display_name(
+                    self.first_name.as_deref(),
+                    self.last_name.as_deref(),
+                    self.organization.as_deref(),
+                    self.nickname.as_deref(),
+                )
+            })
+    }
+}
+
+/// Build a readable name from the parts of a contact card, ignoring empty parts
+fn display_name(
+    first: Option<&str>,
+    last: Option<&str>,
+    organization: Option<&str>,
+    nickname: Option<&str>,
+) -> Option<String> {
+    fn part(part: Option<&str>) -> Option<&str> {
+        part.map(str::trim).filter(|part| !part.is_empty())
+    }
+    match (part(first), part(last)) {
+        (Some(first), Some(last)) => Some(format!("{first} {last}")),
+        (Some(name), None) | (None, Some(name)) => Some(name.to_string()),
+        (None, None) => part(organization).or(part(nickname)).map(str::to_string),
+    }
+}
+
+/// Replace the escape sequences in a vCard value
+fn unescape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n' | 'N') => out.push(' '),
+            Some(escaped) => out.push(escaped),
+            None => out.push(c),
+        }
+    }
+    out.trim().to_string()
+}
+
+/// Split a CSV row into its trimmed fields, allowing quoted fields that contain commas
+fn split_csv_row(line: &str) -> Vec<String> {
+    let mut fields = vec![String::new()];
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                fields.last_mut().unwrap().push('"');
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(String::new()),
+            _ => fields.last_mut().unwrap().push(c),
+        }
+    }
+    fields
+        .into_iter()
+        .map(|field| field.trim().to_string())
+        .collect()
+}
+
+/// Determine if a handle normalized with [`Region::normalize()`] is a phone number or email address
+///
+/// Anything else, like a business chat ID, cannot belong to a contact card.
+fn is_handle(id: &str) -> bool {
+    let digits = id.strip_prefix('+').unwrap_or(id);
+    id.contains('@') || (!digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        env::temp_dir,
+        fs::{remove_file, write},
+        path::PathBuf,
+    };
+
+    use rusqlite::Connection;
+
+    use crate::util::{
+        contacts::{is_handle, split_csv_row, Contacts},
+        phone_number::Region,
+    };
+
+    fn fake_address_book(name: &str, schema: &str) -> PathBuf {
+        let path = temp_dir().join(name);
+        let _ = remove_file(&path);
+        let db = Connection::open(&path).unwrap();
+        db.execute_batch(schema).unwrap();
+        path
+    }
+
+    #[test]
+    fn can_match_phone_spellings() {
+        let mut contacts = Contacts::default();
+        contacts.add("Jane Doe".to_string(), ["+1 (555) 123-4567"]);
+
+        assert_eq!(contacts.name("5551234567"), Some("Jane Doe"));
+        assert_eq!(contacts.name("555.123.4567"), Some("Jane Doe"));
+        assert_eq!(contacts.name("+15551234567"), Some("Jane Doe"));
+    }
+
+    #[test]
+    fn cant_match_other_country() {
+        let mut contacts = Contacts::default();
+        contacts.add("Jane Doe".to_string(), ["+1 555 123 4567"]);
+        contacts.add("John Doe".to_string(), ["+44 20 7946 0958"]);
+
+        // Shares its last 10 digits with Jane's number, but has a different country code
+        assert_eq!(contacts.name("+7 555 123 4567"), None);
+        assert_eq!(contacts.name("020 7946 0958"), None);
+    }
+
+    #[test]
+    fn can_match_in_region() {
+        let mut contacts = Contacts::new(&Region::from_cli("GB").unwrap());
+        contacts.add("John Doe".to_string(), ["020 7946 0958"]);
+
+        assert_eq!(contacts.name("+44 20 7946 0958"), Some("John Doe"));
+    }
+
+    #[test]
+    fn can_match_short_code() {
+        let mut contacts = Contacts::default();
+        contacts.add("Pharmacy".to_string(), ["12345"]);
+
+        assert_eq!(contacts.name("12345"), Some("Pharmacy"));
+        assert_eq!(contacts.name("1234"), None);
+    }
+
+    #[test]
+    fn can_match_email() {
+        let mut contacts = Contacts::default();
+        contacts.add("Jane Doe".to_string(), [" Jane@Example.com "]);
+
+        assert_eq!(contacts.name("e:jane@example.com"), Some("Jane Doe"));
+    }
+
+    #[test]
+    fn cant_match_other() {
+        assert!(!is_handle("urn:biz:2d7c5a40-0a51-4d61-a7b4-4b1d2f0b1a1a"));
+        assert!(!is_handle("chat123456"));
+        assert!(!is_handle(""));
+        assert!(!is_handle("+"));
+        assert!(is_handle("+15551234567"));
+        assert!(is_handle("jane@example.com"));
+    }
+
+    #[test]
+    fn can_add_contacts() {
+        let mut contacts = Contacts::default();
+        contacts.add(
+            "Jane Doe".to_string(),
+            ["+1 (555) 123-4567", "jane@example.com"],
+        );
+        contacts.add("John Doe".to_string(), ["5551234567", "john@example.com"]);
+        contacts.add("Nobody".to_string(), ["not a number"]);
+
+        assert_eq!(contacts.len(), 2);
+        assert_eq!(contacts.name("+15551234567"), Some("Jane Doe"));
+        assert_eq!(contacts.name("JANE@example.com"), Some("Jane Doe"));
+        assert_eq!(contacts.name("john@example.com"), Some("John Doe"));
+        assert_eq!(contacts.name("+15550000000"), None);
+    }
+
+    #[test]
+    fn can_resolve_participants() {
+        let mut contacts = Contacts::default();
+        contacts.add("Jane Doe".to_string(), ["jane@example.com"]);
+
+        let participants = HashMap::from([
+            (0, "Me".to_string()),
+            (1, "+15551234567 jane@example.com".to_string()),
+            (2, "+15550000000".to_string()),
+        ]);
+        let names = contacts.resolve(&participants);
+
+        assert_eq!(names, HashMap::from([(1, "Jane Doe".to_string())]));
+    }
+
+    #[test]
+    fn can_group_participants() {
+        let mut contacts = Contacts::default();
+        contacts.add("Jane Doe".to_string(), ["+15551234567", "jane@example.com"]);
+        contacts.add("Jane Doe".to_string(), ["+15550000000"]);
+
+        let participants = HashMap::from([
+            (1, "+15551234567".to_string()),
+            (2, "jane@example.com".to_string()),
+            (3, "+15550000000".to_string()),
+            (4, "+15559999999".to_string()),
+        ]);
+        let groups = contacts.group(&participants);
+
+        assert_eq!(groups[&1], groups[&2]);
+        assert_ne!(groups[&1], groups[&3]);
+        assert_eq!(groups[&4], "+15559999999");
+    }
+
+    #[test]
+    fn can_parse_vcard() {
+        let contacts = Contacts::parse_vcard(
+            concat!(
+                "BEGIN:VCARD\r\n",
+                "VERSION:3.0\r\n",
+                "N:Doe;Jane;;;\r\n",
+                "FN:Jane Doe\r\n",
+                "TEL;type=CELL;type=VOICE;type=pref:+1 (555) 123-4567\r\n",
+                "item1.EMAIL;type=INTERNET:jane@exam\r\n",
+                " ple.com\r\n",
+                "END:VCARD\r\n",
+                "BEGIN:VCARD\r\n",
+                "VERSION:4.0\r\n",
+                "N:Doe;John;;;\r\n",
+                "TEL;VALUE=uri:tel:+1-555-000-1111\r\n",
+                "END:VCARD\r\n",
+                "BEGIN:VCARD\r\n",
+                "VERSION:3.0\r\n",
+                "ORG:Pizza\\, Pasta & More;Delivery\r\n",
+                "TEL:555-000-2222\r\n",
+                "END:VCARD\r\n",
+                "BEGIN:VCARD\r\n",
+                "VERSION:3.0\r\n",
+                "TEL:555-000-3333\r\n",
+                "END:VCARD\r\n",
+            ),
+            &Region::default(),
+        );
+
+        assert_eq!(contacts.len(), 3);
+        assert_eq!(contacts.name("5551234567"), Some("Jane Doe"));
+        assert_eq!(contacts.name("jane@example.com"), Some("Jane Doe"));
+        assert_eq!(contacts.name("+15550001111"), Some("John Doe"));
+        assert_eq!(contacts.name("+15550002222"), Some("Pizza, Pasta & More"));
+        assert_eq!(contacts.name("+15550003333"), None);
+    }
+
+    #[test]
+    fn can_parse_csv() {
+        let contacts = Contacts::parse_csv(
+            concat!(
+                "handle,name\n",
+                "+15551234567,Jane Doe\n",
+                "\n",
+                "jane@example.com, Jane Doe \n",
+                "5550001111,\"Doe, John\"\n",
+                "\"5550002222\",\"The \"\"Pizza\"\" Place\"\n",
+            ),
+            &Region::default(),
+        )
+        .unwrap();
+
+        assert_eq!(contacts.len(), 3);
+        assert_eq!(contacts.name("+15551234567"), Some("Jane Doe"));
+        assert_eq!(contacts.name("jane@example.com"), Some("Jane Doe"));
+        assert_eq!(contacts.name("+15550001111"), Some("Doe, John"));
+        assert_eq!(contacts.name("+15550002222"), Some("The \"Pizza\" Place"));
+    }
+
+    #[test]
+    fn cant_parse_csv_missing_name() {
+        let contacts =
+            Contacts::parse_csv("+15551234567,Jane Doe\n+15550000000\n", &Region::default());
+        assert!(contacts.is_err());
+    }
+
+    #[test]
+    fn can_split_csv_row() {
+        assert_eq!(split_csv_row("a, \"b, c\",d"), vec!["a", "b, c", "d"]);
+    }
+
+    #[test]
+    fn can_read_from_path() {
+        let vcard = temp_dir().join("imessage-database-contacts.VCF");
+        write(
+            &vcard,
+            "BEGIN:VCARD\nFN:Jane Doe\nEMAIL:jane@example.com\nEND:VCARD\n",
+        )
+        .unwrap();
+        let csv = temp_dir().join("imessage-database-contacts.csv");
+        write(&csv, "jane@example.com,Jane\n").unwrap();
+
+        let from_vcard = Contacts::from_path(&vcard, &Region::default()).unwrap();
+        let from_csv = Contacts::from_path(&csv, &Region::default()).unwrap();
+        remove_file(&vcard).unwrap();
+        remove_file(&csv).unwrap();
+
+        assert_eq!(from_vcard.name("jane@example.com"), Some("Jane Doe"));
+        assert_eq!(from_csv.name("jane@example.com"), Some("Jane"));
+    }
+
+    #[test]
+    fn can_read_macos_address_book() {
+        let path = fake_address_book(
+            "imessage-database-macos-contacts.abcddb",
+            "
+            CREATE TABLE ZABCDRECORD (Z_PK INTEGER PRIMARY KEY, ZFIRSTNAME TEXT, ZLASTNAME TEXT, ZORGANIZATION TEXT, ZNICKNAME TEXT);
+            CREATE TABLE ZABCDPHONENUMBER (Z_PK INTEGER PRIMARY KEY, ZOWNER INTEGER, ZFULLNUMBER TEXT);
+            CREATE TABLE ZABCDEMAILADDRESS (Z_PK INTEGER PRIMARY KEY, ZOWNER INTEGER, ZADDRESS TEXT);
+            INSERT INTO ZABCDRECORD VALUES (1, 'Jane', 'Doe', NULL, NULL), (2, NULL, NULL, 'Pizza Place', NULL), (3, NULL, NULL, NULL, NULL);
+            INSERT INTO ZABCDPHONENUMBER VALUES (1, 1, '+1 (555) 123-4567'), (2, 2, '555-000-1111'), (3, 3, '555-000-2222');
+            INSERT INTO ZABCDEMAILADDRESS VALUES (1, 1, 'jane@example.com');
+            ",
+        );
+        let contacts = Contacts::from_address_book(&path, &Region::default()).unwrap();
+        remove_file(&path).unwrap();
+
+        assert_eq!(contacts.len(), 2);
+        assert_eq!(contacts.name("+15551234567"), Some("Jane Doe"));
+        assert_eq!(contacts.name("jane@example.com"), Some("Jane Doe"));
+        assert_eq!(contacts.name("+15550001111"), Some("Pizza Place"));
+        assert_eq!(contacts.name("+15550002222"), None);
+    }
+
+    #[test]
+    fn can_read_ios_address_book() {
+        let path = fake_address_book(
+            "imessage-database-ios-contacts.sqlitedb",
+            "
+            CREATE TABLE ABPerson (ROWID INTEGER PRIMARY KEY, First TEXT, Last TEXT, Organization TEXT, Nickname TEXT);
+            CREATE TABLE ABMultiValue (UID INTEGER PRIMARY KEY, record_id INTEGER, property INTEGER, value TEXT);
+            INSERT INTO ABPerson VALUES (1, 'Jane', NULL, NULL, NULL);
+            INSERT INTO ABMultiValue VALUES (1, 1, 3, '(555) 123-4567'), (2, 1, 4, 'jane@example.com'), (3, 1, 5, '555-000-1111');
+            ",
+        );
+        let contacts = Contacts::from_address_book(&path, &Region::default()).unwrap();
+        remove_file(&path).unwrap();
+
+        assert_eq!(contacts.len(), 1);
+        assert_eq!(contacts.name("+15551234567"), Some("Jane"));
+        assert_eq!(contacts.name("jane@example.com"), Some("Jane"));
+        assert_eq!(contacts.name("+15550001111"), None);
+    }
+
+    #[test]
+    fn cant_read_other_database() {
+        let path = fake_address_book(
+            "imessage-database-not-contacts.db",
+            "CREATE TABLE message (ROWID INTEGER PRIMARY KEY);",
+        );
+        let contacts = Contacts::from_address_book(&path, &Region::default());
+        remove_file(&path).unwrap();
+
+        assert!(contacts.is_err());
+    }
+
+    #[test]
+    fn cant_read_missing_database() {
+        let contacts = Contacts::from_address_book(
+            &temp_dir().join("imessage-database-missing.abcddb"),
+            &Region::default(),
+        );
+        assert!(contacts.is_err());
+    }
+}
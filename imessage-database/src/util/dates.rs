@@ -4,12 +4,102 @@
  Most dates are stored as nanosecond-precision unix timestamps with an epoch of `1/1/2001 00:00:00` in the local time zone.
 */
 
-use chrono::{DateTime, Duration, Local, NaiveDateTime, TimeZone, Utc};
+use chrono::{DateTime, Duration, FixedOffset, Local, SecondsFormat, TimeZone, Utc};
+use chrono_tz::Tz;
 
 use crate::error::message::MessageError;
 
 const SEPARATOR: &str = ", ";
 pub const TIMESTAMP_FACTOR: i64 = 1000000000;
+/// Default `strftime` format for readable dates, like `May 17, 2022  8:29:42 PM`
+pub const DEFAULT_DATE_FORMAT: &str = "%b %d, %Y %l:%M:%S %p";
+/// `strftime` format for [ISO 8601](https://en.wikipedia.org/wiki/ISO_8601) dates, like `2022-05-17T20:29:42-07:00`
+pub const ISO_DATE_FORMAT: &str = "%Y-%m-%dT%H:%M:%S%:z";
+
+/// Represents the time zone and format used to render dates
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DateFormat {
+    /// The IANA time zone dates are rendered in, or the machine's local time zone if `None`
+    pub time_zone: Option<Tz>,
+    /// The `strftime` format used for readable dates
+    pub format: String,
+}
+
+impl Default for DateFormat {
+    fn default() -> Self {
+        Self {
+            time_zone: None,
+            format: DEFAULT_DATE_FORMAT.to_string(),
+        }
+    }
+}
+
+impl DateFormat {
+    /// Convert a date to the time zone dates are rendered in
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// use chrono::offset::Local;
+    /// use imessage_database::util::dates::DateFormat;
+    ///
+    /// let date_format = DateFormat {
+    ///     time_zone: Some("Asia/Tokyo".parse().unwrap()),
+    ///     ..Default::default()
+    /// };
+    /// let date = date_format.in_zone(&Local::now());
+    /// ```
+    pub fn in_zone(&self, date: &DateTime<Local>) -> DateTime<FixedOffset> {
+        match self.time_zone {
+            Some(time_zone) => date.with_timezone(&time_zone).fixed_offset(),
+            None => date.fixed_offset(),
+        }
+    }
+
+    /// Format a date from the iMessage table for reading, in the configured time zone and format
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// use chrono::offset::Local;
+    /// use imessage_database::util::dates::{DateFormat, ISO_DATE_FORMAT};
+    ///
+    /// let date_format = DateFormat {
+    ///     time_zone: Some("UTC".parse().unwrap()),
+    ///     format: ISO_DATE_FORMAT.to_string(),
+    /// };
+    /// let date = date_format.format(&Ok(Local::now()));
+    /// println!("{date}");
+    /// ```
+    pub fn format(&self, date: &Result<DateTime<Local>, MessageError>) -> String {
+        match date {
+            // Format in the named zone so `%Z` renders its abbreviation
+            Ok(d) => match self.time_zone {
+                Some(time_zone) => d.with_timezone(&time_zone).format(&self.format).to_string(),
+                None => d.format(&self.format).to_string(),
+            },
+            Err(why) => why.to_string(),
+        }
+    }
+
+    /// Format a date from the iMessage table as an [RFC 3339](https://www.rfc-editor.org/rfc/rfc3339) (ISO 8601) timestamp in the configured time zone
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// use chrono::offset::Local;
+    /// use imessage_database::util::dates::DateFormat;
+    ///
+    /// let date = DateFormat::default().format_iso(&Ok(Local::now()));
+    /// println!("{date}");
+    /// ```
+    pub fn format_iso(&self, date: &Result<DateTime<Local>, MessageError>) -> String {
+        match date {
+            Ok(d) => self.in_zone(d).to_rfc3339_opts(SecondsFormat::Secs, false),
+            Err(why) => why.to_string(),
+        }
+    }
+}
 
 /// Get the date offset for the iMessage Database
 ///
@@ -26,8 +116,9 @@ pub fn get_offset() -> i64 {
 /// This is used to create date data for anywhere dates are stored in the table, including
 /// `PLIST` payloads or [`streamtyped`](crate::util::streamtyped) data.
 pub fn get_local_time(date_stamp: &i64, offset: &i64) -> Result<DateTime<Local>, MessageError> {
-    let utc_stamp = NaiveDateTime::from_timestamp_opt((date_stamp / TIMESTAMP_FACTOR) + offset, 0)
-        .ok_or(MessageError::InvalidTimestamp(*date_stamp))?;
+    let utc_stamp = DateTime::from_timestamp((date_stamp / TIMESTAMP_FACTOR) + offset, 0)
+        .ok_or(MessageError::InvalidTimestamp(*date_stamp))?
+        .naive_utc();
     Ok(Local.from_utc_datetime(&utc_stamp))
 }
 
@@ -49,6 +140,24 @@ pub fn format(date: &Result<DateTime<Local>, MessageError>) -> String {
     }
 }
 
+/// Format a date from the iMessage table as an [RFC 3339](https://www.rfc-editor.org/rfc/rfc3339) (ISO 8601) timestamp
+///
+/// # Example:
+///
+/// ```
+/// use chrono::offset::Local;
+/// use imessage_database::util::dates::format_iso;
+///
+/// let date = format_iso(&Ok(Local::now()));
+/// println!("{date}");
+/// ```
+pub fn format_iso(date: &Result<DateTime<Local>, MessageError>) -> String {
+    match date {
+        Ok(d) => d.to_rfc3339_opts(SecondsFormat::Secs, false),
+        Err(why) => why.to_string(),
+    }
+}
+
 /// Generate a readable diff from two local timestamps.
 ///
 /// # Example:
@@ -128,7 +237,7 @@ pub fn readable_diff(
 mod tests {
     use crate::{
         error::message::MessageError,
-        util::dates::{format, readable_diff},
+        util::dates::{format, format_iso, readable_diff, DateFormat, ISO_DATE_FORMAT},
     };
     use chrono::prelude::*;
 
@@ -150,6 +259,94 @@ mod tests {
         assert_eq!(format(&date), "May 20, 2020 10:10:11 AM");
     }
 
+    #[test]
+    fn can_format_date_iso() {
+        let date = Local
+            .with_ymd_and_hms(2020, 5, 20, 9, 10, 11)
+            .single()
+            .ok_or(MessageError::InvalidTimestamp(0));
+        let expected = format!(
+            "2020-05-20T09:10:11{}",
+            date.as_ref().unwrap().format("%:z")
+        );
+        assert_eq!(format_iso(&date), expected);
+    }
+
+    #[test]
+    fn cant_format_date_iso_invalid() {
+        let date = Err(MessageError::InvalidTimestamp(0));
+        assert_eq!(
+            format_iso(&date),
+            MessageError::InvalidTimestamp(0).to_string()
+        );
+    }
+
+    #[test]
+    fn can_format_date_default() {
+        let date = Local
+            .with_ymd_and_hms(2020, 5, 20, 9, 10, 11)
+            .single()
+            .ok_or(MessageError::InvalidTimestamp(0));
+        assert_eq!(DateFormat::default().format(&date), format(&date));
+        assert_eq!(DateFormat::default().format_iso(&date), format_iso(&date));
+    }
+
+    #[test]
+    fn can_format_date_time_zone() {
+        let date = Utc
+            .with_ymd_and_hms(2020, 5, 20, 9, 10, 11)
+            .unwrap()
+            .with_timezone(&Local);
+        let date_format = DateFormat {
+            time_zone: Some("America/New_York".parse().unwrap()),
+            ..Default::default()
+        };
+        assert_eq!(date_format.format(&Ok(date)), "May 20, 2020  5:10:11 AM");
+        assert_eq!(
+            date_format.format_iso(&Ok(date)),
+            "2020-05-20T05:10:11-04:00"
+        );
+    }
+
+    #[test]
+    fn can_format_date_custom_format() {
+        let date = Utc
+            .with_ymd_and_hms(2020, 12, 20, 9, 10, 11)
+            .unwrap()
+            .with_timezone(&Local);
+        let date_format = DateFormat {
+            time_zone: Some("Europe/Berlin".parse().unwrap()),
+            format: "%d.%m.%Y %H:%M %Z".to_string(),
+        };
+        assert_eq!(date_format.format(&Ok(date)), "20.12.2020 10:10 CET");
+    }
+
+    #[test]
+    fn can_format_date_iso_preset() {
+        let date = Utc
+            .with_ymd_and_hms(2020, 5, 20, 9, 10, 11)
+            .unwrap()
+            .with_timezone(&Local);
+        let date_format = DateFormat {
+            time_zone: Some("UTC".parse().unwrap()),
+            format: ISO_DATE_FORMAT.to_string(),
+        };
+        assert_eq!(date_format.format(&Ok(date)), "2020-05-20T09:10:11+00:00");
+    }
+
+    #[test]
+    fn cant_format_date_time_zone_invalid() {
+        let date = Err(MessageError::InvalidTimestamp(0));
+        let date_format = DateFormat {
+            time_zone: Some("UTC".parse().unwrap()),
+            ..Default::default()
+        };
+        assert_eq!(
+            date_format.format(&date),
+            MessageError::InvalidTimestamp(0).to_string()
+        );
+    }
+
     #[test]
     fn cant_format_diff_backwards() {
         let end = Ok(Local.with_ymd_and_hms(2020, 5, 20, 9, 10, 11).unwrap());
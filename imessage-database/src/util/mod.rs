@@ -2,9 +2,11 @@
  This module defines common utilities used across table queries.
 */
 
+pub mod contacts;
 pub mod dates;
 pub mod dirs;
 pub mod output;
+pub mod phone_number;
 pub mod platform;
 pub mod plist;
 pub mod query_context;
@@ -0,0 +1,216 @@
+/*!
+ Contains logic for normalizing phone numbers to [E.164](https://en.wikipedia.org/wiki/E.164) so different spellings of the same handle match.
+*/
+
+use std::fmt::Display;
+
+/// Numbers with fewer digits than this are treated as short codes and are never given a country code
+const MIN_NATIONAL_DIGITS: usize = 7;
+
+/// Represents the region used to read phone numbers that do not include a country code
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Region {
+    /// ISO 3166 country code, like `US`
+    pub code: &'static str,
+    /// Country calling code, like `1`
+    pub calling_code: &'static str,
+    /// Prefix dialed before national numbers, like the `0` in `020 7946 0958`
+    trunk_prefix: &'static str,
+    /// Prefix dialed before international numbers, like the `011` in `011 44 20 7946 0958`
+    international_prefix: &'static str,
+}
+
+impl Region {
+    const fn new(
+        code: &'static str,
+        calling_code: &'static str,
+        trunk_prefix: &'static str,
+        international_prefix: &'static str,
+    ) -> Self {
+        Self {
+            code,
+            calling_code,
+            trunk_prefix,
+            international_prefix,
+        }
+    }
+
+    /// Given user's input, return a variant if the input matches one
+    pub fn from_cli(region: &str) -> Option<Self> {
+        REGIONS
+            .iter()
+            .find(|candidate| candidate.code.eq_ignore_ascii_case(region))
+            .copied()
+    }
+
+    /// Normalize a handle so different spellings of the same phone number or email address match
+    ///
+    /// - Email addresses lose any `e:` prefix and are lowercased
+    /// - Phone numbers are converted to E.164, like `+15558675309`, using this region for numbers without a country code
+    /// - Short codes and anything else, like business chat IDs, are returned as-is
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// use imessage_database::util::phone_number::Region;
+    ///
+    /// let region = Region::default();
+    /// assert_eq!(region.normalize("(555) 867-5309"), "+15558675309");
+    /// assert_eq!(region.normalize("+1 555 867 5309"), "+15558675309");
+    /// assert_eq!(region.normalize("e:Jenny@Example.com"), "jenny@example.com");
+    /// ```
+    pub fn normalize(&self, id: &str) -> String {
+        let id = id.trim();
+        let email = id
+            .strip_prefix("e:")
+            .or_else(|| id.strip_prefix("E:"))
+            .unwrap_or(id);
+        if email.contains('@') {
+            return email.to_lowercase();
+        }
+
+        let is_phone_number = id.chars().all(|c| {
+            c.is_ascii_digit() || c.is_whitespace() || matches!(c, '+' | '(' | ')' | '-' | '.')
+        });
+        let digits: String = id.chars().filter(char::is_ascii_digit).collect();
+        if !is_phone_number || digits.is_empty() {
+            return id.to_string();
+        }
+
+        if id.starts_with('+') {
+            format!("+{digits}")
+        } else if let Some(number) = digits.strip_prefix(self.international_prefix) {
+            format!("+{number}")
+        } else if digits.len() < MIN_NATIONAL_DIGITS {
+            digits
+        } else {
+            let national = match self.trunk_prefix {
+                "" => &digits,
+                trunk_prefix => digits.strip_prefix(trunk_prefix).unwrap_or(&digits),
+            };
+            format!("+{}{national}", self.calling_code)
+        }
+    }
+}
+
+impl Default for Region {
+    fn default() -> Self {
+        REGIONS[0]
+    }
+}
+
+impl Display for Region {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(fmt, "{}", self.code)
+    }
+}
+
+/// Supported regions, with the default region first
+const REGIONS: &[Region] = &[
+    Region::new("US", "1", "1", "011"),
+    Region::new("CA", "1", "1", "011"),
+    Region::new("MX", "52", "", "00"),
+    Region::new("BR", "55", "0", "00"),
+    Region::new("AR", "54", "0", "00"),
+    Region::new("CL", "56", "", "00"),
+    Region::new("CO", "57", "", "00"),
+    Region::new("GB", "44", "0", "00"),
+    Region::new("IE", "353", "0", "00"),
+    Region::new("FR", "33", "0", "00"),
+    Region::new("DE", "49", "0", "00"),
+    Region::new("AT", "43", "0", "00"),
+    Region::new("CH", "41", "0", "00"),
+    Region::new("NL", "31", "0", "00"),
+    Region::new("BE", "32", "0", "00"),
+    Region::new("IT", "39", "", "00"),
+    Region::new("ES", "34", "", "00"),
+    Region::new("PT", "351", "", "00"),
+    Region::new("DK", "45", "", "00"),
+    Region::new("NO", "47", "", "00"),
+    Region::new("SE", "46", "0", "00"),
+    Region::new("FI", "358", "0", "00"),
+    Region::new("PL", "48", "", "00"),
+    Region::new("CZ", "420", "", "00"),
+    Region::new("GR", "30", "", "00"),
+    Region::new("TR", "90", "0", "00"),
+    Region::new("UA", "380", "0", "00"),
+    Region::new("RU", "7", "8", "810"),
+    Region::new("IL", "972", "0", "00"),
+    Region::new("AE", "971", "0", "00"),
+    Region::new("SA", "966", "0", "00"),
+    Region::new("EG", "20", "0", "00"),
+    Region::new("NG", "234", "0", "009"),
+    Region::new("KE", "254", "0", "000"),
+    Region::new("ZA", "27", "0", "00"),
+    Region::new("IN", "91", "0", "00"),
+    Region::new("PK", "92", "0", "00"),
+    Region::new("CN", "86", "0", "00"),
+    Region::new("HK", "852", "", "001"),
+    Region::new("TW", "886", "0", "002"),
+    Region::new("JP", "81", "0", "010"),
+    Region::new("KR", "82", "0", "001"),
+    Region::new("SG", "65", "", "000"),
+    Region::new("MY", "60", "0", "00"),
+    Region::new("TH", "66", "0", "001"),
+    Region::new("VN", "84", "0", "00"),
+    Region::new("PH", "63", "0", "00"),
+    Region::new("ID", "62", "0", "001"),
+    Region::new("AU", "61", "0", "0011"),
+    Region::new("NZ", "64", "0", "00"),
+];
+
+#[cfg(test)]
+mod tests {
+    use crate::util::phone_number::Region;
+
+    #[test]
+    fn can_parse_region() {
+        assert_eq!(Region::from_cli("gb").unwrap().calling_code, "44");
+        assert_eq!(Region::from_cli("US"), Some(Region::default()));
+        assert_eq!(Region::from_cli("XX"), None);
+    }
+
+    #[test]
+    fn can_normalize_national_number() {
+        let region = Region::default();
+        assert_eq!(region.normalize("5551234567"), "+15551234567");
+        assert_eq!(region.normalize("1 (555) 123-4567"), "+15551234567");
+        assert_eq!(region.normalize("555.123.4567"), "+15551234567");
+    }
+
+    #[test]
+    fn can_normalize_international_number() {
+        let region = Region::default();
+        assert_eq!(region.normalize("+1 (555) 123-4567"), "+15551234567");
+        assert_eq!(region.normalize("+44 20 7946 0958"), "+442079460958");
+        assert_eq!(region.normalize("011 44 20 7946 0958"), "+442079460958");
+    }
+
+    #[test]
+    fn can_normalize_with_trunk_prefix() {
+        let region = Region::from_cli("GB").unwrap();
+        assert_eq!(region.normalize("020 7946 0958"), "+442079460958");
+        assert_eq!(region.normalize("00 1 555 123 4567"), "+15551234567");
+    }
+
+    #[test]
+    fn can_normalize_without_trunk_prefix() {
+        let region = Region::from_cli("IT").unwrap();
+        assert_eq!(region.normalize("06 1234 5678"), "+390612345678");
+    }
+
+    #[test]
+    fn can_normalize_email() {
+        let region = Region::default();
+        assert_eq!(region.normalize("e:Jane@Example.com"), "jane@example.com");
+        assert_eq!(region.normalize("jane@example.com"), "jane@example.com");
+    }
+
+    #[test]
+    fn cant_normalize_other() {
+        let region = Region::default();
+        assert_eq!(region.normalize("12345"), "12345");
+        assert_eq!(region.normalize("chat123456"), "chat123456");
+        assert_eq!(region.normalize("urn:biz:1234"), "urn:biz:1234");
+    }
+}
@@ -1,13 +1,121 @@
 /*!
  Contains logic for handling query filter configurations.
 */
-use chrono::prelude::*;
+use std::collections::BTreeSet;
+
+use chrono::{prelude::*, Days, Months, TimeDelta};
+
+use regex::Regex;
+use rusqlite::Connection;
 
 use crate::{
-    error::query_context::QueryContextError,
+    error::{query_context::QueryContextError, table::TableError},
+    tables::{
+        handle::Handle,
+        table::{CHAT, CHAT_HANDLE_JOIN, CHAT_MESSAGE_JOIN, MESSAGE},
+    },
     util::dates::{get_offset, TIMESTAMP_FACTOR},
 };
 
+#[derive(Debug, Default, PartialEq, Eq)]
+/// Represents a set of chats, matched by `ROWID`, `chat_identifier`, or `display_name`.
+pub struct ChatFilter {
+    /// Chat `ROWID`s to match
+    pub ids: BTreeSet<i32>,
+    /// Chat identifiers to match, like a phone number, email address, or group chat ID
+    pub identifiers: BTreeSet<String>,
+    /// Chat display names to match
+    pub display_names: BTreeSet<String>,
+}
+
+impl ChatFilter {
+    /// Match a chat by its identifier or display name, or by its `ROWID` if `chat` is a number
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// use imessage_database::util::query_context::ChatFilter;
+    ///
+    /// let mut filter = ChatFilter::default();
+    /// filter.add("Book Club");
+    /// filter.add("+15558675309");
+    /// filter.add("3");
+    /// ```
+    pub fn add(&mut self, chat: &str) {
+        if let Ok(id) = chat.parse::<i32>() {
+            self.ids.insert(id);
+        }
+        self.identifiers.insert(chat.to_string());
+        self.display_names.insert(chat.to_string());
+    }
+
+    /// Determine if the filter matches any chats
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty() && self.identifiers.is_empty() && self.display_names.is_empty()
+    }
+
+    /// Generate a SQL query that selects the `ROWID` of every matching chat
+    fn generate_statement(&self) -> String {
+        let mut conditions = vec![];
+        if !self.ids.is_empty() {
+            conditions.push(format!("ROWID IN ({})", join(&self.ids)));
+        }
+        if !self.identifiers.is_empty() {
+            conditions.push(format!(
+                "chat_identifier IN ({})",
+                join(self.identifiers.iter().map(|item| quote(item)))
+            ));
+        }
+        if !self.display_names.is_empty() {
+            conditions.push(format!(
+                "display_name IN ({})",
+                join(self.display_names.iter().map(|item| quote(item)))
+            ));
+        }
+        format!("SELECT ROWID FROM {CHAT} WHERE {}", conditions.join(" OR "))
+    }
+}
+
+#[derive(Debug)]
+/// Represents a filter on the text of a message, which is applied after the text is parsed instead of in SQL.
+pub enum TextFilter {
+    /// Match text that contains this lowercase string, ignoring case
+    Substring(String),
+    /// Match text that matches this regular expression
+    Regex(Regex),
+}
+
+impl TextFilter {
+    /// Determine if a message's text matches the filter
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// use imessage_database::util::query_context::TextFilter;
+    ///
+    /// let filter = TextFilter::Substring("dinner".to_string());
+    /// assert!(filter.is_match("Dinner at 7?"));
+    /// ```
+    pub fn is_match(&self, text: &str) -> bool {
+        match self {
+            TextFilter::Substring(substring) => text.to_lowercase().contains(substring),
+            TextFilter::Regex(regex) => regex.is_match(text),
+        }
+    }
+}
+
+impl PartialEq for TextFilter {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (TextFilter::Substring(a), TextFilter::Substring(b)) => a == b,
+            (TextFilter::Regex(a), TextFilter::Regex(b)) => a.as_str() == b.as_str(),
+            _ => false,
+        }
+    }
+}
+
+impl Eq for TextFilter {}
+
 #[derive(Debug, Default, PartialEq, Eq)]
 /// Represents filter configurations for a SQL query.
 pub struct QueryContext {
@@ -15,6 +123,28 @@ pub struct QueryContext {
     pub start: Option<i64>,
     /// The end date filter. Only messages sent before this date will be included.
     pub end: Option<i64>,
+    /// The resume filter. Only messages with a `ROWID` greater than this will be included, unless they belong to a chat in `refresh_chats`.
+    pub last_message: Option<i32>,
+    /// Chat IDs whose messages are all included when resuming, even if they were exported before
+    pub refresh_chats: BTreeSet<i32>,
+    /// The chat include filter. If any chats are set, only messages in matching chats will be included.
+    pub include_chats: ChatFilter,
+    /// The chat exclude filter. Messages in matching chats will not be included.
+    pub exclude_chats: ChatFilter,
+    /// The participant filter. If any handles are set, only messages in conversations with any of them will be included.
+    pub participants: BTreeSet<String>,
+    /// The sender filter. If any handles are set, only messages sent by any of them will be included.
+    pub senders: BTreeSet<String>,
+    /// Handle `ROWID`s that belong to the same people as `participants`, set by [`QueryContext::resolve_handles`]
+    pub participant_handles: BTreeSet<i32>,
+    /// Handle `ROWID`s that belong to the same people as `senders`, set by [`QueryContext::resolve_handles`]
+    pub sender_handles: BTreeSet<i32>,
+    /// The text filter. If set, only messages whose parsed text matches will be included.
+    ///
+    /// This filter is not part of the SQL query, since many messages only store their text in `attributedBody`.
+    pub text: Option<TextFilter>,
+    /// The number of messages in the same conversation to include before and after each text match
+    pub text_context: usize,
 }
 
 impl QueryContext {
@@ -26,6 +156,8 @@ impl QueryContext {
     ///
     /// let mut context = QueryContext::default();
     /// context.set_start("2023-01-01");
+    /// context.set_start("2023-01-01T09:30:00-08:00");
+    /// context.set_start("24h");
     /// ```
     pub fn set_start(&mut self, start: &str) -> Result<(), QueryContextError> {
         let timestamp = QueryContext::sanitize_date(start)
@@ -42,6 +174,7 @@ impl QueryContext {
     ///
     /// let mut context = QueryContext::default();
     /// context.set_end("2023-01-01");
+    /// context.set_end("yesterday");
     /// ```
     pub fn set_end(&mut self, end: &str) -> Result<(), QueryContextError> {
         let timestamp = QueryContext::sanitize_date(end)
@@ -50,8 +183,188 @@ impl QueryContext {
         Ok(())
     }
 
-    /// Ensure a date string is valid
+    /// Resume a previous export, only including messages added after `last_message` and every message in `refresh_chats`
+    /// # Example:
+    ///
+    /// ```
+    /// use std::collections::BTreeSet;
+    /// use imessage_database::util::query_context::QueryContext;
+    ///
+    /// let mut context = QueryContext::default();
+    /// context.set_resume(1000, BTreeSet::from([2, 3]));
+    /// ```
+    pub fn set_resume(&mut self, last_message: i32, refresh_chats: BTreeSet<i32>) {
+        self.last_message = Some(last_message);
+        self.refresh_chats = refresh_chats;
+    }
+
+    /// Only include messages from a chat, matched by its identifier, display name, or `ROWID`
+    /// # Example:
+    ///
+    /// ```
+    /// use imessage_database::util::query_context::QueryContext;
+    ///
+    /// let mut context = QueryContext::default();
+    /// context.include_chat("Book Club");
+    /// ```
+    pub fn include_chat(&mut self, chat: &str) {
+        self.include_chats.add(chat);
+    }
+
+    /// Exclude messages from a chat, matched by its identifier, display name, or `ROWID`
+    /// # Example:
+    ///
+    /// ```
+    /// use imessage_database::util::query_context::QueryContext;
+    ///
+    /// let mut context = QueryContext::default();
+    /// context.exclude_chat("+15558675309");
+    /// ```
+    pub fn exclude_chat(&mut self, chat: &str) {
+        self.exclude_chats.add(chat);
+    }
+
+    /// Only include messages from conversations with a handle, like a phone number or email address
+    ///
+    /// The handle is matched when [`QueryContext::resolve_handles`] runs.
+    /// # Example:
+    ///
+    /// ```
+    /// use imessage_database::util::query_context::QueryContext;
+    ///
+    /// let mut context = QueryContext::default();
+    /// context.include_participant("+15558675309");
+    /// ```
+    pub fn include_participant(&mut self, handle: &str) {
+        self.participants.insert(handle.to_string());
+    }
+
+    /// Only include messages sent by a handle, like a phone number or email address
+    ///
+    /// The handle is matched when [`QueryContext::resolve_handles`] runs.
+    /// # Example:
+    ///
+    /// ```
+    /// use imessage_database::util::query_context::QueryContext;
+    ///
+    /// let mut context = QueryContext::default();
+    /// context.include_sender("person@example.com");
+    /// ```
+    pub fn include_sender(&mut self, handle: &str) {
+        self.senders.insert(handle.to_string());
+    }
+
+    /// Only include messages that contain some text, ignoring case
+    /// # Example:
+    ///
+    /// ```
+    /// use imessage_database::util::query_context::QueryContext;
+    ///
+    /// let mut context = QueryContext::default();
+    /// context.set_text("dinner");
+    /// ```
+    pub fn set_text(&mut self, text: &str) {
+        self.text = Some(TextFilter::Substring(text.to_lowercase()));
+    }
+
+    /// Only include messages whose text matches a regular expression
+    ///
+    /// Matching is case sensitive unless the pattern starts with `(?i)`.
+    /// # Example:
+    ///
+    /// ```
+    /// use imessage_database::util::query_context::QueryContext;
+    ///
+    /// let mut context = QueryContext::default();
+    /// context.set_text_regex(r"(?i)flight \w+\d+");
+    /// ```
+    pub fn set_text_regex(&mut self, pattern: &str) -> Result<(), QueryContextError> {
+        let regex = Regex::new(pattern)
+            .map_err(|why| QueryContextError::InvalidRegex(pattern.to_string(), why.to_string()))?;
+        self.text = Some(TextFilter::Regex(regex));
+        Ok(())
+    }
+
+    /// Include a number of messages before and after each text match, so matches read in context
+    /// # Example:
+    ///
+    /// ```
+    /// use imessage_database::util::query_context::QueryContext;
+    ///
+    /// let mut context = QueryContext::default();
+    /// context.set_text("dinner");
+    /// context.set_text_context(3);
+    /// ```
+    pub fn set_text_context(&mut self, messages: usize) {
+        self.text_context = messages;
+    }
+
+    /// Determine if a message's parsed text passes the text filter, if there is one
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// use imessage_database::util::query_context::QueryContext;
+    ///
+    /// let mut context = QueryContext::default();
+    /// assert!(context.matches_text(None));
+    /// context.set_text("dinner");
+    /// assert!(context.matches_text(Some("Dinner at 7?")));
+    /// assert!(!context.matches_text(None));
+    /// ```
+    pub fn matches_text(&self, text: Option<&str>) -> bool {
+        match &self.text {
+            Some(filter) => text.is_some_and(|text| filter.is_match(text)),
+            None => true,
+        }
+    }
+
+    /// Find every handle that belongs to the same people as the participant and sender filters
+    ///
+    /// Handles that share a `person_centric_id` with a match are included, so a person's other
+    /// phone numbers and email addresses match too.
+    /// # Example:
+    ///
+    /// ```
+    /// use imessage_database::util::dirs::default_db_path;
+    /// use imessage_database::tables::table::get_connection;
+    /// use imessage_database::util::query_context::QueryContext;
+    ///
+    /// let db_path = default_db_path();
+    /// let conn = get_connection(&db_path).unwrap();
+    /// let mut context = QueryContext::default();
+    /// context.include_participant("+15558675309");
+    /// context.resolve_handles(&conn);
+    /// ```
+    pub fn resolve_handles(&mut self, db: &Connection) -> Result<(), TableError> {
+        if !self.participants.is_empty() {
+            self.participant_handles = Handle::get_person_handles(db, &self.participants)?;
+        }
+        if !self.senders.is_empty() {
+            self.sender_handles = Handle::get_person_handles(db, &self.senders)?;
+        }
+        Ok(())
+    }
+
+    /// Ensure a date string is valid, converting it to a timestamp in the iMessage epoch
+    ///
+    /// Dates can be a day like `2023-01-01` at local midnight, an RFC 3339 timestamp like
+    /// `2023-01-01T09:30:00-08:00`, or a time relative to now like `7d`, `3 months ago`, or `yesterday`.
     fn sanitize_date(date: &str) -> Option<i64> {
+        let date_time = QueryContext::parse_day(date)
+            .or_else(|| {
+                DateTime::parse_from_rfc3339(date)
+                    .ok()
+                    .map(|date_time| date_time.with_timezone(&Local))
+            })
+            .or_else(|| QueryContext::parse_relative(date, Local::now()))?;
+        let stamp = date_time.timestamp_nanos_opt()?;
+
+        Some(stamp - (get_offset() * TIMESTAMP_FACTOR))
+    }
+
+    /// Parse a `YYYY-MM-DD` date as local midnight
+    fn parse_day(date: &str) -> Option<DateTime<Local>> {
         if date.len() < 9 {
             return None;
         }
@@ -76,10 +389,45 @@ impl QueryContext {
             return None;
         }
 
-        let local = Local.with_ymd_and_hms(year, month, day, 0, 0, 0).single()?;
-        let stamp = local.timestamp_nanos_opt().unwrap_or(0);
+        Local.with_ymd_and_hms(year, month, day, 0, 0, 0).single()
+    }
 
-        Some(stamp - (get_offset() * TIMESTAMP_FACTOR))
+    /// Parse a time relative to `now`, like `now`, `today`, `yesterday`, `24h`, `7d`, `2 weeks`, or `3 months ago`
+    ///
+    /// `today` and `yesterday` are local midnight. Days, weeks, months, and years are calendar units, so `1d` is the same
+    /// time on the previous day even across a daylight saving time change.
+    fn parse_relative(date: &str, now: DateTime<Local>) -> Option<DateTime<Local>> {
+        let date = date.trim().to_lowercase();
+        let midnight = |days: u64| {
+            now.date_naive()
+                .checked_sub_days(Days::new(days))?
+                .and_hms_opt(0, 0, 0)?
+                .and_local_timezone(Local)
+                .earliest()
+        };
+        match date.as_str() {
+            "now" => return Some(now),
+            "today" => return midnight(0),
+            "yesterday" => return midnight(1),
+            _ => {}
+        }
+
+        let amount = date.strip_suffix("ago").unwrap_or(&date).trim_end();
+        let (count, unit) = amount.split_at(amount.find(|c: char| !c.is_ascii_digit())?);
+        let count = count.parse::<u32>().ok()?;
+        match unit.trim_start() {
+            "m" | "min" | "mins" | "minute" | "minutes" => {
+                now.checked_sub_signed(TimeDelta::try_minutes(count.into())?)
+            }
+            "h" | "hr" | "hrs" | "hour" | "hours" => {
+                now.checked_sub_signed(TimeDelta::try_hours(count.into())?)
+            }
+            "d" | "day" | "days" => now.checked_sub_days(Days::new(count.into())),
+            "w" | "week" | "weeks" => now.checked_sub_days(Days::new(u64::from(count) * 7)),
+            "mo" | "month" | "months" => now.checked_sub_months(Months::new(count)),
+            "y" | "year" | "years" => now.checked_sub_months(Months::new(count.checked_mul(12)?)),
+            _ => None,
+        }
     }
 
     /// Determine if the current `QueryContext` has any filters present
@@ -96,9 +444,84 @@ impl QueryContext {
     /// ```
     pub fn has_filters(&self) -> bool {
         [self.start, self.end].iter().any(Option::is_some)
+            || self.last_message.is_some()
+            || self.has_chat_filters()
+            || self.has_handle_filters()
+    }
+
+    /// Determine if the current `QueryContext` includes or excludes any chats
+    pub fn has_chat_filters(&self) -> bool {
+        !self.include_chats.is_empty() || !self.exclude_chats.is_empty()
+    }
+
+    /// Generate the SQL conditions that select messages in the included chats and not in the excluded chats
+    ///
+    /// `message_id` is the column that holds the message's `ROWID`.
+    /// # Example:
+    ///
+    /// ```
+    /// use imessage_database::util::query_context::QueryContext;
+    ///
+    /// let mut context = QueryContext::default();
+    /// context.include_chat("Book Club");
+    /// let filters = context.generate_chat_filters("m.ROWID");
+    /// ```
+    pub fn generate_chat_filters(&self, message_id: &str) -> Vec<String> {
+        let mut filters = vec![];
+        if !self.include_chats.is_empty() {
+            filters.push(format!(
+                "{message_id} IN (SELECT message_id FROM {CHAT_MESSAGE_JOIN} WHERE chat_id IN ({}))",
+                self.include_chats.generate_statement()
+            ));
+        }
+        if !self.exclude_chats.is_empty() {
+            filters.push(format!(
+                "{message_id} NOT IN (SELECT message_id FROM {CHAT_MESSAGE_JOIN} WHERE chat_id IN ({}))",
+                self.exclude_chats.generate_statement()
+            ));
+        }
+        filters
+    }
+
+    /// Determine if the current `QueryContext` filters messages by participant or sender
+    pub fn has_handle_filters(&self) -> bool {
+        !self.participants.is_empty() || !self.senders.is_empty()
+    }
+
+    /// Generate the SQL conditions that select messages involving the participants and sent by the senders
+    ///
+    /// `message_id` is the column that holds the message's `ROWID`. Handles that did not resolve match no messages.
+    /// # Example:
+    ///
+    /// ```
+    /// use imessage_database::util::query_context::QueryContext;
+    ///
+    /// let mut context = QueryContext::default();
+    /// context.include_sender("+15558675309");
+    /// let filters = context.generate_handle_filters("m.ROWID");
+    /// ```
+    pub fn generate_handle_filters(&self, message_id: &str) -> Vec<String> {
+        let mut filters = vec![];
+        if !self.participants.is_empty() {
+            // Messages that are not in a chat only have the handle they were sent to or from
+            let handles = join(&self.participant_handles);
+            filters.push(format!(
+                "{message_id} IN (SELECT ROWID FROM {MESSAGE} WHERE handle_id IN ({handles}) UNION SELECT message_id FROM {CHAT_MESSAGE_JOIN} WHERE chat_id IN (SELECT chat_id FROM {CHAT_HANDLE_JOIN} WHERE handle_id IN ({handles})))"
+            ));
+        }
+        if !self.senders.is_empty() {
+            // Messages sent by the database owner in a direct chat use the recipient's handle
+            filters.push(format!(
+                "{message_id} IN (SELECT ROWID FROM {MESSAGE} WHERE is_from_me = 0 AND handle_id IN ({}))",
+                join(&self.sender_handles)
+            ));
+        }
+        filters
     }
 
     /// Generate the SQL `WHERE` clause described by this `QueryContext`
+    ///
+    /// Dates are compared against `field`; the resume, chat, and handle filters expect the message table to be aliased as `m`.
     /// # Example:
     ///
     /// ```
@@ -109,30 +532,58 @@ impl QueryContext {
     /// let filters = context.generate_filter_statement("field_name");
     /// ```
     pub fn generate_filter_statement(&self, field: &str) -> String {
-        let mut filters = String::new();
+        let mut filters = vec![];
         if let Some(start) = self.start {
-            filters.push_str(&format!("    {field} >= {start}"));
+            filters.push(format!("{field} >= {start}"));
         }
         if let Some(end) = self.end {
-            if !filters.is_empty() {
-                filters.push_str(" AND ");
+            filters.push(format!("{field} <= {end}"));
+        }
+        if let Some(last_message) = self.last_message {
+            if self.refresh_chats.is_empty() {
+                filters.push(format!("m.ROWID > {last_message}"));
+            } else {
+                filters.push(format!(
+                    "(m.ROWID > {last_message} OR m.ROWID IN (SELECT message_id FROM {CHAT_MESSAGE_JOIN} WHERE chat_id IN ({})))",
+                    join(&self.refresh_chats)
+                ));
             }
-            filters.push_str(&format!("    {field} <= {end}"));
         }
+        filters.extend(self.generate_chat_filters("m.ROWID"));
+        filters.extend(self.generate_handle_filters("m.ROWID"));
 
         if !filters.is_empty() {
             return format!(
                 " WHERE
-                 {filters}"
+                 {}",
+                filters
+                    .iter()
+                    .map(|filter| format!("    {filter}"))
+                    .collect::<Vec<_>>()
+                    .join(" AND ")
             );
         }
-        filters
+        String::new()
     }
 }
 
+/// Join items into a comma-separated SQL list
+fn join<T: ToString>(items: impl IntoIterator<Item = T>) -> String {
+    items
+        .into_iter()
+        .map(|item| item.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Quote a string as a SQL literal
+fn quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "''"))
+}
+
 #[cfg(test)]
 mod use_tests {
-    use std::env::set_var;
+    use std::{collections::BTreeSet, env::set_var};
 
     use chrono::prelude::*;
 
@@ -157,11 +608,12 @@ mod use_tests {
         let mut context = QueryContext::default();
         context.set_start("2020-01-01").unwrap();
 
-        let from_timestamp = NaiveDateTime::from_timestamp_opt(
+        let from_timestamp = DateTime::from_timestamp(
             (context.start.unwrap() / TIMESTAMP_FACTOR) + get_offset(),
             0,
         )
-        .unwrap();
+        .unwrap()
+        .naive_utc();
         let local = Local.from_utc_datetime(&from_timestamp);
 
         assert_eq!(format(&Ok(local)), "Jan 01, 2020 12:00:00 AM");
@@ -182,11 +634,10 @@ mod use_tests {
         let mut context = QueryContext::default();
         context.set_end("2020-01-01").unwrap();
 
-        let from_timestamp = NaiveDateTime::from_timestamp_opt(
-            (context.end.unwrap() / TIMESTAMP_FACTOR) + get_offset(),
-            0,
-        )
-        .unwrap();
+        let from_timestamp =
+            DateTime::from_timestamp((context.end.unwrap() / TIMESTAMP_FACTOR) + get_offset(), 0)
+                .unwrap()
+                .naive_utc();
         let local = Local.from_utc_datetime(&from_timestamp);
 
         assert_eq!(format(&Ok(local)), "Jan 01, 2020 12:00:00 AM");
@@ -208,18 +659,18 @@ mod use_tests {
         context.set_start("2020-01-01").unwrap();
         context.set_end("2020-02-02").unwrap();
 
-        let from_timestamp = NaiveDateTime::from_timestamp_opt(
+        let from_timestamp = DateTime::from_timestamp(
             (context.start.unwrap() / TIMESTAMP_FACTOR) + get_offset(),
             0,
         )
-        .unwrap();
+        .unwrap()
+        .naive_utc();
         let local_start = Local.from_utc_datetime(&from_timestamp);
 
-        let from_timestamp = NaiveDateTime::from_timestamp_opt(
-            (context.end.unwrap() / TIMESTAMP_FACTOR) + get_offset(),
-            0,
-        )
-        .unwrap();
+        let from_timestamp =
+            DateTime::from_timestamp((context.end.unwrap() / TIMESTAMP_FACTOR) + get_offset(), 0)
+                .unwrap()
+                .naive_utc();
         let local_end = Local.from_utc_datetime(&from_timestamp);
 
         assert_eq!(format(&Ok(local_start)), "Jan 01, 2020 12:00:00 AM");
@@ -233,6 +684,151 @@ mod use_tests {
         assert!(context.has_filters());
     }
 
+    #[test]
+    fn can_create_resume() {
+        let mut context = QueryContext::default();
+        context.set_resume(100, BTreeSet::new());
+
+        assert_eq!(
+            context.generate_filter_statement("m.date"),
+            " WHERE\n                     m.ROWID > 100"
+        );
+        assert!(context.has_filters());
+    }
+
+    #[test]
+    fn can_create_resume_refresh() {
+        // Set timezone to PST for consistent Local time
+        set_var("TZ", "PST");
+
+        let mut context = QueryContext::default();
+        context.set_start("2020-01-01").unwrap();
+        context.set_resume(100, BTreeSet::from([3, 1]));
+
+        assert_eq!(
+            context.generate_filter_statement("m.date"),
+            " WHERE\n                     m.date >= 599558400000000000 AND     (m.ROWID > 100 OR m.ROWID IN (SELECT message_id FROM chat_message_join WHERE chat_id IN (1, 3)))"
+        );
+        assert!(context.has_filters());
+    }
+
+    #[test]
+    fn can_create_include_chats() {
+        let mut context = QueryContext::default();
+        context.include_chat("Book Club");
+        context.include_chat("3");
+
+        assert_eq!(
+            context.generate_filter_statement("m.date"),
+            " WHERE\n                     m.ROWID IN (SELECT message_id FROM chat_message_join WHERE chat_id IN (SELECT ROWID FROM chat WHERE ROWID IN (3) OR chat_identifier IN ('3', 'Book Club') OR display_name IN ('3', 'Book Club')))"
+        );
+        assert!(context.has_chat_filters());
+        assert!(context.has_filters());
+    }
+
+    #[test]
+    fn can_create_exclude_chats() {
+        // Set timezone to PST for consistent Local time
+        set_var("TZ", "PST");
+
+        let mut context = QueryContext::default();
+        context.set_start("2020-01-01").unwrap();
+        context.exclude_chat("Mom's Phone");
+
+        assert_eq!(
+            context.generate_filter_statement("m.date"),
+            " WHERE\n                     m.date >= 599558400000000000 AND     m.ROWID NOT IN (SELECT message_id FROM chat_message_join WHERE chat_id IN (SELECT ROWID FROM chat WHERE chat_identifier IN ('Mom''s Phone') OR display_name IN ('Mom''s Phone')))"
+        );
+        assert!(context.include_chats.is_empty());
+        assert!(context.has_filters());
+    }
+
+    #[test]
+    fn can_create_chat_ids() {
+        let mut context = QueryContext::default();
+        context.exclude_chats.ids.insert(5);
+
+        assert_eq!(
+            context.generate_chat_filters("message_id"),
+            vec!["message_id NOT IN (SELECT message_id FROM chat_message_join WHERE chat_id IN (SELECT ROWID FROM chat WHERE ROWID IN (5)))"]
+        );
+    }
+
+    #[test]
+    fn can_create_participants() {
+        let mut context = QueryContext::default();
+        context.include_participant("+15558675309");
+        context.participant_handles = BTreeSet::from([1, 4]);
+
+        assert_eq!(
+            context.generate_filter_statement("m.date"),
+            " WHERE\n                     m.ROWID IN (SELECT ROWID FROM message WHERE handle_id IN (1, 4) UNION SELECT message_id FROM chat_message_join WHERE chat_id IN (SELECT chat_id FROM chat_handle_join WHERE handle_id IN (1, 4)))"
+        );
+        assert!(context.has_handle_filters());
+        assert!(context.has_filters());
+    }
+
+    #[test]
+    fn can_create_senders() {
+        let mut context = QueryContext::default();
+        context.include_sender("person@example.com");
+        context.sender_handles = BTreeSet::from([2]);
+
+        assert_eq!(
+            context.generate_handle_filters("message_id"),
+            vec!["message_id IN (SELECT ROWID FROM message WHERE is_from_me = 0 AND handle_id IN (2))"]
+        );
+        assert!(context.has_filters());
+    }
+
+    #[test]
+    fn can_create_senders_unresolved() {
+        let mut context = QueryContext::default();
+        context.include_sender("person@example.com");
+
+        assert_eq!(
+            context.generate_handle_filters("m.ROWID"),
+            vec!["m.ROWID IN (SELECT ROWID FROM message WHERE is_from_me = 0 AND handle_id IN ())"]
+        );
+    }
+
+    #[test]
+    fn can_match_text() {
+        let mut context = QueryContext::default();
+        context.set_text("DINNER");
+
+        assert!(context.matches_text(Some("Dinner at 7?")));
+        assert!(!context.matches_text(Some("Lunch at noon")));
+        assert!(!context.matches_text(None));
+        assert!(!context.has_filters());
+        assert_eq!(context.generate_filter_statement("m.date"), "");
+    }
+
+    #[test]
+    fn can_match_text_regex() {
+        let mut context = QueryContext::default();
+        context.set_text_regex(r"\bflight \w{2}\d+").unwrap();
+
+        assert!(context.matches_text(Some("My flight UA123 lands at 5")));
+        assert!(!context.matches_text(Some("My Flight UA123 lands at 5")));
+    }
+
+    #[test]
+    fn can_match_text_regex_ignoring_case() {
+        let mut context = QueryContext::default();
+        context.set_text_regex(r"(?i)\bflight \w{2}\d+").unwrap();
+
+        assert!(context.matches_text(Some("My Flight UA123 lands at 5")));
+    }
+
+    #[test]
+    fn cant_match_text_invalid_regex() {
+        let mut context = QueryContext::default();
+        assert!(context.set_text_regex("(unclosed").is_err());
+        assert!(context.text.is_none());
+        assert!(context.matches_text(Some("anything")));
+    }
+
     #[test]
     fn can_create_invalid_start() {
         let mut context = QueryContext::default();
@@ -252,7 +848,20 @@ mod use_tests {
 
 #[cfg(test)]
 mod sanitize_tests {
-    use crate::util::query_context::QueryContext;
+    use std::env::set_var;
+
+    use chrono::prelude::*;
+
+    use crate::util::{
+        dates::{get_offset, TIMESTAMP_FACTOR},
+        query_context::QueryContext,
+    };
+
+    fn now() -> DateTime<Local> {
+        // Set timezone to PST for consistent Local time
+        set_var("TZ", "PST");
+        Local.with_ymd_and_hms(2023, 3, 15, 12, 30, 0).unwrap()
+    }
 
     #[test]
     fn can_sanitize_good() {
@@ -295,4 +904,76 @@ mod sanitize_tests {
         let res = QueryContext::sanitize_date("2020–01–01");
         assert!(res.is_none());
     }
+
+    #[test]
+    fn can_sanitize_rfc_3339() {
+        let res = QueryContext::sanitize_date("2020-01-01T08:30:00-08:00").unwrap();
+        let expected = DateTime::parse_from_rfc3339("2020-01-01T16:30:00Z")
+            .unwrap()
+            .timestamp();
+        assert_eq!(res, (expected - get_offset()) * TIMESTAMP_FACTOR);
+    }
+
+    #[test]
+    fn can_sanitize_relative() {
+        assert!(QueryContext::sanitize_date("7d").is_some());
+        assert!(QueryContext::sanitize_date("3 months ago").is_some());
+        assert!(QueryContext::sanitize_date("yesterday").is_some());
+    }
+
+    #[test]
+    fn can_parse_relative_units() {
+        let now = now();
+        let parse = |date| QueryContext::parse_relative(date, now).unwrap();
+
+        assert_eq!(parse("now"), now);
+        assert_eq!(
+            parse("30m"),
+            Local.with_ymd_and_hms(2023, 3, 15, 12, 0, 0).unwrap()
+        );
+        assert_eq!(
+            parse("24h"),
+            Local.with_ymd_and_hms(2023, 3, 14, 12, 30, 0).unwrap()
+        );
+        assert_eq!(
+            parse("7d"),
+            Local.with_ymd_and_hms(2023, 3, 8, 12, 30, 0).unwrap()
+        );
+        assert_eq!(
+            parse("2 weeks"),
+            Local.with_ymd_and_hms(2023, 3, 1, 12, 30, 0).unwrap()
+        );
+        assert_eq!(
+            parse("3 months ago"),
+            Local.with_ymd_and_hms(2022, 12, 15, 12, 30, 0).unwrap()
+        );
+        assert_eq!(
+            parse("1y"),
+            Local.with_ymd_and_hms(2022, 3, 15, 12, 30, 0).unwrap()
+        );
+    }
+
+    #[test]
+    fn can_parse_relative_days() {
+        let now = now();
+        let parse = |date| QueryContext::parse_relative(date, now).unwrap();
+
+        assert_eq!(
+            parse("Today"),
+            Local.with_ymd_and_hms(2023, 3, 15, 0, 0, 0).unwrap()
+        );
+        assert_eq!(
+            parse("yesterday"),
+            Local.with_ymd_and_hms(2023, 3, 14, 0, 0, 0).unwrap()
+        );
+    }
+
+    #[test]
+    fn can_reject_bad_relative() {
+        let now = now();
+        assert!(QueryContext::parse_relative("d", now).is_none());
+        assert!(QueryContext::parse_relative("7 fortnights", now).is_none());
+        assert!(QueryContext::parse_relative("-7d", now).is_none());
+        assert!(QueryContext::parse_relative("tomorrow", now).is_none());
+    }
 }
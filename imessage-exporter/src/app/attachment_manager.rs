@@ -1,6 +1,6 @@
 use std::{
     fmt::Display,
-    fs::{copy, create_dir_all, metadata},
+    fs::{copy, create_dir_all, metadata, File},
     path::{Path, PathBuf},
 };
 
@@ -9,14 +9,15 @@ use imessage_database::tables::{attachment::Attachment, messages::Message};
 use uuid::Uuid;
 
 use crate::app::{
-    converter::{convert_heic, Converter, ImageType},
+    converter::{convert_heic, convert_heic_to_bytes, Converter, ImageType},
     runtime::Config,
 };
 
 /// Represents different ways the app can interact with attachment data
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Default)]
 pub enum AttachmentManager {
     /// Do not copy attachments
+    #[default]
     Disabled,
     /// Copy and convert attachments to more compatible formats using a [`Converter`]
     Compatible,
@@ -60,6 +61,18 @@ impl AttachmentManager {
                 return None;
             }
 
+            // Reuse the copy an incremental export already made
+            let copied = config
+                .manifest
+                .borrow()
+                .attachments
+                .get(&attachment.rowid)
+                .map(|path| config.options.export_path.join(path));
+            if let Some(copied) = copied.filter(|path| path.exists()) {
+                attachment.copied_path = Some(copied);
+                return Some(());
+            }
+
             // Create a path to copy the file to
             let mut to = config.attachment_path();
 
@@ -76,11 +89,11 @@ impl AttachmentManager {
             match self {
                 AttachmentManager::Compatible => match &config.converter {
                     Some(converter) => {
-                        Self::copy_convert(from, &mut to, converter, attachment.is_sticker);
+                        Self::copy_convert(from, &mut to, converter, attachment.is_sticker, config);
                     }
-                    None => Self::copy_raw(from, &to),
+                    None => Self::copy_raw(from, &to, config),
                 },
-                AttachmentManager::Efficient => Self::copy_raw(from, &to),
+                AttachmentManager::Efficient => Self::copy_raw(from, &to, config),
                 AttachmentManager::Disabled => unreachable!(),
             };
 
@@ -95,18 +108,28 @@ impl AttachmentManager {
 
                 let atime = FileTime::from_last_access_time(&metadata);
 
-                if let Err(why) = set_file_times(&to, atime, mtime) {
+                if let Err(why) = set_file_times(config.output_path(&to), atime, mtime) {
                     eprintln!("Unable to update {to:?} metadata: {why}");
                 }
             }
+
+            // Remember the copy so the next incremental export does not make another
+            if config.options.incremental && to.exists() {
+                if let Ok(relative) = to.strip_prefix(&config.options.export_path) {
+                    config
+                        .manifest
+                        .borrow_mut()
+                        .attachments
+                        .insert(attachment.rowid, relative.to_path_buf());
+                }
+            }
             attachment.copied_path = Some(to);
         }
         Some(())
     }
 
-    /// Copy a file without altering it
-    fn copy_raw(from: &Path, to: &Path) {
-        // Ensure the directory tree exists
+    /// Ensure the directory tree for a copied file exists
+    fn create_parent(to: &Path) {
         if let Some(folder) = to.parent() {
             if !folder.exists() {
                 if let Err(why) = create_dir_all(folder) {
@@ -114,18 +137,68 @@ impl AttachmentManager {
                 }
             }
         }
-        if let Err(why) = copy(from, to) {
+    }
+
+    /// Copy a file without altering it, encrypting the copy if requested
+    fn copy_raw(from: &Path, to: &Path, config: &Config) {
+        Self::create_parent(to);
+        let copied = match &config.options.encryption {
+            Some(encryption) => {
+                File::open(from).and_then(|mut file| encryption.encrypt(&mut file, to))
+            }
+            None => copy(from, to).map(|_| ()),
+        };
+        if let Err(why) = copied {
             eprintln!("Unable to copy {from:?} to {to:?}: {why}");
         };
     }
 
+    /// Convert an image, encrypting the converted image if requested
+    ///
+    /// Encrypted images are converted without writing the converted image to the export; if that is not possible, the original is copied instead
+    fn convert(
+        from: &Path,
+        to: &mut PathBuf,
+        converter: &Converter,
+        output_type: &ImageType,
+        config: &Config,
+    ) {
+        match &config.options.encryption {
+            Some(encryption) => match convert_heic_to_bytes(from, converter, output_type) {
+                Some(image) => {
+                    to.set_extension(output_type.to_str());
+                    Self::create_parent(to);
+                    if let Err(why) = encryption.encrypt(&mut image.as_slice(), to) {
+                        eprintln!("Unable to copy {from:?} to {to:?}: {why}");
+                    }
+                }
+                None => {
+                    eprintln!("Unable to convert {from:?}, copying the original instead");
+                    Self::copy_raw(from, to, config);
+                }
+            },
+            None => {
+                to.set_extension(output_type.to_str());
+                if convert_heic(from, to, converter, output_type).is_none() {
+                    eprintln!("Unable to convert {from:?}");
+                }
+            }
+        }
+    }
+
     /// Copy a file, converting if possible
     ///
     /// - Sticker `HEIC` files convert to `PNG`
     /// - Sticker `HEICS` files convert to `GIF`
     /// - Attachment `HEIC` files convert to `JPEG`
     /// - Other files are copied with their original formats
-    fn copy_convert(from: &Path, to: &mut PathBuf, converter: &Converter, is_sticker: bool) {
+    fn copy_convert(
+        from: &Path,
+        to: &mut PathBuf,
+        converter: &Converter,
+        is_sticker: bool,
+        config: &Config,
+    ) {
         let original_extension = from.extension().unwrap_or_default();
 
         // Handle sticker attachments
@@ -140,35 +213,22 @@ impl AttachmentManager {
             };
 
             match output_type {
-                Some(output_type) => {
-                    to.set_extension(output_type.to_str());
-                    if convert_heic(from, to, converter, &output_type).is_none() {
-                        eprintln!("Unable to convert {from:?}");
-                    }
+                Some(output_type) => Self::convert(from, to, converter, &output_type, config),
+                None => {
+                    eprintln!("Unable to convert {from:?}, copying the original instead");
+                    Self::copy_raw(from, to, config);
                 }
-                None => Self::copy_raw(from, to),
             }
         }
         // Normal attachments always get converted to jpeg
         else if original_extension == "heic" || original_extension == "HEIC" {
-            let output_type = ImageType::Jpeg;
-            // Update extension for conversion
-            to.set_extension(output_type.to_str());
-            if convert_heic(from, to, converter, &output_type).is_none() {
-                eprintln!("Unable to convert {from:?}");
-            }
+            Self::convert(from, to, converter, &ImageType::Jpeg, config);
         } else {
-            Self::copy_raw(from, to);
+            Self::copy_raw(from, to, config);
         }
     }
 }
 
-impl Default for AttachmentManager {
-    fn default() -> Self {
-        Self::Disabled
-    }
-}
-
 impl Display for AttachmentManager {
     fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -178,3 +238,40 @@ impl Display for AttachmentManager {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        env::temp_dir,
+        fs::{create_dir_all, remove_dir_all, write},
+    };
+
+    use crate::app::{
+        attachment_manager::AttachmentManager,
+        converter::{Converter, ImageType},
+        encryption::Encryption,
+        options::Options,
+        runtime::Config,
+    };
+
+    #[test]
+    fn can_copy_original_when_conversion_fails() {
+        let export_path = temp_dir().join("imessage-exporter-attachment-fallback");
+        let _ = remove_dir_all(&export_path);
+        create_dir_all(&export_path).unwrap();
+        let from = export_path.join("original.heic");
+        write(&from, b"not an image").unwrap();
+
+        let mut config = Config::new(Options::fake()).unwrap();
+        config.options.encryption = Some(Encryption::new(&[], false).unwrap());
+
+        // The image is not valid, so no converter can convert it
+        let mut to = export_path.join("attachments").join("copy.heic");
+        AttachmentManager::convert(&from, &mut to, &Converter::Sips, &ImageType::Jpeg, &config);
+        let actual = config.read_output(&to).unwrap();
+        remove_dir_all(&export_path).unwrap();
+
+        assert_eq!(to, export_path.join("attachments").join("copy.heic"));
+        assert_eq!(actual, b"not an image");
+    }
+}
@@ -1,9 +1,11 @@
 use std::{
-    fs::create_dir_all,
+    fs::{create_dir_all, read},
     path::Path,
     process::{Command, Stdio},
 };
 
+use tempfile::Builder;
+
 #[derive(Debug)]
 pub enum ImageType {
     #[allow(non_camel_case_types)]
@@ -47,7 +49,7 @@ impl Converter {
 /// Determine if a shell program exists on the system
 fn exists(name: &str) -> bool {
     if let Ok(process) = Command::new("type")
-        .args(&vec![name])
+        .args(vec![name])
         .stdout(Stdio::null())
         .stderr(Stdio::null())
         .stdin(Stdio::null())
@@ -95,7 +97,7 @@ pub fn convert_heic(
         Converter::Sips => {
             // Build the command
             match Command::new("sips")
-                .args(&vec![
+                .args(vec![
                     "-s",
                     "format",
                     output_image_type.to_str(),
@@ -124,7 +126,7 @@ pub fn convert_heic(
         Converter::Imagemagick => {
             // Build the command
             match Command::new("convert")
-                .args(&vec![from_path, to_path])
+                .args(vec![from_path, to_path])
                 .stdout(Stdio::null())
                 .stderr(Stdio::null())
                 .stdin(Stdio::null())
@@ -148,6 +150,60 @@ pub fn convert_heic(
     Some(())
 }
 
+/// Convert a HEIC image file to the provided format, returning the converted image instead of writing it to disk
+///
+/// `ImageMagick` writes the converted image to standard output. `sips` also prints its progress there, so it writes
+/// to a private temporary directory instead, which is deleted as soon as the image is read.
+pub fn convert_heic_to_bytes(
+    from: &Path,
+    converter: &Converter,
+    output_image_type: &ImageType,
+) -> Option<Vec<u8>> {
+    // Get the path we want to copy from
+    let from_path = from.to_str()?;
+
+    match converter {
+        Converter::Sips => {
+            let dir = match Builder::new().prefix("imessage-exporter-").tempdir() {
+                Ok(dir) => dir,
+                Err(why) => {
+                    eprintln!("Conversion failed: {why}");
+                    return None;
+                }
+            };
+            let to = dir
+                .path()
+                .join("converted")
+                .with_extension(output_image_type.to_str());
+            convert_heic(from, &to, converter, output_image_type)?;
+            // `sips` does not always exit with an error, so a missing file means the conversion failed
+            read(&to).ok()
+        }
+        Converter::Imagemagick => {
+            // Build the command, where `-` is standard output
+            match Command::new("convert")
+                .args(vec![
+                    from_path,
+                    &format!("{}:-", output_image_type.to_str()),
+                ])
+                .stderr(Stdio::null())
+                .stdin(Stdio::null())
+                .output()
+            {
+                Ok(output) if output.status.success() => Some(output.stdout),
+                Ok(output) => {
+                    eprintln!("Conversion failed: {}", output.status);
+                    None
+                }
+                Err(why) => {
+                    eprintln!("Conversion failed: {why}");
+                    None
+                }
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::exists;
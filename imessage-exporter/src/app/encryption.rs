@@ -0,0 +1,552 @@
+/*!
+ Contains logic for encrypting export files with [age](https://age-encryption.org) so plaintext never touches the disk.
+*/
+
+use std::{
+    cell::RefCell,
+    collections::{hash_map::Entry, HashMap},
+    env,
+    fmt::{Debug, Formatter},
+    fs::{remove_file, File},
+    io::{copy, Error, Read, Result, Write},
+    iter::once,
+    path::{Path, PathBuf},
+};
+
+use age::{
+    secrecy::{ExposeSecret, SecretString},
+    stream::StreamWriter,
+    x25519, Decryptor, Encryptor, Identity, Recipient,
+};
+
+use crate::app::error::RuntimeError;
+
+/// Extension added to the name of every encrypted file
+pub const AGE_EXTENSION: &str = "age";
+/// Name of the file that holds the key to a passphrase-protected export
+pub const IDENTITY_FILE: &str = "identity.age";
+/// Environment variable read for the passphrase before prompting for one
+pub const PASSPHRASE_VAR: &str = "IMESSAGE_EXPORTER_PASSPHRASE";
+
+/// A file written from start to finish in one pass, encrypted if requested
+pub enum OutputFile {
+    Plain(File),
+    Encrypted(StreamWriter<File>),
+}
+
+impl OutputFile {
+    /// Create the file at `path`, replacing it if it exists
+    pub fn create(path: &Path, encryption: Option<&Encryption>) -> Result<Self> {
+        match encryption {
+            Some(encryption) => encryption.create(path).map(OutputFile::Encrypted),
+            None => File::create(path).map(OutputFile::Plain),
+        }
+    }
+
+    /// Finish writing the file; encrypted files that are not finished cannot be decrypted
+    pub fn finish(self) -> Result<()> {
+        match self {
+            OutputFile::Plain(mut file) => file.flush(),
+            OutputFile::Encrypted(stream) => stream.finish().map(|_| ()),
+        }
+    }
+}
+
+impl Write for OutputFile {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        match self {
+            OutputFile::Plain(file) => file.write(buf),
+            OutputFile::Encrypted(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        match self {
+            OutputFile::Plain(file) => file.flush(),
+            OutputFile::Encrypted(stream) => stream.flush(),
+        }
+    }
+}
+
+/// Encrypts every file written to an export
+///
+/// - Files are encrypted to each `age` recipient and written with an extra `.age` extension
+/// - Passphrase-protected exports encrypt files to a new key, which is saved to [`IDENTITY_FILE`] encrypted with the passphrase
+/// - Files are also encrypted to a key that only exists while the export runs, so exporters can read back what they wrote
+pub struct Encryption {
+    /// Public keys every file is encrypted to
+    recipients: Vec<x25519::Recipient>,
+    /// If true, save the key for this export, protected by a passphrase
+    passphrase: bool,
+    /// Key generated for this export
+    session: x25519::Identity,
+    /// Files that are still being written, keyed by their plaintext path
+    streams: RefCell<HashMap<PathBuf, StreamWriter<File>>>,
+}
+
+impl Encryption {
+    /// Create an instance given user input, ensuring each recipient is a valid public key
+    pub fn new(
+        recipients: &[&String],
+        passphrase: bool,
+    ) -> std::result::Result<Self, RuntimeError> {
+        let recipients = recipients
+            .iter()
+            .map(|recipient| {
+                recipient.parse().map_err(|why| {
+                    RuntimeError::InvalidOptions(format!(
+                        "`{recipient}` is not a valid age recipient! Must be a public key like `age1...`\n{why}"
+                    ))
+                })
+            })
+            .collect::<std::result::Result<_, _>>()?;
+        Ok(Self {
+            recipients,
+            passphrase,
+            session: x25519::Identity::generate(),
+            streams: RefCell::new(HashMap::new()),
+        })
+    }
+
+    /// Get the path an export file is written to once it is encrypted
+    pub fn path(path: &Path) -> PathBuf {
+        let mut encrypted = path.as_os_str().to_owned();
+        encrypted.push(".");
+        encrypted.push(AGE_EXTENSION);
+        PathBuf::from(encrypted)
+    }
+
+    /// Save the key for a passphrase-protected export, prompting for the passphrase if it is not set in the environment
+    pub fn save_identity(&self, export_path: &Path) -> std::result::Result<(), RuntimeError> {
+        if !self.passphrase {
+            return Ok(());
+        }
+
+        let passphrase = match env::var(PASSPHRASE_VAR) {
+            Ok(passphrase) => passphrase,
+            Err(_) => {
+                let passphrase = rpassword::prompt_password("Passphrase for the export: ")
+                    .map_err(RuntimeError::DiskError)?;
+                let confirmation = rpassword::prompt_password("Confirm passphrase: ")
+                    .map_err(RuntimeError::DiskError)?;
+                if passphrase != confirmation {
+                    return Err(RuntimeError::InvalidOptions(
+                        "Passphrases do not match!".to_string(),
+                    ));
+                }
+                passphrase
+            }
+        };
+        if passphrase.is_empty() {
+            return Err(RuntimeError::InvalidOptions(
+                "Passphrase must not be empty!".to_string(),
+            ));
+        }
+
+        // Written in the same format as `age-keygen`, so `age -d -i identity.age` can decrypt the export
+        let identity = format!(
+            "# public key: {}\n{}\n",
+            self.session.to_public(),
+            self.session.to_string().expose_secret()
+        );
+        let file =
+            File::create(export_path.join(IDENTITY_FILE)).map_err(RuntimeError::DiskError)?;
+        let mut writer = Encryptor::with_user_passphrase(SecretString::from(passphrase))
+            .wrap_output(file)
+            .map_err(RuntimeError::DiskError)?;
+        writer
+            .write_all(identity.as_bytes())
+            .map_err(RuntimeError::DiskError)?;
+        writer.finish().map_err(RuntimeError::DiskError)?;
+        Ok(())
+    }
+
+    /// Start encrypting a file, replacing it if it exists
+    fn create(&self, path: &Path) -> Result<StreamWriter<File>> {
+        let file = File::create(Self::path(path))?;
+
+        let session = self.session.to_public();
+        let recipients = self
+            .recipients
+            .iter()
+            .chain(once(&session))
+            .map(|recipient| recipient as &dyn Recipient);
+        Encryptor::with_recipients(recipients)
+            .map_err(Error::other)?
+            .wrap_output(file)
+    }
+
+    /// Get the stream for a file that is being written, starting it if it has not been written to yet
+    ///
+    /// Files that were already closed are reopened with their contents, so they can be added to
+    fn stream<'a>(
+        &self,
+        streams: &'a mut HashMap<PathBuf, StreamWriter<File>>,
+        path: &Path,
+    ) -> Result<&'a mut StreamWriter<File>> {
+        Ok(match streams.entry(path.to_path_buf()) {
+            Entry::Occupied(stream) => stream.into_mut(),
+            Entry::Vacant(entry) => {
+                let mut contents = vec![];
+                if Self::path(path).exists() {
+                    self.decrypt(path)?.read_to_end(&mut contents)?;
+                }
+                let stream = entry.insert(self.create(path)?);
+                stream.write_all(&contents)?;
+                stream
+            }
+        })
+    }
+
+    /// Finish writing a file, returning `true` if it was still being written
+    ///
+    /// Exporters close files they are done with, so each file only holds a stream while it is being written
+    pub fn close(&self, path: &Path) -> Result<bool> {
+        match self.streams.borrow_mut().remove(path) {
+            Some(stream) => stream.finish().map(|_| true),
+            None => Ok(false),
+        }
+    }
+
+    /// Decrypt a file that is no longer being written
+    fn decrypt(&self, path: &Path) -> Result<impl Read> {
+        Decryptor::new(File::open(Self::path(path))?)
+            .map_err(Error::other)?
+            .decrypt(once(&self.session as &dyn Identity))
+            .map_err(Error::other)
+    }
+
+    /// Read a file, returning `true` if it was still being written
+    fn contents(&self, path: &Path) -> Result<(Vec<u8>, bool)> {
+        let open = self.close(path)?;
+        let mut bytes = vec![];
+        self.decrypt(path)?.read_to_end(&mut bytes)?;
+        Ok((bytes, open))
+    }
+
+    /// Add to the end of a file
+    pub fn append(&self, path: &Path, bytes: &[u8]) -> Result<()> {
+        let mut streams = self.streams.borrow_mut();
+        self.stream(&mut streams, path)?.write_all(bytes)
+    }
+
+    /// Replace the contents of a file, leaving it open so it can be added to
+    pub fn replace(&self, path: &Path, bytes: &[u8]) -> Result<()> {
+        // Discard the rest of the stream being replaced
+        self.streams.borrow_mut().remove(path);
+
+        let mut stream = self.create(path)?;
+        stream.write_all(bytes)?;
+        self.streams.borrow_mut().insert(path.to_path_buf(), stream);
+        Ok(())
+    }
+
+    /// Read the contents of a file, leaving it open if it is still being written
+    pub fn read(&self, path: &Path) -> Result<Vec<u8>> {
+        let (bytes, open) = self.contents(path)?;
+        if open {
+            self.replace(path, &bytes)?;
+        }
+        Ok(bytes)
+    }
+
+    /// Remove a suffix from the end of a file, if it ends with it
+    pub fn truncate(&self, path: &Path, suffix: &[u8]) -> Result<()> {
+        let (bytes, open) = self.contents(path)?;
+        match bytes.strip_suffix(suffix) {
+            Some(rest) => self.replace(path, rest),
+            None if open => self.replace(path, &bytes),
+            None => Ok(()),
+        }
+    }
+
+    /// Add the contents of one file to the end of another, removing the original
+    pub fn append_file(&self, from: &Path, to: &Path) -> Result<()> {
+        self.close(from)?;
+        let mut reader = self.decrypt(from)?;
+        let mut streams = self.streams.borrow_mut();
+        copy(&mut reader, self.stream(&mut streams, to)?)?;
+        remove_file(Self::path(from))
+    }
+
+    /// Encrypt everything that can be read from `from` into a new file
+    pub fn encrypt(&self, from: &mut impl Read, to: &Path) -> Result<()> {
+        let mut file = OutputFile::create(to, Some(self))?;
+        copy(from, &mut file)?;
+        file.finish()
+    }
+
+    /// Finish writing every open file; files that are not finished cannot be decrypted
+    pub fn finish(&self) -> Result<()> {
+        self.streams
+            .borrow_mut()
+            .drain()
+            .try_for_each(|(_, stream)| stream.finish().map(|_| ()))
+    }
+}
+
+impl Debug for Encryption {
+    fn fmt(&self, fmt: &mut Formatter<'_>) -> std::fmt::Result {
+        fmt.debug_struct("Encryption")
+            .field(
+                "recipients",
+                &self
+                    .recipients
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>(),
+            )
+            .field("passphrase", &self.passphrase)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Two instances are equal if they encrypt to the same recipients; the key generated for each export is ignored
+impl PartialEq for Encryption {
+    fn eq(&self, other: &Self) -> bool {
+        self.recipients == other.recipients && self.passphrase == other.passphrase
+    }
+}
+
+impl Eq for Encryption {}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        env::{set_var, temp_dir},
+        fs::{create_dir_all, read, remove_dir_all, File},
+        io::{Read, Write},
+        iter::once,
+        path::{Path, PathBuf},
+    };
+
+    use age::{scrypt, secrecy::SecretString, x25519, Decryptor, Identity};
+
+    use crate::app::encryption::{Encryption, OutputFile, IDENTITY_FILE, PASSPHRASE_VAR};
+
+    fn export_path(name: &str) -> PathBuf {
+        let path = temp_dir().join(format!("imessage-exporter-encryption-{name}"));
+        let _ = remove_dir_all(&path);
+        create_dir_all(&path).unwrap();
+        path
+    }
+
+    fn decrypt(path: &Path, identity: &dyn Identity) -> String {
+        let mut out = String::new();
+        Decryptor::new(File::open(path).unwrap())
+            .unwrap()
+            .decrypt(once(identity))
+            .unwrap()
+            .read_to_string(&mut out)
+            .unwrap();
+        out
+    }
+
+    #[test]
+    fn can_parse_recipients() {
+        let recipient = x25519::Identity::generate().to_public().to_string();
+        let encryption = Encryption::new(&[&recipient], false).unwrap();
+        assert_eq!(encryption.recipients.len(), 1);
+        assert_eq!(encryption.recipients[0].to_string(), recipient);
+    }
+
+    #[test]
+    fn cant_parse_invalid_recipient() {
+        let recipient = "age1notakey".to_string();
+        assert!(Encryption::new(&[&recipient], false).is_err());
+    }
+
+    #[test]
+    fn can_get_path() {
+        assert_eq!(
+            Encryption::path(Path::new("/tmp/export/Book Club.html")),
+            PathBuf::from("/tmp/export/Book Club.html.age")
+        );
+    }
+
+    #[test]
+    fn can_encrypt_to_recipient() {
+        let export_path = export_path("recipient");
+        let identity = x25519::Identity::generate();
+        let recipient = identity.to_public().to_string();
+        let encryption = Encryption::new(&[&recipient], false).unwrap();
+
+        let path = export_path.join("orphaned.txt");
+        encryption.append(&path, b"Hello").unwrap();
+        encryption.append(&path, b" world").unwrap();
+        encryption.finish().unwrap();
+
+        let encrypted = Encryption::path(&path);
+        let bytes = read(&encrypted).unwrap();
+        let actual = decrypt(&encrypted, &identity);
+        remove_dir_all(&export_path).unwrap();
+
+        assert!(!path.exists());
+        assert!(bytes.starts_with(b"age-encryption.org/v1\n"));
+        assert_eq!(actual, "Hello world");
+    }
+
+    #[test]
+    fn can_read_open_file() {
+        let export_path = export_path("read");
+        let encryption = Encryption::new(&[], false).unwrap();
+
+        let path = export_path.join("orphaned.txt");
+        encryption.append(&path, b"first\n").unwrap();
+        let first = encryption.read(&path).unwrap();
+        // The file can still be added to once it has been read
+        encryption.append(&path, b"second\n").unwrap();
+        let second = encryption.read(&path).unwrap();
+        encryption.finish().unwrap();
+        remove_dir_all(&export_path).unwrap();
+
+        assert_eq!(first, b"first\n");
+        assert_eq!(second, b"first\nsecond\n");
+    }
+
+    #[test]
+    fn can_append_to_closed_file() {
+        let export_path = export_path("reopen");
+        let encryption = Encryption::new(&[], false).unwrap();
+
+        let path = export_path.join("orphaned.txt");
+        encryption.append(&path, b"first\n").unwrap();
+        let was_open = encryption.close(&path).unwrap();
+        let is_open = encryption.close(&path).unwrap();
+        encryption.append(&path, b"second\n").unwrap();
+        let actual = encryption.read(&path).unwrap();
+        encryption.finish().unwrap();
+        remove_dir_all(&export_path).unwrap();
+
+        assert!(was_open);
+        assert!(!is_open);
+        assert_eq!(actual, b"first\nsecond\n");
+    }
+
+    #[test]
+    fn can_replace_file() {
+        let export_path = export_path("replace");
+        let encryption = Encryption::new(&[], false).unwrap();
+
+        let path = export_path.join("index.html");
+        encryption.append(&path, b"old").unwrap();
+        encryption.replace(&path, b"new").unwrap();
+        encryption.append(&path, b" page").unwrap();
+        let actual = encryption.read(&path).unwrap();
+        remove_dir_all(&export_path).unwrap();
+
+        assert_eq!(actual, b"new page");
+    }
+
+    #[test]
+    fn can_truncate_file() {
+        let export_path = export_path("truncate");
+        let encryption = Encryption::new(&[], false).unwrap();
+
+        let path = export_path.join("orphaned.html");
+        encryption.append(&path, b"<p>message</p></body>").unwrap();
+        encryption.finish().unwrap();
+        encryption.truncate(&path, b"</body>").unwrap();
+        encryption.truncate(&path, b"</body>").unwrap();
+        let actual = encryption.read(&path).unwrap();
+        remove_dir_all(&export_path).unwrap();
+
+        assert_eq!(actual, b"<p>message</p>");
+    }
+
+    #[test]
+    fn can_append_file() {
+        let export_path = export_path("append");
+        let encryption = Encryption::new(&[], false).unwrap();
+
+        let body = export_path.join("sms.part");
+        let path = export_path.join("sms.xml");
+        encryption.append(&path, b"<smses>\n").unwrap();
+        encryption.append(&body, b"  <sms />\n").unwrap();
+        encryption.append_file(&body, &path).unwrap();
+        encryption.append(&path, b"</smses>\n").unwrap();
+        let actual = encryption.read(&path).unwrap();
+        let body_exists = Encryption::path(&body).exists();
+        remove_dir_all(&export_path).unwrap();
+
+        assert_eq!(actual, b"<smses>\n  <sms />\n</smses>\n");
+        assert!(!body_exists);
+    }
+
+    #[test]
+    fn can_encrypt_reader() {
+        let export_path = export_path("reader");
+        let identity = x25519::Identity::generate();
+        let recipient = identity.to_public().to_string();
+        let encryption = Encryption::new(&[&recipient], false).unwrap();
+
+        let path = export_path.join("attachment.jpeg");
+        encryption.encrypt(&mut b"image".as_slice(), &path).unwrap();
+        let actual = decrypt(&Encryption::path(&path), &identity);
+        remove_dir_all(&export_path).unwrap();
+
+        assert_eq!(actual, "image");
+    }
+
+    #[test]
+    fn can_write_output_file() {
+        let export_path = export_path("output");
+        let encryption = Encryption::new(&[], false).unwrap();
+
+        let plain = export_path.join("plain.parquet");
+        let mut file = OutputFile::create(&plain, None).unwrap();
+        file.write_all(b"plain").unwrap();
+        file.finish().unwrap();
+
+        let encrypted = export_path.join("encrypted.parquet");
+        let mut file = OutputFile::create(&encrypted, Some(&encryption)).unwrap();
+        file.write_all(b"encrypted").unwrap();
+        file.finish().unwrap();
+
+        let plain_actual = read(&plain).unwrap();
+        let encrypted_actual = encryption.read(&encrypted).unwrap();
+        remove_dir_all(&export_path).unwrap();
+
+        assert_eq!(plain_actual, b"plain");
+        assert_eq!(encrypted_actual, b"encrypted");
+    }
+
+    #[test]
+    fn can_save_identity() {
+        let export_path = export_path("identity");
+        set_var(PASSPHRASE_VAR, "correct horse battery staple");
+        let encryption = Encryption::new(&[], true).unwrap();
+        encryption.save_identity(&export_path).unwrap();
+
+        let path = export_path.join("orphaned.txt");
+        encryption.append(&path, b"Hello").unwrap();
+        encryption.finish().unwrap();
+
+        // Unlock the saved key with the passphrase, then use it to decrypt the export
+        let passphrase = scrypt::Identity::new(SecretString::from(
+            "correct horse battery staple".to_string(),
+        ));
+        let saved = decrypt(&export_path.join(IDENTITY_FILE), &passphrase);
+        let identity: x25519::Identity = saved
+            .lines()
+            .find(|line| !line.starts_with('#'))
+            .unwrap()
+            .parse()
+            .unwrap();
+        let actual = decrypt(&Encryption::path(&path), &identity);
+        remove_dir_all(&export_path).unwrap();
+
+        assert_eq!(actual, "Hello");
+    }
+
+    #[test]
+    fn can_skip_identity_without_passphrase() {
+        let export_path = export_path("no-identity");
+        let encryption = Encryption::new(&[], false).unwrap();
+        encryption.save_identity(&export_path).unwrap();
+        let identity_exists = export_path.join(IDENTITY_FILE).exists();
+        remove_dir_all(&export_path).unwrap();
+
+        assert!(!identity_exists);
+    }
+}
@@ -7,7 +7,10 @@ use std::{
     io::Error as IoError,
 };
 
-use imessage_database::{error::table::TableError, util::size::format_file_size};
+use imessage_database::{
+    error::{contacts::ContactsError, table::TableError},
+    util::size::format_file_size,
+};
 
 use crate::app::options::OPTION_BYPASS_FREE_SPACE_CHECK;
 
@@ -17,7 +20,10 @@ pub enum RuntimeError {
     InvalidOptions(String),
     DiskError(IoError),
     DatabaseError(TableError),
+    ContactsError(ContactsError),
     NotEnoughAvailableSpace(u64, u64),
+    ExportDatabaseError(rusqlite::Error),
+    ExportParquetError(parquet::errors::ParquetError),
 }
 
 impl Display for RuntimeError {
@@ -26,6 +32,7 @@ impl Display for RuntimeError {
             RuntimeError::InvalidOptions(why) => write!(fmt, "Invalid options!\n{why}"),
             RuntimeError::DiskError(why) => write!(fmt, "{why}"),
             RuntimeError::DatabaseError(why) => write!(fmt, "{why}"),
+            RuntimeError::ContactsError(why) => write!(fmt, "{why}"),
             RuntimeError::NotEnoughAvailableSpace(estimated_bytes, available_bytes) => {
                 write!(
                     fmt, 
@@ -35,6 +42,12 @@ impl Display for RuntimeError {
                     OPTION_BYPASS_FREE_SPACE_CHECK
                 )
             }
+            RuntimeError::ExportDatabaseError(why) => {
+                write!(fmt, "Unable to write to export database: {why}")
+            }
+            RuntimeError::ExportParquetError(why) => {
+                write!(fmt, "Unable to write Parquet file: {why}")
+            }
         }
     }
 }
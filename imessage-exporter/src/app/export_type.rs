@@ -13,6 +13,18 @@ pub enum ExportType {
     Txt,
     /// JSONL file export
     Jsonl,
+    /// CSV file export
+    Csv,
+    /// SQLite database export
+    Sqlite,
+    /// Markdown file export
+    Markdown,
+    /// mbox email archive export
+    Mbox,
+    /// Android SMS Backup & Restore XML export
+    Xml,
+    /// Apache Parquet columnar export
+    Parquet,
 }
 
 impl ExportType {
@@ -22,6 +34,12 @@ impl ExportType {
             "txt" => Some(Self::Txt),
             "html" => Some(Self::Html),
             "jsonl" => Some(Self::Jsonl),
+            "csv" => Some(Self::Csv),
+            "sqlite" => Some(Self::Sqlite),
+            "md" => Some(Self::Markdown),
+            "mbox" => Some(Self::Mbox),
+            "xml" => Some(Self::Xml),
+            "parquet" => Some(Self::Parquet),
             _ => None,
         }
     }
@@ -33,6 +51,12 @@ impl Display for ExportType {
             ExportType::Txt => write!(fmt, "txt"),
             ExportType::Html => write!(fmt, "html"),
             ExportType::Jsonl => write!(fmt, "jsonl"),
+            ExportType::Csv => write!(fmt, "csv"),
+            ExportType::Sqlite => write!(fmt, "sqlite"),
+            ExportType::Markdown => write!(fmt, "md"),
+            ExportType::Mbox => write!(fmt, "mbox"),
+            ExportType::Xml => write!(fmt, "xml"),
+            ExportType::Parquet => write!(fmt, "parquet"),
         }
     }
 }
@@ -66,15 +90,101 @@ mod tests {
 
     #[test]
     fn can_parse_jsonl_any_case() {
-        assert!(matches!(ExportType::from_cli("jsonl"), Some(ExportType::Jsonl)));
-        assert!(matches!(ExportType::from_cli("JSONL"), Some(ExportType::Jsonl)));
-        assert!(matches!(ExportType::from_cli("jSOnL"), Some(ExportType::Jsonl)));
+        assert!(matches!(
+            ExportType::from_cli("jsonl"),
+            Some(ExportType::Jsonl)
+        ));
+        assert!(matches!(
+            ExportType::from_cli("JSONL"),
+            Some(ExportType::Jsonl)
+        ));
+        assert!(matches!(
+            ExportType::from_cli("jSOnL"),
+            Some(ExportType::Jsonl)
+        ));
+    }
+
+    #[test]
+    fn can_parse_csv_any_case() {
+        assert!(matches!(ExportType::from_cli("csv"), Some(ExportType::Csv)));
+        assert!(matches!(ExportType::from_cli("CSV"), Some(ExportType::Csv)));
+        assert!(matches!(ExportType::from_cli("cSv"), Some(ExportType::Csv)));
+    }
+
+    #[test]
+    fn can_parse_sqlite_any_case() {
+        assert!(matches!(
+            ExportType::from_cli("sqlite"),
+            Some(ExportType::Sqlite)
+        ));
+        assert!(matches!(
+            ExportType::from_cli("SQLITE"),
+            Some(ExportType::Sqlite)
+        ));
+        assert!(matches!(
+            ExportType::from_cli("SQLite"),
+            Some(ExportType::Sqlite)
+        ));
+    }
+
+    #[test]
+    fn can_parse_markdown_any_case() {
+        assert!(matches!(
+            ExportType::from_cli("md"),
+            Some(ExportType::Markdown)
+        ));
+        assert!(matches!(
+            ExportType::from_cli("MD"),
+            Some(ExportType::Markdown)
+        ));
+        assert!(matches!(
+            ExportType::from_cli("mD"),
+            Some(ExportType::Markdown)
+        ));
+    }
+
+    #[test]
+    fn can_parse_mbox_any_case() {
+        assert!(matches!(
+            ExportType::from_cli("mbox"),
+            Some(ExportType::Mbox)
+        ));
+        assert!(matches!(
+            ExportType::from_cli("MBOX"),
+            Some(ExportType::Mbox)
+        ));
+        assert!(matches!(
+            ExportType::from_cli("mBoX"),
+            Some(ExportType::Mbox)
+        ));
+    }
+
+    #[test]
+    fn can_parse_xml_any_case() {
+        assert!(matches!(ExportType::from_cli("xml"), Some(ExportType::Xml)));
+        assert!(matches!(ExportType::from_cli("XML"), Some(ExportType::Xml)));
+        assert!(matches!(ExportType::from_cli("xMl"), Some(ExportType::Xml)));
+    }
+
+    #[test]
+    fn can_parse_parquet_any_case() {
+        assert!(matches!(
+            ExportType::from_cli("parquet"),
+            Some(ExportType::Parquet)
+        ));
+        assert!(matches!(
+            ExportType::from_cli("PARQUET"),
+            Some(ExportType::Parquet)
+        ));
+        assert!(matches!(
+            ExportType::from_cli("ParQuet"),
+            Some(ExportType::Parquet)
+        ));
     }
 
     #[test]
     fn cant_parse_invalid() {
         assert!(ExportType::from_cli("pdf").is_none());
-        assert!(ExportType::from_cli("csv").is_none());
         assert!(ExportType::from_cli("").is_none());
     }
 }
@@ -0,0 +1,212 @@
+/*!
+ Contains the manifest that incremental exports use to pick up where the previous export stopped.
+*/
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs::{read_to_string, remove_file, write},
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{app::error::RuntimeError, exporters::html::Summary};
+
+/// Name of the manifest file written to the export directory
+pub const MANIFEST: &str = "manifest.json";
+
+/// State of an exported conversation
+#[derive(Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Conversation {
+    /// Largest `ROWID` of the messages written for the conversation
+    pub last_message: i32,
+    /// Date of the latest message written for the conversation
+    pub last_date: i64,
+    /// Files the conversation was written to, relative to the export directory
+    pub files: BTreeSet<PathBuf>,
+}
+
+/// Record of what a previous export wrote, used to only export what changed since
+#[derive(Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Manifest {
+    /// Format the export was written in
+    pub export_type: String,
+    /// Largest message `ROWID` in the database when the export ran
+    pub last_message: i32,
+    /// Most recent time a message was sent or edited when the export ran
+    pub last_change: i64,
+    /// State of each exported conversation, keyed by its unique chat ID, or `Orphaned`
+    pub conversations: BTreeMap<String, Conversation>,
+    /// Copied attachment files relative to the export directory, keyed by attachment `ROWID`
+    pub attachments: BTreeMap<i32, PathBuf>,
+    /// Statistics for each file listed on the HTML index page, keyed by path relative to the export directory
+    pub summaries: BTreeMap<PathBuf, Summary>,
+    /// Conversations that changed since the last export, which are written again from scratch
+    #[serde(skip)]
+    pub stale: BTreeSet<String>,
+}
+
+impl Manifest {
+    /// Read the manifest from an export directory, if there is one
+    pub fn load(export_path: &Path) -> Result<Option<Self>, RuntimeError> {
+        let path = export_path.join(MANIFEST);
+        match read_to_string(&path) {
+            Ok(data) => serde_json::from_str(&data).map(Some).map_err(|why| {
+                RuntimeError::InvalidOptions(format!("Unable to read manifest {path:?}: {why}"))
+            }),
+            Err(why) if why.kind() == ErrorKind::NotFound => Ok(None),
+            Err(why) => Err(RuntimeError::DiskError(why)),
+        }
+    }
+
+    /// Write the manifest to an export directory
+    pub fn save(&self, export_path: &Path) -> Result<(), RuntimeError> {
+        let data = serde_json::to_string_pretty(self).map_err(|why| {
+            RuntimeError::InvalidOptions(format!("Unable to build manifest: {why}"))
+        })?;
+        write(export_path.join(MANIFEST), data).map_err(RuntimeError::DiskError)
+    }
+
+    /// Note that a message in `conversation` was written to `file`
+    pub fn record(&mut self, conversation: String, rowid: i32, date: i64, file: PathBuf) {
+        let state = self.conversations.entry(conversation).or_default();
+        state.last_message = state.last_message.max(rowid);
+        state.last_date = state.last_date.max(date);
+        state.files.insert(file);
+        self.last_message = self.last_message.max(rowid);
+    }
+
+    /// Mark conversations as stale, along with any conversation that shares a file with them
+    ///
+    /// Stale files are removed before they are written again, so every conversation in them has to be written again too.
+    pub fn mark_stale(&mut self, conversations: impl IntoIterator<Item = String>) {
+        self.stale.extend(conversations);
+        loop {
+            let files: BTreeSet<&PathBuf> = self
+                .stale
+                .iter()
+                .filter_map(|key| self.conversations.get(key))
+                .flat_map(|state| &state.files)
+                .collect();
+            let sharing: Vec<String> = self
+                .conversations
+                .iter()
+                .filter(|(key, state)| {
+                    !self.stale.contains(*key)
+                        && state.files.iter().any(|file| files.contains(file))
+                })
+                .map(|(key, _)| key.clone())
+                .collect();
+            if sharing.is_empty() {
+                break;
+            }
+            self.stale.extend(sharing);
+        }
+    }
+
+    /// Remove the files written for stale conversations so they can be exported again
+    pub fn prune(&mut self, export_path: &Path) {
+        for key in &self.stale {
+            if let Some(state) = self.conversations.remove(key) {
+                for file in state.files {
+                    let path = export_path.join(&file);
+                    if let Err(why) = remove_file(&path) {
+                        if why.kind() != ErrorKind::NotFound {
+                            eprintln!("Unable to remove {path:?}: {why}");
+                        }
+                    }
+                    self.summaries.remove(&file);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::BTreeSet,
+        env::temp_dir,
+        fs::{create_dir_all, remove_dir_all, write},
+        path::PathBuf,
+    };
+
+    use crate::{app::manifest::Manifest, exporters::html::Summary};
+
+    #[test]
+    fn can_record() {
+        let mut manifest = Manifest::default();
+        manifest.record("1".to_string(), 5, 100, PathBuf::from("a.txt"));
+        manifest.record("1".to_string(), 3, 50, PathBuf::from("b.txt"));
+        manifest.record("2".to_string(), 4, 75, PathBuf::from("c.txt"));
+
+        let state = &manifest.conversations["1"];
+        assert_eq!(state.last_message, 5);
+        assert_eq!(state.last_date, 100);
+        assert_eq!(
+            state.files,
+            BTreeSet::from([PathBuf::from("a.txt"), PathBuf::from("b.txt")])
+        );
+        assert_eq!(manifest.last_message, 5);
+    }
+
+    #[test]
+    fn can_mark_stale_shared_files() {
+        let mut manifest = Manifest::default();
+        manifest.record("1".to_string(), 1, 1, PathBuf::from("Name.txt"));
+        manifest.record("2".to_string(), 2, 2, PathBuf::from("Name.txt"));
+        manifest.record("2".to_string(), 3, 3, PathBuf::from("Other.txt"));
+        manifest.record("3".to_string(), 4, 4, PathBuf::from("Other.txt"));
+        manifest.record("4".to_string(), 5, 5, PathBuf::from("Unrelated.txt"));
+
+        manifest.mark_stale(["1".to_string()]);
+        assert_eq!(
+            manifest.stale,
+            BTreeSet::from(["1".to_string(), "2".to_string(), "3".to_string()])
+        );
+    }
+
+    #[test]
+    fn can_save_load_prune() {
+        let dir = temp_dir().join("imessage-exporter-manifest-test");
+        create_dir_all(&dir).unwrap();
+        write(dir.join("Name.html"), "").unwrap();
+        write(dir.join("Other.html"), "").unwrap();
+
+        let mut manifest = Manifest {
+            export_type: "html".to_string(),
+            ..Default::default()
+        };
+        manifest.record("1".to_string(), 1, 1, PathBuf::from("Name.html"));
+        manifest.record("2".to_string(), 2, 2, PathBuf::from("Other.html"));
+        manifest
+            .summaries
+            .insert(PathBuf::from("Name.html"), Summary::default());
+        manifest
+            .attachments
+            .insert(7, PathBuf::from("attachments/1/a.jpg"));
+        manifest.save(&dir).unwrap();
+
+        let mut loaded = Manifest::load(&dir).unwrap().unwrap();
+        assert_eq!(loaded, manifest);
+
+        loaded.mark_stale(["1".to_string()]);
+        loaded.prune(&dir);
+        let name_exists = dir.join("Name.html").exists();
+        let other_exists = dir.join("Other.html").exists();
+        remove_dir_all(&dir).unwrap();
+
+        assert!(!name_exists);
+        assert!(other_exists);
+        assert!(!loaded.conversations.contains_key("1"));
+        assert!(loaded.conversations.contains_key("2"));
+        assert!(loaded.summaries.is_empty());
+    }
+
+    #[test]
+    fn can_load_missing() {
+        let dir = temp_dir().join("imessage-exporter-manifest-missing-test");
+        assert!(Manifest::load(&dir).unwrap().is_none());
+    }
+}
@@ -1,8 +1,13 @@
 pub mod attachment_manager;
 pub mod converter;
+pub mod encryption;
 pub mod error;
 pub mod export_type;
+pub mod manifest;
 pub mod options;
+pub mod pagination;
+pub mod period;
 pub mod progress;
+pub mod redactor;
 pub mod runtime;
 pub mod sanitizers;
@@ -1,22 +1,34 @@
 use std::path::PathBuf;
 
+use chrono::format::{Item, StrftimeItems};
 use clap::{crate_version, Arg, ArgAction, ArgMatches, Command};
 
 use imessage_database::{
     tables::{attachment::DEFAULT_ATTACHMENT_ROOT, table::DEFAULT_PATH_IOS},
     util::{
+        dates::{DateFormat, DEFAULT_DATE_FORMAT, ISO_DATE_FORMAT},
         dirs::{default_db_path, home},
+        phone_number::Region,
         platform::Platform,
         query_context::QueryContext,
     },
 };
 
 use crate::app::{
-    attachment_manager::AttachmentManager, error::RuntimeError, export_type::ExportType,
+    attachment_manager::AttachmentManager,
+    encryption::{Encryption, AGE_EXTENSION, IDENTITY_FILE, PASSPHRASE_VAR},
+    error::RuntimeError,
+    export_type::ExportType,
+    manifest::MANIFEST,
+    pagination::Pagination,
+    period::Period,
+    redactor::Redactor,
 };
 
 /// Default export directory name
 pub const DEFAULT_OUTPUT_DIR: &str = "imessage_export";
+/// Default size cap, in megabytes, for attachments embedded in HTML exports
+pub const DEFAULT_EMBED_MAX_SIZE: &str = "10";
 
 // CLI Arg Names
 pub const OPTION_DB_PATH: &str = "db-path";
@@ -32,15 +44,39 @@ pub const OPTION_CUSTOM_NAME: &str = "custom-name";
 pub const OPTION_PLATFORM: &str = "platform";
 pub const OPTION_BYPASS_FREE_SPACE_CHECK: &str = "ignore-disk-warning";
 pub const OPTION_USE_CALLER_ID: &str = "use-caller-id";
+pub const OPTION_COMBINE_CHATS: &str = "combine-chats";
+pub const OPTION_EMBED_ATTACHMENTS: &str = "embed-attachments";
+pub const OPTION_PAGINATE: &str = "paginate";
+pub const OPTION_TEMPLATE_DIR: &str = "template-dir";
+pub const OPTION_SEARCH_INDEX: &str = "search-index";
+pub const OPTION_SPLIT_BY: &str = "split-by";
+pub const OPTION_INCREMENTAL: &str = "incremental";
+pub const OPTION_CONVERSATIONS: &str = "conversations";
+pub const OPTION_EXCLUDE_CONVERSATIONS: &str = "exclude-conversations";
+pub const OPTION_PARTICIPANTS: &str = "participants";
+pub const OPTION_SENT_BY: &str = "sent-by";
+pub const OPTION_CONTAINS: &str = "contains";
+pub const OPTION_REGEX: &str = "regex";
+pub const OPTION_CONTEXT: &str = "context";
+pub const OPTION_TIME_ZONE: &str = "time-zone";
+pub const OPTION_DATE_FORMAT: &str = "date-format";
+pub const OPTION_CONTACTS: &str = "contacts";
+pub const OPTION_REGION: &str = "region";
+pub const OPTION_REDACT: &str = "redact";
+pub const OPTION_REDACT_PATTERN: &str = "redact-pattern";
+pub const OPTION_ENCRYPT_TO: &str = "encrypt-to";
+pub const OPTION_ENCRYPT_PASSPHRASE: &str = "encrypt-passphrase";
 
 // Other CLI Text
-pub const SUPPORTED_FILE_TYPES: &str = "txt, html, jsonl";
+pub const SUPPORTED_FILE_TYPES: &str = "txt, html, jsonl, csv, sqlite, md, mbox, xml, parquet";
 pub const SUPPORTED_PLATFORMS: &str = "macOS, iOS";
 pub const SUPPORTED_ATTACHMENT_MANAGER_MODES: &str = "compatible, efficient, disabled";
+pub const SUPPORTED_PAGINATION_MODES: &str = "message count, month, or size like 50MB";
+pub const SUPPORTED_PERIODS: &str = "year, month, day";
 pub const ABOUT: &str = concat!(
     "The `imessage-exporter` binary exports iMessage data to\n",
-    "`txt`, `html`, or `jsonl` formats. It can also run diagnostics\n",
-    "to find problems with the iMessage database."
+    "`txt`, `html`, `jsonl`, `csv`, `sqlite`, `md`, `mbox`, `xml`, or `parquet` formats.\n",
+    "It can also run diagnostics to find problems with the iMessage database."
 );
 
 #[derive(Debug, PartialEq, Eq)]
@@ -69,6 +105,30 @@ pub struct Options {
     pub platform: Platform,
     /// If true, disable the free disk space check
     pub ignore_disk_space: bool,
+    /// If true, write all conversations to a single file instead of one file per chat
+    pub combine_chats: bool,
+    /// If set, inline image, audio, and video attachments up to this many bytes into HTML exports
+    pub embed_attachments: Option<u64>,
+    /// If set, split each conversation in HTML exports into pages
+    pub paginate: Option<Pagination>,
+    /// Directory containing templates and a stylesheet that replace the defaults in HTML exports
+    pub template_dir: Option<PathBuf>,
+    /// If true, write a search index and search controls for each conversation in HTML exports
+    pub search_index: bool,
+    /// If set, write each conversation to a folder with one file per time period
+    pub split_by: Option<Period>,
+    /// If true, resume the export in the export directory, only writing what changed since it ran
+    pub incremental: bool,
+    /// The time zone and format used to render dates
+    pub date_format: DateFormat,
+    /// Path to an address book used to resolve handles to contact names
+    pub contacts_path: Option<PathBuf>,
+    /// The region used to read phone numbers without a country code when deduplicating handles
+    pub region: Region,
+    /// If set, remove names, handles, and other identifying details from the export
+    pub redactor: Option<Redactor>,
+    /// If set, encrypt every file written to the export
+    pub encryption: Option<Encryption>,
 }
 
 impl Options {
@@ -86,6 +146,46 @@ impl Options {
         let use_caller_id = args.get_flag(OPTION_USE_CALLER_ID);
         let platform_type: Option<&String> = args.get_one(OPTION_PLATFORM);
         let ignore_disk_space = args.get_flag(OPTION_BYPASS_FREE_SPACE_CHECK);
+        let combine_chats = args.get_flag(OPTION_COMBINE_CHATS);
+        let embed_max_size: Option<&String> = args.get_one(OPTION_EMBED_ATTACHMENTS);
+        let pagination_type: Option<&String> = args.get_one(OPTION_PAGINATE);
+        let template_dir: Option<&String> = args.get_one(OPTION_TEMPLATE_DIR);
+        let search_index = args.get_flag(OPTION_SEARCH_INDEX);
+        let split_period: Option<&String> = args.get_one(OPTION_SPLIT_BY);
+        let incremental = args.get_flag(OPTION_INCREMENTAL);
+        let conversations: Vec<&String> = args
+            .get_many(OPTION_CONVERSATIONS)
+            .map(Iterator::collect)
+            .unwrap_or_default();
+        let exclude_conversations: Vec<&String> = args
+            .get_many(OPTION_EXCLUDE_CONVERSATIONS)
+            .map(Iterator::collect)
+            .unwrap_or_default();
+        let participants: Vec<&String> = args
+            .get_many(OPTION_PARTICIPANTS)
+            .map(Iterator::collect)
+            .unwrap_or_default();
+        let sent_by: Vec<&String> = args
+            .get_many(OPTION_SENT_BY)
+            .map(Iterator::collect)
+            .unwrap_or_default();
+        let contains: Option<&String> = args.get_one(OPTION_CONTAINS);
+        let regex: Option<&String> = args.get_one(OPTION_REGEX);
+        let text_context: Option<&String> = args.get_one(OPTION_CONTEXT);
+        let time_zone: Option<&String> = args.get_one(OPTION_TIME_ZONE);
+        let date_format_str: Option<&String> = args.get_one(OPTION_DATE_FORMAT);
+        let contacts_path: Option<&String> = args.get_one(OPTION_CONTACTS);
+        let region_code: Option<&String> = args.get_one(OPTION_REGION);
+        let redact = args.get_flag(OPTION_REDACT);
+        let redact_patterns: Vec<&String> = args
+            .get_many(OPTION_REDACT_PATTERN)
+            .map(Iterator::collect)
+            .unwrap_or_default();
+        let encrypt_to: Vec<&String> = args
+            .get_many(OPTION_ENCRYPT_TO)
+            .map(Iterator::collect)
+            .unwrap_or_default();
+        let encrypt_passphrase = args.get_flag(OPTION_ENCRYPT_PASSPHRASE);
 
         // Build the export type
         let export_type: Option<ExportType> = match export_file_type {
@@ -118,6 +218,61 @@ impl Options {
                 "Option {OPTION_END_DATE} is enabled, which requires `--{OPTION_EXPORT_TYPE}`"
             )));
         }
+        if !conversations.is_empty() && export_file_type.is_none() {
+            return Err(RuntimeError::InvalidOptions(format!(
+                "Option {OPTION_CONVERSATIONS} is enabled, which requires `--{OPTION_EXPORT_TYPE}`"
+            )));
+        }
+        if !exclude_conversations.is_empty() && export_file_type.is_none() {
+            return Err(RuntimeError::InvalidOptions(format!(
+                "Option {OPTION_EXCLUDE_CONVERSATIONS} is enabled, which requires `--{OPTION_EXPORT_TYPE}`"
+            )));
+        }
+        if !participants.is_empty() && export_file_type.is_none() {
+            return Err(RuntimeError::InvalidOptions(format!(
+                "Option {OPTION_PARTICIPANTS} is enabled, which requires `--{OPTION_EXPORT_TYPE}`"
+            )));
+        }
+        if !sent_by.is_empty() && export_file_type.is_none() {
+            return Err(RuntimeError::InvalidOptions(format!(
+                "Option {OPTION_SENT_BY} is enabled, which requires `--{OPTION_EXPORT_TYPE}`"
+            )));
+        }
+        if (contains.is_some() || regex.is_some()) && export_file_type.is_none() {
+            return Err(RuntimeError::InvalidOptions(format!(
+                "Option {OPTION_CONTAINS} or {OPTION_REGEX} is enabled, which requires `--{OPTION_EXPORT_TYPE}`"
+            )));
+        }
+        if time_zone.is_some() && export_file_type.is_none() {
+            return Err(RuntimeError::InvalidOptions(format!(
+                "Option {OPTION_TIME_ZONE} is enabled, which requires `--{OPTION_EXPORT_TYPE}`"
+            )));
+        }
+        if date_format_str.is_some() && export_file_type.is_none() {
+            return Err(RuntimeError::InvalidOptions(format!(
+                "Option {OPTION_DATE_FORMAT} is enabled, which requires `--{OPTION_EXPORT_TYPE}`"
+            )));
+        }
+        if contacts_path.is_some() && export_file_type.is_none() {
+            return Err(RuntimeError::InvalidOptions(format!(
+                "Option {OPTION_CONTACTS} is enabled, which requires `--{OPTION_EXPORT_TYPE}`"
+            )));
+        }
+        if redact && export_file_type.is_none() {
+            return Err(RuntimeError::InvalidOptions(format!(
+                "Option {OPTION_REDACT} is enabled, which requires `--{OPTION_EXPORT_TYPE}`"
+            )));
+        }
+        if !redact_patterns.is_empty() && !redact {
+            return Err(RuntimeError::InvalidOptions(format!(
+                "Option {OPTION_REDACT_PATTERN} is enabled, which requires `--{OPTION_REDACT}`"
+            )));
+        }
+        if (!encrypt_to.is_empty() || encrypt_passphrase) && export_file_type.is_none() {
+            return Err(RuntimeError::InvalidOptions(format!(
+                "Option {OPTION_ENCRYPT_TO} or {OPTION_ENCRYPT_PASSPHRASE} is enabled, which requires `--{OPTION_EXPORT_TYPE}`"
+            )));
+        }
         if use_caller_id && export_file_type.is_none() {
             return Err(RuntimeError::InvalidOptions(format!(
                 "Option {OPTION_USE_CALLER_ID} is enabled, which requires `--{OPTION_EXPORT_TYPE}`"
@@ -131,6 +286,41 @@ impl Options {
             );
         }
 
+        // Warn the user if they are exporting to a file type that does not support combined output
+        if combine_chats && export_file_type != Some(&"csv".to_string()) {
+            eprintln!(
+                "Option {OPTION_COMBINE_CHATS} is enabled, but the format specified is not `csv`!"
+            );
+        }
+
+        // Warn the user if they are exporting to a file type that does not support embedded attachments
+        if embed_max_size.is_some() && export_file_type != Some(&"html".to_string()) {
+            eprintln!(
+                "Option {OPTION_EMBED_ATTACHMENTS} is enabled, but the format specified is not `html`!"
+            );
+        }
+
+        // Warn the user if they are exporting to a file type that does not support pagination
+        if pagination_type.is_some() && export_file_type != Some(&"html".to_string()) {
+            eprintln!(
+                "Option {OPTION_PAGINATE} is enabled, but the format specified is not `html`!"
+            );
+        }
+
+        // Warn the user if they are exporting to a file type that does not use templates
+        if template_dir.is_some() && export_file_type != Some(&"html".to_string()) {
+            eprintln!(
+                "Option {OPTION_TEMPLATE_DIR} is enabled, but the format specified is not `html`!"
+            );
+        }
+
+        // Warn the user if they are exporting to a file type that does not support search
+        if search_index && export_file_type != Some(&"html".to_string()) {
+            eprintln!(
+                "Option {OPTION_SEARCH_INDEX} is enabled, but the format specified is not `html`!"
+            );
+        }
+
         // Ensure that if diagnostics are enabled, no other options are
         if diagnostic && attachment_manager_type.is_some() {
             return Err(RuntimeError::InvalidOptions(format!(
@@ -163,12 +353,43 @@ impl Options {
             )));
         }
 
+        // Ensure that conversations are not split into both pages and periods
+        if pagination_type.is_some() && split_period.is_some() {
+            return Err(RuntimeError::InvalidOptions(format!(
+                "`--{OPTION_SPLIT_BY}` is enabled; `--{OPTION_PAGINATE}` is disallowed"
+            )));
+        }
+
+        // Ensure that messages are matched by a single text filter, and that context is only used with one
+        if contains.is_some() && regex.is_some() {
+            return Err(RuntimeError::InvalidOptions(format!(
+                "`--{OPTION_CONTAINS}` is enabled; `--{OPTION_REGEX}` is disallowed"
+            )));
+        }
+        if text_context.is_some() && contains.is_none() && regex.is_none() {
+            return Err(RuntimeError::InvalidOptions(format!(
+                "Option {OPTION_CONTEXT} is enabled, which requires `--{OPTION_CONTAINS}` or `--{OPTION_REGEX}`"
+            )));
+        }
+
         // Ensure that there are no custom name conflicts
         if custom_name.is_some() && use_caller_id {
             return Err(RuntimeError::InvalidOptions(format!(
                 "`--{OPTION_CUSTOM_NAME}` is enabled; `--{OPTION_USE_CALLER_ID}` is disallowed"
             )));
         }
+        if redact && use_caller_id {
+            return Err(RuntimeError::InvalidOptions(format!(
+                "`--{OPTION_REDACT}` is enabled; `--{OPTION_USE_CALLER_ID}` is disallowed"
+            )));
+        }
+
+        // Ensure that encrypted exports are written from scratch, since they cannot be read to resume them
+        if (!encrypt_to.is_empty() || encrypt_passphrase) && incremental {
+            return Err(RuntimeError::InvalidOptions(format!(
+                "`--{OPTION_ENCRYPT_TO}` or `--{OPTION_ENCRYPT_PASSPHRASE}` is enabled; `--{OPTION_INCREMENTAL}` is disallowed"
+            )));
+        }
 
         // Build query context
         let mut query_context = QueryContext::default();
@@ -182,6 +403,33 @@ impl Options {
                 return Err(RuntimeError::InvalidOptions(format!("{why}")));
             }
         }
+        conversations
+            .iter()
+            .for_each(|chat| query_context.include_chat(chat));
+        exclude_conversations
+            .iter()
+            .for_each(|chat| query_context.exclude_chat(chat));
+        participants
+            .iter()
+            .for_each(|handle| query_context.include_participant(handle));
+        sent_by
+            .iter()
+            .for_each(|handle| query_context.include_sender(handle));
+        if let Some(text) = contains {
+            query_context.set_text(text);
+        }
+        if let Some(pattern) = regex {
+            if let Err(why) = query_context.set_text_regex(pattern) {
+                return Err(RuntimeError::InvalidOptions(format!("{why}")));
+            }
+        }
+        if let Some(messages) = text_context {
+            query_context.set_text_context(messages.parse::<usize>().map_err(|_| {
+                RuntimeError::InvalidOptions(format!(
+                    "{messages} is not a valid number of messages for {OPTION_CONTEXT}!"
+                ))
+            })?);
+        }
 
         // We have to allocate a PathBuf here because it can be created from data owned by this function in the default state
         let db_path = match user_path {
@@ -225,8 +473,126 @@ impl Options {
             None => AttachmentManager::default(),
         };
 
+        // Convert the embedded attachment size cap from megabytes to bytes
+        let embed_attachments = match embed_max_size {
+            Some(size) => Some(
+                size.parse::<u64>()
+                    .map_err(|_| {
+                        RuntimeError::InvalidOptions(format!(
+                            "{size} is not a valid size for {OPTION_EMBED_ATTACHMENTS}! Must be a whole number of megabytes"
+                        ))
+                    })?
+                    .saturating_mul(1024 * 1024),
+            ),
+            None => None,
+        };
+
+        // Determine where to split conversations into pages
+        let paginate = match pagination_type {
+            Some(pagination) => Some(Pagination::from_cli(pagination).ok_or(
+                RuntimeError::InvalidOptions(format!(
+                    "{pagination} is not a valid page size! Must be one of <{SUPPORTED_PAGINATION_MODES}>"
+                )),
+            )?),
+            None => None,
+        };
+
+        // Determine the region used to read phone numbers without a country code
+        let region = match region_code {
+            Some(code) => Region::from_cli(code).ok_or(RuntimeError::InvalidOptions(format!(
+                "{code} is not a supported region! Must be a two-letter country code like US, GB, or DE"
+            )))?,
+            None => Region::default(),
+        };
+
+        // Build the rules used to redact the export, if requested
+        let redactor = if redact {
+            Some(Redactor::new(&redact_patterns)?)
+        } else {
+            None
+        };
+
+        // Build the keys used to encrypt the export, if requested
+        let encryption = if !encrypt_to.is_empty() || encrypt_passphrase {
+            Some(Encryption::new(&encrypt_to, encrypt_passphrase)?)
+        } else {
+            None
+        };
+
+        // Validate that the contacts database exists, if provided
+        if let Some(path) = contacts_path {
+            if !PathBuf::from(path).is_file() {
+                return Err(RuntimeError::InvalidOptions(format!(
+                    "Supplied {OPTION_CONTACTS} `{path}` does not exist!"
+                )));
+            }
+        }
+
+        // Validate that the template directory exists, if provided
+        if let Some(path) = template_dir {
+            if !PathBuf::from(path).is_dir() {
+                return Err(RuntimeError::InvalidOptions(format!(
+                    "Supplied {OPTION_TEMPLATE_DIR} `{path}` is not a directory!"
+                )));
+            }
+        }
+
+        // Determine how to split conversations into files, ignoring formats that do not write a file per conversation
+        let split_by = match split_period {
+            Some(period) => {
+                let period = Period::from_cli(period).ok_or(RuntimeError::InvalidOptions(
+                    format!("{period} is not a valid period! Must be one of <{SUPPORTED_PERIODS}>"),
+                ))?;
+                match export_type {
+                    Some(ExportType::Sqlite | ExportType::Parquet | ExportType::Xml) => {
+                        eprintln!(
+                            "Option {OPTION_SPLIT_BY} is enabled, but the format specified does not write a file per conversation!"
+                        );
+                        None
+                    }
+                    _ => Some(period),
+                }
+            }
+            None => None,
+        };
+
+        // Determine if the export can be resumed, ignoring formats that do not write a file per conversation
+        let incremental = match export_type {
+            Some(ExportType::Sqlite | ExportType::Parquet | ExportType::Xml) if incremental => {
+                eprintln!(
+                    "Option {OPTION_INCREMENTAL} is enabled, but the format specified does not write a file per conversation!"
+                );
+                false
+            }
+            _ => incremental,
+        };
+
+        // Build the time zone and format used to render dates
+        let date_format = DateFormat {
+            time_zone: match time_zone {
+                Some(name) => Some(name.parse().map_err(|_| {
+                    RuntimeError::InvalidOptions(format!(
+                        "{name} is not a valid time zone! Must be an IANA time zone name like `America/Los_Angeles` or `UTC`"
+                    ))
+                })?),
+                None => None,
+            },
+            format: match date_format_str.map(String::as_str) {
+                Some("iso") => ISO_DATE_FORMAT.to_string(),
+                Some(format) => {
+                    if StrftimeItems::new(format).any(|item| item == Item::Error) {
+                        return Err(RuntimeError::InvalidOptions(format!(
+                            "{format} is not a valid date format! Must be `iso` or a `strftime` format like `%Y-%m-%d %H:%M`"
+                        )));
+                    }
+                    format.to_string()
+                }
+                None => DEFAULT_DATE_FORMAT.to_string(),
+            },
+        };
+
         // Validate the provided export path
-        let export_path = validate_path(user_export_path, &export_type.as_ref())?;
+        let export_path = validate_path(user_export_path, &export_type.as_ref(), incremental)?;
 
         Ok(Options {
             db_path,
@@ -241,6 +607,18 @@ impl Options {
             use_caller_id,
             platform,
             ignore_disk_space,
+            combine_chats,
+            embed_attachments,
+            paginate,
+            template_dir: template_dir.map(PathBuf::from),
+            search_index,
+            split_by,
+            incremental,
+            date_format,
+            contacts_path: contacts_path.map(PathBuf::from),
+            region,
+            redactor,
+            encryption,
         })
     }
 
@@ -253,20 +631,55 @@ impl Options {
     }
 }
 
+#[cfg(test)]
+impl Options {
+    /// Build options with no export type and every optional feature turned off, for tests
+    pub fn fake() -> Self {
+        Self {
+            db_path: default_db_path(),
+            attachment_root: None,
+            attachment_manager: AttachmentManager::Disabled,
+            diagnostic: false,
+            export_type: None,
+            export_path: PathBuf::new(),
+            query_context: QueryContext::default(),
+            no_lazy: false,
+            custom_name: None,
+            use_caller_id: false,
+            platform: Platform::macOS,
+            ignore_disk_space: false,
+            combine_chats: false,
+            embed_attachments: None,
+            paginate: None,
+            template_dir: None,
+            search_index: false,
+            split_by: None,
+            incremental: false,
+            date_format: DateFormat::default(),
+            contacts_path: None,
+            region: Region::default(),
+            redactor: None,
+            encryption: None,
+        }
+    }
+}
+
 /// Ensure export path is empty or does not contain files of the existing export type
 ///
 /// We have to allocate a `PathBuf` here because it can be created from data owned by this function in the default state
 fn validate_path(
     export_path: Option<&String>,
     export_type: &Option<&ExportType>,
+    incremental: bool,
 ) -> Result<PathBuf, RuntimeError> {
     // Build a path from the user-provided data or the default location
     let resolved_path =
         PathBuf::from(export_path.unwrap_or(&format!("{}/{DEFAULT_OUTPUT_DIR}", home())));
 
     // If there is an export type selected, ensure we do not overwrite files of the same type
+    // Incremental exports add to the files described by the manifest a previous export left behind
     if let Some(export_type) = export_type {
-        if resolved_path.exists() {
+        if resolved_path.exists() && !(incremental && resolved_path.join(MANIFEST).exists()) {
             // Get the word to use if there is a problem with the specified path
             let path_word = match export_path {
                 Some(_) => "Specified",
@@ -278,8 +691,12 @@ fn validate_path(
                 Ok(files) => {
                     let export_type_extension = export_type.to_string();
                     for file in files.flatten() {
-                        if file
-                            .path()
+                        // Encrypted files keep the export type's extension before their own
+                        let mut path = file.path();
+                        if path.extension().is_some_and(|s| s == AGE_EXTENSION) {
+                            path.set_extension("");
+                        }
+                        if path
                             .extension()
                             .is_some_and(|s| s.to_str().unwrap_or("") == export_type_extension)
                         {
@@ -367,17 +784,17 @@ fn get_command() -> Command {
             Arg::new(OPTION_START_DATE)
                 .short('s')
                 .long(OPTION_START_DATE)
-                .help("The start date filter\nOnly messages sent on or after this date will be included\n")
+                .help("The start date filter\nOnly messages sent on or after this date will be included\nAccepts a day like `2023-01-01`, an RFC 3339 timestamp like `2023-01-01T09:30:00-08:00`,\nor a time before now like `24h`, `7d`, `2 weeks`, `3 months ago`, `today`, or `yesterday`\n")
                 .display_order(7)
-                .value_name("YYYY-MM-DD"),
+                .value_name("date"),
         )
         .arg(
             Arg::new(OPTION_END_DATE)
                 .short('e')
                 .long(OPTION_END_DATE)
-                .help("The end date filter\nOnly messages sent before this date will be included\n")
+                .help("The end date filter\nOnly messages sent before this date will be included\nAccepts a day like `2023-01-01`, an RFC 3339 timestamp like `2023-01-01T09:30:00-08:00`,\nor a time before now like `24h`, `7d`, `2 weeks`, `3 months ago`, `today`, or `yesterday`\n")
                 .display_order(8)
-                .value_name("YYYY-MM-DD"),
+                .value_name("date"),
         )
         .arg(
             Arg::new(OPTION_DISABLE_LAZY_LOADING)
@@ -410,6 +827,168 @@ fn get_command() -> Command {
                 .action(ArgAction::SetTrue)
                 .display_order(12)
         )
+        .arg(
+            Arg::new(OPTION_COMBINE_CHATS)
+                .long(OPTION_COMBINE_CHATS)
+                .help("Write every conversation to a single file instead of one file per chat\nOnly supported for `csv` exports\n")
+                .action(ArgAction::SetTrue)
+                .display_order(13)
+        )
+        .arg(
+            Arg::new(OPTION_EMBED_ATTACHMENTS)
+                .long(OPTION_EMBED_ATTACHMENTS)
+                .help(format!("Inline images, audio, and video into HTML exports as `data:` URIs, so each conversation is a single portable file\nAttachments larger than the cap, in megabytes, are linked instead\nIf the cap is omitted, the default is {DEFAULT_EMBED_MAX_SIZE} MB\n"))
+                .num_args(0..=1)
+                .default_missing_value(DEFAULT_EMBED_MAX_SIZE)
+                .display_order(14)
+                .value_name("max-size-mb"),
+        )
+        .arg(
+            Arg::new(OPTION_PAGINATE)
+                .long(OPTION_PAGINATE)
+                .help("Split each conversation in HTML exports into pages linked with previous and next navigation\nPages can hold a number of messages, a calendar month, or up to a size like `50MB`\n")
+                .display_order(15)
+                .value_name(SUPPORTED_PAGINATION_MODES),
+        )
+        .arg(
+            Arg::new(OPTION_TEMPLATE_DIR)
+                .long(OPTION_TEMPLATE_DIR)
+                .help("Directory of templates and a stylesheet used to build HTML exports\nFiles missing from the directory fall back to the built-in defaults\n")
+                .display_order(16)
+                .value_name("path/to/templates"),
+        )
+        .arg(
+            Arg::new(OPTION_SEARCH_INDEX)
+                .long(OPTION_SEARCH_INDEX)
+                .help("Write a search index next to each conversation in HTML exports\nPages get controls to search text, filter by sender or attachments, and jump to a date, all offline\n")
+                .action(ArgAction::SetTrue)
+                .display_order(17)
+        )
+        .arg(
+            Arg::new(OPTION_SPLIT_BY)
+                .long(OPTION_SPLIT_BY)
+                .help("Write each conversation to a folder with one file per time period, like `Chat Name/2023-05.html`\nNot supported for `sqlite`, `parquet`, or `xml` exports\n")
+                .display_order(18)
+                .value_name(SUPPORTED_PERIODS),
+        )
+        .arg(
+            Arg::new(OPTION_INCREMENTAL)
+                .long(OPTION_INCREMENTAL)
+                .help("Only export messages added since the previous export to the same directory, using the `manifest.json` it wrote\nConversations with edited, unsent, or newly reacted messages are exported again from scratch\nNot supported for `sqlite`, `parquet`, or `xml` exports\n")
+                .action(ArgAction::SetTrue)
+                .display_order(19)
+        )
+        .arg(
+            Arg::new(OPTION_CONVERSATIONS)
+                .long(OPTION_CONVERSATIONS)
+                .help("Only export messages from a conversation, matched by its chat identifier, display name, or chat ID\nRepeat to export several conversations\n")
+                .action(ArgAction::Append)
+                .display_order(20)
+                .value_name("identifier, name, or ID"),
+        )
+        .arg(
+            Arg::new(OPTION_EXCLUDE_CONVERSATIONS)
+                .long(OPTION_EXCLUDE_CONVERSATIONS)
+                .help("Do not export messages from a conversation, matched by its chat identifier, display name, or chat ID\nRepeat to exclude several conversations\n")
+                .action(ArgAction::Append)
+                .display_order(21)
+                .value_name("identifier, name, or ID"),
+        )
+        .arg(
+            Arg::new(OPTION_PARTICIPANTS)
+                .long(OPTION_PARTICIPANTS)
+                .help("Only export conversations that include a phone number or email address, in direct and group chats\nOther handles that belong to the same contact match too\nRepeat to include several people\n")
+                .action(ArgAction::Append)
+                .display_order(22)
+                .value_name("phone or email"),
+        )
+        .arg(
+            Arg::new(OPTION_SENT_BY)
+                .long(OPTION_SENT_BY)
+                .help("Only export messages sent by a phone number or email address\nOther handles that belong to the same contact match too\nRepeat to include several people\n")
+                .action(ArgAction::Append)
+                .display_order(23)
+                .value_name("phone or email"),
+        )
+        .arg(
+            Arg::new(OPTION_CONTAINS)
+                .long(OPTION_CONTAINS)
+                .help(format!("Only export messages that contain some text, ignoring case\nConflicts with --{OPTION_REGEX}\n"))
+                .display_order(24)
+                .value_name("text"),
+        )
+        .arg(
+            Arg::new(OPTION_REGEX)
+                .long(OPTION_REGEX)
+                .help(format!("Only export messages whose text matches a regular expression\nMatching is case sensitive; start the pattern with `(?i)` to ignore case\nConflicts with --{OPTION_CONTAINS}\n"))
+                .display_order(25)
+                .value_name("pattern"),
+        )
+        .arg(
+            Arg::new(OPTION_CONTEXT)
+                .long(OPTION_CONTEXT)
+                .help(format!("Also export this many messages before and after each text match, in the same conversation\nRequires --{OPTION_CONTAINS} or --{OPTION_REGEX}\n"))
+                .display_order(26)
+                .value_name("messages"),
+        )
+        .arg(
+            Arg::new(OPTION_TIME_ZONE)
+                .long(OPTION_TIME_ZONE)
+                .help("Render dates in an IANA time zone instead of the machine's local time zone\n")
+                .display_order(27)
+                .value_name("America/Los_Angeles, UTC, etc."),
+        )
+        .arg(
+            Arg::new(OPTION_DATE_FORMAT)
+                .long(OPTION_DATE_FORMAT)
+                .help(format!("Render dates with a `strftime` format, or `iso` for ISO 8601 timestamps\nIf omitted, the default is `{DEFAULT_DATE_FORMAT}`\n"))
+                .display_order(28)
+                .value_name("iso or format"),
+        )
+        .arg(
+            Arg::new(OPTION_CONTACTS)
+                .long(OPTION_CONTACTS)
+                .help("Specify an optional path to contacts used to show names instead of phone numbers and email addresses\nFor macOS, specify a path to an `AddressBook-v22.abcddb` file\nFor iOS, specify a path to an `AddressBook.sqlitedb` file from a backup\nAlso accepts a `.vcf` file or a `.csv` file of `handle,name` rows\n")
+                .display_order(29)
+                .value_name("path/to/contacts"),
+        )
+        .arg(
+            Arg::new(OPTION_REGION)
+                .long(OPTION_REGION)
+                .help(format!("Specify the region used to read phone numbers without a country code when merging duplicate handles\nIf omitted, the default is `{}`\n", Region::default()))
+                .display_order(30)
+                .value_name("US, GB, DE, etc."),
+        )
+        .arg(
+            Arg::new(OPTION_REDACT)
+                .long(OPTION_REDACT)
+                .help("Remove identifying details so the export can be shared\nHandles become pseudonyms like `Contact 7`, and URLs, email addresses, and phone numbers in messages are masked\nAttachment filenames are hashed with a key unique to each export, and link previews and shared locations lose their details\n")
+                .action(ArgAction::SetTrue)
+                .display_order(31),
+        )
+        .arg(
+            Arg::new(OPTION_REDACT_PATTERN)
+                .long(OPTION_REDACT_PATTERN)
+                .help(format!("Also mask message text that matches a regular expression\nRepeat to add several patterns\nRequires --{OPTION_REDACT}\n"))
+                .action(ArgAction::Append)
+                .display_order(32)
+                .value_name("pattern"),
+        )
+        .arg(
+            Arg::new(OPTION_ENCRYPT_TO)
+                .long(OPTION_ENCRYPT_TO)
+                .help("Encrypt every exported file and copied attachment to an age public key, like `age1...`\nRepeat to add several recipients\nFiles are written with a `.age` extension and can be decrypted with `age -d -i key.txt`\n")
+                .action(ArgAction::Append)
+                .display_order(33)
+                .value_name("recipient"),
+        )
+        .arg(
+            Arg::new(OPTION_ENCRYPT_PASSPHRASE)
+                .long(OPTION_ENCRYPT_PASSPHRASE)
+                .help(format!("Encrypt every exported file and copied attachment with a passphrase\nThe passphrase is read from `{PASSPHRASE_VAR}` if it is set, otherwise it is prompted for\nFiles can be decrypted with `age -d -i {IDENTITY_FILE}`\n"))
+                .action(ArgAction::SetTrue)
+                .display_order(34),
+        )
 }
 
 /// Parse arguments from the command line
@@ -419,14 +998,24 @@ pub fn from_command_line() -> ArgMatches {
 
 #[cfg(test)]
 mod arg_tests {
+    use std::{env::temp_dir, fs};
+
     use imessage_database::util::{
-        dirs::default_db_path, platform::Platform, query_context::QueryContext,
+        dates::{DateFormat, ISO_DATE_FORMAT},
+        dirs::default_db_path,
+        phone_number::Region,
+        platform::Platform,
+        query_context::QueryContext,
     };
 
     use crate::app::{
         attachment_manager::AttachmentManager,
+        encryption::Encryption,
         export_type::ExportType,
         options::{get_command, validate_path, Options},
+        pagination::Pagination,
+        period::Period,
+        redactor::Redactor,
     };
 
     #[test]
@@ -446,13 +1035,25 @@ mod arg_tests {
             attachment_manager: AttachmentManager::default(),
             diagnostic: true,
             export_type: None,
-            export_path: validate_path(None, &None).unwrap(),
+            export_path: validate_path(None, &None, false).unwrap(),
             query_context: QueryContext::default(),
             no_lazy: false,
             custom_name: None,
             use_caller_id: false,
             platform: Platform::default(),
             ignore_disk_space: false,
+            combine_chats: false,
+            embed_attachments: None,
+            paginate: None,
+            template_dir: None,
+            search_index: false,
+            split_by: None,
+            incremental: false,
+            date_format: DateFormat::default(),
+            contacts_path: None,
+            region: Region::default(),
+            redactor: None,
+            encryption: None,
         };
 
         assert_eq!(actual, expected);
@@ -554,13 +1155,25 @@ mod arg_tests {
             attachment_manager: AttachmentManager::default(),
             diagnostic: false,
             export_type: Some(ExportType::Html),
-            export_path: validate_path(Some(&tmp_dir), &None).unwrap(),
+            export_path: validate_path(Some(&tmp_dir), &None, false).unwrap(),
             query_context: QueryContext::default(),
             no_lazy: false,
             custom_name: None,
             use_caller_id: false,
             platform: Platform::default(),
             ignore_disk_space: false,
+            combine_chats: false,
+            embed_attachments: None,
+            paginate: None,
+            template_dir: None,
+            search_index: false,
+            split_by: None,
+            incremental: false,
+            date_format: DateFormat::default(),
+            contacts_path: None,
+            region: Region::default(),
+            redactor: None,
+            encryption: None,
         };
 
         assert_eq!(actual, expected);
@@ -583,74 +1196,120 @@ mod arg_tests {
             attachment_manager: AttachmentManager::default(),
             diagnostic: false,
             export_type: Some(ExportType::Txt),
-            export_path: validate_path(None, &None).unwrap(),
+            export_path: validate_path(None, &None, false).unwrap(),
             query_context: QueryContext::default(),
             no_lazy: true,
             custom_name: None,
             use_caller_id: false,
             platform: Platform::default(),
             ignore_disk_space: false,
+            combine_chats: false,
+            embed_attachments: None,
+            paginate: None,
+            template_dir: None,
+            search_index: false,
+            split_by: None,
+            incremental: false,
+            date_format: DateFormat::default(),
+            contacts_path: None,
+            region: Region::default(),
+            redactor: None,
+            encryption: None,
         };
 
         assert_eq!(actual, expected);
     }
 
     #[test]
-    fn cant_build_option_attachment_manager_no_export_type() {
+    fn can_build_option_export_csv_combined() {
         // Get matches from sample args
-        let cli_args: Vec<&str> = vec!["imessage-exporter", "-c", "compatible"];
+        let cli_args: Vec<&str> = vec!["imessage-exporter", "-f", "csv", "--combine-chats"];
         let command = get_command();
         let args = command.get_matches_from(cli_args);
 
         // Build the Options
-        let actual = Options::from_args(&args);
+        let actual = Options::from_args(&args).unwrap();
 
-        assert!(actual.is_err());
+        // Expected data
+        let expected = Options {
+            db_path: default_db_path(),
+            attachment_root: None,
+            attachment_manager: AttachmentManager::default(),
+            diagnostic: false,
+            export_type: Some(ExportType::Csv),
+            export_path: validate_path(None, &None, false).unwrap(),
+            query_context: QueryContext::default(),
+            no_lazy: false,
+            custom_name: None,
+            use_caller_id: false,
+            platform: Platform::default(),
+            ignore_disk_space: false,
+            combine_chats: true,
+            embed_attachments: None,
+            paginate: None,
+            template_dir: None,
+            search_index: false,
+            split_by: None,
+            incremental: false,
+            date_format: DateFormat::default(),
+            contacts_path: None,
+            region: Region::default(),
+            redactor: None,
+            encryption: None,
+        };
+
+        assert_eq!(actual, expected);
     }
 
     #[test]
-    fn cant_build_option_export_path_no_export_type() {
+    fn can_build_option_export_html_embedded_default_size() {
         // Get matches from sample args
-        let cli_args: Vec<&str> = vec!["imessage-exporter", "-o", "~/test"];
+        let cli_args: Vec<&str> = vec!["imessage-exporter", "-f", "html", "--embed-attachments"];
         let command = get_command();
         let args = command.get_matches_from(cli_args);
 
         // Build the Options
-        let actual = Options::from_args(&args);
+        let actual = Options::from_args(&args).unwrap();
 
-        assert!(actual.is_err());
+        assert_eq!(actual.embed_attachments, Some(10 * 1024 * 1024));
     }
 
     #[test]
-    fn cant_build_option_start_date_path_no_export_type() {
+    fn can_build_option_export_html_embedded_custom_size() {
         // Get matches from sample args
-        let cli_args: Vec<&str> = vec!["imessage-exporter", "-s", "2020-01-01"];
+        let cli_args: Vec<&str> = vec![
+            "imessage-exporter",
+            "-f",
+            "html",
+            "--embed-attachments",
+            "25",
+        ];
         let command = get_command();
         let args = command.get_matches_from(cli_args);
 
         // Build the Options
-        let actual = Options::from_args(&args);
+        let actual = Options::from_args(&args).unwrap();
 
-        assert!(actual.is_err());
+        assert_eq!(actual.embed_attachments, Some(25 * 1024 * 1024));
     }
 
     #[test]
-    fn cant_build_option_end_date_path_no_export_type() {
+    fn can_build_option_export_html_paginated() {
         // Get matches from sample args
-        let cli_args: Vec<&str> = vec!["imessage-exporter", "-e", "2020-01-01"];
+        let cli_args: Vec<&str> = vec!["imessage-exporter", "-f", "html", "--paginate", "month"];
         let command = get_command();
         let args = command.get_matches_from(cli_args);
 
         // Build the Options
-        let actual = Options::from_args(&args);
+        let actual = Options::from_args(&args).unwrap();
 
-        assert!(actual.is_err());
+        assert_eq!(actual.paginate, Some(Pagination::Month));
     }
 
     #[test]
-    fn cant_build_option_invalid_date() {
+    fn cant_build_option_export_html_paginated_invalid() {
         // Get matches from sample args
-        let cli_args: Vec<&str> = vec!["imessage-exporter", "-f", "html", "-e", "2020-32-32"];
+        let cli_args: Vec<&str> = vec!["imessage-exporter", "-f", "html", "--paginate", "weekly"];
         let command = get_command();
         let args = command.get_matches_from(cli_args);
 
@@ -661,84 +1320,835 @@ mod arg_tests {
     }
 
     #[test]
-    fn cant_build_option_invalid_platform() {
+    fn can_build_option_export_html_template_dir() {
         // Get matches from sample args
-        let cli_args: Vec<&str> = vec!["imessage-exporter", "-a", "iPad"];
+        let template_dir = temp_dir();
+        let cli_args: Vec<&str> = vec![
+            "imessage-exporter",
+            "-f",
+            "html",
+            "--template-dir",
+            template_dir.to_str().unwrap(),
+        ];
         let command = get_command();
         let args = command.get_matches_from(cli_args);
 
         // Build the Options
-        let actual = Options::from_args(&args);
+        let actual = Options::from_args(&args).unwrap();
 
-        assert!(actual.is_err());
+        assert_eq!(actual.template_dir, Some(template_dir));
     }
 
     #[test]
-    fn cant_build_option_invalid_export_type() {
+    fn can_build_option_export_txt_split_by() {
         // Get matches from sample args
-        let cli_args: Vec<&str> = vec!["imessage-exporter", "-f", "pdf"];
+        let cli_args: Vec<&str> = vec!["imessage-exporter", "-f", "txt", "--split-by", "month"];
         let command = get_command();
         let args = command.get_matches_from(cli_args);
 
         // Build the Options
-        let actual = Options::from_args(&args);
+        let actual = Options::from_args(&args).unwrap();
 
-        assert!(actual.is_err());
+        assert_eq!(actual.split_by, Some(Period::Month));
     }
 
     #[test]
-    fn can_build_option_custom_name() {
+    fn can_build_option_export_sqlite_split_by_ignored() {
         // Get matches from sample args
-        let cli_args: Vec<&str> = vec!["imessage-exporter", "-f", "txt", "-m", "Name"];
+        let cli_args: Vec<&str> = vec!["imessage-exporter", "-f", "sqlite", "--split-by", "day"];
         let command = get_command();
         let args = command.get_matches_from(cli_args);
 
         // Build the Options
         let actual = Options::from_args(&args).unwrap();
 
-        // Expected data
-        let expected = Options {
-            db_path: default_db_path(),
-            attachment_root: None,
-            attachment_manager: AttachmentManager::default(),
-            diagnostic: false,
-            export_type: Some(ExportType::Txt),
-            export_path: validate_path(None, &None).unwrap(),
-            query_context: QueryContext::default(),
-            no_lazy: false,
-            custom_name: Some("Name".to_string()),
-            use_caller_id: false,
-            platform: Platform::default(),
-            ignore_disk_space: false,
-        };
-
-        assert_eq!(actual, expected);
+        assert_eq!(actual.split_by, None);
     }
 
     #[test]
-    fn can_build_option_caller_id() {
+    fn cant_build_option_export_split_by_invalid() {
         // Get matches from sample args
-        let cli_args: Vec<&str> = vec!["imessage-exporter", "-f", "txt", "-i"];
+        let cli_args: Vec<&str> = vec!["imessage-exporter", "-f", "txt", "--split-by", "week"];
         let command = get_command();
         let args = command.get_matches_from(cli_args);
 
         // Build the Options
-        let actual = Options::from_args(&args).unwrap();
+        let actual = Options::from_args(&args);
 
-        // Expected data
-        let expected = Options {
-            db_path: default_db_path(),
-            attachment_root: None,
-            attachment_manager: AttachmentManager::default(),
-            diagnostic: false,
+        assert!(actual.is_err());
+    }
+
+    #[test]
+    fn cant_build_option_export_html_split_by_paginated() {
+        // Get matches from sample args
+        let cli_args: Vec<&str> = vec![
+            "imessage-exporter",
+            "-f",
+            "html",
+            "--split-by",
+            "year",
+            "--paginate",
+            "100",
+        ];
+        let command = get_command();
+        let args = command.get_matches_from(cli_args);
+
+        // Build the Options
+        let actual = Options::from_args(&args);
+
+        assert!(actual.is_err());
+    }
+
+    #[test]
+    fn can_build_option_export_html_search_index() {
+        // Get matches from sample args
+        let cli_args: Vec<&str> = vec!["imessage-exporter", "-f", "html", "--search-index"];
+        let command = get_command();
+        let args = command.get_matches_from(cli_args);
+
+        // Build the Options
+        let actual = Options::from_args(&args).unwrap();
+
+        assert!(actual.search_index);
+    }
+
+    #[test]
+    fn can_build_option_export_txt_incremental() {
+        // Get matches from sample args
+        let cli_args: Vec<&str> = vec!["imessage-exporter", "-f", "txt", "--incremental"];
+        let command = get_command();
+        let args = command.get_matches_from(cli_args);
+
+        // Build the Options
+        let actual = Options::from_args(&args).unwrap();
+
+        assert!(actual.incremental);
+    }
+
+    #[test]
+    fn can_build_option_export_xml_incremental_ignored() {
+        // Get matches from sample args
+        let cli_args: Vec<&str> = vec!["imessage-exporter", "-f", "xml", "--incremental"];
+        let command = get_command();
+        let args = command.get_matches_from(cli_args);
+
+        // Build the Options
+        let actual = Options::from_args(&args).unwrap();
+
+        assert!(!actual.incremental);
+    }
+
+    #[test]
+    fn can_build_option_export_txt_conversations() {
+        // Get matches from sample args
+        let cli_args: Vec<&str> = vec![
+            "imessage-exporter",
+            "-f",
+            "txt",
+            "--conversations",
+            "Book Club",
+            "--conversations",
+            "3",
+            "--exclude-conversations",
+            "+15558675309",
+        ];
+        let command = get_command();
+        let args = command.get_matches_from(cli_args);
+
+        // Build the Options
+        let actual = Options::from_args(&args).unwrap();
+
+        // Expected data
+        let mut expected = QueryContext::default();
+        expected.include_chat("Book Club");
+        expected.include_chat("3");
+        expected.exclude_chat("+15558675309");
+
+        assert_eq!(actual.query_context, expected);
+        assert!(actual.query_context.include_chats.ids.contains(&3));
+    }
+
+    #[test]
+    fn can_build_option_export_txt_participants() {
+        // Get matches from sample args
+        let cli_args: Vec<&str> = vec![
+            "imessage-exporter",
+            "-f",
+            "txt",
+            "--participants",
+            "+15558675309",
+            "--sent-by",
+            "person@example.com",
+        ];
+        let command = get_command();
+        let args = command.get_matches_from(cli_args);
+
+        // Build the Options
+        let actual = Options::from_args(&args).unwrap();
+
+        // Expected data
+        let mut expected = QueryContext::default();
+        expected.include_participant("+15558675309");
+        expected.include_sender("person@example.com");
+
+        assert_eq!(actual.query_context, expected);
+    }
+
+    #[test]
+    fn can_build_option_export_txt_contains() {
+        // Get matches from sample args
+        let cli_args: Vec<&str> = vec![
+            "imessage-exporter",
+            "-f",
+            "txt",
+            "--contains",
+            "Dinner",
+            "--context",
+            "3",
+        ];
+        let command = get_command();
+        let args = command.get_matches_from(cli_args);
+
+        // Build the Options
+        let actual = Options::from_args(&args).unwrap();
+
+        // Expected data
+        let mut expected = QueryContext::default();
+        expected.set_text("Dinner");
+        expected.set_text_context(3);
+
+        assert_eq!(actual.query_context, expected);
+    }
+
+    #[test]
+    fn can_build_option_export_txt_regex() {
+        // Get matches from sample args
+        let cli_args: Vec<&str> = vec!["imessage-exporter", "-f", "txt", "--regex", r"^\d{6}$"];
+        let command = get_command();
+        let args = command.get_matches_from(cli_args);
+
+        // Build the Options
+        let actual = Options::from_args(&args).unwrap();
+
+        assert!(actual.query_context.matches_text(Some("123456")));
+        assert!(!actual.query_context.matches_text(Some("code 123456")));
+    }
+
+    #[test]
+    fn cant_build_option_export_txt_invalid_regex() {
+        // Get matches from sample args
+        let cli_args: Vec<&str> = vec!["imessage-exporter", "-f", "txt", "--regex", "(unclosed"];
+        let command = get_command();
+        let args = command.get_matches_from(cli_args);
+
+        // Build the Options
+        let actual = Options::from_args(&args);
+
+        assert!(actual.is_err());
+    }
+
+    #[test]
+    fn cant_build_option_export_txt_contains_and_regex() {
+        // Get matches from sample args
+        let cli_args: Vec<&str> = vec![
+            "imessage-exporter",
+            "-f",
+            "txt",
+            "--contains",
+            "dinner",
+            "--regex",
+            "dinner",
+        ];
+        let command = get_command();
+        let args = command.get_matches_from(cli_args);
+
+        // Build the Options
+        let actual = Options::from_args(&args);
+
+        assert!(actual.is_err());
+    }
+
+    #[test]
+    fn cant_build_option_export_txt_context_no_text() {
+        // Get matches from sample args
+        let cli_args: Vec<&str> = vec!["imessage-exporter", "-f", "txt", "--context", "3"];
+        let command = get_command();
+        let args = command.get_matches_from(cli_args);
+
+        // Build the Options
+        let actual = Options::from_args(&args);
+
+        assert!(actual.is_err());
+    }
+
+    #[test]
+    fn cant_build_option_export_html_template_dir_missing() {
+        // Get matches from sample args
+        let cli_args: Vec<&str> = vec![
+            "imessage-exporter",
+            "-f",
+            "html",
+            "--template-dir",
+            "/does/not/exist",
+        ];
+        let command = get_command();
+        let args = command.get_matches_from(cli_args);
+
+        // Build the Options
+        let actual = Options::from_args(&args);
+
+        assert!(actual.is_err());
+    }
+
+    #[test]
+    fn cant_build_option_export_html_embedded_invalid_size() {
+        // Get matches from sample args
+        let cli_args: Vec<&str> = vec![
+            "imessage-exporter",
+            "-f",
+            "html",
+            "--embed-attachments",
+            "big",
+        ];
+        let command = get_command();
+        let args = command.get_matches_from(cli_args);
+
+        // Build the Options
+        let actual = Options::from_args(&args);
+
+        assert!(actual.is_err());
+    }
+
+    #[test]
+    fn cant_build_option_attachment_manager_no_export_type() {
+        // Get matches from sample args
+        let cli_args: Vec<&str> = vec!["imessage-exporter", "-c", "compatible"];
+        let command = get_command();
+        let args = command.get_matches_from(cli_args);
+
+        // Build the Options
+        let actual = Options::from_args(&args);
+
+        assert!(actual.is_err());
+    }
+
+    #[test]
+    fn cant_build_option_export_path_no_export_type() {
+        // Get matches from sample args
+        let cli_args: Vec<&str> = vec!["imessage-exporter", "-o", "~/test"];
+        let command = get_command();
+        let args = command.get_matches_from(cli_args);
+
+        // Build the Options
+        let actual = Options::from_args(&args);
+
+        assert!(actual.is_err());
+    }
+
+    #[test]
+    fn cant_build_option_start_date_path_no_export_type() {
+        // Get matches from sample args
+        let cli_args: Vec<&str> = vec!["imessage-exporter", "-s", "2020-01-01"];
+        let command = get_command();
+        let args = command.get_matches_from(cli_args);
+
+        // Build the Options
+        let actual = Options::from_args(&args);
+
+        assert!(actual.is_err());
+    }
+
+    #[test]
+    fn cant_build_option_end_date_path_no_export_type() {
+        // Get matches from sample args
+        let cli_args: Vec<&str> = vec!["imessage-exporter", "-e", "2020-01-01"];
+        let command = get_command();
+        let args = command.get_matches_from(cli_args);
+
+        // Build the Options
+        let actual = Options::from_args(&args);
+
+        assert!(actual.is_err());
+    }
+
+    #[test]
+    fn cant_build_option_conversations_no_export_type() {
+        // Get matches from sample args
+        let cli_args: Vec<&str> = vec!["imessage-exporter", "--conversations", "Book Club"];
+        let command = get_command();
+        let args = command.get_matches_from(cli_args);
+
+        // Build the Options
+        let actual = Options::from_args(&args);
+
+        assert!(actual.is_err());
+    }
+
+    #[test]
+    fn cant_build_option_sent_by_no_export_type() {
+        // Get matches from sample args
+        let cli_args: Vec<&str> = vec!["imessage-exporter", "--sent-by", "+15558675309"];
+        let command = get_command();
+        let args = command.get_matches_from(cli_args);
+
+        // Build the Options
+        let actual = Options::from_args(&args);
+
+        assert!(actual.is_err());
+    }
+
+    #[test]
+    fn can_build_option_relative_and_rfc_3339_dates() {
+        // Get matches from sample args
+        let cli_args: Vec<&str> = vec![
+            "imessage-exporter",
+            "-f",
+            "txt",
+            "-s",
+            "3 months ago",
+            "-e",
+            "2030-01-01T09:30:00-08:00",
+        ];
+        let command = get_command();
+        let args = command.get_matches_from(cli_args);
+
+        // Build the Options
+        let actual = Options::from_args(&args).unwrap();
+
+        assert!(actual.query_context.start < actual.query_context.end);
+    }
+
+    #[test]
+    fn cant_build_option_invalid_date() {
+        // Get matches from sample args
+        let cli_args: Vec<&str> = vec!["imessage-exporter", "-f", "html", "-e", "2020-32-32"];
+        let command = get_command();
+        let args = command.get_matches_from(cli_args);
+
+        // Build the Options
+        let actual = Options::from_args(&args);
+
+        assert!(actual.is_err());
+    }
+
+    #[test]
+    fn can_build_option_time_zone_and_iso_format() {
+        // Get matches from sample args
+        let cli_args: Vec<&str> = vec![
+            "imessage-exporter",
+            "-f",
+            "txt",
+            "--time-zone",
+            "Europe/Berlin",
+            "--date-format",
+            "iso",
+        ];
+        let command = get_command();
+        let args = command.get_matches_from(cli_args);
+
+        // Build the Options
+        let actual = Options::from_args(&args).unwrap();
+
+        assert_eq!(
+            actual.date_format,
+            DateFormat {
+                time_zone: Some("Europe/Berlin".parse().unwrap()),
+                format: ISO_DATE_FORMAT.to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn cant_build_option_invalid_time_zone() {
+        // Get matches from sample args
+        let cli_args: Vec<&str> = vec![
+            "imessage-exporter",
+            "-f",
+            "txt",
+            "--time-zone",
+            "Mars/Olympus_Mons",
+        ];
+        let command = get_command();
+        let args = command.get_matches_from(cli_args);
+
+        // Build the Options
+        let actual = Options::from_args(&args);
+
+        assert!(actual.is_err());
+    }
+
+    #[test]
+    fn cant_build_option_invalid_date_format() {
+        // Get matches from sample args
+        let cli_args: Vec<&str> = vec!["imessage-exporter", "-f", "txt", "--date-format", "%Y-%Q"];
+        let command = get_command();
+        let args = command.get_matches_from(cli_args);
+
+        // Build the Options
+        let actual = Options::from_args(&args);
+
+        assert!(actual.is_err());
+    }
+
+    #[test]
+    fn can_build_option_contacts() {
+        // Get matches from sample args
+        let contacts = temp_dir().join("imessage-exporter-contacts.abcddb");
+        fs::File::create(&contacts).unwrap();
+        let cli_args: Vec<&str> = vec![
+            "imessage-exporter",
+            "-f",
+            "txt",
+            "--contacts",
+            contacts.to_str().unwrap(),
+        ];
+        let command = get_command();
+        let args = command.get_matches_from(cli_args);
+
+        // Build the Options
+        let actual = Options::from_args(&args).unwrap();
+        fs::remove_file(&contacts).unwrap();
+
+        assert_eq!(actual.contacts_path, Some(contacts));
+    }
+
+    #[test]
+    fn cant_build_option_missing_contacts() {
+        // Get matches from sample args
+        let contacts = temp_dir().join("imessage-exporter-missing-contacts.abcddb");
+        let cli_args: Vec<&str> = vec![
+            "imessage-exporter",
+            "-f",
+            "txt",
+            "--contacts",
+            contacts.to_str().unwrap(),
+        ];
+        let command = get_command();
+        let args = command.get_matches_from(cli_args);
+
+        // Build the Options
+        let actual = Options::from_args(&args);
+
+        assert!(actual.is_err());
+    }
+
+    #[test]
+    fn cant_build_option_contacts_no_export() {
+        // Get matches from sample args
+        let cli_args: Vec<&str> = vec!["imessage-exporter", "--contacts", "AddressBook-v22.abcddb"];
+        let command = get_command();
+        let args = command.get_matches_from(cli_args);
+
+        // Build the Options
+        let actual = Options::from_args(&args);
+
+        assert!(actual.is_err());
+    }
+
+    #[test]
+    fn can_build_option_region() {
+        // Get matches from sample args
+        let cli_args: Vec<&str> = vec!["imessage-exporter", "-f", "txt", "--region", "gb"];
+        let command = get_command();
+        let args = command.get_matches_from(cli_args);
+
+        // Build the Options
+        let actual = Options::from_args(&args).unwrap();
+
+        assert_eq!(actual.region, Region::from_cli("GB").unwrap());
+    }
+
+    #[test]
+    fn can_build_option_redact() {
+        // Get matches from sample args
+        let cli_args: Vec<&str> = vec![
+            "imessage-exporter",
+            "-f",
+            "txt",
+            "--redact",
+            "--redact-pattern",
+            r"(?i)project \w+",
+        ];
+        let command = get_command();
+        let args = command.get_matches_from(cli_args);
+
+        // Build the Options
+        let actual = Options::from_args(&args).unwrap();
+
+        let pattern = r"(?i)project \w+".to_string();
+        assert_eq!(actual.redactor, Some(Redactor::new(&[&pattern]).unwrap()));
+    }
+
+    #[test]
+    fn cant_build_option_redact_no_export() {
+        // Get matches from sample args
+        let cli_args: Vec<&str> = vec!["imessage-exporter", "--redact"];
+        let command = get_command();
+        let args = command.get_matches_from(cli_args);
+
+        // Build the Options
+        let actual = Options::from_args(&args);
+
+        assert!(actual.is_err());
+    }
+
+    #[test]
+    fn cant_build_option_redact_pattern_no_redact() {
+        // Get matches from sample args
+        let cli_args: Vec<&str> = vec![
+            "imessage-exporter",
+            "-f",
+            "txt",
+            "--redact-pattern",
+            "secret",
+        ];
+        let command = get_command();
+        let args = command.get_matches_from(cli_args);
+
+        // Build the Options
+        let actual = Options::from_args(&args);
+
+        assert!(actual.is_err());
+    }
+
+    #[test]
+    fn cant_build_option_invalid_redact_pattern() {
+        // Get matches from sample args
+        let cli_args: Vec<&str> = vec![
+            "imessage-exporter",
+            "-f",
+            "txt",
+            "--redact",
+            "--redact-pattern",
+            "(unclosed",
+        ];
+        let command = get_command();
+        let args = command.get_matches_from(cli_args);
+
+        // Build the Options
+        let actual = Options::from_args(&args);
+
+        assert!(actual.is_err());
+    }
+
+    #[test]
+    fn cant_build_option_redact_caller_id() {
+        // Get matches from sample args
+        let cli_args: Vec<&str> = vec![
+            "imessage-exporter",
+            "-f",
+            "txt",
+            "--redact",
+            "--use-caller-id",
+        ];
+        let command = get_command();
+        let args = command.get_matches_from(cli_args);
+
+        // Build the Options
+        let actual = Options::from_args(&args);
+
+        assert!(actual.is_err());
+    }
+
+    #[test]
+    fn can_build_option_encrypt_to() {
+        let recipient = age::x25519::Identity::generate().to_public().to_string();
+
+        // Get matches from sample args
+        let cli_args: Vec<&str> =
+            vec!["imessage-exporter", "-f", "txt", "--encrypt-to", &recipient];
+        let command = get_command();
+        let args = command.get_matches_from(cli_args);
+
+        // Build the Options
+        let actual = Options::from_args(&args).unwrap();
+
+        assert_eq!(
+            actual.encryption,
+            Some(Encryption::new(&[&recipient], false).unwrap())
+        );
+    }
+
+    #[test]
+    fn can_build_option_encrypt_passphrase() {
+        // Get matches from sample args
+        let cli_args: Vec<&str> = vec!["imessage-exporter", "-f", "html", "--encrypt-passphrase"];
+        let command = get_command();
+        let args = command.get_matches_from(cli_args);
+
+        // Build the Options
+        let actual = Options::from_args(&args).unwrap();
+
+        assert_eq!(actual.encryption, Some(Encryption::new(&[], true).unwrap()));
+    }
+
+    #[test]
+    fn cant_build_option_encrypt_no_export() {
+        // Get matches from sample args
+        let cli_args: Vec<&str> = vec!["imessage-exporter", "--encrypt-passphrase"];
+        let command = get_command();
+        let args = command.get_matches_from(cli_args);
+
+        // Build the Options
+        let actual = Options::from_args(&args);
+
+        assert!(actual.is_err());
+    }
+
+    #[test]
+    fn cant_build_option_invalid_encrypt_to() {
+        // Get matches from sample args
+        let cli_args: Vec<&str> = vec![
+            "imessage-exporter",
+            "-f",
+            "txt",
+            "--encrypt-to",
+            "ssh-ed25519 AAAA",
+        ];
+        let command = get_command();
+        let args = command.get_matches_from(cli_args);
+
+        // Build the Options
+        let actual = Options::from_args(&args);
+
+        assert!(actual.is_err());
+    }
+
+    #[test]
+    fn cant_build_option_encrypt_incremental() {
+        // Get matches from sample args
+        let cli_args: Vec<&str> = vec![
+            "imessage-exporter",
+            "-f",
+            "txt",
+            "--encrypt-passphrase",
+            "--incremental",
+        ];
+        let command = get_command();
+        let args = command.get_matches_from(cli_args);
+
+        // Build the Options
+        let actual = Options::from_args(&args);
+
+        assert!(actual.is_err());
+    }
+
+    #[test]
+    fn cant_build_option_invalid_region() {
+        // Get matches from sample args
+        let cli_args: Vec<&str> = vec!["imessage-exporter", "-f", "txt", "--region", "Atlantis"];
+        let command = get_command();
+        let args = command.get_matches_from(cli_args);
+
+        // Build the Options
+        let actual = Options::from_args(&args);
+
+        assert!(actual.is_err());
+    }
+
+    #[test]
+    fn cant_build_option_invalid_platform() {
+        // Get matches from sample args
+        let cli_args: Vec<&str> = vec!["imessage-exporter", "-a", "iPad"];
+        let command = get_command();
+        let args = command.get_matches_from(cli_args);
+
+        // Build the Options
+        let actual = Options::from_args(&args);
+
+        assert!(actual.is_err());
+    }
+
+    #[test]
+    fn cant_build_option_invalid_export_type() {
+        // Get matches from sample args
+        let cli_args: Vec<&str> = vec!["imessage-exporter", "-f", "pdf"];
+        let command = get_command();
+        let args = command.get_matches_from(cli_args);
+
+        // Build the Options
+        let actual = Options::from_args(&args);
+
+        assert!(actual.is_err());
+    }
+
+    #[test]
+    fn can_build_option_custom_name() {
+        // Get matches from sample args
+        let cli_args: Vec<&str> = vec!["imessage-exporter", "-f", "txt", "-m", "Name"];
+        let command = get_command();
+        let args = command.get_matches_from(cli_args);
+
+        // Build the Options
+        let actual = Options::from_args(&args).unwrap();
+
+        // Expected data
+        let expected = Options {
+            db_path: default_db_path(),
+            attachment_root: None,
+            attachment_manager: AttachmentManager::default(),
+            diagnostic: false,
+            export_type: Some(ExportType::Txt),
+            export_path: validate_path(None, &None, false).unwrap(),
+            query_context: QueryContext::default(),
+            no_lazy: false,
+            custom_name: Some("Name".to_string()),
+            use_caller_id: false,
+            platform: Platform::default(),
+            ignore_disk_space: false,
+            combine_chats: false,
+            embed_attachments: None,
+            paginate: None,
+            template_dir: None,
+            search_index: false,
+            split_by: None,
+            incremental: false,
+            date_format: DateFormat::default(),
+            contacts_path: None,
+            region: Region::default(),
+            redactor: None,
+            encryption: None,
+        };
+
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn can_build_option_caller_id() {
+        // Get matches from sample args
+        let cli_args: Vec<&str> = vec!["imessage-exporter", "-f", "txt", "-i"];
+        let command = get_command();
+        let args = command.get_matches_from(cli_args);
+
+        // Build the Options
+        let actual = Options::from_args(&args).unwrap();
+
+        // Expected data
+        let expected = Options {
+            db_path: default_db_path(),
+            attachment_root: None,
+            attachment_manager: AttachmentManager::default(),
+            diagnostic: false,
             export_type: Some(ExportType::Txt),
-            export_path: validate_path(None, &None).unwrap(),
+            export_path: validate_path(None, &None, false).unwrap(),
             query_context: QueryContext::default(),
             no_lazy: false,
             custom_name: None,
             use_caller_id: true,
             platform: Platform::default(),
             ignore_disk_space: false,
+            combine_chats: false,
+            embed_attachments: None,
+            paginate: None,
+            template_dir: None,
+            search_index: false,
+            split_by: None,
+            incremental: false,
+            date_format: DateFormat::default(),
+            contacts_path: None,
+            region: Region::default(),
+            redactor: None,
+            encryption: None,
         };
 
         assert_eq!(actual, expected);
@@ -773,12 +2183,14 @@ mod arg_tests {
 
 #[cfg(test)]
 mod path_tests {
+    use std::env::temp_dir;
     use std::fs;
     use std::io::Write;
     use std::path::PathBuf;
 
     use crate::app::{
         export_type::ExportType,
+        manifest::MANIFEST,
         options::{validate_path, DEFAULT_OUTPUT_DIR},
     };
     use imessage_database::util::dirs::home;
@@ -789,7 +2201,7 @@ mod path_tests {
         let export_path = Some(&tmp);
         let export_type = Some(ExportType::Txt);
 
-        let result = validate_path(export_path, &export_type.as_ref());
+        let result = validate_path(export_path, &export_type.as_ref(), false);
 
         assert_eq!(result.unwrap(), PathBuf::from("/tmp"));
     }
@@ -800,7 +2212,7 @@ mod path_tests {
         let export_path = Some(&tmp);
         let export_type = Some(ExportType::Txt);
 
-        let result = validate_path(export_path, &export_type.as_ref());
+        let result = validate_path(export_path, &export_type.as_ref(), false);
 
         let mut tmp = PathBuf::from("/tmp");
         tmp.push("fake1.html");
@@ -817,7 +2229,7 @@ mod path_tests {
         let export_path = Some(&tmp);
         let export_type = Some(ExportType::Txt);
 
-        let result = validate_path(export_path, &export_type.as_ref());
+        let result = validate_path(export_path, &export_type.as_ref(), false);
 
         let mut tmp = PathBuf::from("/tmp");
         tmp.push("fake2.txt");
@@ -828,12 +2240,48 @@ mod path_tests {
         fs::remove_file(&tmp).unwrap();
     }
 
+    #[test]
+    fn cant_validate_same_type_encrypted() {
+        let dir = temp_dir().join("imessage-exporter-validate-encrypted");
+        fs::create_dir_all(&dir).unwrap();
+        fs::File::create(dir.join("Chat.txt.age")).unwrap();
+        let export_path = Some(dir.to_string_lossy().to_string());
+
+        let txt = validate_path(export_path.as_ref(), &Some(&ExportType::Txt), false);
+        let html = validate_path(export_path.as_ref(), &Some(&ExportType::Html), false);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(txt.is_err());
+        assert_eq!(html.unwrap(), dir);
+    }
+
+    #[test]
+    fn can_validate_same_type_incremental() {
+        let dir = temp_dir().join("imessage-exporter-validate-incremental");
+        fs::create_dir_all(&dir).unwrap();
+        fs::File::create(dir.join("Chat.txt")).unwrap();
+        let export_path = Some(dir.to_string_lossy().to_string());
+        let export_type = Some(ExportType::Txt);
+
+        // An export without a manifest cannot be resumed
+        let without_manifest = validate_path(export_path.as_ref(), &export_type.as_ref(), true);
+
+        fs::File::create(dir.join(MANIFEST)).unwrap();
+        let with_manifest = validate_path(export_path.as_ref(), &export_type.as_ref(), true);
+        let not_incremental = validate_path(export_path.as_ref(), &export_type.as_ref(), false);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(without_manifest.is_err());
+        assert_eq!(with_manifest.unwrap(), dir);
+        assert!(not_incremental.is_err());
+    }
+
     #[test]
     fn can_validate_none() {
         let export_path = None;
         let export_type = None;
 
-        let result = validate_path(export_path, &export_type);
+        let result = validate_path(export_path, &export_type, false);
 
         assert_eq!(
             result.unwrap(),
@@ -0,0 +1,99 @@
+/*!
+ Contains data structures used to describe how large exports are split into pages.
+*/
+
+use std::fmt::Display;
+
+/// Represents the point at which a conversation is split into a new page
+#[derive(PartialEq, Eq, Debug)]
+pub enum Pagination {
+    /// Start a new page after this many messages
+    Messages(usize),
+    /// Start a new page for each calendar month
+    Month,
+    /// Start a new page before a page grows past this many bytes
+    Size(u64),
+}
+
+impl Pagination {
+    /// Given user's input, return a variant if the input matches one
+    ///
+    /// Accepts `month`, a number of messages, or a size with a `KB`, `MB`, or `GB` suffix.
+    pub fn from_cli(pagination: &str) -> Option<Self> {
+        let pagination = pagination.trim().to_lowercase();
+        if pagination == "month" {
+            return Some(Self::Month);
+        }
+
+        let (number, multiplier) = match pagination.len().checked_sub(2) {
+            Some(idx) if pagination.is_char_boundary(idx) => match &pagination[idx..] {
+                "kb" => (&pagination[..idx], 1024),
+                "mb" => (&pagination[..idx], 1024 * 1024),
+                "gb" => (&pagination[..idx], 1024 * 1024 * 1024),
+                _ => (pagination.as_str(), 0),
+            },
+            _ => (pagination.as_str(), 0),
+        };
+
+        match (number.trim().parse::<u64>().ok()?, multiplier) {
+            (0, _) => None,
+            (count, 0) => Some(Self::Messages(usize::try_from(count).ok()?)),
+            (size, multiplier) => Some(Self::Size(size.checked_mul(multiplier)?)),
+        }
+    }
+}
+
+impl Display for Pagination {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Pagination::Messages(count) => write!(fmt, "{count} messages"),
+            Pagination::Month => write!(fmt, "month"),
+            Pagination::Size(size) => write!(fmt, "{size} bytes"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::app::pagination::Pagination;
+
+    #[test]
+    fn can_parse_month_any_case() {
+        assert_eq!(Pagination::from_cli("month"), Some(Pagination::Month));
+        assert_eq!(Pagination::from_cli("MONTH"), Some(Pagination::Month));
+    }
+
+    #[test]
+    fn can_parse_messages() {
+        assert_eq!(
+            Pagination::from_cli("5000"),
+            Some(Pagination::Messages(5000))
+        );
+    }
+
+    #[test]
+    fn can_parse_size_any_case() {
+        assert_eq!(
+            Pagination::from_cli("50MB"),
+            Some(Pagination::Size(50 * 1024 * 1024))
+        );
+        assert_eq!(
+            Pagination::from_cli("512kb"),
+            Some(Pagination::Size(512 * 1024))
+        );
+        assert_eq!(
+            Pagination::from_cli("1 GB"),
+            Some(Pagination::Size(1024 * 1024 * 1024))
+        );
+    }
+
+    #[test]
+    fn cant_parse_invalid() {
+        assert_eq!(Pagination::from_cli("weekly"), None);
+        assert_eq!(Pagination::from_cli("0"), None);
+        assert_eq!(Pagination::from_cli("0MB"), None);
+        assert_eq!(Pagination::from_cli("MB"), None);
+        assert_eq!(Pagination::from_cli(""), None);
+        assert_eq!(Pagination::from_cli("é"), None);
+    }
+}
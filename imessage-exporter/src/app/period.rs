@@ -0,0 +1,81 @@
+/*!
+ Contains data structures used to describe how exports are split into files by time period.
+*/
+
+use std::fmt::Display;
+
+use chrono::{DateTime, TimeZone};
+
+/// Represents the span of time covered by each file in a split export
+#[derive(PartialEq, Eq, Debug)]
+pub enum Period {
+    /// One file per calendar year, like `2023`
+    Year,
+    /// One file per calendar month, like `2023-05`
+    Month,
+    /// One file per day, like `2023-05-17`
+    Day,
+}
+
+impl Period {
+    /// Given user's input, return a variant if the input matches one
+    pub fn from_cli(period: &str) -> Option<Self> {
+        match period.to_lowercase().as_str() {
+            "year" => Some(Self::Year),
+            "month" => Some(Self::Month),
+            "day" => Some(Self::Day),
+            _ => None,
+        }
+    }
+
+    /// Get the name of the file for the period a date falls in
+    pub fn name<Tz: TimeZone>(&self, date: &DateTime<Tz>) -> String
+    where
+        Tz::Offset: Display,
+    {
+        match self {
+            Period::Year => date.format("%Y"),
+            Period::Month => date.format("%Y-%m"),
+            Period::Day => date.format("%Y-%m-%d"),
+        }
+        .to_string()
+    }
+}
+
+impl Display for Period {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Period::Year => write!(fmt, "year"),
+            Period::Month => write!(fmt, "month"),
+            Period::Day => write!(fmt, "day"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Local, TimeZone};
+
+    use crate::app::period::Period;
+
+    #[test]
+    fn can_parse_period_any_case() {
+        assert_eq!(Period::from_cli("year"), Some(Period::Year));
+        assert_eq!(Period::from_cli("Month"), Some(Period::Month));
+        assert_eq!(Period::from_cli("DAY"), Some(Period::Day));
+    }
+
+    #[test]
+    fn cant_parse_invalid() {
+        assert_eq!(Period::from_cli("week"), None);
+        assert_eq!(Period::from_cli(""), None);
+    }
+
+    #[test]
+    fn can_name_periods() {
+        let date = Local.with_ymd_and_hms(2023, 5, 7, 12, 0, 0).unwrap();
+        assert_eq!(Period::Year.name(&date), "2023");
+        assert_eq!(Period::Month.name(&date), "2023-05");
+        assert_eq!(Period::Day.name(&date), "2023-05-07");
+    }
+}
@@ -0,0 +1,572 @@
+/*!
+ Contains logic for removing identifying details from exports so they can be shared.
+*/
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::{Debug, Formatter, Result as FmtResult},
+    path::Path,
+};
+
+use hmac::{Hmac, Mac};
+use regex::{Captures, Regex};
+use sha1::Sha1;
+
+use imessage_database::{
+    message_types::{
+        edited::EditedMessage,
+        placemark::{Placemark, PlacemarkMessage},
+        url::URLMessage,
+        variants::URLOverride,
+    },
+    tables::{attachment::Attachment, chat::Chat, messages::Message, table::ME},
+};
+
+use crate::app::error::RuntimeError;
+
+/// Replaces links in message text and link previews
+pub const URL_MASK: &str = "[url]";
+/// Replaces email addresses in message text
+pub const EMAIL_MASK: &str = "[email]";
+/// Replaces phone numbers in message text
+pub const PHONE_MASK: &str = "[phone]";
+/// Replaces shared locations
+pub const LOCATION_MASK: &str = "[location]";
+/// Replaces text matched by custom patterns and group chat names
+pub const REDACTED: &str = "[redacted]";
+
+/// Runs of digits shorter than this are not treated as phone numbers
+const MIN_PHONE_DIGITS: usize = 7;
+/// Runs of digits longer than this are not treated as phone numbers, per E.164
+const MAX_PHONE_DIGITS: usize = 15;
+/// Number of hex digits kept from the hash of an attachment's filename
+const FILENAME_HASH_LENGTH: usize = 16;
+/// Number of random bytes in the key used to hash attachment filenames
+const FILENAME_KEY_LENGTH: usize = 32;
+
+/// Removes names, handles, and other identifying details from the data written to an export
+///
+/// - Handles are replaced with stable pseudonyms, like `Contact 7`
+/// - Chats are named by their members' pseudonyms, or `Chat 3` if they have none
+/// - URLs, email addresses, and phone numbers in message text are masked, along with any text matched by custom patterns
+/// - Attachment filenames are replaced with a keyed hash, keeping the extension
+/// - Link previews and shared locations lose their details
+pub struct Redactor {
+    /// Matches links, with or without a scheme, leaving out trailing punctuation
+    url: Regex,
+    /// Matches email addresses
+    email: Regex,
+    /// Matches digits grouped like a phone number; matches are checked with [`is_phone_number`]
+    phone: Regex,
+    /// Custom rules, applied before the built-in ones
+    patterns: Vec<Regex>,
+    /// Random key for hashing attachment filenames, so the hashes cannot be reversed by hashing common names
+    key: [u8; FILENAME_KEY_LENGTH],
+}
+
+impl Redactor {
+    /// Build a redactor that also masks text matching each of the custom patterns
+    pub fn new(patterns: &[&String]) -> Result<Self, RuntimeError> {
+        Ok(Self {
+            url: Regex::new(r#"(?i)\b(?:[a-z][a-z0-9+.-]*://|www\.)[^\s<>\x{FFFC}\x{FFFD}]*[^\s<>\x{FFFC}\x{FFFD}.,!?;:'")\]]"#)
+                .expect("built-in URL pattern is valid"),
+            email: Regex::new(r"(?i)\b[a-z0-9._%+-]+@[a-z0-9-]+(?:\.[a-z0-9-]+)*\.[a-z]{2,}\b")
+                .expect("built-in email pattern is valid"),
+            // Numbers are only masked when they are grouped like phone numbers, so dates, times,
+            // version strings, and lists of numbers like `10 12 15 20` are kept as-is
+            phone: Regex::new(concat!(
+                // International, like `+1 (555) 867-5309` or `+33 1 23 45 67 89`
+                r"\+\d{1,3}(?:[ .-]?\(?\d{1,5}\)?){1,5}|",
+                // Area code in parentheses, like `(555) 867-5309`
+                r"\(\d{2,5}\)[ .-]?\d{3,4}[ .-]?\d{4}\b|",
+                // Pairs with a trunk prefix, like `01.23.45.67.89`
+                r"\b0\d(?:[ .-]\d{2}){4}\b|",
+                // Three groups, like `555-867-5309` or `020 7946 0958`
+                r"\b\d{2,5}[ .-]\d{3,4}[ .-]\d{4}\b|",
+                // Two groups, like `07911 123456`
+                r"\b\d{3,5}[ .-]\d{6,8}\b|",
+                // No separators, like `5558675309`
+                r"\b\d{7,15}\b",
+            ))
+            .expect("built-in phone number pattern is valid"),
+            patterns: patterns
+                .iter()
+                .map(|pattern| {
+                    Regex::new(pattern).map_err(|why| {
+                        RuntimeError::InvalidOptions(format!(
+                            "`{pattern}` is not a valid regular expression!\n{why}"
+                        ))
+                    })
+                })
+                .collect::<Result<Vec<_>, _>>()?,
+            key: rand::random(),
+        })
+    }
+
+    /// Mask custom patterns, URLs, email addresses, and phone numbers in some text
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// use crate::app::redactor::Redactor;
+    ///
+    /// let redactor = Redactor::new(&[]).unwrap();
+    /// let text = redactor.redact("Call 555-867-5309 or see https://example.com");
+    /// assert_eq!(text, "Call [phone] or see [url]");
+    /// ```
+    pub fn redact(&self, text: &str) -> String {
+        let mut out_s = text.to_string();
+        for pattern in &self.patterns {
+            out_s = pattern.replace_all(&out_s, REDACTED).into_owned();
+        }
+        out_s = self.url.replace_all(&out_s, URL_MASK).into_owned();
+        out_s = self.email.replace_all(&out_s, EMAIL_MASK).into_owned();
+        self.phone
+            .replace_all(&out_s, |caps: &Captures| {
+                if is_phone_number(&caps[0]) {
+                    PHONE_MASK.to_string()
+                } else {
+                    caps[0].to_string()
+                }
+            })
+            .into_owned()
+    }
+
+    /// Redact the parts of a message that are written to an export
+    ///
+    /// The text, subject, and group chat name are masked and the number the message was sent from is dropped.
+    pub fn redact_message(&self, message: &mut Message) {
+        message.text = message.text.as_deref().map(|text| self.redact(text));
+        message.subject = message
+            .subject
+            .as_deref()
+            .map(|subject| self.redact(subject));
+        if message.group_title.is_some() {
+            message.group_title = Some(REDACTED.to_string());
+        }
+        message.destination_caller_id = None;
+    }
+
+    /// Redact the previous versions of an edited message
+    pub fn redact_edits(&self, edited: &mut EditedMessage) {
+        edited
+            .events
+            .iter_mut()
+            .for_each(|event| event.text = self.redact(&event.text));
+    }
+
+    /// Replace an attachment's filename with a hash of it, keeping the extension
+    ///
+    /// The hash is keyed with a random key that is generated for each export, so the same name hashes
+    /// the same way within an export but names cannot be recovered by hashing guesses like `IMG_1234.HEIC`.
+    /// The file itself can still be found, since the path on disk is not changed.
+    pub fn redact_attachment(&self, attachment: &mut Attachment) {
+        if attachment.transfer_name.is_none() && attachment.filename.is_none() {
+            return;
+        }
+        let name = attachment.filename();
+        let mut mac =
+            Hmac::<Sha1>::new_from_slice(&self.key).expect("HMAC accepts keys of any length");
+        mac.update(name.as_bytes());
+        let hash = format!("{:x}", mac.finalize().into_bytes());
+        let hash = &hash[..FILENAME_HASH_LENGTH];
+        attachment.transfer_name = Some(
+            match Path::new(name).extension().and_then(|ext| ext.to_str()) {
+                Some(ext) => format!("{hash}.{ext}"),
+                None => hash.to_string(),
+            },
+        );
+    }
+
+    /// Remove the details of a link preview or shared location, leaving a placeholder
+    pub fn strip_link<'a>(&self, balloon: URLOverride<'a>) -> URLOverride<'a> {
+        match balloon {
+            URLOverride::SharedPlacemark(_) => URLOverride::SharedPlacemark(PlacemarkMessage {
+                url: None,
+                original_url: None,
+                place_name: Some(LOCATION_MASK),
+                placemark: Placemark::default(),
+            }),
+            _ => URLOverride::Normal(URLMessage {
+                title: Some(URL_MASK),
+                summary: None,
+                url: None,
+                original_url: None,
+                item_type: None,
+                images: vec![],
+                icons: vec![],
+                site_name: None,
+                placeholder: false,
+            }),
+        }
+    }
+
+    /// Remove a chat's name and identifier, so it is named by its members instead
+    pub fn redact_chat(chat: &mut Chat, number: usize) {
+        chat.chat_identifier = format!("Chat {number}");
+        chat.display_name = None;
+    }
+
+    /// Remove the names and identifiers of every chat, numbering them from 1 in `ROWID` order
+    ///
+    /// The numbers only show up for chats without members, and do not reveal the chats' `ROWID`s.
+    pub fn redact_chats(chatrooms: &mut HashMap<i32, Chat>) {
+        let mut rowids: Vec<i32> = chatrooms.keys().copied().collect();
+        rowids.sort_unstable();
+        for (idx, rowid) in rowids.iter().enumerate() {
+            if let Some(chat) = chatrooms.get_mut(rowid) {
+                Self::redact_chat(chat, idx + 1);
+            }
+        }
+    }
+
+    /// Build a pseudonym for each handle, like `Contact 7`
+    ///
+    /// Handles that belong to the same person share a pseudonym. People are numbered from 1 in order of the
+    /// lowest handle `ROWID` among them, so the same person gets the same name each time a database is exported
+    /// without revealing any `ROWID`s.
+    pub fn pseudonyms(real_participants: &HashMap<i32, i32>) -> HashMap<i32, String> {
+        let mut lowest: BTreeMap<i32, i32> = BTreeMap::new();
+        for (handle_id, real_id) in real_participants {
+            lowest
+                .entry(*real_id)
+                .and_modify(|id| *id = (*id).min(*handle_id))
+                .or_insert(*handle_id);
+        }
+
+        // Handle ID 0 is self, so it is not numbered
+        let mut order: Vec<i32> = lowest.values().copied().filter(|id| *id != 0).collect();
+        order.sort_unstable();
+        let numbers: HashMap<i32, usize> = order
+            .iter()
+            .enumerate()
+            .map(|(idx, handle_id)| (*handle_id, idx + 1))
+            .collect();
+
+        let mut pseudonyms: HashMap<i32, String> = real_participants
+            .iter()
+            .filter_map(|(handle_id, real_id)| {
+                numbers
+                    .get(&lowest[real_id])
+                    .map(|number| (*handle_id, format!("Contact {number}")))
+            })
+            .collect();
+        // Handle ID 0 is self in group chats
+        pseudonyms.insert(0, ME.to_string());
+        pseudonyms
+    }
+}
+
+impl Debug for Redactor {
+    fn fmt(&self, fmt: &mut Formatter<'_>) -> FmtResult {
+        // The key is left out, since knowing it would allow reversing the filename hashes
+        fmt.debug_struct("Redactor")
+            .field("url", &self.url)
+            .field("email", &self.email)
+            .field("phone", &self.phone)
+            .field("patterns", &self.patterns)
+            .finish_non_exhaustive()
+    }
+}
+
+impl PartialEq for Redactor {
+    fn eq(&self, other: &Self) -> bool {
+        self.patterns.len() == other.patterns.len()
+            && self
+                .patterns
+                .iter()
+                .zip(&other.patterns)
+                .all(|(a, b)| a.as_str() == b.as_str())
+    }
+}
+
+impl Eq for Redactor {}
+
+/// Determine if a match of the phone number pattern has enough digits to be a phone number
+fn is_phone_number(candidate: &str) -> bool {
+    let digits = candidate.chars().filter(char::is_ascii_digit).count();
+    (MIN_PHONE_DIGITS..=MAX_PHONE_DIGITS).contains(&digits)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use imessage_database::{
+        message_types::{
+            placemark::{Placemark, PlacemarkMessage},
+            url::URLMessage,
+            variants::URLOverride,
+        },
+        tables::{attachment::Attachment, chat::Chat, table::ME},
+    };
+
+    use crate::app::redactor::{Redactor, LOCATION_MASK, URL_MASK};
+
+    fn fake_attachment() -> Attachment {
+        Attachment {
+            rowid: 1,
+            filename: Some("~/Library/Messages/Attachments/a/b/Passport Scan.pdf".to_string()),
+            uti: None,
+            mime_type: None,
+            transfer_name: Some("Passport Scan.pdf".to_string()),
+            total_bytes: 0,
+            is_sticker: false,
+            hide_attachment: 0,
+            copied_path: None,
+        }
+    }
+
+    #[test]
+    fn can_redact_urls() {
+        let redactor = Redactor::new(&[]).unwrap();
+        assert_eq!(
+            redactor.redact("See https://example.com/a?b=c and www.example.org!"),
+            "See [url] and [url]!"
+        );
+    }
+
+    #[test]
+    fn can_redact_emails() {
+        let redactor = Redactor::new(&[]).unwrap();
+        assert_eq!(
+            redactor.redact("Email Jane.Doe+work@mail.example.co.uk today"),
+            "Email [email] today"
+        );
+    }
+
+    #[test]
+    fn can_redact_phone_numbers() {
+        let redactor = Redactor::new(&[]).unwrap();
+        assert_eq!(
+            redactor.redact("Call +1 (555) 867-5309 or 020 7946 0958"),
+            "Call [phone] or [phone]"
+        );
+    }
+
+    #[test]
+    fn cant_redact_dates_and_times() {
+        let redactor = Redactor::new(&[]).unwrap();
+        for text in [
+            "2023-05-14 10:30",
+            "2023-05-14T10:30:00",
+            "On 14.05.2023 at 10:30:15",
+            "Due 05/14/2023, 9:05",
+            "2023/5/4 and 10:30 2023-05-14",
+        ] {
+            assert_eq!(redactor.redact(text), text);
+        }
+    }
+
+    #[test]
+    fn can_redact_phone_numbers_near_dates() {
+        let redactor = Redactor::new(&[]).unwrap();
+        assert_eq!(
+            redactor.redact("On 2023-05-14 at 10:30 call 555.867.5309"),
+            "On 2023-05-14 at 10:30 call [phone]"
+        );
+        assert_eq!(redactor.redact("Call 01.23.45.67.89"), "Call [phone]");
+    }
+
+    #[test]
+    fn can_redact_phone_number_formats() {
+        let redactor = Redactor::new(&[]).unwrap();
+        for text in [
+            "555-867-5309",
+            "(555) 867-5309",
+            "+1-555-867-5309",
+            "+15558675309",
+            "5558675309",
+            "+44 20 7946 0958",
+            "07911 123456",
+            "01 23 45 67 89",
+            "+33 1 23 45 67 89",
+        ] {
+            assert_eq!(redactor.redact(text), "[phone]", "{text}");
+        }
+    }
+
+    #[test]
+    fn cant_redact_number_lists() {
+        let redactor = Redactor::new(&[]).unwrap();
+        for text in [
+            "scores 10 12 15 20",
+            "scores 10 12 15 20 25",
+            "1 500 000",
+            "12 500 000 people",
+            "version 1.2.3.4567",
+            "in 2023 1500 people came",
+        ] {
+            assert_eq!(redactor.redact(text), text);
+        }
+    }
+
+    #[test]
+    fn cant_redact_short_numbers() {
+        let redactor = Redactor::new(&[]).unwrap();
+        assert_eq!(
+            redactor.redact("Meet at 10.30 in room 42, code 1234"),
+            "Meet at 10.30 in room 42, code 1234"
+        );
+    }
+
+    #[test]
+    fn can_redact_custom_patterns() {
+        let pattern = r"(?i)project \w+".to_string();
+        let redactor = Redactor::new(&[&pattern]).unwrap();
+        assert_eq!(
+            redactor.redact("Project Falcon ships today"),
+            "[redacted] ships today"
+        );
+    }
+
+    #[test]
+    fn cant_build_invalid_pattern() {
+        let pattern = "(unclosed".to_string();
+        assert!(Redactor::new(&[&pattern]).is_err());
+    }
+
+    #[test]
+    fn can_keep_replacement_chars() {
+        let redactor = Redactor::new(&[]).unwrap();
+        assert_eq!(
+            redactor.redact("\u{FFFC}https://example.com\u{FFFC}"),
+            "\u{FFFC}[url]\u{FFFC}"
+        );
+    }
+
+    #[test]
+    fn can_hash_attachment_filename() {
+        let redactor = Redactor::new(&[]).unwrap();
+        let mut attachment = fake_attachment();
+        redactor.redact_attachment(&mut attachment);
+
+        let name = attachment.filename().to_string();
+        assert!(name.ends_with(".pdf"));
+        assert!(!name.contains("Passport"));
+        assert_eq!(name.len(), 20);
+
+        // The same name always hashes the same way within an export
+        let mut other = fake_attachment();
+        redactor.redact_attachment(&mut other);
+        assert_eq!(other.filename(), name);
+    }
+
+    #[test]
+    fn can_hash_attachment_filename_with_export_key() {
+        let mut attachment = fake_attachment();
+        Redactor::new(&[])
+            .unwrap()
+            .redact_attachment(&mut attachment);
+
+        // Each export hashes with a new key, so hashes cannot be matched against known names
+        let mut other = fake_attachment();
+        Redactor::new(&[]).unwrap().redact_attachment(&mut other);
+        assert_ne!(other.filename(), attachment.filename());
+    }
+
+    #[test]
+    fn can_keep_attachment_path() {
+        let redactor = Redactor::new(&[]).unwrap();
+        let mut attachment = fake_attachment();
+        redactor.redact_attachment(&mut attachment);
+        assert_eq!(
+            attachment.filename,
+            Some("~/Library/Messages/Attachments/a/b/Passport Scan.pdf".to_string())
+        );
+    }
+
+    #[test]
+    fn can_strip_url() {
+        let redactor = Redactor::new(&[]).unwrap();
+        let balloon = URLOverride::Normal(URLMessage {
+            title: Some("Private page"),
+            summary: Some("Details"),
+            url: Some("https://example.com/private"),
+            original_url: None,
+            item_type: None,
+            images: vec!["https://example.com/image.png"],
+            icons: vec![],
+            site_name: Some("Example"),
+            placeholder: false,
+        });
+        match redactor.strip_link(balloon) {
+            URLOverride::Normal(stripped) => {
+                assert_eq!(stripped.title, Some(URL_MASK));
+                assert!(stripped.get_url().is_none());
+                assert!(stripped.images.is_empty());
+                assert!(stripped.summary.is_none());
+                assert!(stripped.site_name.is_none());
+            }
+            _ => panic!("Expected a URL message!"),
+        }
+    }
+
+    #[test]
+    fn can_strip_placemark() {
+        let redactor = Redactor::new(&[]).unwrap();
+        let balloon = URLOverride::SharedPlacemark(PlacemarkMessage {
+            url: Some("https://maps.apple.com/?q=home"),
+            original_url: None,
+            place_name: Some("Home"),
+            placemark: Placemark {
+                address: Some("1 Infinite Loop"),
+                ..Default::default()
+            },
+        });
+        match redactor.strip_link(balloon) {
+            URLOverride::SharedPlacemark(stripped) => assert_eq!(
+                stripped,
+                PlacemarkMessage {
+                    url: None,
+                    original_url: None,
+                    place_name: Some(LOCATION_MASK),
+                    placemark: Placemark::default(),
+                }
+            ),
+            _ => panic!("Expected a placemark message!"),
+        }
+    }
+
+    #[test]
+    fn can_redact_chat() {
+        let mut chat = Chat {
+            rowid: 3,
+            chat_identifier: "+15558675309".to_string(),
+            service_name: None,
+            display_name: Some("Family".to_string()),
+        };
+        Redactor::redact_chat(&mut chat, 1);
+        assert_eq!(chat.chat_identifier, "Chat 1");
+        assert_eq!(chat.display_name, None);
+    }
+
+    #[test]
+    fn can_number_redacted_chats() {
+        let chat = |rowid: i32| Chat {
+            rowid,
+            chat_identifier: "+15558675309".to_string(),
+            service_name: None,
+            display_name: None,
+        };
+        let mut chatrooms = HashMap::from([(40, chat(40)), (12, chat(12)), (95, chat(95))]);
+        Redactor::redact_chats(&mut chatrooms);
+
+        assert_eq!(chatrooms[&12].chat_identifier, "Chat 1");
+        assert_eq!(chatrooms[&40].chat_identifier, "Chat 2");
+        assert_eq!(chatrooms[&95].chat_identifier, "Chat 3");
+    }
+
+    #[test]
+    fn can_build_stable_pseudonyms() {
+        let real_participants = HashMap::from([(0, 0), (40, 1), (90, 1), (70, 2)]);
+        let pseudonyms = Redactor::pseudonyms(&real_participants);
+
+        // People are numbered densely by their lowest handle ROWID, which is not exposed
+        assert_eq!(pseudonyms[&0], ME);
+        assert_eq!(pseudonyms[&40], "Contact 1");
+        assert_eq!(pseudonyms[&90], "Contact 1");
+        assert_eq!(pseudonyms[&70], "Contact 2");
+        assert_eq!(pseudonyms.len(), 4);
+    }
+}
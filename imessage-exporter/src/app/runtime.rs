@@ -1,8 +1,11 @@
 use std::{
+    cell::RefCell,
     cmp::min,
-    collections::{BTreeSet, HashMap, HashSet},
-    fs::create_dir_all,
-    path::PathBuf,
+    collections::{BTreeSet, HashMap, HashSet, VecDeque},
+    ffi::OsStr,
+    fs::{create_dir_all, read, remove_file, write, File},
+    io::{self, copy, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
 };
 
 use fs2::available_space;
@@ -10,10 +13,11 @@ use rusqlite::Connection;
 
 use crate::{
     app::{
-        attachment_manager::AttachmentManager, converter::Converter, error::RuntimeError,
-        export_type::ExportType, options::Options, sanitizers::sanitize_filename,
+        attachment_manager::AttachmentManager, converter::Converter, encryption::Encryption,
+        error::RuntimeError, export_type::ExportType, manifest::Manifest, options::Options,
+        redactor::Redactor, sanitizers::sanitize_filename,
     },
-    Exporter, HTML, TXT, JSONL
+    Exporter, Markdown, Mbox, Parquet, SQLite, CSV, HTML, JSONL, TXT, XML,
 };
 
 use imessage_database::{
@@ -25,13 +29,65 @@ use imessage_database::{
         handle::Handle,
         messages::Message,
         table::{
-            get_connection, get_db_size, Cacheable, Deduplicate, Diagnostic, ATTACHMENTS_DIR,
-            MAX_LENGTH, ME, ORPHANED, UNKNOWN,
+            get_connection, get_db_size, Cacheable, Deduplicate, Diagnostic, Table,
+            ATTACHMENTS_DIR, MAX_LENGTH, ME, ORPHANED, UNKNOWN,
         },
     },
-    util::{dates::get_offset, size::format_file_size},
+    util::{contacts::Contacts, dates::get_offset, size::format_file_size},
 };
 
+/// Collects text filter matches along with the messages around them in the same conversation
+struct TextMatches {
+    /// Number of messages to keep before and after each match
+    context: usize,
+    /// For each conversation, the messages that may come before the next match and how many to keep after the last match
+    conversations: HashMap<Option<i32>, (VecDeque<i32>, usize)>,
+    /// Messages that match or are near a match
+    matches: HashSet<i32>,
+    /// For each match that selects messages before it: its conversation, its `ROWID`, and the earliest `ROWID` it selects
+    windows: Vec<(Option<i32>, i32, i32)>,
+}
+
+impl TextMatches {
+    fn new(context: usize) -> Self {
+        Self {
+            context,
+            conversations: HashMap::new(),
+            matches: HashSet::new(),
+            windows: vec![],
+        }
+    }
+
+    /// Add the next message in a conversation, in the order messages are exported
+    fn add(&mut self, conversation: Option<i32>, rowid: i32, is_match: bool) {
+        let (before, after) = self.conversations.entry(conversation).or_default();
+        if is_match {
+            if let Some(earliest) = before.iter().min() {
+                self.windows.push((conversation, rowid, *earliest));
+            }
+            self.matches.extend(before.drain(..));
+            self.matches.insert(rowid);
+            *after = self.context;
+        } else if *after > 0 {
+            self.matches.insert(rowid);
+            *after -= 1;
+        } else {
+            before.push_back(rowid);
+            if before.len() > self.context {
+                before.pop_front();
+            }
+        }
+    }
+
+    /// Get the conversations where a match after `last_message` selects messages at or before it
+    fn reaching_before(&self, last_message: i32) -> impl Iterator<Item = Option<i32>> + '_ {
+        self.windows
+            .iter()
+            .filter(move |(_, rowid, earliest)| *rowid > last_message && *earliest <= last_message)
+            .map(|(conversation, _, _)| *conversation)
+    }
+}
+
 /// Stores the application state and handles application lifecycle
 pub struct Config {
     /// Map of chatroom ID to chatroom information
@@ -42,6 +98,10 @@ pub struct Config {
     pub chatroom_participants: HashMap<i32, BTreeSet<i32>>,
     /// Map of participant ID to contact info
     pub participants: HashMap<i32, String>,
+    /// Map of participant ID to the name of the contact it belongs to, if contacts were provided
+    pub names: HashMap<i32, String>,
+    /// Map of participant ID to the phone number or email address of that single handle
+    pub addresses: HashMap<i32, String>,
     /// Map of participant ID to an internal unique participant ID
     pub real_participants: HashMap<i32, i32>,
     /// Messages that are reactions to other messages
@@ -54,6 +114,10 @@ pub struct Config {
     pub db: Connection,
     /// Converter type used when converting image files
     pub converter: Option<Converter>,
+    /// What incremental exports have written so far, including previous runs
+    pub manifest: RefCell<Manifest>,
+    /// Messages that match the text filter or are near a match, if there is a text filter
+    pub text_matches: Option<HashSet<i32>>,
 }
 
 impl Config {
@@ -99,10 +163,16 @@ impl Config {
         match &attachment.copied_path {
             Some(path) => {
                 if let Ok(relative_path) = path.strip_prefix(&self.options.export_path) {
+                    // Split exports write each file one folder below the attachments folder
+                    if self.options.split_by.is_some() {
+                        return Path::new("..").join(relative_path).display().to_string();
+                    }
                     return relative_path.display().to_string();
                 }
                 path.display().to_string()
             }
+            // The full path includes the original filename
+            None if self.options.redactor.is_some() => attachment.filename().to_string(),
             None => attachment
                 .resolved_attachment_path(
                     &self.options.platform,
@@ -113,6 +183,195 @@ impl Config {
         }
     }
 
+    /// Move `path` to the file for the time period a message was sent in, if the export is split by time period
+    ///
+    /// Split exports write each conversation to a folder with a file for each period, like `Chat Name/2023-05.html`.
+    /// Returns the previous path if it changed, so exporters can close the old file and open the new one.
+    pub fn partition(&self, path: &mut PathBuf, message: &Message) -> Option<PathBuf> {
+        let period = self.options.split_by.as_ref()?.name(
+            &self
+                .options
+                .date_format
+                .in_zone(&message.date(&self.offset).ok()?),
+        );
+
+        // Paths start out as `Chat Name.ext` until the first message is partitioned
+        let partitioned = path.parent() != Some(self.options.export_path.as_path());
+        if partitioned && path.file_stem() == Some(OsStr::new(&period)) {
+            return None;
+        }
+        let name = if partitioned {
+            path.parent()?.file_name()?
+        } else {
+            path.file_stem()?
+        };
+
+        let mut new_path = self.options.export_path.join(name);
+        if let Err(why) = create_dir_all(&new_path) {
+            eprintln!("Unable to create {new_path:?}: {why}");
+        }
+        new_path.push(period);
+        if let Some(extension) = path.extension() {
+            new_path.set_extension(extension);
+        }
+        Some(std::mem::replace(path, new_path))
+    }
+
+    /// Note that a message was written to `path`, so incremental exports know where to resume
+    pub fn record(&self, message: &Message, path: &Path) {
+        if !self.options.incremental {
+            return;
+        }
+        let conversation = self
+            .conversation(message)
+            .map_or(ORPHANED.to_string(), |(_, id)| id.to_string());
+        let file = path
+            .strip_prefix(&self.options.export_path)
+            .unwrap_or(path)
+            .to_path_buf();
+        self.manifest
+            .borrow_mut()
+            .record(conversation, message.rowid, message.date, file);
+    }
+
+    /// Load the manifest from a previous export, if any, and only select messages that changed since it ran
+    ///
+    /// New messages are appended to the existing files. Conversations with edited or unsent messages, new
+    /// reactions or replies to messages that were already exported, new messages that belong before ones
+    /// that were already exported, or new text matches whose context reaches back into the previous export
+    /// are marked stale, so they are written again from scratch.
+    fn resume(&mut self, text_matches: Option<&TextMatches>) -> Result<(), RuntimeError> {
+        let export_type = match &self.options.export_type {
+            Some(export_type) if self.options.incremental => export_type.to_string(),
+            _ => return Ok(()),
+        };
+        let Some(mut manifest) = Manifest::load(&self.options.export_path)? else {
+            // Nothing to resume, so this is a full export that writes the first manifest
+            self.manifest = RefCell::new(Manifest {
+                export_type,
+                ..Default::default()
+            });
+            return Ok(());
+        };
+        if manifest.export_type != export_type {
+            return Err(RuntimeError::InvalidOptions(format!(
+                "Export path {:?} contains an incremental \"{}\" export, which cannot be resumed as \"{export_type}\"!",
+                self.options.export_path, manifest.export_type
+            )));
+        }
+
+        let key = |chat_id: &i32| {
+            self.real_chatrooms
+                .get(chat_id)
+                .map_or(ORPHANED.to_string(), ToString::to_string)
+        };
+        let mut stale: BTreeSet<String> =
+            Message::get_changed_chats(&self.db, manifest.last_message, manifest.last_change)
+                .map_err(RuntimeError::DatabaseError)?
+                .iter()
+                .map(key)
+                .collect();
+
+        // Paginated HTML conversations are rebuilt with every new message, since new messages can move page breaks
+        let append = !matches!(self.options.export_type, Some(ExportType::Html))
+            || self.options.paginate.is_none();
+        for (chat_id, earliest) in Message::get_new_chats(&self.db, manifest.last_message)
+            .map_err(RuntimeError::DatabaseError)?
+        {
+            let conversation = key(&chat_id);
+            let late = manifest
+                .conversations
+                .get(&conversation)
+                .is_some_and(|state| earliest < state.last_date);
+            if late || !append {
+                stale.insert(conversation);
+            }
+        }
+        if let Some(text_matches) = text_matches {
+            stale.extend(
+                text_matches
+                    .reaching_before(manifest.last_message)
+                    .map(|conversation| {
+                        conversation.map_or(ORPHANED.to_string(), |id| id.to_string())
+                    }),
+            );
+        }
+        manifest.mark_stale(stale);
+
+        // Select every message in the stale conversations, along with new messages in the rest
+        let refresh_chats = self
+            .real_chatrooms
+            .iter()
+            .filter(|(_, id)| manifest.stale.contains(&id.to_string()))
+            .map(|(chat_id, _)| *chat_id)
+            .collect();
+        self.options
+            .query_context
+            .set_resume(manifest.last_message, refresh_chats);
+
+        eprintln!(
+            "Resuming the previous export, writing {} changed conversations again...",
+            manifest.stale.len()
+        );
+        self.manifest = RefCell::new(manifest);
+        Ok(())
+    }
+
+    /// Determine if a message passes the text filter, either because it matches or because it is near a match
+    ///
+    /// Without `--context`, the message's text is parsed and matched here, so there is no need for [`Config::match_text()`]
+    /// to parse every message in a pass of its own.
+    pub fn is_selected(&self, message: &mut Message) -> bool {
+        match &self.text_matches {
+            Some(matches) => matches.contains(&message.rowid),
+            None => {
+                let context = &self.options.query_context;
+                context.text.is_none()
+                    || (!message.is_reaction()
+                        && context.matches_text(message.gen_text(&self.db).ok()))
+            }
+        }
+    }
+
+    /// Find the messages that match the text filter, along with the messages around each match in the same conversation
+    ///
+    /// Message text is often only stored in `attributedBody`, so every message has to be parsed before it can be matched.
+    /// This runs before [`Config::resume()`] narrows the query, so the context around new matches in an incremental
+    /// export is the same as in a full export. Without `--context`, matches are found by [`Config::is_selected()`] instead.
+    fn match_text(&self) -> Result<Option<TextMatches>, RuntimeError> {
+        let context = &self.options.query_context;
+        if context.text.is_none() || context.text_context == 0 {
+            return Ok(None);
+        }
+        eprintln!("Finding messages that match the text filter...");
+
+        let mut statement =
+            Message::stream_rows(&self.db, context).map_err(RuntimeError::DatabaseError)?;
+        let messages = statement
+            .query_map([], |row| Ok(Message::from_row(row)))
+            .map_err(|err| RuntimeError::DatabaseError(TableError::Messages(err)))?;
+
+        let mut matches = TextMatches::new(context.text_context);
+        let mut current_message_row = -1;
+        for message in messages {
+            let mut msg = Message::extract(message).map_err(RuntimeError::DatabaseError)?;
+
+            // Reactions are rendered with the message they react to, so they are not context of their own
+            if msg.rowid == current_message_row || msg.is_reaction() {
+                continue;
+            }
+            current_message_row = msg.rowid;
+
+            let _ = msg.gen_text(&self.db);
+            matches.add(
+                self.conversation(&msg).map(|(_, id)| *id),
+                msg.rowid,
+                context.matches_text(msg.text.as_deref()),
+            );
+        }
+        Ok(Some(matches))
+    }
+
     /// Get a filename for a chat, possibly using cached data.
     ///
     /// If the chat has an assigned name, use that, truncating if necessary.
@@ -144,13 +403,28 @@ impl Config {
         sanitize_filename(&filename)
     }
 
+    /// Get a readable name for a chat
+    ///
+    /// Unlike [`Config::filename()`], the name is not truncated or sanitized. If the chat has no
+    /// assigned name, use a list of its members, falling back to the unique `chat_identifier` field.
+    pub fn conversation_name(&self, chatroom: &Chat) -> String {
+        match (
+            chatroom.display_name(),
+            self.chatroom_participants.get(&chatroom.rowid),
+        ) {
+            (Some(name), _) => name.to_string(),
+            (None, Some(participants)) => self.filename_from_participants(participants),
+            (None, None) => chatroom.chat_identifier.clone(),
+        }
+    }
+
     /// Generate a filename from a set of participants, truncating if the name is too long
     ///
     /// - All names:
     ///   - Contact 1, Contact 2
     /// - Truncated Names
     ///   - Contact 1, Contact 2, ... Contact 13 and 4 others
-    fn filename_from_participants(&self, participants: &BTreeSet<i32>) -> String {
+    pub fn filename_from_participants(&self, participants: &BTreeSet<i32>) -> String {
         let mut added = 0;
         let mut out_s = String::with_capacity(MAX_LENGTH);
         for participant_id in participants {
@@ -191,31 +465,64 @@ impl Config {
     /// let options = Options::from_args(&args);
     /// let app = Config::new(options).unwrap();
     /// ```
-    pub fn new(options: Options) -> Result<Config, RuntimeError> {
+    pub fn new(mut options: Options) -> Result<Config, RuntimeError> {
         let conn = get_connection(&options.get_db_path()).map_err(RuntimeError::DatabaseError)?;
+        options
+            .query_context
+            .resolve_handles(&conn)
+            .map_err(RuntimeError::DatabaseError)?;
         eprintln!("Building cache...");
         eprintln!("[1/4] Caching chats...");
-        let chatrooms = Chat::cache(&conn).map_err(RuntimeError::DatabaseError)?;
+        let mut chatrooms = Chat::cache(&conn).map_err(RuntimeError::DatabaseError)?;
         eprintln!("[2/4] Caching chatrooms...");
         let chatroom_participants =
             ChatToHandle::cache(&conn).map_err(RuntimeError::DatabaseError)?;
         eprintln!("[3/4] Caching participants...");
-        let participants = Handle::cache(&conn).map_err(RuntimeError::DatabaseError)?;
+        let mut participants = Handle::cache(&conn).map_err(RuntimeError::DatabaseError)?;
+        let mut addresses = Handle::ids(&conn).map_err(RuntimeError::DatabaseError)?;
+        // Deduplicate by normalized handle and then by contact card before resolving names,
+        // so different contacts that share a name are not merged
+        let mut handles = Handle::normalize(&participants, &options.region);
+        let mut names = HashMap::new();
+        if let Some(path) = &options.contacts_path {
+            let contacts =
+                Contacts::from_path(path, &options.region).map_err(RuntimeError::ContactsError)?;
+            handles = contacts.group(&handles);
+            names = contacts.resolve(&participants);
+        }
+        let real_participants = Handle::dedupe(&handles);
+        if options.redactor.is_some() {
+            participants = Redactor::pseudonyms(&real_participants);
+            names.clear();
+            addresses.clone_from(&participants);
+            Redactor::redact_chats(&mut chatrooms);
+        }
         eprintln!("[4/4] Caching reactions...");
         let reactions = Message::cache(&conn).map_err(RuntimeError::DatabaseError)?;
         eprintln!("Cache built!");
-        Ok(Config {
+        let mut config = Config {
             chatrooms,
-            real_chatrooms: ChatToHandle::dedupe(&chatroom_participants),
+            real_chatrooms: ChatToHandle::dedupe(&ChatToHandle::with_participants(
+                &chatroom_participants,
+                &real_participants,
+            )),
             chatroom_participants,
-            real_participants: Handle::dedupe(&participants),
+            real_participants,
             participants,
+            names,
+            addresses,
             reactions,
             options,
             offset: get_offset(),
             db: conn,
             converter: Converter::determine(),
-        })
+            manifest: RefCell::default(),
+            text_matches: None,
+        };
+        let text_matches = config.match_text()?;
+        config.resume(text_matches.as_ref())?;
+        config.text_matches = text_matches.map(|matches| matches.matches);
+        Ok(config)
     }
 
     /// Ensure there is available disk space for the requested export
@@ -314,6 +621,11 @@ impl Config {
             // Ensure the path we want to export to exists
             create_dir_all(&self.options.export_path).map_err(RuntimeError::DiskError)?;
 
+            // Save the key to a passphrase-protected export before anything is encrypted with it
+            if let Some(encryption) = &self.options.encryption {
+                encryption.save_identity(&self.options.export_path)?;
+            }
+
             // Ensure the path we want to copy attachments to exists, if requested
             if !matches!(self.options.attachment_manager, AttachmentManager::Disabled) {
                 create_dir_all(self.attachment_path()).map_err(RuntimeError::DiskError)?;
@@ -324,6 +636,14 @@ impl Config {
                 self.ensure_free_space()?;
             }
 
+            // Remove the files for conversations that are written again from scratch
+            let latest = if self.options.incremental {
+                self.manifest.borrow_mut().prune(&self.options.export_path);
+                Some(Message::get_latest(&self.db).map_err(RuntimeError::DatabaseError)?)
+            } else {
+                None
+            };
+
             // Create exporter, pass it data we care about, then kick it off
             match export_type {
                 ExportType::Html => {
@@ -335,12 +655,149 @@ impl Config {
                 ExportType::Jsonl => {
                     JSONL::new(self).iter_messages()?;
                 }
+                ExportType::Csv => {
+                    CSV::new(self).iter_messages()?;
+                }
+                ExportType::Sqlite => {
+                    SQLite::new(self).iter_messages()?;
+                }
+                ExportType::Markdown => {
+                    Markdown::new(self).iter_messages()?;
+                }
+                ExportType::Mbox => {
+                    Mbox::new(self).iter_messages()?;
+                }
+                ExportType::Xml => {
+                    XML::new(self).iter_messages()?;
+                }
+                ExportType::Parquet => {
+                    Parquet::new(self).iter_messages()?;
+                }
+            }
+
+            // Finish the encrypted files that are still open
+            if let Some(encryption) = &self.options.encryption {
+                encryption.finish().map_err(RuntimeError::DiskError)?;
+            }
+
+            // Save where the next incremental export resumes from
+            if let Some((last_message, last_change)) = latest {
+                let mut manifest = self.manifest.borrow_mut();
+                manifest.last_message = manifest.last_message.max(last_message);
+                manifest.last_change = last_change;
+                manifest.save(&self.options.export_path)?;
             }
         }
         println!("Done!");
         Ok(())
     }
 
+    /// Parse a message's text, redacting it if requested
+    pub fn gen_text(&self, message: &mut Message) {
+        let _ = message.gen_text(&self.db);
+        if let Some(redactor) = &self.options.redactor {
+            redactor.redact_message(message);
+        }
+    }
+
+    /// Get the attachments for a message, redacting their filenames if requested
+    pub fn attachments(&self, message: &Message) -> Result<Vec<Attachment>, TableError> {
+        let mut attachments = Attachment::from_message(&self.db, message)?;
+        if let Some(redactor) = &self.options.redactor {
+            attachments
+                .iter_mut()
+                .for_each(|attachment| redactor.redact_attachment(attachment));
+        }
+        Ok(attachments)
+    }
+
+    /// Get the path an export file is written to, which has an extra extension if the export is encrypted
+    pub fn output_path(&self, path: &Path) -> PathBuf {
+        match &self.options.encryption {
+            Some(_) => Encryption::path(path),
+            None => path.to_path_buf(),
+        }
+    }
+
+    /// Determine if an export file has been written to
+    pub fn output_exists(&self, path: &Path) -> bool {
+        self.output_path(path).exists()
+    }
+
+    /// Add text to the end of an export file, encrypting it if requested
+    pub fn write_to_file(&self, file: &Path, text: &str) {
+        let written = match &self.options.encryption {
+            Some(encryption) => encryption.append(file, text.as_bytes()),
+            None => File::options()
+                .append(true)
+                .create(true)
+                .open(file)
+                .and_then(|mut file| file.write_all(text.as_bytes())),
+        };
+        if let Err(why) = written {
+            eprintln!("Unable to write to {file:?}: {why:?}");
+        }
+    }
+
+    /// Read an export file, decrypting it if the export is encrypted
+    pub fn read_output(&self, path: &Path) -> io::Result<Vec<u8>> {
+        match &self.options.encryption {
+            Some(encryption) => encryption.read(path),
+            None => read(path),
+        }
+    }
+
+    /// Replace the contents of an export file
+    pub fn replace_output(&self, path: &Path, contents: &[u8]) -> io::Result<()> {
+        match &self.options.encryption {
+            Some(encryption) => encryption.replace(path, contents),
+            None => write(path, contents),
+        }
+    }
+
+    /// Remove a suffix from the end of an export file, if it ends with it
+    pub fn truncate_output(&self, path: &Path, suffix: &[u8]) -> io::Result<()> {
+        if let Some(encryption) = &self.options.encryption {
+            return encryption.truncate(path, suffix);
+        }
+
+        // Only read the end of the file, since it may be large
+        let suffix_len = suffix.len() as u64;
+        let mut tail = vec![0; suffix.len()];
+        let mut file = File::options().read(true).write(true).open(path)?;
+        let len = file.metadata()?.len();
+        if len < suffix_len {
+            return Ok(());
+        }
+        file.seek(SeekFrom::Start(len - suffix_len))?;
+        file.read_exact(&mut tail)?;
+        if tail == suffix {
+            file.set_len(len - suffix_len)?;
+        }
+        Ok(())
+    }
+
+    /// Add the contents of one export file to the end of another, removing the original
+    pub fn append_output(&self, from: &Path, to: &Path) -> io::Result<()> {
+        if let Some(encryption) = &self.options.encryption {
+            return encryption.append_file(from, to);
+        }
+        let mut file = File::options().append(true).create(true).open(to)?;
+        copy(&mut File::open(from)?, &mut file)?;
+        remove_file(from)
+    }
+
+    /// Finish an export file the exporter is done writing to, so encrypted exports do not keep its stream for the rest of the run
+    ///
+    /// Plain files are closed after each write, so there is nothing to do for them.
+    pub fn close_output(&self, path: &Path) {
+        if let Some(encryption) = &self.options.encryption {
+            if let Err(why) = encryption.close(path) {
+                eprintln!("Unable to write to {path:?}: {why:?}");
+            }
+        }
+    }
+
     /// Determine who sent a message
     pub fn who<'a, 'b: 'a>(
         &'a self,
@@ -349,12 +806,16 @@ impl Config {
         destination_caller_id: &'b Option<String>,
     ) -> &'a str {
         if is_from_me {
-            if self.options.use_caller_id {
+            if self.options.use_caller_id && self.options.redactor.is_none() {
                 return destination_caller_id.as_deref().unwrap_or(ME);
             }
             return self.options.custom_name.as_deref().unwrap_or(ME);
         } else if let Some(handle_id) = handle_id {
-            return match self.participants.get(&handle_id) {
+            return match self
+                .names
+                .get(&handle_id)
+                .or_else(|| self.participants.get(&handle_id))
+            {
                 Some(contact) => contact,
                 None => UNKNOWN,
             };
@@ -365,36 +826,16 @@ impl Config {
 
 #[cfg(test)]
 mod filename_tests {
-    use crate::{app::attachment_manager::AttachmentManager, Config, Options};
-    use imessage_database::{
-        tables::{
-            chat::Chat,
-            table::{get_connection, MAX_LENGTH},
-        },
-        util::{dirs::default_db_path, platform::Platform, query_context::QueryContext},
+    use crate::{app::redactor::Redactor, Config, Options};
+    use imessage_database::tables::{
+        chat::Chat,
+        table::{get_connection, MAX_LENGTH},
     };
     use std::{
+        cell::RefCell,
         collections::{BTreeSet, HashMap},
-        path::PathBuf,
     };
 
-    fn fake_options() -> Options {
-        Options {
-            db_path: default_db_path(),
-            attachment_root: None,
-            attachment_manager: AttachmentManager::Disabled,
-            diagnostic: false,
-            export_type: None,
-            export_path: PathBuf::new(),
-            query_context: QueryContext::default(),
-            no_lazy: false,
-            custom_name: None,
-            use_caller_id: false,
-            platform: Platform::macOS,
-            ignore_disk_space: false,
-        }
-    }
-
     fn fake_chat() -> Chat {
         Chat {
             rowid: 0,
@@ -411,25 +852,29 @@ mod filename_tests {
             real_chatrooms: HashMap::new(),
             chatroom_participants: HashMap::new(),
             participants: HashMap::new(),
+            names: HashMap::new(),
+            addresses: HashMap::new(),
             real_participants: HashMap::new(),
             reactions: HashMap::new(),
             options,
             offset: 0,
             db: connection,
             converter: Some(crate::app::converter::Converter::Sips),
+            manifest: RefCell::default(),
+            text_matches: None,
         }
     }
 
     #[test]
     fn can_create() {
-        let options = fake_options();
+        let options = Options::fake();
         let app = fake_app(options);
         app.start().unwrap();
     }
 
     #[test]
     fn can_get_filename_good() {
-        let options = fake_options();
+        let options = Options::fake();
         let mut app = fake_app(options);
 
         // Create participant data
@@ -449,7 +894,7 @@ mod filename_tests {
 
     #[test]
     fn can_get_filename_long_multiple() {
-        let options = fake_options();
+        let options = Options::fake();
         let mut app = fake_app(options);
 
         // Create participant data
@@ -505,7 +950,7 @@ mod filename_tests {
 
     #[test]
     fn can_get_filename_single_long() {
-        let options = fake_options();
+        let options = Options::fake();
         let mut app = fake_app(options);
 
         // Create participant data
@@ -523,7 +968,7 @@ mod filename_tests {
 
     #[test]
     fn can_get_filename_chat_display_name_long() {
-        let options = fake_options();
+        let options = Options::fake();
         let app = fake_app(options);
 
         // Create chat
@@ -537,7 +982,7 @@ mod filename_tests {
 
     #[test]
     fn can_get_filename_chat_display_name_normal() {
-        let options = fake_options();
+        let options = Options::fake();
         let app = fake_app(options);
 
         // Create chat
@@ -551,7 +996,7 @@ mod filename_tests {
 
     #[test]
     fn can_get_filename_chat_display_name_short() {
-        let options = fake_options();
+        let options = Options::fake();
         let app = fake_app(options);
 
         // Create chat
@@ -565,7 +1010,7 @@ mod filename_tests {
 
     #[test]
     fn can_get_filename_chat_participants() {
-        let options = fake_options();
+        let options = Options::fake();
         let mut app = fake_app(options);
 
         // Create chat
@@ -586,9 +1031,35 @@ mod filename_tests {
         assert_eq!(filename, "Person 10, Person 11");
     }
 
+    #[test]
+    fn can_get_filename_chat_redacted() {
+        let mut options = Options::fake();
+        options.redactor = Some(Redactor::new(&[]).unwrap());
+        let mut app = fake_app(options);
+
+        // Create chat
+        let mut chat = fake_chat();
+        chat.display_name = Some("Family".to_string());
+        Redactor::redact_chat(&mut chat, 1);
+
+        // Create participant data
+        app.participants =
+            Redactor::pseudonyms(&HashMap::from([(0, 0), (10, 1), (11, 2), (12, 1)]));
+
+        // Add participants
+        let mut people = BTreeSet::new();
+        people.insert(11);
+        people.insert(12);
+        app.chatroom_participants.insert(chat.rowid, people);
+
+        // Get filename
+        let filename = app.filename(&chat);
+        assert_eq!(filename, "Contact 2, Contact 1");
+    }
+
     #[test]
     fn can_get_filename_chat_no_participants() {
-        let options = fake_options();
+        let options = Options::fake();
         let app = fake_app(options);
 
         // Create chat
@@ -598,33 +1069,41 @@ mod filename_tests {
         let filename = app.filename(&chat);
         assert_eq!(filename, "Default");
     }
+
+    #[test]
+    fn can_get_conversation_name_display_name() {
+        let options = Options::fake();
+        let app = fake_app(options);
+
+        // Create chat
+        let mut chat = fake_chat();
+        chat.display_name = Some("Book Club/Wine".to_string());
+
+        assert_eq!(app.conversation_name(&chat), "Book Club/Wine");
+    }
+
+    #[test]
+    fn can_get_conversation_name_participants() {
+        let options = Options::fake();
+        let mut app = fake_app(options);
+
+        // Create chat
+        let chat = fake_chat();
+
+        // Create participant data
+        app.participants.insert(10, "Person 10".to_string());
+        app.chatroom_participants
+            .insert(chat.rowid, BTreeSet::from([10]));
+
+        assert_eq!(app.conversation_name(&chat), "Person 10");
+    }
 }
 
 #[cfg(test)]
 mod who_tests {
-    use crate::{app::attachment_manager::AttachmentManager, Config, Options};
-    use imessage_database::{
-        tables::{chat::Chat, messages::Message, table::get_connection},
-        util::{dirs::default_db_path, platform::Platform, query_context::QueryContext},
-    };
-    use std::{collections::HashMap, path::PathBuf};
-
-    fn fake_options() -> Options {
-        Options {
-            db_path: default_db_path(),
-            attachment_root: None,
-            attachment_manager: AttachmentManager::Disabled,
-            diagnostic: false,
-            export_type: None,
-            export_path: PathBuf::new(),
-            query_context: QueryContext::default(),
-            no_lazy: false,
-            custom_name: None,
-            use_caller_id: false,
-            platform: Platform::macOS,
-            ignore_disk_space: false,
-        }
-    }
+    use crate::{app::redactor::Redactor, Config, Options};
+    use imessage_database::tables::{chat::Chat, messages::Message, table::get_connection};
+    use std::{cell::RefCell, collections::HashMap};
 
     fn fake_chat() -> Chat {
         Chat {
@@ -642,16 +1121,20 @@ mod who_tests {
             real_chatrooms: HashMap::new(),
             chatroom_participants: HashMap::new(),
             participants: HashMap::new(),
+            names: HashMap::new(),
+            addresses: HashMap::new(),
             real_participants: HashMap::new(),
             reactions: HashMap::new(),
             options,
             offset: 0,
             db: connection,
             converter: Some(crate::app::converter::Converter::Sips),
+            manifest: RefCell::default(),
+            text_matches: None,
         }
     }
 
-    fn blank() -> Message {
+    pub fn blank() -> Message {
         Message {
             rowid: i32::default(),
             guid: String::default(),
@@ -684,7 +1167,7 @@ mod who_tests {
 
     #[test]
     fn can_get_who_them() {
-        let options = fake_options();
+        let options = Options::fake();
         let mut app = fake_app(options);
 
         // Create participant data
@@ -695,9 +1178,24 @@ mod who_tests {
         assert_eq!(who, "Person 10".to_string());
     }
 
+    #[test]
+    fn can_get_who_them_contact() {
+        let options = Options::fake();
+        let mut app = fake_app(options);
+
+        // Create participant data
+        app.participants.insert(10, "+15558675309".to_string());
+        app.names.insert(10, "Person 10".to_string());
+
+        // Get participant name
+        let who = app.who(Some(10), false, &None);
+        assert_eq!(who, "Person 10".to_string());
+        assert_eq!(app.participants[&10], "+15558675309");
+    }
+
     #[test]
     fn can_get_who_them_missing() {
-        let options = fake_options();
+        let options = Options::fake();
         let app = fake_app(options);
 
         // Get participant name
@@ -707,7 +1205,7 @@ mod who_tests {
 
     #[test]
     fn can_get_who_me() {
-        let options = fake_options();
+        let options = Options::fake();
         let app = fake_app(options);
 
         // Get participant name
@@ -717,7 +1215,7 @@ mod who_tests {
 
     #[test]
     fn can_get_who_me_caller_id() {
-        let mut options = fake_options();
+        let mut options = Options::fake();
         options.use_caller_id = true;
         let app = fake_app(options);
 
@@ -729,7 +1227,7 @@ mod who_tests {
 
     #[test]
     fn can_get_who_me_custom() {
-        let mut options = fake_options();
+        let mut options = Options::fake();
         options.custom_name = Some("Name".to_string());
         let app = fake_app(options);
 
@@ -740,7 +1238,7 @@ mod who_tests {
 
     #[test]
     fn can_get_who_none_me() {
-        let options = fake_options();
+        let options = Options::fake();
         let app = fake_app(options);
 
         // Get participant name
@@ -750,7 +1248,7 @@ mod who_tests {
 
     #[test]
     fn can_get_who_me_none_caller_id() {
-        let mut options = fake_options();
+        let mut options = Options::fake();
         options.use_caller_id = true;
         let app = fake_app(options);
 
@@ -760,9 +1258,22 @@ mod who_tests {
         assert_eq!(who, "test".to_string());
     }
 
+    #[test]
+    fn can_get_who_me_caller_id_redacted() {
+        let mut options = Options::fake();
+        options.use_caller_id = true;
+        options.redactor = Some(Redactor::new(&[]).unwrap());
+        let app = fake_app(options);
+
+        // Get participant name
+        let caller_id = Some("+15558675309".to_string());
+        let who = app.who(Some(0), true, &caller_id);
+        assert_eq!(who, "Me".to_string());
+    }
+
     #[test]
     fn can_get_who_none_them() {
-        let options = fake_options();
+        let options = Options::fake();
         let app = fake_app(options);
 
         // Get participant name
@@ -772,7 +1283,7 @@ mod who_tests {
 
     #[test]
     fn can_get_chat_valid() {
-        let options = fake_options();
+        let options = Options::fake();
         let mut app = fake_app(options);
 
         // Create chat
@@ -791,7 +1302,7 @@ mod who_tests {
 
     #[test]
     fn can_get_chat_valid_deleted() {
-        let options = fake_options();
+        let options = Options::fake();
         let mut app = fake_app(options);
 
         // Create chat
@@ -811,7 +1322,7 @@ mod who_tests {
 
     #[test]
     fn can_get_chat_invalid() {
-        let options = fake_options();
+        let options = Options::fake();
         let mut app = fake_app(options);
 
         // Create chat
@@ -830,7 +1341,7 @@ mod who_tests {
 
     #[test]
     fn can_get_chat_none() {
-        let options = fake_options();
+        let options = Options::fake();
         let mut app = fake_app(options);
 
         // Create chat
@@ -851,29 +1362,23 @@ mod who_tests {
 
 #[cfg(test)]
 mod directory_tests {
-    use crate::{app::attachment_manager::AttachmentManager, Config, Options};
-    use imessage_database::{
-        tables::{attachment::Attachment, table::get_connection},
-        util::{dirs::default_db_path, platform::Platform, query_context::QueryContext},
+    use crate::{
+        app::{
+            encryption::Encryption, period::Period, redactor::Redactor, runtime::who_tests::blank,
+        },
+        Config, Options,
+    };
+    use imessage_database::tables::{
+        attachment::Attachment,
+        table::{get_connection, ORPHANED},
+    };
+    use std::{
+        cell::RefCell,
+        collections::HashMap,
+        env::temp_dir,
+        fs::{create_dir_all, read, remove_dir_all},
+        path::PathBuf,
     };
-    use std::{collections::HashMap, path::PathBuf};
-
-    fn fake_options() -> Options {
-        Options {
-            db_path: default_db_path(),
-            attachment_root: None,
-            attachment_manager: AttachmentManager::Disabled,
-            diagnostic: false,
-            export_type: None,
-            export_path: PathBuf::new(),
-            query_context: QueryContext::default(),
-            no_lazy: false,
-            custom_name: None,
-            use_caller_id: false,
-            platform: Platform::macOS,
-            ignore_disk_space: false,
-        }
-    }
 
     fn fake_app(options: Options) -> Config {
         let connection = get_connection(&options.db_path).unwrap();
@@ -882,12 +1387,16 @@ mod directory_tests {
             real_chatrooms: HashMap::new(),
             chatroom_participants: HashMap::new(),
             participants: HashMap::new(),
+            names: HashMap::new(),
+            addresses: HashMap::new(),
             real_participants: HashMap::new(),
             reactions: HashMap::new(),
             options,
             offset: 0,
             db: connection,
             converter: Some(crate::app::converter::Converter::Sips),
+            manifest: RefCell::default(),
+            text_matches: None,
         }
     }
 
@@ -907,7 +1416,7 @@ mod directory_tests {
 
     #[test]
     fn can_get_valid_attachment_sub_dir() {
-        let options = fake_options();
+        let options = Options::fake();
         let mut app = fake_app(options);
 
         // Create chatroom ID
@@ -920,7 +1429,7 @@ mod directory_tests {
 
     #[test]
     fn can_get_invalid_attachment_sub_dir() {
-        let options = fake_options();
+        let options = Options::fake();
         let mut app = fake_app(options);
 
         // Create chatroom ID
@@ -933,7 +1442,7 @@ mod directory_tests {
 
     #[test]
     fn can_get_missing_attachment_sub_dir() {
-        let options = fake_options();
+        let options = Options::fake();
         let mut app = fake_app(options);
 
         // Create chatroom ID
@@ -946,7 +1455,7 @@ mod directory_tests {
 
     #[test]
     fn can_get_path_not_copied() {
-        let options = fake_options();
+        let options = Options::fake();
         let app = fake_app(options);
 
         // Create attachment
@@ -957,9 +1466,29 @@ mod directory_tests {
         assert_eq!(result, expected);
     }
 
+    #[test]
+    fn can_get_path_not_copied_redacted() {
+        let mut options = Options::fake();
+        options.redactor = Some(Redactor::new(&[]).unwrap());
+        let app = fake_app(options);
+
+        // Create attachment
+        let mut attachment = fake_attachment();
+        app.options
+            .redactor
+            .as_ref()
+            .unwrap()
+            .redact_attachment(&mut attachment);
+
+        let result = app.message_attachment_path(&attachment);
+        assert!(result.ends_with(".jpg"));
+        assert!(!result.contains("a/b/c"));
+        assert_ne!(result, "d.jpg");
+    }
+
     #[test]
     fn can_get_path_copied() {
-        let mut options = fake_options();
+        let mut options = Options::fake();
         // Set an export path
         options.export_path = PathBuf::from("/Users/ReagentX/exports");
 
@@ -976,9 +1505,108 @@ mod directory_tests {
         assert_eq!(result, expected);
     }
 
+    #[test]
+    fn can_get_path_copied_split() {
+        let mut options = Options::fake();
+        // Set an export path
+        options.export_path = PathBuf::from("/Users/ReagentX/exports");
+        options.split_by = Some(Period::Month);
+
+        let app = fake_app(options);
+
+        // Create attachment
+        let mut attachment = fake_attachment();
+        let mut full_path = PathBuf::from("/Users/ReagentX/exports/attachments");
+        full_path.push(attachment.filename());
+        attachment.copied_path = Some(full_path);
+
+        let result = app.message_attachment_path(&attachment);
+        let expected = String::from("../attachments/d.jpg");
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn can_partition_path() {
+        let mut options = Options::fake();
+        options.export_path = temp_dir().join("imessage-exporter-partition");
+        options.split_by = Some(Period::Month);
+        let app = fake_app(options);
+
+        let mut message = blank();
+        // Mid-May, so the month is the same in every timezone
+        message.date = 674526582885055488;
+
+        let original = app.options.export_path.join("Book Club.txt");
+        let mut path = original.clone();
+        assert_eq!(app.partition(&mut path, &message), Some(original));
+        assert_eq!(
+            path,
+            app.options
+                .export_path
+                .join("Book Club")
+                .join("1991-05.txt")
+        );
+        assert!(app.options.export_path.join("Book Club").is_dir());
+
+        // Messages in the same period stay in the same file
+        assert_eq!(app.partition(&mut path, &message), None);
+
+        // Messages in a later period move to a new file in the same folder
+        message.date += 60 * 60 * 24 * 30 * 1_000_000_000;
+        assert!(app.partition(&mut path, &message).is_some());
+        assert_eq!(
+            path,
+            app.options
+                .export_path
+                .join("Book Club")
+                .join("1991-06.txt")
+        );
+
+        remove_dir_all(&app.options.export_path).unwrap();
+    }
+
+    #[test]
+    fn cant_partition_path_not_split() {
+        let options = Options::fake();
+        let app = fake_app(options);
+
+        let mut path = PathBuf::from("Book Club.txt");
+        assert_eq!(app.partition(&mut path, &blank()), None);
+        assert_eq!(path, PathBuf::from("Book Club.txt"));
+    }
+
+    #[test]
+    fn can_record_incremental() {
+        let mut options = Options::fake();
+        options.export_path = PathBuf::from("/Users/ReagentX/exports");
+        options.incremental = true;
+        let app = fake_app(options);
+
+        let mut message = blank();
+        message.rowid = 10;
+        message.date = 674526582885055488;
+        app.record(&message, &app.options.export_path.join("Orphaned.txt"));
+
+        let manifest = app.manifest.borrow();
+        let state = &manifest.conversations[ORPHANED];
+        assert_eq!(state.last_message, 10);
+        assert_eq!(state.last_date, 674526582885055488);
+        assert!(state.files.contains(&PathBuf::from("Orphaned.txt")));
+        assert_eq!(manifest.last_message, 10);
+    }
+
+    #[test]
+    fn cant_record_not_incremental() {
+        let options = Options::fake();
+        let app = fake_app(options);
+
+        app.record(&blank(), &PathBuf::from("Orphaned.txt"));
+        assert!(app.manifest.borrow().conversations.is_empty());
+    }
+
     #[test]
     fn can_get_path_copied_bad() {
-        let mut options = fake_options();
+        let mut options = Options::fake();
         // Set an export path
         options.export_path = PathBuf::from("/Users/ReagentX/exports");
 
@@ -992,4 +1620,160 @@ mod directory_tests {
         let expected = String::from("a/b/c/d.jpg");
         assert_eq!(result, expected);
     }
+
+    #[test]
+    fn can_get_output_path_encrypted() {
+        let mut options = Options::fake();
+        options.encryption = Some(Encryption::new(&[], false).unwrap());
+        let app = fake_app(options);
+
+        assert_eq!(
+            app.output_path(&PathBuf::from("/Users/ReagentX/exports/Orphaned.txt")),
+            PathBuf::from("/Users/ReagentX/exports/Orphaned.txt.age")
+        );
+    }
+
+    #[test]
+    fn can_write_output() {
+        let export_path = temp_dir().join("imessage-exporter-output-test");
+        let _ = remove_dir_all(&export_path);
+        create_dir_all(&export_path).unwrap();
+
+        let mut options = Options::fake();
+        options.export_path = export_path.clone();
+        let app = fake_app(options);
+
+        let path = export_path.join("Orphaned.html");
+        app.write_to_file(&path, "<p>message</p></body>");
+        app.truncate_output(&path, b"</body>").unwrap();
+        let exists = app.output_exists(&path);
+        let actual = read(&path).unwrap();
+        remove_dir_all(&export_path).unwrap();
+
+        assert!(exists);
+        assert_eq!(actual, b"<p>message</p>");
+    }
+
+    #[test]
+    fn can_write_output_encrypted() {
+        let export_path = temp_dir().join("imessage-exporter-output-encrypted-test");
+        let _ = remove_dir_all(&export_path);
+        create_dir_all(&export_path).unwrap();
+
+        let mut options = Options::fake();
+        options.export_path = export_path.clone();
+        options.encryption = Some(Encryption::new(&[], false).unwrap());
+        let app = fake_app(options);
+
+        let path = export_path.join("Orphaned.html");
+        app.write_to_file(&path, "<p>message</p></body>");
+        app.truncate_output(&path, b"</body>").unwrap();
+        let exists = app.output_exists(&path);
+        let plaintext_exists = path.exists();
+        let actual = app.read_output(&path).unwrap();
+        let encrypted = read(app.output_path(&path)).unwrap();
+        remove_dir_all(&export_path).unwrap();
+
+        assert!(exists);
+        assert!(!plaintext_exists);
+        assert_eq!(actual, b"<p>message</p>");
+        assert!(!encrypted
+            .windows(b"message".len())
+            .any(|window| window == b"message"));
+    }
+}
+
+#[cfg(test)]
+mod text_match_tests {
+    use std::collections::HashSet;
+
+    use crate::{
+        app::runtime::{who_tests::blank, TextMatches},
+        Config, Options,
+    };
+
+    #[test]
+    fn can_match_with_context() {
+        let mut matches = TextMatches::new(1);
+        matches.add(Some(1), 1, false);
+        matches.add(Some(1), 2, false);
+        matches.add(Some(2), 3, false);
+        matches.add(Some(1), 4, true);
+        matches.add(Some(2), 5, false);
+        matches.add(Some(1), 6, false);
+        matches.add(Some(1), 7, false);
+
+        assert_eq!(matches.matches, HashSet::from([2, 4, 6]));
+    }
+
+    #[test]
+    fn can_match_without_context() {
+        let mut matches = TextMatches::new(0);
+        matches.add(None, 1, false);
+        matches.add(None, 2, true);
+        matches.add(None, 3, false);
+
+        assert_eq!(matches.matches, HashSet::from([2]));
+    }
+
+    #[test]
+    fn can_match_overlapping_context() {
+        let mut matches = TextMatches::new(2);
+        matches.add(Some(1), 1, false);
+        matches.add(Some(1), 2, true);
+        matches.add(Some(1), 3, false);
+        matches.add(Some(1), 4, true);
+        matches.add(Some(1), 5, false);
+        matches.add(Some(1), 6, false);
+        matches.add(Some(1), 7, false);
+
+        assert_eq!(matches.matches, HashSet::from([1, 2, 3, 4, 5, 6]));
+    }
+
+    #[test]
+    fn can_find_context_before_resume() {
+        let mut matches = TextMatches::new(2);
+        matches.add(Some(1), 1, false);
+        matches.add(Some(2), 2, false);
+        matches.add(Some(2), 3, true);
+        matches.add(Some(1), 4, false);
+        matches.add(Some(1), 5, true);
+        matches.add(Some(2), 6, true);
+
+        // The match in conversation 1 pulls in messages 1 and 4, and the one in conversation 2 pulls in message 2
+        assert_eq!(
+            matches.reaching_before(3).collect::<Vec<_>>(),
+            vec![Some(1)]
+        );
+        assert_eq!(
+            matches.reaching_before(2).collect::<Vec<_>>(),
+            vec![Some(2), Some(1)]
+        );
+        assert_eq!(matches.reaching_before(6).count(), 0);
+    }
+
+    #[test]
+    fn can_select_text_without_context() {
+        let mut options = Options::fake();
+        options.query_context.set_text("dinner");
+        let config = Config::new(options).unwrap();
+
+        // Without context, messages are matched as they are exported instead of in a separate pass
+        assert!(config.text_matches.is_none());
+
+        let mut message = blank();
+        message.text = Some("Dinner at 7?".to_string());
+        assert!(config.is_selected(&mut message));
+
+        message.text = Some("Lunch at noon".to_string());
+        assert!(!config.is_selected(&mut message));
+    }
+
+    #[test]
+    fn can_select_without_text_filter() {
+        let config = Config::new(Options::fake()).unwrap();
+
+        let mut message = blank();
+        assert!(config.is_selected(&mut message));
+    }
 }
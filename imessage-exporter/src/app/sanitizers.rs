@@ -4,6 +4,8 @@ use std::borrow::Cow;
 const FILENAME_REPLACEMENT_CHAR: char = '_';
 /// Characters disallowed in a filename
 const FILENAME_DISALLOWED_CHARS: [char; 3] = ['/', '\\', ':'];
+/// Characters that make spreadsheet apps read a CSV field as a formula when it starts with one
+const CSV_FORMULA_CHARS: [char; 6] = ['=', '+', '-', '@', '\t', '\r'];
 
 /// Remove unsafe chars in [this list](FILENAME_DISALLOWED_CHARS).
 pub fn sanitize_filename(filename: &str) -> String {
@@ -20,7 +22,7 @@ pub fn sanitize_filename(filename: &str) -> String {
 }
 
 /// Escapes HTML special characters in the input string.
-pub fn sanitize_html(input: &str) -> Cow<str> {
+pub fn sanitize_html(input: &str) -> Cow<'_, str> {
     for (idx, char) in input.char_indices() {
         if matches!(char, '<' | '>' | '"' | '’' | '&') {
             let mut res = String::from(&input[..idx]);
@@ -38,6 +40,85 @@ pub fn sanitize_html(input: &str) -> Cow<str> {
     Cow::Borrowed(input)
 }
 
+/// Escapes a field for use in a CSV row, per [RFC 4180](https://www.rfc-editor.org/rfc/rfc4180).
+///
+/// Fields that start with [a formula character](CSV_FORMULA_CHARS) are prefixed with `'` so spreadsheet apps
+/// show them as text instead of evaluating them. Fields containing commas, quotes, or line breaks are
+/// wrapped in quotes, and any quotes are doubled.
+pub fn sanitize_csv(input: &str) -> Cow<'_, str> {
+    let field = if input.starts_with(CSV_FORMULA_CHARS) {
+        Cow::Owned(format!("'{input}"))
+    } else {
+        Cow::Borrowed(input)
+    };
+    if field.contains([',', '"', '\n', '\r']) {
+        return Cow::Owned(format!("\"{}\"", field.replace('"', "\"\"")));
+    }
+    field
+}
+
+/// Escapes a value for use as a double-quoted [YAML](https://yaml.org/spec/1.2.2/#731-double-quoted-style) scalar.
+pub fn sanitize_yaml(input: &str) -> String {
+    let mut res = String::with_capacity(input.len() + 2);
+    res.push('"');
+    input.chars().for_each(|c| match c {
+        '"' => res.push_str("\\\""),
+        '\\' => res.push_str("\\\\"),
+        '\n' => res.push_str("\\n"),
+        '\r' => res.push_str("\\r"),
+        '\t' => res.push_str("\\t"),
+        _ => res.push(c),
+    });
+    res.push('"');
+    res
+}
+
+/// Escapes a value for use in an XML attribute.
+///
+/// Line breaks and tabs are kept as character references so parsers do not normalize them away,
+/// and other control characters, which are not allowed in [XML 1.0](https://www.w3.org/TR/xml/#charsets), are removed.
+pub fn sanitize_xml(input: &str) -> Cow<'_, str> {
+    for (idx, char) in input.char_indices() {
+        if matches!(char, '<' | '>' | '"' | '\'' | '&') || char.is_control() {
+            let mut res = String::from(&input[..idx]);
+            input[idx..].chars().for_each(|c| match c {
+                '<' => res.push_str("&lt;"),
+                '>' => res.push_str("&gt;"),
+                '"' => res.push_str("&quot;"),
+                '\'' => res.push_str("&apos;"),
+                '&' => res.push_str("&amp;"),
+                '\n' => res.push_str("&#10;"),
+                '\r' => res.push_str("&#13;"),
+                '\t' => res.push_str("&#9;"),
+                c if c.is_control() => {}
+                _ => res.push(c),
+            });
+            return Cow::Owned(res);
+        }
+    }
+    Cow::Borrowed(input)
+}
+
+/// Percent-encodes a relative file path for use in a link, per [RFC 3986](https://www.rfc-editor.org/rfc/rfc3986#section-2.1).
+///
+/// Path separators are kept, so nested paths still resolve.
+pub fn sanitize_url(input: &str) -> Cow<'_, str> {
+    let is_safe =
+        |byte: u8| byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'_' | b'.' | b'~' | b'/');
+    if input.bytes().all(is_safe) {
+        return Cow::Borrowed(input);
+    }
+    let mut res = String::with_capacity(input.len());
+    input.bytes().for_each(|byte| {
+        if is_safe(byte) {
+            res.push(byte as char);
+        } else {
+            res.push_str(&format!("%{byte:02X}"));
+        }
+    });
+    Cow::Owned(res)
+}
+
 #[cfg(test)]
 mod test_filename {
     use crate::app::sanitizers::sanitize_filename;
@@ -93,3 +174,136 @@ mod tests {
         );
     }
 }
+
+#[cfg(test)]
+mod test_csv {
+    use crate::app::sanitizers::sanitize_csv;
+
+    #[test]
+    fn doesnt_sanitize_plain_field() {
+        assert_eq!(&sanitize_csv("Hello world"), "Hello world");
+    }
+
+    #[test]
+    fn doesnt_sanitize_empty_field() {
+        assert_eq!(&sanitize_csv(""), "");
+    }
+
+    #[test]
+    fn can_sanitize_comma() {
+        assert_eq!(&sanitize_csv("Hello, world"), "\"Hello, world\"");
+    }
+
+    #[test]
+    fn can_sanitize_quotes() {
+        assert_eq!(&sanitize_csv("Say \"hi\""), "\"Say \"\"hi\"\"\"");
+    }
+
+    #[test]
+    fn can_sanitize_newlines() {
+        assert_eq!(&sanitize_csv("Hello\nworld"), "\"Hello\nworld\"");
+        assert_eq!(&sanitize_csv("Hello\r\nworld"), "\"Hello\r\nworld\"");
+    }
+
+    #[test]
+    fn can_sanitize_formulas() {
+        assert_eq!(&sanitize_csv("=1+1"), "'=1+1");
+        assert_eq!(&sanitize_csv("+1"), "'+1");
+        assert_eq!(&sanitize_csv("-1"), "'-1");
+        assert_eq!(&sanitize_csv("@SUM(A1)"), "'@SUM(A1)");
+        assert_eq!(&sanitize_csv("\t=1"), "'\t=1");
+        assert_eq!(&sanitize_csv("\r=1"), "\"'\r=1\"");
+    }
+
+    #[test]
+    fn can_sanitize_quoted_formula() {
+        assert_eq!(
+            &sanitize_csv("=HYPERLINK(\"http://x\", \"y\")"),
+            "\"'=HYPERLINK(\"\"http://x\"\", \"\"y\"\")\""
+        );
+    }
+
+    #[test]
+    fn doesnt_sanitize_inner_formula_chars() {
+        assert_eq!(&sanitize_csv("1+1=2"), "1+1=2");
+    }
+}
+
+#[cfg(test)]
+mod test_yaml {
+    use crate::app::sanitizers::sanitize_yaml;
+
+    #[test]
+    fn can_quote_plain_value() {
+        assert_eq!(sanitize_yaml("Hello world"), "\"Hello world\"");
+    }
+
+    #[test]
+    fn can_quote_empty_value() {
+        assert_eq!(sanitize_yaml(""), "\"\"");
+    }
+
+    #[test]
+    fn can_escape_quotes_and_backslashes() {
+        assert_eq!(sanitize_yaml("a \"b\" \\ c"), "\"a \\\"b\\\" \\\\ c\"");
+    }
+
+    #[test]
+    fn can_escape_line_breaks() {
+        assert_eq!(sanitize_yaml("a\nb\r\tc"), "\"a\\nb\\r\\tc\"");
+    }
+}
+
+#[cfg(test)]
+mod test_xml {
+    use crate::app::sanitizers::sanitize_xml;
+
+    #[test]
+    fn doesnt_sanitize_plain_value() {
+        assert_eq!(&sanitize_xml("Hello world"), "Hello world");
+    }
+
+    #[test]
+    fn can_escape_markup() {
+        assert_eq!(
+            &sanitize_xml("<a href=\"x\">Tom & Jerry's</a>"),
+            "&lt;a href=&quot;x&quot;&gt;Tom &amp; Jerry&apos;s&lt;/a&gt;"
+        );
+    }
+
+    #[test]
+    fn can_keep_line_breaks() {
+        assert_eq!(&sanitize_xml("a\nb\r\tc"), "a&#10;b&#13;&#9;c");
+    }
+
+    #[test]
+    fn can_remove_control_chars() {
+        assert_eq!(&sanitize_xml("a\u{0}b\u{1b}c"), "abc");
+    }
+}
+
+#[cfg(test)]
+mod test_url {
+    use crate::app::sanitizers::sanitize_url;
+
+    #[test]
+    fn doesnt_sanitize_safe_path() {
+        assert_eq!(
+            &sanitize_url("attachments/1/a-b_c.d~e.html"),
+            "attachments/1/a-b_c.d~e.html"
+        );
+    }
+
+    #[test]
+    fn can_sanitize_reserved_chars() {
+        assert_eq!(
+            &sanitize_url("Book Club #2?.html"),
+            "Book%20Club%20%232%3F.html"
+        );
+    }
+
+    #[test]
+    fn can_sanitize_non_ascii() {
+        assert_eq!(&sanitize_url("Café.html"), "Caf%C3%A9.html");
+    }
+}
@@ -0,0 +1,558 @@
+// File to export database as a csv
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    app::{
+        error::RuntimeError, progress::build_progress_bar_export, runtime::Config,
+        sanitizers::sanitize_csv,
+    },
+    exporters::exporter::Exporter,
+};
+
+use imessage_database::{
+    error::table::TableError,
+    message_types::variants::{Announcement, Variant},
+    tables::{
+        attachment::Attachment,
+        messages::{BubbleType, Message},
+        table::{Table, ME, ORPHANED, YOU},
+    },
+};
+
+/// Name of the file all messages are written to when chats are combined
+const COMBINED: &str = "messages";
+/// Column names written as the first row of every file
+const HEADER: &str = "guid,date,sender,chat,service,text,attachment_count,attachments,reactions,reply_to,is_edited,is_deleted\n";
+/// Separator used when a single column contains multiple values
+const LIST_SEPARATOR: &str = "; ";
+
+pub struct CSV<'a> {
+    /// Data that is setup from the application's runtime
+    pub config: &'a Config,
+    /// Handles to files we want to write messages to
+    /// Map of internal unique chatroom ID to a filename
+    pub files: HashMap<i32, PathBuf>,
+    /// Path to file for orphaned messages
+    pub orphaned: PathBuf,
+    /// Path to the single file all messages are written to, if chats are combined
+    pub combined: Option<PathBuf>,
+}
+
+impl<'a> Exporter<'a> for CSV<'a> {
+    fn new(config: &'a Config) -> Self {
+        let mut orphaned = config.options.export_path.clone();
+        orphaned.push(ORPHANED);
+        orphaned.set_extension("csv");
+
+        let combined = config.options.combine_chats.then(|| {
+            let mut combined = config.options.export_path.clone();
+            combined.push(COMBINED);
+            combined.set_extension("csv");
+            combined
+        });
+
+        CSV {
+            config,
+            files: HashMap::new(),
+            orphaned,
+            combined,
+        }
+    }
+
+    fn iter_messages(&mut self) -> Result<(), RuntimeError> {
+        // Tell the user what we are doing
+        eprintln!(
+            "Exporting to {} as csv...",
+            self.config.options.export_path.display()
+        );
+
+        // Write the column names for the files that always exist, unless an incremental export is adding to them
+        // Split exports write them once the file for each period is known
+        let always = self.combined.as_ref().unwrap_or(&self.orphaned);
+        if self.config.options.split_by.is_none() && !self.config.output_exists(always) {
+            self.config.write_to_file(always, HEADER);
+        }
+
+        // Keep track of current message ROWID
+        let mut current_message_row = -1;
+
+        // Set up progress bar
+        let mut current_message = 0;
+        let total_messages =
+            Message::get_count(&self.config.db, &self.config.options.query_context)
+                .map_err(RuntimeError::DatabaseError)?;
+        let pb = build_progress_bar_export(total_messages);
+
+        let mut statement =
+            Message::stream_rows(&self.config.db, &self.config.options.query_context)
+                .map_err(RuntimeError::DatabaseError)?;
+
+        let messages = statement
+            .query_map([], |row| Ok(Message::from_row(row)))
+            .map_err(|err| RuntimeError::DatabaseError(TableError::Messages(err)))?;
+
+        for message in messages {
+            let mut msg = Message::extract(message).map_err(RuntimeError::DatabaseError)?;
+
+            // Early escape if we try and render the same message GUID twice
+            // See https://github.com/ReagentX/imessage-exporter/issues/135 for rationale
+            if msg.rowid == current_message_row {
+                current_message += 1;
+                continue;
+            }
+            current_message_row = msg.rowid;
+
+            // Skip messages that are not near a match for the text filter
+            if !self.config.is_selected(&mut msg) {
+                current_message += 1;
+                continue;
+            }
+
+            // Reactions are summarized in the row of the message they target
+            if !msg.is_reaction() {
+                self.config.gen_text(&mut msg);
+                let row = self.format_row(&msg).map_err(RuntimeError::DatabaseError)?;
+                self.config
+                    .write_to_file(self.get_or_create_file(&msg), &row);
+            }
+
+            current_message += 1;
+            if current_message % 99 == 0 {
+                pb.set_position(current_message);
+            }
+        }
+        pb.finish();
+        Ok(())
+    }
+
+    /// Create a file for the given chat, caching it so we don't need to build it later
+    fn get_or_create_file(&mut self, message: &Message) -> &Path {
+        let split = self.config.options.split_by.is_some();
+        let path = match (&mut self.combined, self.config.conversation(message)) {
+            (Some(combined), _) => combined,
+            (None, Some((chatroom, id))) => self.files.entry(*id).or_insert_with(|| {
+                let mut path = self.config.options.export_path.clone();
+                path.push(self.config.filename(chatroom));
+                path.set_extension("csv");
+
+                // If the file already exists, don't write the headers again
+                // This can happen if multiple chats use the same group name
+                if !self.config.output_exists(&path) && !split {
+                    self.config.write_to_file(&path, HEADER);
+                }
+
+                path
+            }),
+            (None, None) => &mut self.orphaned,
+        };
+
+        // Each file in a split export gets its own column names
+        if let Some(previous) = self.config.partition(path, message) {
+            self.config.close_output(&previous);
+            if !self.config.output_exists(path) {
+                self.config.write_to_file(path, HEADER);
+            }
+        }
+        self.config.record(message, path);
+        path
+    }
+}
+
+impl<'a> CSV<'a> {
+    /// Build a single CSV row for a message, including the trailing newline
+    fn format_row(&self, message: &Message) -> Result<String, TableError> {
+        let attachments = self.format_attachments(message)?;
+
+        let columns = [
+            message.guid.clone(),
+            self.config
+                .options
+                .date_format
+                .format_iso(&message.date(&self.config.offset)),
+            self.config
+                .who(
+                    message.handle_id,
+                    message.is_from_me,
+                    &message.destination_caller_id,
+                )
+                .to_string(),
+            self.format_chat(message),
+            message.service.clone().unwrap_or_default(),
+            self.format_text(message),
+            attachments.len().to_string(),
+            attachments.join(LIST_SEPARATOR),
+            self.format_reactions(message),
+            message.thread_originator_guid.clone().unwrap_or_default(),
+            message.is_edited().to_string(),
+            message.is_deleted().to_string(),
+        ];
+
+        let mut row = columns
+            .iter()
+            .map(|column| sanitize_csv(column))
+            .collect::<Vec<_>>()
+            .join(",");
+        row.push('\n');
+        Ok(row)
+    }
+
+    /// Get the name of the chat a message belongs to
+    ///
+    /// Uses the chat's display name if there is one, otherwise a list of its participants
+    fn format_chat(&self, message: &Message) -> String {
+        match self.config.conversation(message) {
+            Some((chatroom, _)) => match chatroom.display_name() {
+                Some(name) => name.to_string(),
+                None => match self.config.chatroom_participants.get(&chatroom.rowid) {
+                    Some(participants) => self.config.filename_from_participants(participants),
+                    None => chatroom.chat_identifier.clone(),
+                },
+            },
+            None => String::new(),
+        }
+    }
+
+    /// Get the text content of a message, without attachment or app placeholders
+    fn format_text(&self, message: &Message) -> String {
+        if message.is_announcement() {
+            return self.format_announcement(message);
+        }
+
+        message
+            .body()
+            .iter()
+            .filter_map(|part| match part {
+                BubbleType::Text(text) => Some(*text),
+                _ => None,
+            })
+            .collect::<Vec<&str>>()
+            .join("\n")
+    }
+
+    /// Describe a group announcement, i.e. a name or photo change
+    fn format_announcement(&self, message: &Message) -> String {
+        let mut who = self.config.who(
+            message.handle_id,
+            message.is_from_me,
+            &message.destination_caller_id,
+        );
+        // Rename yourself so we render the proper grammar here
+        if who == ME {
+            who = self.config.options.custom_name.as_deref().unwrap_or(YOU);
+        }
+
+        match message.get_announcement() {
+            Some(Announcement::NameChange(name)) => {
+                format!("{who} renamed the conversation to {name}")
+            }
+            Some(Announcement::PhotoChange) => format!("{who} changed the group photo."),
+            Some(Announcement::Unknown(num)) => format!("{who} performed unknown action {num}."),
+            None => String::from("Unable to format announcement!"),
+        }
+    }
+
+    /// Get the filenames of a message's attachments, copying the files if requested
+    fn format_attachments(&self, message: &Message) -> Result<Vec<String>, TableError> {
+        if !message.has_attachments() {
+            return Ok(vec![]);
+        }
+
+        let mut attachments = self.config.attachments(message)?;
+        Ok(attachments
+            .iter_mut()
+            .map(|attachment| self.format_attachment(message, attachment))
+            .collect())
+    }
+
+    /// Get the filename of an attachment, copying the file if requested
+    ///
+    /// Attachments without a transfer name only store a path, so just its last component is used.
+    fn format_attachment(&self, message: &Message, attachment: &mut Attachment) -> String {
+        let _ = self.config.options.attachment_manager.handle_attachment(
+            message,
+            attachment,
+            self.config,
+        );
+        let name = attachment.filename();
+        Path::new(name)
+            .file_name()
+            .map_or(name, |file_name| file_name.to_str().unwrap_or(name))
+            .to_string()
+    }
+
+    /// Summarize the tapbacks and stickers on a message, i.e. `Loved by Name; Sticker by Me`
+    fn format_reactions(&self, message: &Message) -> String {
+        let mut summary = vec![];
+
+        if let Some(reactions_map) = self.config.reactions.get(&message.guid) {
+            let mut indexes: Vec<&usize> = reactions_map.keys().collect();
+            indexes.sort_unstable();
+
+            for reactions in indexes.iter().filter_map(|idx| reactions_map.get(idx)) {
+                for reaction in reactions {
+                    let who = self.config.who(
+                        reaction.handle_id,
+                        reaction.is_from_me,
+                        &reaction.destination_caller_id,
+                    );
+                    match reaction.variant() {
+                        Variant::Reaction(_, true, tapback) => {
+                            summary.push(format!("{tapback:?} by {who}"));
+                        }
+                        Variant::Sticker(_) => summary.push(format!("Sticker by {who}")),
+                        _ => {}
+                    }
+                }
+            }
+        }
+
+        summary.join(LIST_SEPARATOR)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, env::set_var, path::PathBuf};
+
+    use crate::{Config, Exporter, Options, CSV};
+    use imessage_database::tables::{attachment::Attachment, messages::Message};
+
+    pub fn blank() -> Message {
+        Message {
+            rowid: i32::default(),
+            guid: String::default(),
+            text: None,
+            service: Some("iMessage".to_string()),
+            handle_id: Some(i32::default()),
+            destination_caller_id: None,
+            subject: None,
+            date: i64::default(),
+            date_read: i64::default(),
+            date_delivered: i64::default(),
+            is_from_me: false,
+            is_read: false,
+            item_type: 0,
+            group_title: None,
+            group_action_type: 0,
+            associated_message_guid: None,
+            associated_message_type: Some(i32::default()),
+            balloon_bundle_id: None,
+            expressive_send_style_id: None,
+            thread_originator_guid: None,
+            thread_originator_part: None,
+            date_edited: 0,
+            chat_id: None,
+            num_attachments: 0,
+            deleted_from: None,
+            num_replies: 0,
+        }
+    }
+
+    pub fn fake_attachment() -> Attachment {
+        Attachment {
+            rowid: 0,
+            filename: Some("a/b/c/d.jpg".to_string()),
+            uti: Some("public.png".to_string()),
+            mime_type: Some("image/png".to_string()),
+            transfer_name: Some("d.jpg".to_string()),
+            total_bytes: 100,
+            is_sticker: false,
+            hide_attachment: 0,
+            copied_path: None,
+        }
+    }
+
+    #[test]
+    fn can_create() {
+        let options = Options::fake();
+        let config = Config::new(options).unwrap();
+        let exporter = CSV::new(&config);
+        assert_eq!(exporter.files.len(), 0);
+        assert!(exporter.combined.is_none());
+    }
+
+    #[test]
+    fn can_create_combined() {
+        let mut options = Options::fake();
+        options.combine_chats = true;
+        let config = Config::new(options).unwrap();
+        let exporter = CSV::new(&config);
+        assert_eq!(exporter.combined, Some(PathBuf::from("messages.csv")));
+    }
+
+    #[test]
+    fn can_get_combined_file() {
+        let mut options = Options::fake();
+        options.combine_chats = true;
+        let config = Config::new(options).unwrap();
+        let mut exporter = CSV::new(&config);
+
+        let mut message = blank();
+        message.chat_id = Some(1);
+
+        assert_eq!(
+            exporter.get_or_create_file(&message),
+            PathBuf::from("messages.csv")
+        );
+        assert_eq!(exporter.files.len(), 0);
+    }
+
+    #[test]
+    fn can_format_row_from_me() {
+        // Set timezone to PST for consistent Local time
+        set_var("TZ", "PST");
+
+        // Create exporter
+        let options = Options::fake();
+        let config = Config::new(options).unwrap();
+        let exporter = CSV::new(&config);
+
+        // Create fake message
+        let mut message = blank();
+        message.guid = "guid".to_string();
+        // May 17, 2022  8:29:42 PM
+        message.date = 674526582885055488;
+        message.text = Some("Hello, world".to_string());
+        message.is_from_me = true;
+
+        assert_eq!(
+            exporter.format_row(&message).unwrap(),
+            "guid,2022-05-17T17:29:42-07:00,Me,,iMessage,\"Hello, world\",0,,,,false,false\n"
+        );
+    }
+
+    #[test]
+    fn can_format_row_reply() {
+        // Set timezone to PST for consistent Local time
+        set_var("TZ", "PST");
+
+        // Create exporter
+        let options = Options::fake();
+        let mut config = Config::new(options).unwrap();
+        config
+            .participants
+            .insert(999999, "Sample Contact".to_string());
+        let exporter = CSV::new(&config);
+
+        // Create fake message
+        let mut message = blank();
+        message.guid = "guid".to_string();
+        // May 17, 2022  8:29:42 PM
+        message.date = 674526582885055488;
+        message.text = Some("Hello world".to_string());
+        message.handle_id = Some(999999);
+        message.thread_originator_guid = Some("parent".to_string());
+
+        assert_eq!(
+            exporter.format_row(&message).unwrap(),
+            "guid,2022-05-17T17:29:42-07:00,Sample Contact,,iMessage,Hello world,0,,,parent,false,false\n"
+        );
+    }
+
+    #[test]
+    fn can_format_row_with_reactions() {
+        // Create exporter
+        let options = Options::fake();
+        let mut config = Config::new(options).unwrap();
+        config
+            .participants
+            .insert(999999, "Sample Contact".to_string());
+
+        // Create fake reactions
+        let mut loved = blank();
+        loved.handle_id = Some(999999);
+        loved.associated_message_guid = Some("p:0/parent".to_string());
+        loved.associated_message_type = Some(2000);
+
+        let mut sticker = blank();
+        sticker.is_from_me = true;
+        sticker.associated_message_guid = Some("p:1/parent".to_string());
+        sticker.associated_message_type = Some(1000);
+
+        config.reactions.insert(
+            "parent".to_string(),
+            HashMap::from([(0, vec![loved]), (1, vec![sticker])]),
+        );
+        let exporter = CSV::new(&config);
+
+        // Create fake message
+        let mut message = blank();
+        message.guid = "parent".to_string();
+
+        assert_eq!(
+            exporter.format_reactions(&message),
+            "Loved by Sample Contact; Sticker by Me"
+        );
+    }
+
+    #[test]
+    fn can_format_text_without_placeholders() {
+        let options = Options::fake();
+        let config = Config::new(options).unwrap();
+        let exporter = CSV::new(&config);
+
+        let mut message = blank();
+        message.text = Some("\u{FFFC}Check out this photo!".to_string());
+        message.num_attachments = 1;
+
+        assert_eq!(exporter.format_text(&message), "Check out this photo!");
+    }
+
+    #[test]
+    fn can_format_attachment_filename() {
+        let options = Options::fake();
+        let config = Config::new(options).unwrap();
+        let exporter = CSV::new(&config);
+
+        let message = blank();
+        let mut attachment = fake_attachment();
+
+        assert_eq!(
+            exporter.format_attachment(&message, &mut attachment),
+            "d.jpg"
+        );
+    }
+
+    #[test]
+    fn can_format_attachment_filename_without_transfer_name() {
+        let options = Options::fake();
+        let config = Config::new(options).unwrap();
+        let exporter = CSV::new(&config);
+
+        let message = blank();
+        let mut attachment = fake_attachment();
+        attachment.transfer_name = None;
+
+        assert_eq!(
+            exporter.format_attachment(&message, &mut attachment),
+            "d.jpg"
+        );
+    }
+
+    #[test]
+    fn can_format_row_formula() {
+        // Set timezone to PST for consistent Local time
+        set_var("TZ", "PST");
+
+        // Create exporter
+        let options = Options::fake();
+        let config = Config::new(options).unwrap();
+        let exporter = CSV::new(&config);
+
+        // Create fake message
+        let mut message = blank();
+        message.guid = "guid".to_string();
+        // May 17, 2022  8:29:42 PM
+        message.date = 674526582885055488;
+        message.text = Some("=HYPERLINK(\"http://x\")".to_string());
+        message.is_from_me = true;
+
+        assert_eq!(
+            exporter.format_row(&message).unwrap(),
+            "guid,2022-05-17T17:29:42-07:00,Me,,iMessage,\"'=HYPERLINK(\"\"http://x\"\")\",0,,,,false,false\n"
+        );
+    }
+}
@@ -51,7 +51,6 @@ pub(super) trait Writer<'a> {
     fn format_shareplay(&self) -> &str;
     /// Format an edited message
     fn format_edited(&self, msg: &'a Message, indent: &str) -> Result<String, MessageError>;
-    fn write_to_file(file: &Path, text: &str);
 }
 
 /// Defines behavior for formatting custom balloons to the desired output format
@@ -1,16 +1,25 @@
 use std::{
-    collections::HashMap,
-    fs::File,
-    io::Write,
+    collections::{BTreeSet, HashMap},
+    fs::{metadata, read_to_string, File},
+    mem::take,
     path::{Path, PathBuf},
 };
 
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::{Deserialize, Serialize};
+
 use crate::{
     app::{
-        error::RuntimeError, progress::build_progress_bar_export, runtime::Config,
-        sanitizers::sanitize_html,
+        error::RuntimeError,
+        pagination::Pagination,
+        progress::build_progress_bar_export,
+        runtime::Config,
+        sanitizers::{sanitize_html, sanitize_url},
+    },
+    exporters::{
+        exporter::{BalloonFormatter, Exporter, Writer},
+        template::Templates,
     },
-    exporters::exporter::{BalloonFormatter, Exporter, Writer},
 };
 
 use imessage_database::{
@@ -33,14 +42,144 @@ use imessage_database::{
         table::{Table, FITNESS_RECEIVER, ME, ORPHANED, YOU},
     },
     util::{
-        dates::{format, get_local_time, readable_diff, TIMESTAMP_FACTOR},
+        dates::{get_local_time, readable_diff, TIMESTAMP_FACTOR},
         plist::parse_plist,
     },
 };
 
-const HEADER: &str = "<html>\n<head>\n<meta charset=\"UTF-8\">\n<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">";
-const FOOTER: &str = "</body></html>";
-const STYLE: &str = include_str!("resources/style.css");
+const INDEX_SCRIPT: &str = include_str!("resources/index.js");
+const SEARCH_SCRIPT: &str = include_str!("resources/search.js");
+/// Name of the page that links to every exported conversation
+const INDEX: &str = "index";
+
+/// Statistics about an exported file, used to build the index page
+#[derive(Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Summary {
+    /// Name of the conversation
+    pub name: String,
+    /// Members of the conversation, and anyone else who sent a message to it
+    pub participants: BTreeSet<String>,
+    /// Number of messages written to the file
+    pub messages: usize,
+    /// Number of attachments sent in those messages
+    pub attachments: usize,
+    /// Date of the earliest message
+    pub first: Option<i64>,
+    /// Date of the latest message
+    pub last: Option<i64>,
+}
+
+/// The page currently being written for a paginated conversation
+#[derive(Debug, PartialEq, Eq)]
+pub struct Page {
+    /// Path to the first page of the conversation
+    pub first: PathBuf,
+    /// Page number, starting at 1
+    pub number: usize,
+    /// Number of messages written to the page
+    pub messages: usize,
+    /// Number of bytes of messages written to the page
+    pub bytes: usize,
+    /// Year and month of the latest message written to the page
+    pub month: Option<String>,
+}
+
+impl Page {
+    fn new(first: PathBuf) -> Self {
+        Page {
+            first,
+            number: 1,
+            messages: 0,
+            bytes: 0,
+            month: None,
+        }
+    }
+
+    /// Determine if a message of `len` bytes sent in `month` belongs on a new page
+    fn is_full(&self, pagination: &Pagination, month: &Option<String>, len: usize) -> bool {
+        // Every page gets at least one message, even if it is too large to fit
+        if self.messages == 0 {
+            return false;
+        }
+        match pagination {
+            Pagination::Messages(count) => self.messages >= *count,
+            Pagination::Month => self.month != *month,
+            Pagination::Size(size) => (self.bytes + len) as u64 > *size,
+        }
+    }
+
+    /// Get the path to a page of the conversation
+    fn path(&self, number: usize) -> PathBuf {
+        let mut path = self.first.clone();
+        if number > 1 {
+            let stem = self
+                .first
+                .file_stem()
+                .map(|stem| stem.to_string_lossy())
+                .unwrap_or_default();
+            path.set_file_name(format!("{stem} - page {number}"));
+            path.set_extension("html");
+        }
+        path
+    }
+}
+
+/// Search index being written for a conversation
+///
+/// Each entry in the index refers to its sender and page by position, so names are only written once.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct SearchIndex {
+    /// Names of everyone who sent a message
+    pub senders: Vec<String>,
+    /// Filenames of the pages of the conversation
+    pub pages: Vec<String>,
+}
+
+impl SearchIndex {
+    /// Get the path to the index for a conversation, next to its first page
+    fn path(first: &Path) -> PathBuf {
+        first.with_extension("search.js")
+    }
+
+    /// Reopen an index written by a previous export, removing its closing line so more messages can be added
+    fn reopen(path: &Path) -> Option<Self> {
+        let index = read_to_string(path).ok()?;
+        // Entries are on their own lines, so the last line that starts with `]` closes the index
+        let end = index.rfind("\n]")? + 1;
+        let names = index[end..]
+            .trim_end()
+            .strip_prefix("], ")?
+            .strip_suffix(");")?;
+        let (senders, pages) = serde_json::from_str(&format!("[{names}]")).ok()?;
+        File::options()
+            .write(true)
+            .open(path)
+            .and_then(|file| file.set_len(end as u64))
+            .ok()?;
+        Some(SearchIndex { senders, pages })
+    }
+
+    /// Get the position of a sender, adding them if they are new
+    fn sender(&mut self, name: &str) -> usize {
+        match self.senders.iter().position(|sender| sender == name) {
+            Some(position) => position,
+            None => {
+                self.senders.push(name.to_string());
+                self.senders.len() - 1
+            }
+        }
+    }
+
+    /// Get the position of a page, adding it if it is new
+    ///
+    /// Pages are written in order, so only the latest page needs to be checked.
+    fn page(&mut self, name: String) -> usize {
+        if self.pages.last() != Some(&name) {
+            self.pages.push(name);
+        }
+        self.pages.len() - 1
+    }
+}
 
 pub struct HTML<'a> {
     /// Data that is setup from the application's runtime
@@ -50,6 +189,21 @@ pub struct HTML<'a> {
     pub files: HashMap<i32, PathBuf>,
     /// Path to file for orphaned messages
     pub orphaned: PathBuf,
+    /// Statistics about each conversation written, keyed by the path to its first page
+    pub summaries: HashMap<PathBuf, Summary>,
+    /// Current page of each paginated conversation
+    /// Map of internal unique chatroom ID to a page
+    pub pages: HashMap<i32, Page>,
+    /// Pages that contain messages with replies, keyed by the GUID of the message
+    pub threads: HashMap<String, PathBuf>,
+    /// Thread links that point across pages, keyed by the page that contains them
+    ///
+    /// These are rewritten once the export finishes, since replies are written after the message they reply to.
+    pub links: HashMap<PathBuf, Vec<(String, String)>>,
+    /// Templates and stylesheet used to build each page
+    pub templates: Templates,
+    /// Search index for each conversation, keyed by the path to its first page
+    pub search: HashMap<PathBuf, SearchIndex>,
 }
 
 impl<'a> Exporter<'a> for HTML<'a> {
@@ -60,7 +214,17 @@ impl<'a> Exporter<'a> for HTML<'a> {
         HTML {
             config,
             files: HashMap::new(),
+            // Incremental exports keep counting from the files written by previous exports
+            summaries: take(&mut config.manifest.borrow_mut().summaries)
+                .into_iter()
+                .map(|(path, summary)| (config.options.export_path.join(path), summary))
+                .collect(),
             orphaned,
+            pages: HashMap::new(),
+            threads: HashMap::new(),
+            links: HashMap::new(),
+            templates: Templates::load(config.options.template_dir.as_deref()),
+            search: HashMap::new(),
         }
     }
 
@@ -72,7 +236,12 @@ impl<'a> Exporter<'a> for HTML<'a> {
         );
 
         // Write orphaned file headers
-        HTML::write_headers(&self.orphaned);
+        // Split exports write them once the file for each period is known
+        if self.config.output_exists(&self.orphaned) {
+            HTML::reopen(self.config, &self.orphaned, &self.templates.footer);
+        } else if self.config.options.split_by.is_none() {
+            HTML::open_file(self.config, &self.templates, &self.orphaned, ORPHANED);
+        }
 
         // Keep track of current message ROWID
         let mut current_message_row = -1;
@@ -103,18 +272,31 @@ impl<'a> Exporter<'a> for HTML<'a> {
             }
             current_message_row = msg.rowid;
 
+            // Skip messages that are not near a match for the text filter
+            if !self.config.is_selected(&mut msg) {
+                current_message += 1;
+                continue;
+            }
+
             // Render the announcement in-line
             if msg.is_announcement() {
                 let announcement = self.format_announcement(&msg);
-                HTML::write_to_file(self.get_or_create_file(&msg), &announcement);
+                self.paginate(&msg, announcement.len());
+                self.config
+                    .write_to_file(self.get_or_create_file(&msg), &announcement);
             }
             // Message replies and reactions are rendered in context, so no need to render them separately
             else if !msg.is_reaction() {
-                let _ = msg.gen_text(&self.config.db);
+                self.config.gen_text(&mut msg);
                 let message = self
                     .format_message(&msg, 0)
                     .map_err(RuntimeError::DatabaseError)?;
-                HTML::write_to_file(self.get_or_create_file(&msg), &message);
+                self.paginate(&msg, message.len());
+                let message = self.link_threads(&msg, message);
+                let message = self.index_message(&msg, message);
+                self.config
+                    .write_to_file(self.get_or_create_file(&msg), &message);
+                self.summarize(&msg);
             }
             current_message += 1;
             if current_message % 99 == 0 {
@@ -124,33 +306,86 @@ impl<'a> Exporter<'a> for HTML<'a> {
         pb.finish();
 
         eprintln!("Writing HTML footers...");
-        self.files
-            .iter()
-            .for_each(|(_, path)| HTML::write_to_file(path, FOOTER));
-        HTML::write_to_file(&self.orphaned, FOOTER);
+        self.files.iter().for_each(|(id, path)| {
+            if let Some(page) = self.pages.get(id).filter(|page| page.number > 1) {
+                let previous = page.path(page.number - 1);
+                self.config.write_to_file(
+                    path,
+                    &HTML::page_navigation(Some(&previous), page.number, None),
+                );
+            }
+            self.config.write_to_file(path, &self.templates.footer);
+        });
+        if self.config.output_exists(&self.orphaned) {
+            self.config
+                .write_to_file(&self.orphaned, &self.templates.footer);
+        }
+        self.rewrite_links();
+        self.write_search_indexes();
+
+        eprintln!("Writing HTML index...");
+        self.write_index();
+
+        // Keep the statistics for the next incremental export
+        let export_path = &self.config.options.export_path;
+        self.config.manifest.borrow_mut().summaries = self
+            .summaries
+            .drain()
+            .filter_map(|(path, summary)| {
+                Some((path.strip_prefix(export_path).ok()?.to_path_buf(), summary))
+            })
+            .collect();
 
         Ok(())
     }
 
     /// Create a file for the given chat, caching it so we don't need to build it later
     fn get_or_create_file(&mut self, message: &Message) -> &Path {
-        match self.config.conversation(message) {
-            Some((chatroom, id)) => self.files.entry(*id).or_insert_with(|| {
-                let mut path = self.config.options.export_path.clone();
-                path.push(self.config.filename(chatroom));
-                path.set_extension("html");
-
-                // If the file already exists , don't write the headers again
-                // This can happen if multiple chats use the same group name
-                if !path.exists() {
-                    // Write headers if the file does not exist
-                    HTML::write_headers(&path);
-                }
+        let config = self.config;
+        let templates = &self.templates;
+        let split = config.options.split_by.is_some();
+        let (path, chatroom) = match config.conversation(message) {
+            Some((chatroom, id)) => (
+                self.files.entry(*id).or_insert_with(|| {
+                    let mut path = config.options.export_path.clone();
+                    path.push(config.filename(chatroom));
+                    path.set_extension("html");
+
+                    // If the file already exists , don't write the headers again
+                    // This can happen if multiple chats use the same group name, or if an incremental export is adding to it
+                    // Split exports write them once the file for each period is known
+                    if config.output_exists(&path) {
+                        HTML::reopen(config, &path, &templates.footer);
+                    } else if !split {
+                        // Write headers if the file does not exist
+                        let name = config.conversation_name(chatroom);
+                        HTML::open_file(config, templates, &path, &sanitize_html(&name));
+                    }
 
-                path
-            }),
-            None => &self.orphaned,
+                    path
+                }),
+                Some(chatroom),
+            ),
+            None => (&mut self.orphaned, None),
+        };
+
+        // Close the file for the previous time period and open the file for the message's period
+        if let Some(previous) = config.partition(path, message) {
+            if config.output_exists(&previous) {
+                config.write_to_file(&previous, &templates.footer);
+                config.close_output(&previous);
+            }
+            if config.output_exists(path) {
+                HTML::reopen(config, path, &templates.footer);
+            } else {
+                let name = chatroom.map_or(ORPHANED.to_string(), |chatroom| {
+                    config.conversation_name(chatroom)
+                });
+                HTML::open_file(config, templates, path, &sanitize_html(&name));
+            }
         }
+        config.record(message, path);
+        path
     }
 }
 
@@ -159,72 +394,28 @@ impl<'a> Writer<'a> for HTML<'a> {
         // Data we want to write to a file
         let mut formatted_message = String::new();
 
-        // Message div
-        if message.is_reply() && indent_size == 0 {
-            // Add an ID for any top-level message so we can link to them in threads
-            self.add_line(
-                &mut formatted_message,
-                &format!("<div class=\"message\", id=\"r-{}\">", message.guid),
-                "",
-                "",
-            );
+        // Add an ID for any top-level message so we can link to them in threads
+        let id = if message.is_reply() && indent_size == 0 {
+            format!("r-{}", message.guid)
         } else {
-            // No ID needed if the message has no replies
-            self.add_line(&mut formatted_message, "<div class=\"message\">", "", "");
-        }
+            String::new()
+        };
 
-        // Start message div
-        if message.is_from_me {
-            self.add_line(
-                &mut formatted_message,
-                &format!("<div class=\"sent {:?}\">", message.service()),
-                "",
-                "",
-            );
+        // Message type
+        let direction = if message.is_from_me {
+            format!("sent {:?}", message.service())
         } else {
-            self.add_line(&mut formatted_message, "<div class=\"received\">", "", "");
-        }
-
-        // Add message date
-        self.add_line(
-            &mut formatted_message,
-            &self.get_time(message),
-            "<p><span class=\"timestamp\">",
-            "</span>",
-        );
+            String::from("received")
+        };
 
         // Add reply anchor if necessary
-        if message.is_reply() {
-            if indent_size > 0 {
-                // If we are indented it means we are rendering in a thread
-                self.add_line(
-                    &mut formatted_message,
-                    &format!("<a href=\"#r-{}\">⇲</a>", message.guid),
-                    "<span class=\"reply_anchor\">",
-                    "</span>",
-                );
-            } else {
-                // If there is no ident we are rendering a top-level message
-                self.add_line(
-                    &mut formatted_message,
-                    &format!("<a href=\"#{}\">⇱</a>", message.guid),
-                    "<span class=\"reply_anchor\">",
-                    "</span>",
-                );
-            }
-        }
-
-        // Add message sender
-        self.add_line(
-            &mut formatted_message,
-            self.config.who(
-                message.handle_id,
-                message.is_from_me,
-                &message.destination_caller_id,
-            ),
-            "<span class=\"sender\">",
-            "</span></p>",
-        );
+        let reply_anchor = match (message.is_reply(), indent_size > 0) {
+            // If we are indented it means we are rendering in a thread
+            (true, true) => format!("<a href=\"#r-{}\">⇲</a>", message.guid),
+            // If there is no ident we are rendering a top-level message
+            (true, false) => format!("<a href=\"#{}\">⇱</a>", message.guid),
+            (false, _) => String::new(),
+        };
 
         // If message was deleted, annotate it
         if message.is_deleted() {
@@ -238,7 +429,7 @@ impl<'a> Writer<'a> for HTML<'a> {
 
         // Useful message metadata
         let message_parts = message.body();
-        let mut attachments = Attachment::from_message(&self.config.db, message)?;
+        let mut attachments = self.config.attachments(message)?;
         let mut replies = message.get_replies(&self.config.db)?;
 
         // Index of where we are in the attachment Vector
@@ -436,7 +627,7 @@ impl<'a> Writer<'a> for HTML<'a> {
                 replies
                     .iter_mut()
                     .try_for_each(|reply| -> Result<(), TableError> {
-                        let _ = reply.gen_text(&self.config.db);
+                        self.config.gen_text(reply);
                         if !reply.is_reaction() {
                             // Set indent to 1 so we know this is a recursive call
                             self.add_line(
@@ -453,22 +644,29 @@ impl<'a> Writer<'a> for HTML<'a> {
         }
 
         // Add a note if the message is a reply and not rendered in a thread
-        if message.is_reply() && indent_size == 0 {
-            self.add_line(
-                &mut formatted_message,
-                "This message responded to an earlier message.",
-                "<span class=\"reply_context\">",
-                "</span>",
-            );
-        }
-
-        // End message type div
-        self.add_line(&mut formatted_message, "</div>", "", "");
-
-        // End message div
-        self.add_line(&mut formatted_message, "</div>", "", "");
+        let reply_context = if message.is_reply() && indent_size == 0 {
+            "This message responded to an earlier message."
+        } else {
+            ""
+        };
 
-        Ok(formatted_message)
+        Ok(self.templates.message.render(&[
+            ("id", &id),
+            ("guid", &message.guid),
+            ("direction", &direction),
+            ("timestamp", &self.get_time(message)),
+            ("reply_anchor", &reply_anchor),
+            (
+                "sender",
+                self.config.who(
+                    message.handle_id,
+                    message.is_from_me,
+                    &message.destination_caller_id,
+                ),
+            ),
+            ("body", &formatted_message),
+            ("reply_context", reply_context),
+        ]))
     }
 
     fn format_attachment(
@@ -486,40 +684,31 @@ impl<'a> Writer<'a> for HTML<'a> {
         // Build a relative filepath from the fully qualified one on the `Attachment`
         let embed_path = self.config.message_attachment_path(attachment);
 
-        return Ok(match attachment.mime_type() {
-            MediaType::Image(_) => {
-                if self.config.options.no_lazy {
-                    format!("<img src=\"{embed_path}\">")
-                } else {
-                    format!("<img src=\"{embed_path}\" loading=\"lazy\">")
-                }
+        // Inline the file if requested, otherwise link to it
+        // Audio messages fall back to a bare subtype, see `Attachment::mime_type()`
+        let (kind, media_type, data_uri) = match attachment.mime_type() {
+            MediaType::Image(media_type) => {
+                ("image", media_type, self.data_uri(attachment, media_type))
             }
             MediaType::Video(media_type) => {
-                // See https://github.com/ReagentX/imessage-exporter/issues/73 for why duplicate the source tag
-                format!("<video controls> <source src=\"{embed_path}\" type=\"{media_type}\"> <source src=\"{embed_path}\"> </video>")
+                ("video", media_type, self.data_uri(attachment, media_type))
             }
-            MediaType::Audio(media_type) => {
-                format!("<audio controls src=\"{embed_path}\" type=\"{media_type}\" </audio>")
+            MediaType::Audio(media_type) if media_type.contains('/') => {
+                ("audio", media_type, self.data_uri(attachment, media_type))
             }
-            MediaType::Text(_) => {
-                format!(
-                    "<a href=\"{embed_path}\">Click to download {} ({})</a>",
-                    attachment.filename(),
-                    attachment.file_size()
-                )
-            }
-            MediaType::Application(_) => format!(
-                "<a href=\"{embed_path}\">Click to download {} ({})</a>",
-                attachment.filename(),
-                attachment.file_size()
+            MediaType::Audio(media_type) => (
+                "audio",
+                media_type,
+                self.data_uri(attachment, &format!("audio/{media_type}")),
             ),
-            MediaType::Unknown => {
-                format!("<p>Unknown attachment type: {embed_path}</p> <a href=\"{embed_path}\">Download ({})</a>", attachment.file_size())
-            }
-            MediaType::Other(media_type) => {
-                format!("<p>Unable to embed {media_type} attachments: {embed_path}</p>")
+            MediaType::Text(media_type) | MediaType::Application(media_type) => {
+                ("file", media_type, None)
             }
-        });
+            MediaType::Unknown => ("unknown", "", None),
+            MediaType::Other(media_type) => ("other", media_type, None),
+        };
+
+        Ok(self.attachment_to_html(attachment, kind, media_type, data_uri, &embed_path))
     }
 
     fn format_sticker(&self, sticker: &'a mut Attachment, message: &Message) -> String {
@@ -556,7 +745,10 @@ impl<'a> Writer<'a> for HTML<'a> {
             if let Some(payload) = message.payload_data(&self.config.db) {
                 let res = if message.is_url() {
                     let parsed = parse_plist(&payload)?;
-                    let bubble = URLMessage::get_url_message_override(&parsed)?;
+                    let mut bubble = URLMessage::get_url_message_override(&parsed)?;
+                    if let Some(redactor) = &self.config.options.redactor {
+                        bubble = redactor.strip_link(bubble);
+                    }
                     match bubble {
                         URLOverride::Normal(balloon) => self.format_url(&balloon, message),
                         URLOverride::AppleMusic(balloon) => self.format_music(&balloon, message),
@@ -621,15 +813,17 @@ impl<'a> Writer<'a> for HTML<'a> {
                 if !added {
                     return Ok(String::new());
                 }
-                Ok(format!(
-                    "<span class=\"reaction\"><b>{:?}</b> by {}</span>",
-                    reaction,
-                    self.config
-                        .who(msg.handle_id, msg.is_from_me, &msg.destination_caller_id),
-                ))
+                Ok(self.templates.reaction.render(&[
+                    ("reaction", &format!("{reaction:?}")),
+                    (
+                        "sender",
+                        self.config
+                            .who(msg.handle_id, msg.is_from_me, &msg.destination_caller_id),
+                    ),
+                ]))
             }
             Variant::Sticker(_) => {
-                let mut paths = Attachment::from_message(&self.config.db, msg)?;
+                let mut paths = self.config.attachments(msg)?;
                 let who =
                     self.config
                         .who(msg.handle_id, msg.is_from_me, &msg.destination_caller_id);
@@ -677,9 +871,13 @@ impl<'a> Writer<'a> for HTML<'a> {
         if who == ME {
             who = self.config.options.custom_name.as_deref().unwrap_or("You");
         }
-        let timestamp = format(&msg.date(&self.config.offset));
+        let timestamp = self
+            .config
+            .options
+            .date_format
+            .format(&msg.date(&self.config.offset));
 
-        return match msg.get_announcement() {
+        match msg.get_announcement() {
             Some(announcement) => match announcement {
                 Announcement::NameChange(name) => {
                     let clean_name = sanitize_html(name);
@@ -701,7 +899,7 @@ impl<'a> Writer<'a> for HTML<'a> {
             None => String::from(
                 "\n<div class =\"announcement\"><p>Unable to format announcement!</p></div>\n",
             ),
-        };
+        }
     }
 
     fn format_shareplay(&self) -> &str {
@@ -710,8 +908,11 @@ impl<'a> Writer<'a> for HTML<'a> {
 
     fn format_edited(&self, msg: &'a Message, _: &str) -> Result<String, MessageError> {
         if let Some(payload) = msg.message_summary_info(&self.config.db) {
-            let edited_message =
+            let mut edited_message =
                 EditedMessage::from_map(&payload).map_err(MessageError::PlistParseError)?;
+            if let Some(redactor) = &self.config.options.redactor {
+                redactor.redact_edits(&mut edited_message);
+            }
 
             let mut out_s = String::new();
             let mut previous_timestamp: Option<&i64> = None;
@@ -722,7 +923,11 @@ impl<'a> Writer<'a> for HTML<'a> {
                 } else {
                     "They"
                 };
-                let timestamp = format(&msg.date(&self.config.offset));
+                let timestamp = self
+                    .config
+                    .options
+                    .date_format
+                    .format(&msg.date(&self.config.offset));
 
                 out_s.push_str(&format!(
                     "<div class =\"announcement\"><p><span class=\"timestamp\">{timestamp}</span> {who} deleted a message.</p></div>"
@@ -759,15 +964,6 @@ impl<'a> Writer<'a> for HTML<'a> {
         }
         Err(MessageError::PlistParseError(PlistParseError::NoPayload))
     }
-
-    fn write_to_file(file: &Path, text: &str) {
-        match File::options().append(true).create(true).open(file) {
-            Ok(mut file) => {
-                let _ = file.write_all(text.as_bytes());
-            }
-            Err(why) => eprintln!("Unable to write to {file:?}: {why:?}"),
-        };
-    }
 }
 
 impl<'a> BalloonFormatter<&'a Message> for HTML<'a> {
@@ -1179,7 +1375,7 @@ impl<'a> BalloonFormatter<&'a Message> for HTML<'a> {
             // Parse the estimated end time from the message's query string
             let date_stamp = date_str.parse::<f64>().unwrap_or(0.) as i64 * TIMESTAMP_FACTOR;
             let date_time = get_local_time(&date_stamp, &0);
-            let date_string = format(&date_time);
+            let date_string = self.config.options.date_format.format(&date_time);
 
             out_s.push_str("<div class=\"app_footer\">");
 
@@ -1194,7 +1390,7 @@ impl<'a> BalloonFormatter<&'a Message> for HTML<'a> {
             // Parse the estimated end time from the message's query string
             let date_stamp = date_str.parse::<f64>().unwrap_or(0.) as i64 * TIMESTAMP_FACTOR;
             let date_time = get_local_time(&date_stamp, &0);
-            let date_string = format(&date_time);
+            let date_string = self.config.options.date_format.format(&date_time);
 
             out_s.push_str("<div class=\"app_footer\">");
 
@@ -1209,7 +1405,7 @@ impl<'a> BalloonFormatter<&'a Message> for HTML<'a> {
             // Parse the estimated end time from the message's query string
             let date_stamp = date_str.parse::<f64>().unwrap_or(0.) as i64 * TIMESTAMP_FACTOR;
             let date_time = get_local_time(&date_stamp, &0);
-            let date_string = format(&date_time);
+            let date_string = self.config.options.date_format.format(&date_time);
 
             out_s.push_str("<div class=\"app_footer\">");
 
@@ -1236,7 +1432,11 @@ impl<'a> BalloonFormatter<&'a Message> for HTML<'a> {
 
 impl<'a> HTML<'a> {
     fn get_time(&self, message: &Message) -> String {
-        let mut date = format(&message.date(&self.config.offset));
+        let mut date = self
+            .config
+            .options
+            .date_format
+            .format(&message.date(&self.config.offset));
         let read_after = message.time_until_read(&self.config.offset);
         if let Some(time) = read_after {
             if !time.is_empty() {
@@ -1260,138 +1460,540 @@ impl<'a> HTML<'a> {
         }
     }
 
-    fn write_headers(path: &Path) {
-        // Write file header
-        HTML::write_to_file(path, HEADER);
+    /// Inline an attachment as a `data:` URI, if embedding is enabled and the file is under the size cap
+    ///
+    /// Reads the copied file if there is one, so converted attachments are embedded in their compatible format.
+    fn data_uri(&self, attachment: &Attachment, mime_type: &str) -> Option<String> {
+        let max_size = self.config.options.embed_attachments?;
+
+        let bytes = match &attachment.copied_path {
+            Some(path) => {
+                if metadata(self.config.output_path(path)).ok()?.len() > max_size {
+                    return None;
+                }
+                self.config.read_output(path).ok()?
+            }
+            None => {
+                let path = attachment.resolved_attachment_path(
+                    &self.config.options.platform,
+                    &self.config.options.db_path,
+                    self.config.options.attachment_root.as_deref(),
+                )?;
+                if metadata(path).ok()?.len() > max_size {
+                    return None;
+                }
+                attachment
+                    .as_bytes(
+                        &self.config.options.platform,
+                        &self.config.options.db_path,
+                        self.config.options.attachment_root.as_deref(),
+                    )
+                    .ok()??
+            }
+        };
+
+        // Converted files no longer match the MIME type stored in the database
+        let mime_type = match attachment
+            .copied_path
+            .as_ref()
+            .and_then(|path| path.extension())
+            .and_then(|extension| extension.to_str())
+        {
+            Some("jpeg" | "jpg") => "image/jpeg",
+            Some("png") => "image/png",
+            Some("gif") => "image/gif",
+            // Drop parameters like `codecs`, since the URI cannot contain spaces
+            _ => mime_type.split(';').next().unwrap_or(mime_type).trim(),
+        };
 
-        // Write CSS
-        HTML::write_to_file(path, "<style>\n");
-        HTML::write_to_file(path, STYLE);
-        HTML::write_to_file(path, "\n</style>");
-        HTML::write_to_file(path, "\n</head>\n<body>\n");
+        Some(format!(
+            "data:{mime_type};base64,{}",
+            STANDARD.encode(bytes)
+        ))
+    }
+
+    /// Start a new page for a conversation if a message of `len` bytes does not fit on the current one
+    ///
+    /// Orphaned messages are never paginated.
+    fn paginate(&mut self, message: &Message, len: usize) {
+        let config = self.config;
+        let (pagination, chatroom, id) =
+            match (&config.options.paginate, config.conversation(message)) {
+                (Some(pagination), Some((chatroom, id))) => (pagination, chatroom, *id),
+                _ => return,
+            };
+        let current = self.get_or_create_file(message).to_path_buf();
+        let month = message.date(&config.offset).ok().map(|date| {
+            config
+                .options
+                .date_format
+                .in_zone(&date)
+                .format("%Y-%m")
+                .to_string()
+        });
+
+        let page = self
+            .pages
+            .entry(id)
+            .or_insert_with(|| Page::new(current.clone()));
+        if page.is_full(pagination, &month, len) {
+            let previous = (page.number > 1).then(|| page.path(page.number - 1));
+            let next = page.path(page.number + 1);
+
+            // Close the current page
+            config.write_to_file(
+                &current,
+                &HTML::page_navigation(previous.as_deref(), page.number, Some(&next)),
+            );
+            config.write_to_file(&current, &self.templates.footer);
+            config.close_output(&current);
+
+            // Open the next page
+            page.number += 1;
+            page.messages = 0;
+            page.bytes = 0;
+            let name = config.conversation_name(chatroom);
+            config.write_to_file(&next, &self.templates.header(&sanitize_html(&name)));
+            config.write_to_file(
+                &next,
+                &HTML::search_panel(config.options.search_index, &page.first),
+            );
+            config.write_to_file(
+                &next,
+                &HTML::page_navigation(Some(&current), page.number, None),
+            );
+            self.files.insert(id, next);
+        }
+        page.messages += 1;
+        page.bytes += len;
+        page.month = month;
+    }
+
+    /// Build the links between pages of a conversation
+    fn page_navigation(previous: Option<&Path>, number: usize, next: Option<&Path>) -> String {
+        let link = |path: &Path, text: &str| {
+            let filename = path
+                .file_name()
+                .map(|name| name.to_string_lossy())
+                .unwrap_or_default();
+            format!("<a href=\"{}\">{text}</a>", sanitize_url(&filename))
+        };
+        format!(
+            "<div class=\"pagination\">{}<span>Page {number}</span>{}</div>\n",
+            previous
+                .map(|path| link(path, "← Previous page"))
+                .unwrap_or_default(),
+            next.map(|path| link(path, "Next page →"))
+                .unwrap_or_default(),
+        )
+    }
+
+    /// Point thread links at the right file when a reply is on a different page or in a different time period than the message it replies to
+    ///
+    /// Replies are rendered both in the thread under the original message and on their own, and each copy links to the other.
+    fn link_threads(&mut self, message: &Message, formatted: String) -> String {
+        if self.config.options.paginate.is_none() && self.config.options.split_by.is_none() {
+            return formatted;
+        }
+        let current = self.get_or_create_file(message).to_path_buf();
+        if message.has_replies() {
+            self.threads.insert(message.guid.clone(), current.clone());
+        }
+
+        let original = match message
+            .thread_originator_guid
+            .as_ref()
+            .and_then(|guid| self.threads.get(guid))
+        {
+            Some(original) if *original != current => original,
+            _ => return formatted,
+        };
+        let href = |path: &Path| {
+            path.file_name()
+                .map(|name| sanitize_url(&name.to_string_lossy()).to_string())
+                .unwrap_or_default()
+        };
+
+        // The original message was already written, so its page needs to be updated once the export finishes
+        self.links.entry(original.clone()).or_default().push((
+            format!("href=\"#r-{}\"", message.guid),
+            format!("href=\"{}#r-{}\"", href(&current), message.guid),
+        ));
+        formatted.replace(
+            &format!("href=\"#{}\"", message.guid),
+            &format!("href=\"{}#{}\"", href(original), message.guid),
+        )
     }
 
-    fn edited_to_html(&self, timestamp: &str, text: &str, last: bool) -> String {
-        let tag = if last { "tfoot" } else { "tbody" };
-        format!("<{tag}><tr><td><span class=\"timestamp\">{timestamp}</span></td><td>{text}</td></tr></{tag}>")
+    /// Rewrite thread links that point to replies on later pages
+    fn rewrite_links(&self) {
+        self.links
+            .iter()
+            .for_each(|(path, links)| match self.config.read_output(path) {
+                Ok(page) => {
+                    let mut page = String::from_utf8_lossy(&page).into_owned();
+                    links
+                        .iter()
+                        .for_each(|(from, to)| page = page.replace(from, to));
+                    if let Err(why) = self.config.replace_output(path, page.as_bytes()) {
+                        eprintln!("Unable to write to {path:?}: {why:?}");
+                    }
+                }
+                Err(why) => eprintln!("Unable to read {path:?}: {why:?}"),
+            });
     }
 
-    fn balloon_to_html(
-        &self,
-        balloon: &AppMessage,
-        bundle_id: &str,
-        attachments: &mut [Attachment],
-        message: &Message,
-    ) -> String {
-        let mut out_s = String::new();
-        if let Some(url) = balloon.url {
-            out_s.push_str("<a href=\"");
-            out_s.push_str(url);
-            out_s.push_str("\">");
+    /// Get the path to the first page of the conversation a message was written to
+    fn first_page(&mut self, message: &Message) -> PathBuf {
+        let mut path = self.get_or_create_file(message).to_path_buf();
+        if let Some(page) = self
+            .config
+            .conversation(message)
+            .and_then(|(_, id)| self.pages.get(id))
+        {
+            path.clone_from(&page.first);
         }
-        out_s.push_str("<div class=\"app_header\">");
+        path
+    }
 
-        // Image
-        if let Some(image) = balloon.image {
-            out_s.push_str("<img src=\"");
-            out_s.push_str(image);
-            out_s.push_str("\">");
-        } else if let Some(attachment) = attachments.get_mut(0) {
-            out_s.push_str(
-                &self
-                    .format_attachment(attachment, message)
-                    .unwrap_or_default(),
+    /// Update the statistics for the conversation a message was written to
+    fn summarize(&mut self, message: &Message) {
+        // Paginated conversations are listed by their first page
+        let path = self.first_page(message);
+        let config = self.config;
+        // Split conversations are listed once for each time period
+        let period = config
+            .options
+            .split_by
+            .as_ref()
+            .and(path.file_stem())
+            .map(|stem| format!(" ({})", stem.to_string_lossy()))
+            .unwrap_or_default();
+        let summary =
+            self.summaries
+                .entry(path)
+                .or_insert_with(|| match config.conversation(message) {
+                    Some((chatroom, _)) => Summary {
+                        name: format!("{}{period}", config.conversation_name(chatroom)),
+                        participants: config
+                            .chatroom_participants
+                            .get(&chatroom.rowid)
+                            .into_iter()
+                            .flatten()
+                            .map(|handle_id| config.who(Some(*handle_id), false, &None).to_string())
+                            .collect(),
+                        ..Default::default()
+                    },
+                    None => Summary {
+                        name: format!("{ORPHANED}{period}"),
+                        ..Default::default()
+                    },
+                });
+
+        if !message.is_from_me {
+            summary.participants.insert(
+                config
+                    .who(
+                        message.handle_id,
+                        message.is_from_me,
+                        &message.destination_caller_id,
+                    )
+                    .to_string(),
             );
         }
+        summary.messages += 1;
+        summary.attachments += usize::try_from(message.num_attachments).unwrap_or(0);
+        summary.first = Some(
+            summary
+                .first
+                .map_or(message.date, |date| date.min(message.date)),
+        );
+        summary.last = Some(
+            summary
+                .last
+                .map_or(message.date, |date| date.max(message.date)),
+        );
+    }
 
-        // Name
-        out_s.push_str("<div class=\"name\">");
-        out_s.push_str(balloon.app_name.unwrap_or(bundle_id));
-        out_s.push_str("</div>");
+    /// Remove the footer from a file written by a previous export, so more messages can be added to it
+    fn reopen(config: &Config, path: &Path, footer: &str) {
+        if let Err(why) = config.truncate_output(path, footer.as_bytes()) {
+            eprintln!("Unable to reopen {path:?}: {why:?}");
+        }
+    }
 
-        // Title
-        if let Some(title) = balloon.title {
-            out_s.push_str("<div class=\"image_title\">");
-            out_s.push_str(title);
-            out_s.push_str("</div>");
+    /// Write the start of a new file, up to where messages are written
+    fn open_file(config: &Config, templates: &Templates, path: &Path, title: &str) {
+        config.write_to_file(path, &templates.header(title));
+        config.write_to_file(path, &HTML::search_panel(config.options.search_index, path));
+    }
+
+    /// Build the search controls for a page, which load the search index for its conversation
+    fn search_panel(enabled: bool, first: &Path) -> String {
+        if !enabled {
+            return String::new();
+        }
+        let index = SearchIndex::path(first)
+            .file_name()
+            .map(|name| sanitize_url(&name.to_string_lossy()).to_string())
+            .unwrap_or_default();
+        format!(
+            "<div class=\"search\">\n<input class=\"search_query\" type=\"search\" placeholder=\"Search messages\">\n<select class=\"search_sender\"><option value=\"\">Everyone</option></select>\n<label><input class=\"search_attachments\" type=\"checkbox\"> With attachments</label>\n<label>Jump to <input class=\"search_date\" type=\"date\"></label>\n<div class=\"search_results\"></div>\n</div>\n<script>\n{SEARCH_SCRIPT}</script>\n<script src=\"{index}\" defer></script>\n"
+        )
+    }
+
+    /// Add a message to the search index for its conversation, wrapping it so search results can find it on the page
+    fn index_message(&mut self, message: &Message, formatted: String) -> String {
+        if !self.config.options.search_index {
+            return formatted;
+        }
+        let current = self.get_or_create_file(message).to_path_buf();
+        let first = self.first_page(message);
+        let path = SearchIndex::path(&first);
+        let config = self.config;
+
+        let index = self.search.entry(first).or_insert_with(|| {
+            // Add to the index if an incremental export already wrote one for the conversation
+            SearchIndex::reopen(&path).unwrap_or_default()
+        });
+        // Open the index the first time a message is written to the conversation
+        if index.pages.is_empty() {
+            self.config.write_to_file(&path, "searchIndex([\n");
+        }
+        config.record(message, &path);
+        let sender = index.sender(config.who(
+            message.handle_id,
+            message.is_from_me,
+            &message.destination_caller_id,
+        ));
+        let page = index.page(
+            current
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default(),
+        );
+        // Attachments are represented by placeholder characters in the message text
+        let text = message
+            .text
+            .as_deref()
+            .unwrap_or_default()
+            .replace(['\u{FFFC}', '\u{FFFD}'], "");
+        self.config.write_to_file(
+            &path,
+            &format!(
+                "[{},{sender},{},{},{page},{}],\n",
+                message.rowid,
+                message.date / TIMESTAMP_FACTOR + config.offset,
+                u8::from(message.num_attachments > 0),
+                serde_json::to_string(&text).unwrap_or_default(),
+            ),
+        );
+
+        format!(
+            "<div class=\"searchable\" id=\"m-{}\">\n{formatted}</div>\n",
+            message.rowid
+        )
+    }
+
+    /// Close each search index with the names of the senders and pages its entries refer to
+    fn write_search_indexes(&self) {
+        self.search.iter().for_each(|(first, index)| {
+            self.config.write_to_file(
+                &SearchIndex::path(first),
+                &format!(
+                    "], {}, {});\n",
+                    serde_json::to_string(&index.senders).unwrap_or_default(),
+                    serde_json::to_string(&index.pages).unwrap_or_default(),
+                ),
+            );
+        });
+    }
+
+    /// Write a page that links to every exported file, sortable and filterable client-side
+    fn write_index(&self) {
+        let mut path = self.config.options.export_path.clone();
+        path.push(INDEX);
+        path.set_extension("html");
+
+        // The orphaned file is always written, even if it is empty, unless the export is split by time period
+        let orphaned = Summary {
+            name: ORPHANED.to_string(),
+            ..Default::default()
+        };
+        let mut rows: Vec<(&PathBuf, &Summary)> = self.summaries.iter().collect();
+        if !self.summaries.contains_key(&self.orphaned) && self.config.options.split_by.is_none() {
+            rows.push((&self.orphaned, &orphaned));
         }
+        // Show the most recently active conversations first
+        rows.sort_by(|(_, a), (_, b)| b.last.cmp(&a.last).then_with(|| a.name.cmp(&b.name)));
 
-        // Subtitle
-        if let Some(subtitle) = balloon.subtitle {
-            out_s.push_str("<div class=\"image_subtitle\">");
-            out_s.push_str(subtitle);
-            out_s.push_str("</div>");
+        let mut index = self.templates.header("Conversations");
+        index.push_str(
+            "<input class=\"index_filter\" type=\"search\" placeholder=\"Filter conversations\">\n",
+        );
+        index.push_str("<table class=\"index\">\n<thead><tr><th>Conversation</th><th>Participants</th><th data-type=\"number\">Messages</th><th data-type=\"number\">Attachments</th><th data-type=\"number\">First message</th><th data-type=\"number\">Last message</th></tr></thead>\n<tbody>\n");
+        rows.iter()
+            .for_each(|(path, summary)| index.push_str(&self.index_row(path, summary)));
+        index.push_str("</tbody>\n</table>\n<script>\n");
+        index.push_str(INDEX_SCRIPT);
+        index.push_str("</script>\n");
+        index.push_str(&self.templates.footer);
+
+        if let Err(why) = self.config.replace_output(&path, index.as_bytes()) {
+            eprintln!("Unable to write to {path:?}: {why:?}");
         }
+    }
 
-        // ldtext
-        if let Some(ldtext) = balloon.ldtext {
-            out_s.push_str("<div class=\"ldtext\">");
-            out_s.push_str(ldtext);
-            out_s.push_str("</div>");
+    /// Build the index table row for an exported file
+    fn index_row(&self, path: &Path, summary: &Summary) -> String {
+        // Split exports write each file to a folder for its conversation
+        let filename = path
+            .strip_prefix(&self.config.options.export_path)
+            .ok()
+            .or(path.file_name().map(Path::new))
+            .map(|relative| {
+                relative
+                    .components()
+                    .map(|component| component.as_os_str().to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join("/")
+            })
+            .unwrap_or_default();
+        let participants = summary
+            .participants
+            .iter()
+            .map(|participant| sanitize_html(participant))
+            .collect::<Vec<_>>()
+            .join(", ");
+        format!(
+            "<tr><td><a href=\"{}\">{}</a></td><td>{participants}</td><td>{}</td><td>{}</td>{}{}</tr>\n",
+            sanitize_url(&filename),
+            sanitize_html(&summary.name),
+            summary.messages,
+            summary.attachments,
+            self.index_date(summary.first),
+            self.index_date(summary.last),
+        )
+    }
+
+    /// Build an index table cell for a date, sorting by the raw timestamp
+    fn index_date(&self, date: Option<i64>) -> String {
+        match date {
+            Some(date) => format!(
+                "<td data-sort=\"{date}\">{}</td>",
+                self.config
+                    .options
+                    .date_format
+                    .format(&get_local_time(&date, &self.config.offset))
+            ),
+            None => String::from("<td data-sort=\"0\"></td>"),
         }
+    }
 
-        // Header end, footer begin
-        out_s.push_str("</div>");
+    fn edited_to_html(&self, timestamp: &str, text: &str, last: bool) -> String {
+        let tag = if last { "tfoot" } else { "tbody" };
+        format!("<{tag}><tr><td><span class=\"timestamp\">{timestamp}</span></td><td>{text}</td></tr></{tag}>")
+    }
+
+    /// Render an attachment with the attachment template
+    ///
+    /// `kind` is the name of the section the attachment renders in: `image`, `video`, `audio`, `file`, `unknown`, or `other`.
+    fn attachment_to_html(
+        &self,
+        attachment: &Attachment,
+        kind: &str,
+        media_type: &str,
+        data_uri: Option<String>,
+        embed_path: &str,
+    ) -> String {
+        let inline = if data_uri.is_some() { "true" } else { "" };
+        let lazy = if self.config.options.no_lazy {
+            ""
+        } else {
+            "true"
+        };
+        self.templates.attachment.render(&[
+            (kind, "true"),
+            ("src", data_uri.as_deref().unwrap_or(embed_path)),
+            ("path", embed_path),
+            ("mime_type", media_type),
+            ("filename", attachment.filename()),
+            ("size", &attachment.file_size()),
+            ("inline", inline),
+            ("lazy", lazy),
+        ])
+    }
+
+    fn balloon_to_html(
+        &self,
+        balloon: &AppMessage,
+        bundle_id: &str,
+        attachments: &mut [Attachment],
+        message: &Message,
+    ) -> String {
+        // Only embed an attachment if the balloon has no image of its own
+        let attachment = match (balloon.image, attachments.get_mut(0)) {
+            (None, Some(attachment)) => self
+                .format_attachment(attachment, message)
+                .unwrap_or_default(),
+            _ => String::new(),
+        };
 
         // Only write the footer if there is data to write
-        if balloon.caption.is_some()
+        let footer = if balloon.caption.is_some()
             || balloon.subcaption.is_some()
             || balloon.trailing_caption.is_some()
             || balloon.trailing_subcaption.is_some()
         {
-            out_s.push_str("<div class=\"app_footer\">");
-
-            // Caption
-            if let Some(caption) = balloon.caption {
-                out_s.push_str("<div class=\"caption\">");
-                out_s.push_str(caption);
-                out_s.push_str("</div>");
-            }
-
-            // Subcaption
-            if let Some(subcaption) = balloon.subcaption {
-                out_s.push_str("<div class=\"subcaption\">");
-                out_s.push_str(subcaption);
-                out_s.push_str("</div>");
-            }
-
-            // Trailing Caption
-            if let Some(trailing_caption) = balloon.trailing_caption {
-                out_s.push_str("<div class=\"trailing_caption\">");
-                out_s.push_str(trailing_caption);
-                out_s.push_str("</div>");
-            }
-
-            // Trailing Subcaption
-            if let Some(trailing_subcaption) = balloon.trailing_subcaption {
-                out_s.push_str("<div class=\"trailing_subcaption\">");
-                out_s.push_str(trailing_subcaption);
-                out_s.push_str("</div>");
-            }
+            "true"
+        } else {
+            ""
+        };
 
-            out_s.push_str("</div>");
-        }
-        if balloon.url.is_some() {
-            out_s.push_str("</a>");
-        }
-        out_s
+        self.templates.balloon.render(&[
+            ("url", balloon.url.unwrap_or_default()),
+            ("image", balloon.image.unwrap_or_default()),
+            ("attachment", &attachment),
+            ("name", balloon.app_name.unwrap_or(bundle_id)),
+            ("title", balloon.title.unwrap_or_default()),
+            ("subtitle", balloon.subtitle.unwrap_or_default()),
+            ("ldtext", balloon.ldtext.unwrap_or_default()),
+            ("footer", footer),
+            ("caption", balloon.caption.unwrap_or_default()),
+            ("subcaption", balloon.subcaption.unwrap_or_default()),
+            (
+                "trailing_caption",
+                balloon.trailing_caption.unwrap_or_default(),
+            ),
+            (
+                "trailing_subcaption",
+                balloon.trailing_subcaption.unwrap_or_default(),
+            ),
+        ])
     }
 }
 
 #[cfg(test)]
 mod tests {
     use std::{
-        env::{current_dir, set_var},
-        path::PathBuf,
+        collections::BTreeSet,
+        env::{current_dir, set_var, temp_dir},
+        fs::{copy, create_dir_all, read_to_string, remove_dir_all, remove_file},
+        path::{Path, PathBuf},
     };
 
     use crate::{
-        app::attachment_manager::AttachmentManager, exporters::exporter::Writer, Config, Exporter,
-        Options, HTML,
+        app::{pagination::Pagination, period::Period},
+        exporters::{
+            exporter::Writer,
+            html::{Page, Summary},
+            template::Template,
+        },
+        Config, Exporter, Options, HTML,
     };
     use imessage_database::{
-        tables::{attachment::Attachment, messages::Message},
-        util::{dirs::default_db_path, platform::Platform, query_context::QueryContext},
+        tables::{attachment::Attachment, chat::Chat, messages::Message},
+        util::platform::Platform,
     };
 
     pub fn blank() -> Message {
@@ -1425,23 +2027,6 @@ mod tests {
         }
     }
 
-    pub fn fake_options() -> Options {
-        Options {
-            db_path: default_db_path(),
-            attachment_root: None,
-            attachment_manager: AttachmentManager::Disabled,
-            diagnostic: false,
-            export_type: None,
-            export_path: PathBuf::new(),
-            query_context: QueryContext::default(),
-            no_lazy: false,
-            custom_name: None,
-            use_caller_id: false,
-            platform: Platform::macOS,
-            ignore_disk_space: false,
-        }
-    }
-
     pub fn fake_attachment() -> Attachment {
         Attachment {
             rowid: 0,
@@ -1458,7 +2043,7 @@ mod tests {
 
     #[test]
     fn can_create() {
-        let options = fake_options();
+        let options = Options::fake();
         let config = Config::new(options).unwrap();
         let exporter = HTML::new(&config);
         assert_eq!(exporter.files.len(), 0);
@@ -1470,7 +2055,7 @@ mod tests {
         set_var("TZ", "PST");
 
         // Create exporter
-        let options = fake_options();
+        let options = Options::fake();
         let config = Config::new(options).unwrap();
         let exporter = HTML::new(&config);
 
@@ -1489,13 +2074,40 @@ mod tests {
         );
     }
 
+    #[test]
+    fn can_get_time_in_time_zone() {
+        // Set timezone to PST for consistent Local time
+        set_var("TZ", "PST");
+
+        // Create exporter
+        let mut options = Options::fake();
+        options.date_format.time_zone = "Asia/Tokyo".parse().ok();
+        options.date_format.format = "%Y-%m-%d %H:%M %Z".to_string();
+        let config = Config::new(options).unwrap();
+        let exporter = HTML::new(&config);
+
+        // Create fake message
+        let mut message = blank();
+        // May 17, 2022  8:29:42 PM
+        message.date = 674526582885055488;
+        // May 17, 2022  8:29:42 PM
+        message.date_delivered = 674526582885055488;
+        // May 17, 2022  9:30:31 PM
+        message.date_read = 674530231992568192;
+
+        assert_eq!(
+            exporter.get_time(&message),
+            "2022-05-18 09:29 JST (Read by you after 1 hour, 49 seconds)"
+        );
+    }
+
     #[test]
     fn can_get_time_invalid() {
         // Set timezone to PST for consistent Local time
         set_var("TZ", "PST");
 
         // Create exporter
-        let options = fake_options();
+        let options = Options::fake();
         let config = Config::new(options).unwrap();
         let exporter = HTML::new(&config);
 
@@ -1513,7 +2125,7 @@ mod tests {
     #[test]
     fn can_add_line_no_indent() {
         // Create exporter
-        let options = fake_options();
+        let options = Options::fake();
         let config = Config::new(options).unwrap();
         let exporter = HTML::new(&config);
 
@@ -1527,7 +2139,7 @@ mod tests {
     #[test]
     fn can_add_line() {
         // Create exporter
-        let options = fake_options();
+        let options = Options::fake();
         let config = Config::new(options).unwrap();
         let exporter = HTML::new(&config);
 
@@ -1541,7 +2153,7 @@ mod tests {
     #[test]
     fn can_add_line_pre_post() {
         // Create exporter
-        let options = fake_options();
+        let options = Options::fake();
         let config = Config::new(options).unwrap();
         let exporter = HTML::new(&config);
 
@@ -1558,7 +2170,7 @@ mod tests {
         set_var("TZ", "PST");
 
         // Create exporter
-        let options = fake_options();
+        let options = Options::fake();
         let config = Config::new(options).unwrap();
         let exporter = HTML::new(&config);
 
@@ -1575,13 +2187,38 @@ mod tests {
         assert_eq!(actual, expected);
     }
 
+    #[test]
+    fn can_format_html_from_me_custom_template() {
+        // Set timezone to PST for consistent Local time
+        set_var("TZ", "PST");
+
+        // Create exporter
+        let options = Options::fake();
+        let config = Config::new(options).unwrap();
+        let mut exporter = HTML::new(&config);
+        exporter.templates.message =
+            Template::new("<article class=\"{{direction}}\">{{ sender }}: {{body}}</article>\n");
+
+        let mut message = blank();
+        // May 17, 2022  8:29:42 PM
+        message.date = 674526582885055488;
+        message.text = Some("Hello world".to_string());
+        message.is_from_me = true;
+        message.chat_id = Some(0);
+
+        let actual = exporter.format_message(&message, 0).unwrap();
+        let expected = "<article class=\"sent iMessage\">Me: <hr><div class=\"message_part\">\n<span class=\"bubble\">Hello world</span>\n</div>\n</article>\n";
+
+        assert_eq!(actual, expected);
+    }
+
     #[test]
     fn can_format_html_message_with_html() {
         // Set timezone to PST for consistent Local time
         set_var("TZ", "PST");
 
         // Create exporter
-        let options = fake_options();
+        let options = Options::fake();
         let config = Config::new(options).unwrap();
         let exporter = HTML::new(&config);
 
@@ -1604,7 +2241,7 @@ mod tests {
         set_var("TZ", "PST");
 
         // Create exporter
-        let options = fake_options();
+        let options = Options::fake();
         let config = Config::new(options).unwrap();
         let exporter = HTML::new(&config);
 
@@ -1627,7 +2264,7 @@ mod tests {
         set_var("TZ", "PST");
 
         // Create exporter
-        let options = fake_options();
+        let options = Options::fake();
         let config = Config::new(options).unwrap();
         let exporter = HTML::new(&config);
 
@@ -1652,7 +2289,7 @@ mod tests {
         set_var("TZ", "PST");
 
         // Create exporter
-        let options = fake_options();
+        let options = Options::fake();
         let mut config = Config::new(options).unwrap();
         config
             .participants
@@ -1677,7 +2314,7 @@ mod tests {
         set_var("TZ", "PST");
 
         // Create exporter
-        let options = fake_options();
+        let options = Options::fake();
         let mut config = Config::new(options).unwrap();
         config
             .participants
@@ -1707,7 +2344,7 @@ mod tests {
         set_var("TZ", "PST");
 
         // Create exporter
-        let mut options = fake_options();
+        let mut options = Options::fake();
         options.custom_name = Some("Name".to_string());
         let mut config = Config::new(options).unwrap();
         config
@@ -1738,7 +2375,7 @@ mod tests {
         set_var("TZ", "PST");
 
         // Create exporter
-        let options = fake_options();
+        let options = Options::fake();
         let config = Config::new(options).unwrap();
         let exporter = HTML::new(&config);
 
@@ -1759,7 +2396,7 @@ mod tests {
         set_var("TZ", "PST");
 
         // Create exporter
-        let options = fake_options();
+        let options = Options::fake();
         let config = Config::new(options).unwrap();
         let exporter = HTML::new(&config);
 
@@ -1780,7 +2417,7 @@ mod tests {
         set_var("TZ", "PST");
 
         // Create exporter
-        let mut options = fake_options();
+        let mut options = Options::fake();
         options.custom_name = Some("Name".to_string());
         let config = Config::new(options).unwrap();
         let exporter = HTML::new(&config);
@@ -1802,7 +2439,7 @@ mod tests {
         set_var("TZ", "PST");
 
         // Create exporter
-        let options = fake_options();
+        let options = Options::fake();
         let config = Config::new(options).unwrap();
         let exporter = HTML::new(&config);
 
@@ -1824,7 +2461,7 @@ mod tests {
         set_var("TZ", "PST");
 
         // Create exporter
-        let options = fake_options();
+        let options = Options::fake();
         let mut config = Config::new(options).unwrap();
         config
             .participants
@@ -1847,7 +2484,7 @@ mod tests {
     #[test]
     fn can_format_html_attachment_macos() {
         // Create exporter
-        let options = fake_options();
+        let options = Options::fake();
         let config = Config::new(options).unwrap();
         let exporter = HTML::new(&config);
 
@@ -1865,7 +2502,7 @@ mod tests {
     #[test]
     fn can_format_html_attachment_macos_invalid() {
         // Create exporter
-        let options = fake_options();
+        let options = Options::fake();
         let config = Config::new(options).unwrap();
         let exporter = HTML::new(&config);
 
@@ -1882,7 +2519,7 @@ mod tests {
     #[test]
     fn can_format_html_attachment_ios() {
         // Create exporter
-        let options = fake_options();
+        let options = Options::fake();
         let mut config = Config::new(options).unwrap();
         config.options.no_lazy = true;
         config.options.platform = Platform::iOS;
@@ -1901,7 +2538,7 @@ mod tests {
     #[test]
     fn can_format_html_attachment_ios_invalid() {
         // Create exporter
-        let options = fake_options();
+        let options = Options::fake();
         let config = Config::new(options).unwrap();
         let exporter = HTML::new(&config);
 
@@ -1918,7 +2555,7 @@ mod tests {
     #[test]
     fn can_format_html_attachment_sticker() {
         // Create exporter
-        let mut options = fake_options();
+        let mut options = Options::fake();
         options.export_path = current_dir().unwrap().parent().unwrap().to_path_buf();
 
         let config = Config::new(options).unwrap();
@@ -1942,14 +2579,505 @@ mod tests {
 
         assert_eq!(actual, "<img src=\"imessage-database/test_data/stickers/outline.heic\" loading=\"lazy\">\n<div class=\"sticker_effect\">Sent with Outline effect</div>");
     }
+
+    #[test]
+    fn can_format_html_attachment_embedded() {
+        // Create exporter
+        let mut options = Options::fake();
+        options.embed_attachments = Some(1024 * 1024);
+        let config = Config::new(options).unwrap();
+        let exporter = HTML::new(&config);
+
+        let message = blank();
+
+        let mut attachment = fake_attachment();
+        let path = current_dir()
+            .unwrap()
+            .parent()
+            .unwrap()
+            .join("imessage-database/test_data/stickers/outline.heic");
+        attachment.copied_path = Some(path);
+
+        let actual = exporter
+            .format_attachment(&mut attachment, &message)
+            .unwrap();
+
+        assert!(actual.starts_with("<img src=\"data:image/png;base64,AAAA"));
+        assert!(actual.ends_with("\" loading=\"lazy\">"));
+    }
+
+    #[test]
+    fn can_format_html_attachment_embedded_converted() {
+        // Create exporter
+        let mut options = Options::fake();
+        options.embed_attachments = Some(1024 * 1024);
+        let config = Config::new(options).unwrap();
+        let exporter = HTML::new(&config);
+
+        let mut attachment = fake_attachment();
+        attachment.mime_type = Some("image/heic".to_string());
+
+        // Pretend the attachment was converted to a JPEG
+        let converted = temp_dir().join("imessage-exporter-embed-test.jpeg");
+        copy("Cargo.toml", &converted).unwrap();
+        attachment.copied_path = Some(converted.clone());
+
+        assert!(exporter
+            .data_uri(&attachment, "image/heic")
+            .unwrap()
+            .starts_with("data:image/jpeg;base64,"));
+
+        remove_file(converted).unwrap();
+    }
+
+    #[test]
+    fn can_format_html_attachment_over_embed_limit() {
+        // Create exporter
+        let mut options = Options::fake();
+        options.embed_attachments = Some(16);
+        let config = Config::new(options).unwrap();
+        let exporter = HTML::new(&config);
+
+        let message = blank();
+
+        let mut attachment = fake_attachment();
+        let path = current_dir()
+            .unwrap()
+            .parent()
+            .unwrap()
+            .join("imessage-database/test_data/stickers/outline.heic");
+        attachment.copied_path = Some(path.clone());
+
+        let actual = exporter
+            .format_attachment(&mut attachment, &message)
+            .unwrap();
+
+        assert_eq!(
+            actual,
+            format!("<img src=\"{}\" loading=\"lazy\">", path.display())
+        );
+    }
+
+    #[test]
+    fn can_index_messages_for_search() {
+        // Create exporter
+        let mut options = Options::fake();
+        options.export_path = temp_dir().join("imessage-exporter-html-search");
+        options.search_index = true;
+        create_dir_all(&options.export_path).unwrap();
+        let mut config = Config::new(options).unwrap();
+        config.participants.insert(2, "Person 2".to_string());
+        fake_chat(&mut config);
+        let mut exporter = HTML::new(&config);
+
+        // Create fake messages
+        let mut first = blank();
+        first.rowid = 1;
+        first.chat_id = Some(3);
+        first.handle_id = Some(2);
+        first.date = 674526582885055488;
+        first.text = Some("\u{FFFC}Look at \"this\"".to_string());
+        first.num_attachments = 1;
+
+        let mut second = blank();
+        second.rowid = 2;
+        second.chat_id = Some(3);
+        second.is_from_me = true;
+        second.date = 674526682885055488;
+        second.text = Some("Nice".to_string());
+
+        assert_eq!(
+            exporter.index_message(&first, "<p>first</p>\n".to_string()),
+            "<div class=\"searchable\" id=\"m-1\">\n<p>first</p>\n</div>\n"
+        );
+        exporter.index_message(&second, String::new());
+        exporter.write_search_indexes();
+
+        let path = config.options.export_path.join("Book Club - 3.search.js");
+        assert_eq!(
+            read_to_string(&path).unwrap(),
+            "searchIndex([\n[1,0,1652833782,1,0,\"Look at \\\"this\\\"\"],\n[2,1,1652833882,0,0,\"Nice\"],\n], [\"Person 2\",\"Me\"], [\"Book Club - 3.html\"]);\n"
+        );
+
+        remove_dir_all(&config.options.export_path).unwrap();
+    }
+
+    #[test]
+    fn can_reopen_search_index() {
+        // Create exporter
+        let mut options = Options::fake();
+        options.export_path = temp_dir().join("imessage-exporter-html-search-reopen");
+        options.search_index = true;
+        create_dir_all(&options.export_path).unwrap();
+        let mut config = Config::new(options).unwrap();
+        config.participants.insert(2, "Person 2".to_string());
+        fake_chat(&mut config);
+
+        // Create fake messages
+        let mut first = blank();
+        first.rowid = 1;
+        first.chat_id = Some(3);
+        first.handle_id = Some(2);
+        first.date = 674526582885055488;
+        first.text = Some("Hello".to_string());
+
+        let mut second = blank();
+        second.rowid = 2;
+        second.chat_id = Some(3);
+        second.is_from_me = true;
+        second.date = 674526682885055488;
+        second.text = Some("Nice".to_string());
+
+        // Write the index in one export, then add to it in the next
+        let mut exporter = HTML::new(&config);
+        exporter.index_message(&first, String::new());
+        exporter.write_search_indexes();
+
+        let mut exporter = HTML::new(&config);
+        exporter.index_message(&second, String::new());
+        exporter.write_search_indexes();
+
+        let path = config.options.export_path.join("Book Club - 3.search.js");
+        assert_eq!(
+            read_to_string(&path).unwrap(),
+            "searchIndex([\n[1,0,1652833782,0,0,\"Hello\"],\n[2,1,1652833882,0,0,\"Nice\"],\n], [\"Person 2\",\"Me\"], [\"Book Club - 3.html\"]);\n"
+        );
+
+        remove_dir_all(&config.options.export_path).unwrap();
+    }
+
+    #[test]
+    fn can_summarize_messages() {
+        // Create exporter
+        let mut options = Options::fake();
+        options.export_path = temp_dir().join("imessage-exporter-html-summary");
+        create_dir_all(&options.export_path).unwrap();
+        let mut config = Config::new(options).unwrap();
+        config.participants.insert(1, "Person 1".to_string());
+        config.participants.insert(2, "Person 2".to_string());
+        config.chatrooms.insert(
+            3,
+            Chat {
+                rowid: 3,
+                chat_identifier: "chat3".to_string(),
+                service_name: Some("iMessage".to_string()),
+                display_name: Some("Book Club".to_string()),
+            },
+        );
+        config.real_chatrooms.insert(3, 3);
+        config.chatroom_participants.insert(3, BTreeSet::from([1]));
+        let mut exporter = HTML::new(&config);
+
+        // Create fake messages
+        let mut first = blank();
+        first.chat_id = Some(3);
+        first.handle_id = Some(2);
+        first.date = 674526582885055488;
+        first.num_attachments = 2;
+
+        let mut last = blank();
+        last.chat_id = Some(3);
+        last.is_from_me = true;
+        last.date = 674526682885055488;
+
+        exporter.summarize(&last);
+        exporter.summarize(&first);
+
+        let path = config.options.export_path.join("Book Club - 3.html");
+        assert_eq!(
+            exporter.summaries.get(&path),
+            Some(&Summary {
+                name: "Book Club".to_string(),
+                participants: BTreeSet::from(["Person 1".to_string(), "Person 2".to_string()]),
+                messages: 2,
+                attachments: 2,
+                first: Some(674526582885055488),
+                last: Some(674526682885055488),
+            })
+        );
+
+        remove_dir_all(&config.options.export_path).unwrap();
+    }
+
+    #[test]
+    fn can_format_index_row() {
+        // Set timezone to PST for consistent Local time
+        set_var("TZ", "PST");
+
+        // Create exporter
+        let options = Options::fake();
+        let config = Config::new(options).unwrap();
+        let exporter = HTML::new(&config);
+
+        let summary = Summary {
+            name: "Book Club <3".to_string(),
+            participants: BTreeSet::from(["A & B".to_string(), "C".to_string()]),
+            messages: 10,
+            attachments: 1,
+            first: Some(674526582885055488),
+            last: None,
+        };
+
+        let path = config.options.export_path.join("Book Club #3.html");
+        let actual = exporter.index_row(&path, &summary);
+        let expected = "<tr><td><a href=\"Book%20Club%20%233.html\">Book Club &lt;3</a></td><td>A &amp; B, C</td><td>10</td><td>1</td><td data-sort=\"674526582885055488\">May 17, 2022  5:29:42 PM</td><td data-sort=\"0\"></td></tr>\n";
+
+        assert_eq!(actual, expected);
+    }
+
+    fn fake_chat(config: &mut Config) {
+        config.chatrooms.insert(
+            3,
+            Chat {
+                rowid: 3,
+                chat_identifier: "chat3".to_string(),
+                service_name: Some("iMessage".to_string()),
+                display_name: Some("Book Club".to_string()),
+            },
+        );
+        config.real_chatrooms.insert(3, 3);
+    }
+
+    #[test]
+    fn can_get_page_path() {
+        let page = Page::new(PathBuf::from("export/Book Club - 3.html"));
+        assert_eq!(page.path(1), PathBuf::from("export/Book Club - 3.html"));
+        assert_eq!(
+            page.path(2),
+            PathBuf::from("export/Book Club - 3 - page 2.html")
+        );
+    }
+
+    #[test]
+    fn can_fill_page_by_messages() {
+        let mut page = Page::new(PathBuf::from("a.html"));
+        let pagination = Pagination::Messages(2);
+        assert!(!page.is_full(&pagination, &None, 10));
+        page.messages = 1;
+        assert!(!page.is_full(&pagination, &None, 10));
+        page.messages = 2;
+        assert!(page.is_full(&pagination, &None, 10));
+    }
+
+    #[test]
+    fn can_fill_page_by_month() {
+        let mut page = Page::new(PathBuf::from("a.html"));
+        let pagination = Pagination::Month;
+        let may = Some("2022-05".to_string());
+        let june = Some("2022-06".to_string());
+        assert!(!page.is_full(&pagination, &june, 10));
+        page.messages = 1;
+        page.month = may.clone();
+        assert!(!page.is_full(&pagination, &may, 10));
+        assert!(page.is_full(&pagination, &june, 10));
+    }
+
+    #[test]
+    fn can_fill_page_by_size() {
+        let mut page = Page::new(PathBuf::from("a.html"));
+        let pagination = Pagination::Size(100);
+        // The first message always fits, even if it is too large
+        assert!(!page.is_full(&pagination, &None, 1000));
+        page.messages = 1;
+        page.bytes = 60;
+        assert!(!page.is_full(&pagination, &None, 40));
+        assert!(page.is_full(&pagination, &None, 41));
+    }
+
+    #[test]
+    fn can_format_page_navigation() {
+        assert_eq!(
+            HTML::page_navigation(
+                Some(Path::new("export/Book Club.html")),
+                2,
+                Some(Path::new("export/Book Club - page 3.html"))
+            ),
+            "<div class=\"pagination\"><a href=\"Book%20Club.html\">← Previous page</a><span>Page 2</span><a href=\"Book%20Club%20-%20page%203.html\">Next page →</a></div>\n"
+        );
+        assert_eq!(
+            HTML::page_navigation(None, 1, None),
+            "<div class=\"pagination\"><span>Page 1</span></div>\n"
+        );
+    }
+
+    #[test]
+    fn can_paginate_conversation() {
+        // Create exporter
+        let mut options = Options::fake();
+        options.export_path = temp_dir().join("imessage-exporter-html-paginate");
+        options.paginate = Some(Pagination::Messages(2));
+        create_dir_all(&options.export_path).unwrap();
+        let mut config = Config::new(options).unwrap();
+        fake_chat(&mut config);
+        let mut exporter = HTML::new(&config);
+
+        let mut message = blank();
+        message.chat_id = Some(3);
+        for _ in 0..3 {
+            exporter.paginate(&message, 10);
+            exporter
+                .config
+                .write_to_file(exporter.get_or_create_file(&message), "<p>message</p>");
+        }
+
+        let first = config.options.export_path.join("Book Club - 3.html");
+        let second = config
+            .options
+            .export_path
+            .join("Book Club - 3 - page 2.html");
+        assert_eq!(exporter.get_or_create_file(&message), second);
+        assert_eq!(exporter.pages.get(&3).unwrap().number, 2);
+        assert_eq!(exporter.pages.get(&3).unwrap().messages, 1);
+
+        let first_page = read_to_string(&first).unwrap();
+        assert_eq!(first_page.matches("<p>message</p>").count(), 2);
+        assert!(first_page.ends_with(&format!(
+            "<a href=\"Book%20Club%20-%203%20-%20page%202.html\">Next page →</a></div>\n{}",
+            exporter.templates.footer
+        )));
+
+        let second_page = read_to_string(&second).unwrap();
+        assert_eq!(second_page.matches("<p>message</p>").count(), 1);
+        assert!(second_page.contains(
+            "<a href=\"Book%20Club%20-%203.html\">← Previous page</a><span>Page 2</span>"
+        ));
+
+        remove_dir_all(&config.options.export_path).unwrap();
+    }
+
+    #[test]
+    fn can_split_conversation_by_period() {
+        // Create exporter
+        let mut options = Options::fake();
+        options.export_path = temp_dir().join("imessage-exporter-html-split");
+        options.split_by = Some(Period::Month);
+        create_dir_all(&options.export_path).unwrap();
+        let mut config = Config::new(options).unwrap();
+        fake_chat(&mut config);
+        let mut exporter = HTML::new(&config);
+
+        let mut message = blank();
+        message.chat_id = Some(3);
+        // May 17, 2022
+        message.date = 674526582885055488;
+        exporter
+            .config
+            .write_to_file(exporter.get_or_create_file(&message), "<p>May</p>");
+        exporter
+            .config
+            .write_to_file(exporter.get_or_create_file(&message), "<p>May</p>");
+        // June 16, 2022
+        message.date += 60 * 60 * 24 * 30 * 1_000_000_000;
+        exporter
+            .config
+            .write_to_file(exporter.get_or_create_file(&message), "<p>June</p>");
+
+        let folder = config.options.export_path.join("Book Club - 3");
+        let may = read_to_string(folder.join("2022-05.html")).unwrap();
+        assert!(may.starts_with(&exporter.templates.header("Book Club")));
+        assert!(may.ends_with(&format!(
+            "<p>May</p><p>May</p>{}",
+            exporter.templates.footer
+        )));
+
+        let june = read_to_string(folder.join("2022-06.html")).unwrap();
+        assert!(june.starts_with(&exporter.templates.header("Book Club")));
+        assert!(june.ends_with("<p>June</p>"));
+        assert_eq!(
+            exporter.get_or_create_file(&message),
+            folder.join("2022-06.html")
+        );
+
+        remove_dir_all(&config.options.export_path).unwrap();
+    }
+
+    #[test]
+    fn can_link_threads_across_pages() {
+        // Create exporter
+        let mut options = Options::fake();
+        options.export_path = temp_dir().join("imessage-exporter-html-threads");
+        options.paginate = Some(Pagination::Messages(1));
+        create_dir_all(&options.export_path).unwrap();
+        let mut config = Config::new(options).unwrap();
+        fake_chat(&mut config);
+        let mut exporter = HTML::new(&config);
+
+        let mut original = blank();
+        original.guid = "original".to_string();
+        original.chat_id = Some(3);
+        original.num_replies = 1;
+
+        let mut reply = blank();
+        reply.guid = "reply".to_string();
+        reply.chat_id = Some(3);
+        reply.thread_originator_guid = Some("original".to_string());
+
+        exporter.paginate(&original, 10);
+        let formatted = exporter.link_threads(
+            &original,
+            "<div class=\"reply\" id=\"reply\"><a href=\"#r-reply\">⇲</a></div>".to_string(),
+        );
+        let first = exporter.get_or_create_file(&original).to_path_buf();
+        exporter.config.write_to_file(&first, &formatted);
+
+        exporter.paginate(&reply, 10);
+        let formatted = exporter.link_threads(
+            &reply,
+            "<div class=\"message\", id=\"r-reply\"><a href=\"#reply\">⇱</a></div>".to_string(),
+        );
+
+        // The reply links back to the thread on the first page
+        assert_eq!(
+            formatted,
+            "<div class=\"message\", id=\"r-reply\"><a href=\"Book%20Club%20-%203.html#reply\">⇱</a></div>"
+        );
+
+        // The thread links forward to the reply on the second page
+        exporter.rewrite_links();
+        assert!(read_to_string(&first)
+            .unwrap()
+            .contains("<a href=\"Book%20Club%20-%203%20-%20page%202.html#r-reply\">⇲</a>"));
+
+        remove_dir_all(&config.options.export_path).unwrap();
+    }
+
+    #[test]
+    fn cant_embed_html_attachment_disabled() {
+        // Create exporter
+        let options = Options::fake();
+        let config = Config::new(options).unwrap();
+        let exporter = HTML::new(&config);
+
+        let mut attachment = fake_attachment();
+        attachment.copied_path = Some(PathBuf::from("Cargo.toml"));
+
+        assert_eq!(exporter.data_uri(&attachment, "image/png"), None);
+    }
+
+    #[test]
+    fn can_embed_html_attachment_without_parameters() {
+        // Create exporter
+        let mut options = Options::fake();
+        options.embed_attachments = Some(1024 * 1024);
+        let config = Config::new(options).unwrap();
+        let exporter = HTML::new(&config);
+
+        let mut attachment = fake_attachment();
+        attachment.copied_path = Some(PathBuf::from("Cargo.toml"));
+
+        assert!(exporter
+            .data_uri(&attachment, "audio/x-caf; codecs=opus")
+            .unwrap()
+            .starts_with("data:audio/x-caf;base64,"));
+    }
 }
 
 #[cfg(test)]
 mod balloon_format_tests {
     use std::env::set_var;
 
-    use super::tests::{blank, fake_options};
-    use crate::{exporters::exporter::BalloonFormatter, Config, Exporter, HTML};
+    use super::tests::blank;
+    use crate::{exporters::exporter::BalloonFormatter, Config, Exporter, Options, HTML};
     use imessage_database::message_types::{
         app::AppMessage,
         app_store::AppStoreMessage,
@@ -1962,7 +3090,7 @@ mod balloon_format_tests {
     #[test]
     fn can_format_html_url() {
         // Create exporter
-        let options = fake_options();
+        let options = Options::fake();
         let config = Config::new(options).unwrap();
         let exporter = HTML::new(&config);
 
@@ -1987,7 +3115,7 @@ mod balloon_format_tests {
     #[test]
     fn can_format_html_url_no_lazy() {
         // Create exporter
-        let mut options = fake_options();
+        let mut options = Options::fake();
         options.no_lazy = true;
         let config = Config::new(options).unwrap();
         let exporter = HTML::new(&config);
@@ -2013,7 +3141,7 @@ mod balloon_format_tests {
     #[test]
     fn can_format_html_music() {
         // Create exporter
-        let options = fake_options();
+        let options = Options::fake();
         let config = Config::new(options).unwrap();
         let exporter = HTML::new(&config);
 
@@ -2034,7 +3162,7 @@ mod balloon_format_tests {
     #[test]
     fn can_format_html_collaboration() {
         // Create exporter
-        let options = fake_options();
+        let options = Options::fake();
         let config = Config::new(options).unwrap();
         let exporter = HTML::new(&config);
 
@@ -2056,7 +3184,7 @@ mod balloon_format_tests {
     #[test]
     fn can_format_html_apple_pay() {
         // Create exporter
-        let options = fake_options();
+        let options = Options::fake();
         let config = Config::new(options).unwrap();
         let exporter = HTML::new(&config);
 
@@ -2082,7 +3210,7 @@ mod balloon_format_tests {
     #[test]
     fn can_format_html_fitness() {
         // Create exporter
-        let options = fake_options();
+        let options = Options::fake();
         let config = Config::new(options).unwrap();
         let exporter = HTML::new(&config);
 
@@ -2108,7 +3236,7 @@ mod balloon_format_tests {
     #[test]
     fn can_format_html_slideshow() {
         // Create exporter
-        let options = fake_options();
+        let options = Options::fake();
         let config = Config::new(options).unwrap();
         let exporter = HTML::new(&config);
 
@@ -2134,7 +3262,7 @@ mod balloon_format_tests {
     #[test]
     fn can_format_html_find_my() {
         // Create exporter
-        let options = fake_options();
+        let options = Options::fake();
         let config = Config::new(options).unwrap();
         let exporter = HTML::new(&config);
 
@@ -2163,7 +3291,7 @@ mod balloon_format_tests {
         set_var("TZ", "PST");
 
         // Create exporter
-        let options = fake_options();
+        let options = Options::fake();
         let config = Config::new(options).unwrap();
         let exporter = HTML::new(&config);
 
@@ -2192,7 +3320,7 @@ mod balloon_format_tests {
         set_var("TZ", "PST");
 
         // Create exporter
-        let options = fake_options();
+        let options = Options::fake();
         let config = Config::new(options).unwrap();
         let exporter = HTML::new(&config);
 
@@ -2221,7 +3349,7 @@ mod balloon_format_tests {
         set_var("TZ", "PST");
 
         // Create exporter
-        let options = fake_options();
+        let options = Options::fake();
         let config = Config::new(options).unwrap();
         let exporter = HTML::new(&config);
 
@@ -2247,7 +3375,7 @@ mod balloon_format_tests {
     #[test]
     fn can_format_html_app_store() {
         // Create exporter
-        let options = fake_options();
+        let options = Options::fake();
         let config = Config::new(options).unwrap();
         let exporter = HTML::new(&config);
 
@@ -2269,7 +3397,7 @@ mod balloon_format_tests {
     #[test]
     fn can_format_html_placemark() {
         // Create exporter
-        let options = fake_options();
+        let options = Options::fake();
         let config = Config::new(options).unwrap();
         let exporter = HTML::new(&config);
 
@@ -2300,7 +3428,7 @@ mod balloon_format_tests {
     #[test]
     fn can_format_html_generic_app() {
         // Create exporter
-        let options = fake_options();
+        let options = Options::fake();
         let config = Config::new(options).unwrap();
         let exporter = HTML::new(&config);
 
@@ -1,19 +1,24 @@
 // File to export database as a jsonl
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     path::{Path, PathBuf},
 };
 
-use serde_json::to_string;
+use serde_json::{json, to_string, to_value, Value};
 
 use crate::{
     app::{error::RuntimeError, progress::build_progress_bar_export, runtime::Config},
-    exporters::exporter::{Exporter, Writer}, TXT,
+    exporters::exporter::Exporter,
 };
 
 use imessage_database::{
     error::table::TableError,
+    message_types::{
+        expressives::Expressive,
+        variants::{CustomBalloon, Variant},
+    },
     tables::{
+        attachment::MediaType,
         messages::Message,
         table::{Table, ORPHANED},
     },
@@ -27,6 +32,8 @@ pub struct JSONL<'a> {
     pub files: HashMap<i32, PathBuf>,
     /// Path to file for orphaned messages
     pub orphaned: PathBuf,
+    /// GUIDs of the messages written so far, used to find reactions to messages that are not exported
+    pub written: HashSet<String>,
 }
 
 impl<'a> Exporter<'a> for JSONL<'a> {
@@ -38,6 +45,7 @@ impl<'a> Exporter<'a> for JSONL<'a> {
             config,
             files: HashMap::new(),
             orphaned,
+            written: HashSet::new(),
         }
     }
 
@@ -77,10 +85,21 @@ impl<'a> Exporter<'a> for JSONL<'a> {
             }
             current_message_row = msg.rowid;
 
-            let _ = msg.gen_text(&self.config.db);
-            let message: String = to_string(&msg).unwrap() + "\n";
-            TXT::write_to_file(self.get_or_create_file(&msg), &message);
+            // Skip messages that are not near a match for the text filter
+            if !self.config.is_selected(&mut msg) {
+                current_message += 1;
+                continue;
+            }
 
+            if self.should_write(&msg) {
+                self.config.gen_text(&mut msg);
+                let record = self
+                    .format_record(&msg)
+                    .map_err(RuntimeError::DatabaseError)?;
+                let line = format!("{}\n", to_string(&record).unwrap_or_default());
+                self.config
+                    .write_to_file(self.get_or_create_file(&msg), &line);
+            }
 
             current_message += 1;
             if current_message % 99 == 0 {
@@ -93,54 +112,582 @@ impl<'a> Exporter<'a> for JSONL<'a> {
 
     /// Create a file for the given chat, caching it so we don't need to build it later
     fn get_or_create_file(&mut self, message: &Message) -> &Path {
-        match self.config.conversation(message) {
+        let path = match self.config.conversation(message) {
             Some((chatroom, id)) => self.files.entry(*id).or_insert_with(|| {
                 let mut path = self.config.options.export_path.clone();
                 path.push(self.config.filename(chatroom));
                 path.set_extension("jsonl");
                 path
             }),
-            None => &self.orphaned,
+            None => &mut self.orphaned,
+        };
+        if let Some(previous) = self.config.partition(path, message) {
+            self.config.close_output(&previous);
+        }
+        self.config.record(message, path);
+        path
+    }
+}
+
+impl<'a> JSONL<'a> {
+    /// Determine if a message gets its own record, remembering the messages that do
+    ///
+    /// Reactions and stickers are nested in the record of the message they target. If a filter
+    /// excluded that message, so it was not written before the reaction, the reaction is written
+    /// as its own record instead, with `reaction_to` set to the message it targets.
+    fn should_write(&mut self, message: &Message) -> bool {
+        if message.is_reaction()
+            && message
+                .clean_associated_guid()
+                .is_some_and(|(_, guid)| self.written.contains(guid))
+        {
+            return false;
+        }
+        self.written.insert(message.guid.clone());
+        true
+    }
+
+    /// Build the JSON record for a single message
+    ///
+    /// The record contains all of the columns from the `message` table, plus:
+    /// - `readable_date`: the message's `date`, in the configured time zone and format
+    /// - `sender`: the resolved name of the sender
+    /// - `chat`: the conversation the message belongs to, if any
+    /// - `attachments`: the message's attachments, with resolved paths
+    /// - `variant`: the parsed message [`Variant`]
+    /// - `expressive`: the parsed message [`Expressive`], if any
+    /// - `reactions`: tapbacks on the message, grouped by body part
+    /// - `stickers`: stickers placed on the message, grouped by body part
+    /// - `reaction_to`: the message a reaction or sticker targets, if any
+    /// - `reply_to`: the thread the message replies to, if any
+    /// - `replies`: thread replies to the message, grouped by body part
+    ///
+    /// Thread replies are also written as their own records, so a reply appears both on its
+    /// own line and nested in the record of the message it replies to. Consumers that need
+    /// each message once can skip top-level records where `reply_to` is set, or dedupe on `guid`.
+    fn format_record(&self, message: &Message) -> Result<Value, TableError> {
+        let mut record = to_value(message).unwrap_or_else(|_| json!({}));
+
+        if let Some(map) = record.as_object_mut() {
+            map.insert(
+                "readable_date".to_string(),
+                json!(self.readable_date(message)),
+            );
+            map.insert(
+                "sender".to_string(),
+                json!(self.config.who(
+                    message.handle_id,
+                    message.is_from_me,
+                    &message.destination_caller_id
+                )),
+            );
+            map.insert("chat".to_string(), self.format_chat(message));
+            map.insert(
+                "attachments".to_string(),
+                Value::Array(self.format_attachments(message)?),
+            );
+            map.insert(
+                "variant".to_string(),
+                Self::format_variant(&message.variant()),
+            );
+            map.insert(
+                "expressive".to_string(),
+                Self::format_expressive(&message.get_expressive()),
+            );
+
+            let (reactions, stickers) = self.format_reactions(message)?;
+            map.insert("reactions".to_string(), reactions);
+            map.insert("stickers".to_string(), stickers);
+            map.insert("reaction_to".to_string(), Self::format_reaction_to(message));
+            map.insert("reply_to".to_string(), Self::format_reply_to(message));
+            map.insert("replies".to_string(), self.format_replies(message)?);
+        }
+
+        Ok(record)
+    }
+
+    /// Format the date a message was sent in the configured time zone and format
+    fn readable_date(&self, message: &Message) -> String {
+        self.config
+            .options
+            .date_format
+            .format(&message.date(&self.config.offset))
+    }
+
+    /// Build the chat metadata for a message, or `null` if the message has no chat
+    fn format_chat(&self, message: &Message) -> Value {
+        match self.config.conversation(message) {
+            Some((chatroom, id)) => {
+                let participants: Vec<&str> = self
+                    .config
+                    .chatroom_participants
+                    .get(&chatroom.rowid)
+                    .map(|handles| {
+                        handles
+                            .iter()
+                            .map(|handle_id| self.config.who(Some(*handle_id), false, &None))
+                            .collect()
+                    })
+                    .unwrap_or_default();
+
+                json!({
+                    "id": id,
+                    "identifier": chatroom.chat_identifier,
+                    "service": chatroom.service_name,
+                    "name": chatroom.display_name(),
+                    "participants": participants,
+                })
+            }
+            None => Value::Null,
+        }
+    }
+
+    /// Build the attachment metadata for a message, copying the files if requested
+    fn format_attachments(&self, message: &Message) -> Result<Vec<Value>, TableError> {
+        if !message.has_attachments() {
+            return Ok(vec![]);
+        }
+
+        let mut attachments = self.config.attachments(message)?;
+        Ok(attachments
+            .iter_mut()
+            .map(|attachment| {
+                // Copy the attachment if the user requested it; this also resolves `copied_path`
+                let _ = self.config.options.attachment_manager.handle_attachment(
+                    message,
+                    attachment,
+                    self.config,
+                );
+
+                let mime_type = match attachment.mime_type() {
+                    MediaType::Image(mime)
+                    | MediaType::Video(mime)
+                    | MediaType::Audio(mime)
+                    | MediaType::Text(mime)
+                    | MediaType::Application(mime)
+                    | MediaType::Other(mime) => Some(mime),
+                    MediaType::Unknown => None,
+                };
+
+                let sticker_effect = attachment
+                    .get_sticker_effect(
+                        &self.config.options.platform,
+                        &self.config.options.db_path,
+                        self.config.options.attachment_root.as_deref(),
+                    )
+                    .ok()
+                    .flatten()
+                    .map(|effect| effect.to_string());
+
+                json!({
+                    "rowid": attachment.rowid,
+                    "filename": attachment.filename(),
+                    "transfer_name": attachment.transfer_name,
+                    "uti": attachment.uti,
+                    "mime_type": mime_type,
+                    "total_bytes": attachment.total_bytes,
+                    "is_sticker": attachment.is_sticker,
+                    "sticker_effect": sticker_effect,
+                    "path": self.config.message_attachment_path(attachment),
+                })
+            })
+            .collect())
+    }
+
+    /// Build the tapbacks and stickers that target a message from the reactions cache
+    ///
+    /// Both are grouped by the index of the body part they target, i.e.
+    /// `[{"index": 0, "messages": [...]}]`. Removed tapbacks are omitted.
+    fn format_reactions(&self, message: &Message) -> Result<(Value, Value), TableError> {
+        let mut reactions = vec![];
+        let mut stickers = vec![];
+
+        if let Some(reactions_map) = self.config.reactions.get(&message.guid) {
+            for (idx, messages) in Self::sorted_parts(reactions_map) {
+                let mut tapbacks = vec![];
+                let mut placed_stickers = vec![];
+
+                for reaction in messages {
+                    let sender = self.config.who(
+                        reaction.handle_id,
+                        reaction.is_from_me,
+                        &reaction.destination_caller_id,
+                    );
+                    match reaction.variant() {
+                        Variant::Reaction(_, true, tapback) => tapbacks.push(json!({
+                            "guid": reaction.guid,
+                            "date": reaction.date,
+                            "readable_date": self.readable_date(reaction),
+                            "sender": sender,
+                            "reaction": format!("{tapback:?}"),
+                        })),
+                        Variant::Sticker(_) => placed_stickers.push(json!({
+                            "guid": reaction.guid,
+                            "date": reaction.date,
+                            "readable_date": self.readable_date(reaction),
+                            "sender": sender,
+                            "attachments": self.format_attachments(reaction)?,
+                        })),
+                        _ => {}
+                    }
+                }
+
+                if !tapbacks.is_empty() {
+                    reactions.push(json!({ "index": idx, "messages": tapbacks }));
+                }
+                if !placed_stickers.is_empty() {
+                    stickers.push(json!({ "index": idx, "messages": placed_stickers }));
+                }
+            }
+        }
+
+        Ok((Value::Array(reactions), Value::Array(stickers)))
+    }
+
+    /// Build the full records of the thread replies to a message, grouped by the index of the body part they reply to
+    fn format_replies(&self, message: &Message) -> Result<Value, TableError> {
+        if !message.has_replies() {
+            return Ok(Value::Array(vec![]));
+        }
+
+        let mut replies = message.get_replies(&self.config.db)?;
+        let mut out_v = vec![];
+
+        let mut indexes: Vec<usize> = replies.keys().copied().collect();
+        indexes.sort_unstable();
+
+        for idx in indexes {
+            if let Some(messages) = replies.get_mut(&idx) {
+                let mut records = vec![];
+                for reply in messages.iter_mut() {
+                    if !reply.is_reaction() {
+                        self.config.gen_text(reply);
+                        records.push(self.format_record(reply)?);
+                    }
+                }
+                out_v.push(json!({ "index": idx, "messages": records }));
+            }
+        }
+
+        Ok(Value::Array(out_v))
+    }
+
+    /// Build the GUID and body part index of the message a reaction or sticker targets, or `null` if the message is not a reaction
+    fn format_reaction_to(message: &Message) -> Value {
+        match message.clean_associated_guid() {
+            Some((index, guid)) if message.is_reaction() => json!({
+                "guid": guid,
+                "index": index,
+            }),
+            _ => Value::Null,
+        }
+    }
+
+    /// Build the GUID and body part index of the message a thread reply responds to, or `null` if the message is not a reply
+    fn format_reply_to(message: &Message) -> Value {
+        match &message.thread_originator_guid {
+            Some(guid) => json!({
+                "guid": guid,
+                "index": message.get_reply_index(),
+            }),
+            None => Value::Null,
+        }
+    }
+
+    /// Iterate over a map of body part index to messages in body part order
+    fn sorted_parts(parts: &HashMap<usize, Vec<Message>>) -> Vec<(&usize, &Vec<Message>)> {
+        let mut out_v: Vec<(&usize, &Vec<Message>)> = parts.iter().collect();
+        out_v.sort_unstable_by_key(|(idx, _)| **idx);
+        out_v
+    }
+
+    /// Build a tagged representation of a message [`Variant`]
+    fn format_variant(variant: &Variant) -> Value {
+        match variant {
+            Variant::Normal => json!({ "type": "normal" }),
+            Variant::Edited => json!({ "type": "edited" }),
+            Variant::SharePlay => json!({ "type": "shareplay" }),
+            Variant::Unknown(code) => json!({ "type": "unknown", "code": code }),
+            Variant::Sticker(index) => json!({ "type": "sticker", "index": index }),
+            Variant::Reaction(index, added, reaction) => json!({
+                "type": "reaction",
+                "index": index,
+                "added": added,
+                "reaction": format!("{reaction:?}"),
+            }),
+            Variant::App(balloon) => match balloon {
+                CustomBalloon::Application(bundle_id) => json!({
+                    "type": "app",
+                    "balloon": "Application",
+                    "bundle_id": bundle_id,
+                }),
+                _ => json!({ "type": "app", "balloon": format!("{balloon:?}") }),
+            },
+        }
+    }
+
+    /// Build a tagged representation of a message [`Expressive`], or `null` if there is none
+    fn format_expressive(expressive: &Expressive) -> Value {
+        match expressive {
+            Expressive::Screen(effect) => {
+                json!({ "type": "screen", "effect": format!("{effect:?}") })
+            }
+            Expressive::Bubble(effect) => {
+                json!({ "type": "bubble", "effect": format!("{effect:?}") })
+            }
+            Expressive::Unknown(effect) => json!({ "type": "unknown", "effect": effect }),
+            Expressive::None => Value::Null,
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::
-        path::PathBuf
-    ;
+    use std::collections::HashMap;
 
-    use crate::{
-        app::attachment_manager::AttachmentManager, Config, Exporter,
-        Options, JSONL,
-    };
+    use crate::{Config, Exporter, Options, JSONL};
     use imessage_database::{
-        util::{dirs::default_db_path, platform::Platform, query_context::QueryContext},
+        message_types::{
+            expressives::{BubbleEffect, Expressive},
+            variants::{CustomBalloon, Reaction, Variant},
+        },
+        tables::messages::Message,
     };
+    use serde_json::{json, Value};
 
-    pub fn fake_options() -> Options {
-        Options {
-            db_path: default_db_path(),
-            attachment_root: None,
-            attachment_manager: AttachmentManager::Disabled,
-            diagnostic: false,
-            export_type: None,
-            export_path: PathBuf::new(),
-            query_context: QueryContext::default(),
-            no_lazy: false,
-            custom_name: None,
-            use_caller_id: false,
-            platform: Platform::macOS,
-            ignore_disk_space: false,
+    pub fn blank() -> Message {
+        Message {
+            rowid: i32::default(),
+            guid: String::default(),
+            text: None,
+            service: Some("iMessage".to_string()),
+            handle_id: Some(i32::default()),
+            destination_caller_id: None,
+            subject: None,
+            date: i64::default(),
+            date_read: i64::default(),
+            date_delivered: i64::default(),
+            is_from_me: false,
+            is_read: false,
+            item_type: 0,
+            group_title: None,
+            group_action_type: 0,
+            associated_message_guid: None,
+            associated_message_type: Some(i32::default()),
+            balloon_bundle_id: None,
+            expressive_send_style_id: None,
+            thread_originator_guid: None,
+            thread_originator_part: None,
+            date_edited: 0,
+            chat_id: None,
+            num_attachments: 0,
+            deleted_from: None,
+            num_replies: 0,
         }
     }
 
     #[test]
     fn can_create() {
-        let options = fake_options();
+        let options = Options::fake();
         let config = Config::new(options).unwrap();
         let exporter = JSONL::new(&config);
         assert_eq!(exporter.files.len(), 0);
     }
+
+    #[test]
+    fn can_format_record_from_me() {
+        // Create exporter
+        let mut options = Options::fake();
+        options.custom_name = Some("Name".to_string());
+        let config = Config::new(options).unwrap();
+        let exporter = JSONL::new(&config);
+
+        // Create fake message
+        let mut message = blank();
+        message.rowid = 1;
+        message.text = Some("Hello world".to_string());
+        message.is_from_me = true;
+
+        let record = exporter.format_record(&message).unwrap();
+
+        assert_eq!(record["rowid"], 1);
+        assert_eq!(record["text"], "Hello world");
+        assert_eq!(record["sender"], "Name");
+        assert_eq!(record["chat"], Value::Null);
+        assert_eq!(record["attachments"], json!([]));
+        assert_eq!(record["variant"], json!({ "type": "normal" }));
+        assert_eq!(record["expressive"], Value::Null);
+        assert_eq!(record["reactions"], json!([]));
+        assert_eq!(record["stickers"], json!([]));
+        assert_eq!(record["reaction_to"], Value::Null);
+        assert_eq!(record["reply_to"], Value::Null);
+        assert_eq!(record["replies"], json!([]));
+    }
+
+    #[test]
+    fn can_write_orphaned_reaction() {
+        // Create exporter
+        let options = Options::fake();
+        let config = Config::new(options).unwrap();
+        let mut exporter = JSONL::new(&config);
+
+        // Create fake reaction to a message that a filter excluded
+        let target = "00000000-0000-0000-0000-000000000001";
+        let mut reaction = blank();
+        reaction.guid = "reaction".to_string();
+        reaction.associated_message_guid = Some(format!("p:1/{target}"));
+        reaction.associated_message_type = Some(2000);
+
+        assert!(exporter.should_write(&reaction));
+        let record = exporter.format_record(&reaction).unwrap();
+        assert_eq!(record["reaction_to"], json!({ "guid": target, "index": 1 }));
+    }
+
+    #[test]
+    fn cant_write_nested_reaction() {
+        // Create exporter
+        let options = Options::fake();
+        let config = Config::new(options).unwrap();
+        let mut exporter = JSONL::new(&config);
+
+        // Create fake message and a reaction to it
+        let target = "00000000-0000-0000-0000-000000000001";
+        let mut message = blank();
+        message.guid = target.to_string();
+
+        let mut reaction = blank();
+        reaction.guid = "reaction".to_string();
+        reaction.associated_message_guid = Some(format!("p:0/{target}"));
+        reaction.associated_message_type = Some(2000);
+
+        // The reaction is nested in the record of the message that was written
+        assert!(exporter.should_write(&message));
+        assert!(!exporter.should_write(&reaction));
+    }
+
+    #[test]
+    fn can_format_record_reply() {
+        // Create exporter
+        let options = Options::fake();
+        let config = Config::new(options).unwrap();
+        let exporter = JSONL::new(&config);
+
+        // Create fake message
+        let mut message = blank();
+        message.thread_originator_guid = Some("parent".to_string());
+        message.thread_originator_part = Some("1:0:10".to_string());
+
+        let record = exporter.format_record(&message).unwrap();
+
+        assert_eq!(record["reply_to"], json!({ "guid": "parent", "index": 1 }));
+        assert_eq!(record["replies"], json!([]));
+    }
+
+    #[test]
+    fn can_format_record_with_reactions() {
+        // Create exporter
+        let mut options = Options::fake();
+        options.date_format.time_zone = "UTC".parse().ok();
+        let mut config = Config::new(options).unwrap();
+        config
+            .participants
+            .insert(999999, "Sample Contact".to_string());
+
+        // Create fake reactions
+        let mut loved = blank();
+        loved.guid = "loved".to_string();
+        loved.handle_id = Some(999999);
+        loved.associated_message_guid = Some("p:1/parent".to_string());
+        loved.associated_message_type = Some(2000);
+
+        let mut removed = blank();
+        removed.guid = "removed".to_string();
+        removed.associated_message_guid = Some("p:0/parent".to_string());
+        removed.associated_message_type = Some(3001);
+
+        config.reactions.insert(
+            "parent".to_string(),
+            HashMap::from([(0, vec![removed]), (1, vec![loved])]),
+        );
+        let exporter = JSONL::new(&config);
+
+        // Create fake message
+        let mut message = blank();
+        message.guid = "parent".to_string();
+
+        let record = exporter.format_record(&message).unwrap();
+
+        assert_eq!(
+            record["reactions"],
+            json!([{
+                "index": 1,
+                "messages": [{
+                    "guid": "loved",
+                    "date": 0,
+                    "readable_date": "Jan 01, 2001 12:00:00 AM",
+                    "sender": "Sample Contact",
+                    "reaction": "Loved",
+                }],
+            }])
+        );
+        assert_eq!(record["stickers"], json!([]));
+    }
+
+    #[test]
+    fn can_format_record_from_them() {
+        // Create exporter
+        let options = Options::fake();
+        let mut config = Config::new(options).unwrap();
+        config
+            .participants
+            .insert(999999, "Sample Contact".to_string());
+        let exporter = JSONL::new(&config);
+
+        // Create fake message
+        let mut message = blank();
+        message.handle_id = Some(999999);
+        message.expressive_send_style_id =
+            Some("com.apple.MobileSMS.expressivesend.impact".to_string());
+
+        let record = exporter.format_record(&message).unwrap();
+
+        assert_eq!(record["sender"], "Sample Contact");
+        assert_eq!(
+            record["expressive"],
+            json!({ "type": "bubble", "effect": "Slam" })
+        );
+    }
+
+    #[test]
+    fn can_format_variant_reaction() {
+        let variant = Variant::Reaction(2, true, Reaction::Loved);
+        assert_eq!(
+            JSONL::format_variant(&variant),
+            json!({ "type": "reaction", "index": 2, "added": true, "reaction": "Loved" })
+        );
+    }
+
+    #[test]
+    fn can_format_variant_app() {
+        let variant = Variant::App(CustomBalloon::Application("com.example.app"));
+        assert_eq!(
+            JSONL::format_variant(&variant),
+            json!({ "type": "app", "balloon": "Application", "bundle_id": "com.example.app" })
+        );
+
+        let variant = Variant::App(CustomBalloon::URL);
+        assert_eq!(
+            JSONL::format_variant(&variant),
+            json!({ "type": "app", "balloon": "URL" })
+        );
+    }
+
+    #[test]
+    fn can_format_expressive() {
+        assert_eq!(
+            JSONL::format_expressive(&Expressive::Bubble(BubbleEffect::Gentle)),
+            json!({ "type": "bubble", "effect": "Gentle" })
+        );
+        assert_eq!(JSONL::format_expressive(&Expressive::None), Value::Null);
+    }
 }
@@ -0,0 +1,1619 @@
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+use chrono::NaiveDate;
+
+use crate::{
+    app::{
+        error::RuntimeError, progress::build_progress_bar_export, runtime::Config,
+        sanitizers::sanitize_yaml,
+    },
+    exporters::exporter::{BalloonFormatter, Exporter, Writer},
+};
+
+use imessage_database::{
+    error::{message::MessageError, plist::PlistParseError, table::TableError},
+    message_types::{
+        app::AppMessage,
+        app_store::AppStoreMessage,
+        collaboration::CollaborationMessage,
+        edited::EditedMessage,
+        expressives::{BubbleEffect, Expressive, ScreenEffect},
+        handwriting::HandwrittenMessage,
+        music::MusicMessage,
+        placemark::PlacemarkMessage,
+        url::URLMessage,
+        variants::{Announcement, BalloonProvider, CustomBalloon, URLOverride, Variant},
+    },
+    tables::{
+        attachment::{Attachment, MediaType},
+        messages::{BubbleType, Message},
+        table::{Table, FITNESS_RECEIVER, ME, ORPHANED, YOU},
+    },
+    util::{
+        dates::{get_local_time, readable_diff, TIMESTAMP_FACTOR},
+        plist::parse_plist,
+    },
+};
+
+/// Format used for the heading that starts each day of a conversation
+const DAY_FORMAT: &str = "%A, %B %-d, %Y";
+/// Format used for message times, which are rendered under a day heading
+const TIME_FORMAT: &str = "%-I:%M:%S %p";
+/// Markdown hard line break, used to keep lines together in a single paragraph
+const LINE_BREAK: &str = "  \n";
+
+/// Metadata collected before writing a conversation, rendered as YAML front matter at the top of its file
+#[derive(Debug, Default)]
+struct FrontMatter {
+    /// Human readable name of the conversation
+    title: String,
+    /// Everyone who is in or sent a message to the conversation
+    participants: BTreeSet<String>,
+    /// Timestamp of the first message in the conversation
+    start: Option<String>,
+    /// Timestamp of the last message in the conversation
+    end: Option<String>,
+    /// The most recent day heading written to the file
+    last_day: Option<String>,
+}
+
+impl FrontMatter {
+    /// Render the YAML front matter block and top-level heading for a conversation file
+    fn render(&self) -> String {
+        let mut out_s = String::from("---\n");
+        out_s.push_str(&format!("title: {}\n", sanitize_yaml(&self.title)));
+        if self.participants.is_empty() {
+            out_s.push_str("participants: []\n");
+        } else {
+            out_s.push_str("participants:\n");
+            self.participants.iter().for_each(|participant| {
+                out_s.push_str(&format!("  - {}\n", sanitize_yaml(participant)));
+            });
+        }
+        if let Some(start) = &self.start {
+            out_s.push_str(&format!("start: {start}\n"));
+        }
+        if let Some(end) = &self.end {
+            out_s.push_str(&format!("end: {end}\n"));
+        }
+        out_s.push_str("---\n\n");
+        out_s.push_str(&format!("# {}\n\n", self.title));
+        out_s
+    }
+
+    /// Read the front matter from a file written by a previous export, along with the rest of the file so it can be written again under new front matter
+    fn reopen(config: &Config, path: &Path) -> Option<(Self, String)> {
+        let file = String::from_utf8(config.read_output(path).ok()?).ok()?;
+        let (yaml, rest) = file.strip_prefix("---\n")?.split_once("---\n\n")?;
+        // Skip the heading that follows the front matter
+        let (_, body) = rest.split_once("\n\n")?;
+
+        // Values are written as double-quoted YAML, which escapes the same way as JSON
+        let mut front_matter = FrontMatter::default();
+        for line in yaml.lines() {
+            if let Some(title) = line.strip_prefix("title: ") {
+                front_matter.title = serde_json::from_str(title).ok()?;
+            } else if let Some(participant) = line.strip_prefix("  - ") {
+                front_matter
+                    .participants
+                    .insert(serde_json::from_str(participant).ok()?);
+            } else if let Some(start) = line.strip_prefix("start: ") {
+                front_matter.start = Some(start.to_string());
+            } else if let Some(end) = line.strip_prefix("end: ") {
+                front_matter.end = Some(end.to_string());
+            }
+        }
+        front_matter.last_day = body
+            .lines()
+            .rev()
+            .filter_map(|line| line.strip_prefix("## "))
+            .find(|day| NaiveDate::parse_from_str(day, DAY_FORMAT).is_ok())
+            .map(String::from);
+
+        Some((front_matter, body.to_string()))
+    }
+}
+
+pub struct Markdown<'a> {
+    /// Data that is setup from the application's runtime
+    pub config: &'a Config,
+    /// Handles to files we want to write messages to
+    /// Map of internal unique chatroom ID to a filename
+    pub files: HashMap<i32, PathBuf>,
+    /// Path to file for orphaned messages
+    pub orphaned: PathBuf,
+    /// Front matter for each file we write to
+    front_matter: HashMap<PathBuf, FrontMatter>,
+    /// Files whose front matter has already been written
+    started: HashSet<PathBuf>,
+}
+
+impl<'a> Exporter<'a> for Markdown<'a> {
+    fn new(config: &'a Config) -> Self {
+        let mut orphaned = config.options.export_path.clone();
+        orphaned.push(ORPHANED);
+        orphaned.set_extension("md");
+        Markdown {
+            config,
+            files: HashMap::new(),
+            orphaned,
+            front_matter: HashMap::new(),
+            started: HashSet::new(),
+        }
+    }
+
+    fn iter_messages(&mut self) -> Result<(), RuntimeError> {
+        // Tell the user what we are doing
+        eprintln!(
+            "Exporting to {} as md...",
+            self.config.options.export_path.display()
+        );
+
+        // The front matter is written before the first message in each file, so it has to be known up front
+        self.collect_front_matter()?;
+
+        // Keep track of current message ROWID
+        let mut current_message_row = -1;
+
+        // Set up progress bar
+        let mut current_message = 0;
+        let total_messages =
+            Message::get_count(&self.config.db, &self.config.options.query_context)
+                .map_err(RuntimeError::DatabaseError)?;
+        let pb = build_progress_bar_export(total_messages);
+
+        let mut statement =
+            Message::stream_rows(&self.config.db, &self.config.options.query_context)
+                .map_err(RuntimeError::DatabaseError)?;
+
+        let messages = statement
+            .query_map([], |row| Ok(Message::from_row(row)))
+            .map_err(|err| RuntimeError::DatabaseError(TableError::Messages(err)))?;
+
+        for message in messages {
+            let mut msg = Message::extract(message).map_err(RuntimeError::DatabaseError)?;
+
+            // Early escape if we try and render the same message GUID twice
+            // See https://github.com/ReagentX/imessage-exporter/issues/135 for rationale
+            if msg.rowid == current_message_row {
+                current_message += 1;
+                continue;
+            }
+            current_message_row = msg.rowid;
+
+            // Skip messages that are not near a match for the text filter
+            if !self.config.is_selected(&mut msg) {
+                current_message += 1;
+                continue;
+            }
+
+            // Render the announcement in-line
+            if msg.is_announcement() {
+                let announcement = self.format_announcement(&msg);
+                self.write_message(&msg, &announcement);
+            }
+            // Message replies and reactions are rendered in context, so no need to render them separately
+            else if !msg.is_reaction() {
+                self.config.gen_text(&mut msg);
+                let message = self
+                    .format_message(&msg, 0)
+                    .map_err(RuntimeError::DatabaseError)?;
+                self.write_message(&msg, &message);
+            }
+            current_message += 1;
+            if current_message % 99 == 0 {
+                pb.set_position(current_message);
+            }
+        }
+        pb.finish();
+        Ok(())
+    }
+
+    /// Create a file for the given chat, caching it so we don't need to build it later
+    fn get_or_create_file(&mut self, message: &Message) -> &Path {
+        let config = self.config;
+        let path = self.file_for(message);
+        config.record(message, path);
+        path
+    }
+}
+
+impl<'a> Writer<'a> for Markdown<'a> {
+    fn format_message(&self, message: &Message, indent_size: usize) -> Result<String, TableError> {
+        // Data we want to write to a file
+        let mut formatted_message = String::new();
+
+        // Add message sender and time; replies may be on a different day than their parent, so they get the full date
+        let who = self.config.who(
+            message.handle_id,
+            message.is_from_me,
+            &message.destination_caller_id,
+        );
+        self.add_block(
+            &mut formatted_message,
+            &format!("**{who}** · {}", self.get_time(message, indent_size > 0)),
+        );
+
+        // If message was deleted, annotate it
+        if message.is_deleted() {
+            self.add_block(
+                &mut formatted_message,
+                "*This message was deleted from the conversation!*",
+            );
+        }
+
+        // Useful message metadata
+        let message_parts = message.body();
+        let mut attachments = self.config.attachments(message)?;
+        let mut replies = message.get_replies(&self.config.db)?;
+
+        // Index of where we are in the attachment Vector
+        let mut attachment_index: usize = 0;
+
+        // Render subject
+        if let Some(subject) = &message.subject {
+            self.add_block(&mut formatted_message, &format!("**{subject}**"));
+        }
+
+        // If message was removed, display it
+        if message_parts.is_empty() && message.is_edited() {
+            let edited = match self.format_edited(message, "") {
+                Ok(s) => s,
+                Err(why) => format!("{}, {}", message.guid, why),
+            };
+            self.add_block(&mut formatted_message, &edited);
+        }
+
+        // Handle SharePlay
+        if message.is_shareplay() {
+            self.add_block(&mut formatted_message, self.format_shareplay());
+        }
+
+        // Generate the message body from it's components
+        for (idx, message_part) in message_parts.iter().enumerate() {
+            // Render edited messages
+            if message.is_edited() {
+                let edited = match self.format_edited(message, "") {
+                    Ok(s) => s,
+                    Err(why) => format!("{}, {}", message.guid, why),
+                };
+                self.add_block(&mut formatted_message, &edited);
+                continue;
+            }
+            match message_part {
+                // Fitness messages have a prefix that we need to replace with the opposite if who sent the message
+                BubbleType::Text(text) => {
+                    if text.starts_with(FITNESS_RECEIVER) {
+                        self.add_block(
+                            &mut formatted_message,
+                            &hard_breaks(&text.replace(FITNESS_RECEIVER, YOU)),
+                        );
+                    } else {
+                        self.add_block(&mut formatted_message, &hard_breaks(text));
+                    }
+                }
+                BubbleType::Attachment => match attachments.get_mut(attachment_index) {
+                    Some(attachment) => {
+                        if attachment.is_sticker {
+                            let result = self.format_sticker(attachment, message);
+                            self.add_block(&mut formatted_message, &result);
+                        } else {
+                            match self.format_attachment(attachment, message) {
+                                Ok(result) => {
+                                    attachment_index += 1;
+                                    self.add_block(&mut formatted_message, &result);
+                                }
+                                Err(result) => {
+                                    self.add_block(
+                                        &mut formatted_message,
+                                        &format!("*Attachment missing: {result}*"),
+                                    );
+                                }
+                            }
+                        }
+                    }
+                    // Attachment does not exist in attachments table
+                    None => self.add_block(&mut formatted_message, "*Attachment missing!*"),
+                },
+                BubbleType::App => match self.format_app(message, &mut attachments, "") {
+                    Ok(ok_bubble) => self.add_block(&mut formatted_message, &ok_bubble),
+                    Err(why) => self.add_block(
+                        &mut formatted_message,
+                        &format!("*Unable to format app message: {why}*"),
+                    ),
+                },
+            };
+
+            // Handle expressives
+            if message.expressive_send_style_id.is_some() {
+                let expressive = self.format_expressive(message);
+                if !expressive.is_empty() {
+                    self.add_block(&mut formatted_message, &format!("*{expressive}*"));
+                }
+            }
+
+            // Handle Reactions
+            if let Some(reactions_map) = self.config.reactions.get(&message.guid) {
+                if let Some(reactions) = reactions_map.get(&idx) {
+                    let mut formatted_reactions = String::new();
+                    reactions
+                        .iter()
+                        .try_for_each(|reaction| -> Result<(), TableError> {
+                            let formatted = self.format_reaction(reaction)?;
+                            if !formatted.is_empty() {
+                                formatted_reactions.push_str("\n- ");
+                                formatted_reactions.push_str(&formatted);
+                            }
+                            Ok(())
+                        })?;
+
+                    if !formatted_reactions.is_empty() {
+                        self.add_block(
+                            &mut formatted_message,
+                            &format!("*Reactions:*{formatted_reactions}"),
+                        );
+                    }
+                }
+            }
+
+            // Handle Replies
+            if let Some(replies) = replies.get_mut(&idx) {
+                replies
+                    .iter_mut()
+                    .try_for_each(|reply| -> Result<(), TableError> {
+                        self.config.gen_text(reply);
+                        if !reply.is_reaction() {
+                            self.add_block(&mut formatted_message, &self.format_message(reply, 1)?);
+                        }
+                        Ok(())
+                    })?;
+            }
+        }
+
+        // Add a note if the message is a reply
+        if message.is_reply() && indent_size == 0 {
+            self.add_block(
+                &mut formatted_message,
+                "*This message responded to an earlier message.*",
+            );
+        }
+
+        // Thread replies are nested in a blockquote under the message they responded to
+        if indent_size > 0 {
+            return Ok(blockquote(&formatted_message));
+        }
+
+        Ok(formatted_message)
+    }
+
+    fn format_attachment(
+        &self,
+        attachment: &'a mut Attachment,
+        message: &Message,
+    ) -> Result<String, &'a str> {
+        // Copy the file, if requested
+        self.config
+            .options
+            .attachment_manager
+            .handle_attachment(message, attachment, self.config)
+            .ok_or(attachment.filename())?;
+
+        // Build a relative filepath from the fully qualified one on the `Attachment`
+        let embed_path = self.config.message_attachment_path(attachment);
+
+        Ok(match attachment.mime_type() {
+            MediaType::Image(_) => format!("![{}](<{embed_path}>)", attachment.filename()),
+            _ => format!(
+                "[{}](<{embed_path}>) ({})",
+                attachment.filename(),
+                attachment.file_size()
+            ),
+        })
+    }
+
+    fn format_sticker(&self, sticker: &'a mut Attachment, message: &Message) -> String {
+        let who = self.config.who(
+            message.handle_id,
+            message.is_from_me,
+            &message.destination_caller_id,
+        );
+        match self.format_attachment(sticker, message) {
+            Ok(sticker_embed) => {
+                let sticker_effect = sticker.get_sticker_effect(
+                    &self.config.options.platform,
+                    &self.config.options.db_path,
+                    self.config.options.attachment_root.as_deref(),
+                );
+                if let Ok(Some(sticker_effect)) = sticker_effect {
+                    return format!("{sticker_effect} Sticker from {who}: {sticker_embed}");
+                }
+                format!("Sticker from {who}: {sticker_embed}")
+            }
+            Err(path) => format!("Sticker from {who}: {path}"),
+        }
+    }
+
+    fn format_app(
+        &self,
+        message: &'a Message,
+        attachments: &mut Vec<Attachment>,
+        indent: &str,
+    ) -> Result<String, PlistParseError> {
+        if let Variant::App(balloon) = message.variant() {
+            let mut app_bubble = String::new();
+
+            // Handwritten messages use a different payload type, so handle that first
+            if matches!(balloon, CustomBalloon::Handwriting) {
+                return Ok(self.format_handwriting(&HandwrittenMessage::new(), indent));
+            }
+
+            if let Some(payload) = message.payload_data(&self.config.db) {
+                // Handle URL messages separately since they are a special case
+                let res = if message.is_url() {
+                    let parsed = parse_plist(&payload)?;
+                    let mut bubble = URLMessage::get_url_message_override(&parsed)?;
+                    if let Some(redactor) = &self.config.options.redactor {
+                        bubble = redactor.strip_link(bubble);
+                    }
+                    match bubble {
+                        URLOverride::Normal(balloon) => self.format_url(&balloon, indent),
+                        URLOverride::AppleMusic(balloon) => self.format_music(&balloon, indent),
+                        URLOverride::Collaboration(balloon) => {
+                            self.format_collaboration(&balloon, indent)
+                        }
+                        URLOverride::AppStore(balloon) => self.format_app_store(&balloon, indent),
+                        URLOverride::SharedPlacemark(balloon) => {
+                            self.format_placemark(&balloon, indent)
+                        }
+                    }
+                // Handwriting uses a different payload type than the rest of the branches
+                } else {
+                    // Handle the app case
+                    let parsed = parse_plist(&payload)?;
+                    match AppMessage::from_map(&parsed) {
+                        Ok(bubble) => match balloon {
+                            CustomBalloon::Application(bundle_id) => {
+                                self.format_generic_app(&bubble, bundle_id, attachments, indent)
+                            }
+                            CustomBalloon::ApplePay => self.format_apple_pay(&bubble, indent),
+                            CustomBalloon::Fitness => self.format_fitness(&bubble, indent),
+                            CustomBalloon::Slideshow => self.format_slideshow(&bubble, indent),
+                            CustomBalloon::CheckIn => self.format_check_in(&bubble, indent),
+                            CustomBalloon::FindMy => self.format_find_my(&bubble, indent),
+                            CustomBalloon::Handwriting => unreachable!(),
+                            CustomBalloon::URL => unreachable!(),
+                        },
+                        Err(why) => return Err(why),
+                    }
+                };
+                app_bubble.push_str(&res);
+            } else {
+                // Sometimes, URL messages are missing their payloads
+                if message.is_url() {
+                    if let Some(text) = &message.text {
+                        return Ok(link(text, text));
+                    }
+                }
+                return Err(PlistParseError::NoPayload);
+            };
+            Ok(app_bubble)
+        } else {
+            Err(PlistParseError::WrongMessageType)
+        }
+    }
+
+    fn format_reaction(&self, msg: &Message) -> Result<String, TableError> {
+        match msg.variant() {
+            Variant::Reaction(_, added, reaction) => {
+                if !added {
+                    return Ok(String::new());
+                }
+                Ok(format!(
+                    "{:?} by {}",
+                    reaction,
+                    self.config
+                        .who(msg.handle_id, msg.is_from_me, &msg.destination_caller_id),
+                ))
+            }
+            Variant::Sticker(_) => {
+                let mut paths = self.config.attachments(msg)?;
+                let who =
+                    self.config
+                        .who(msg.handle_id, msg.is_from_me, &msg.destination_caller_id);
+                // Sticker messages have only one attachment, the sticker image
+                Ok(if let Some(sticker) = paths.get_mut(0) {
+                    self.format_sticker(sticker, msg)
+                } else {
+                    format!("Sticker from {who} not found!")
+                })
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn format_expressive(&self, msg: &'a Message) -> &'a str {
+        match msg.get_expressive() {
+            Expressive::Screen(effect) => match effect {
+                ScreenEffect::Confetti => "Sent with Confetti",
+                ScreenEffect::Echo => "Sent with Echo",
+                ScreenEffect::Fireworks => "Sent with Fireworks",
+                ScreenEffect::Balloons => "Sent with Balloons",
+                ScreenEffect::Heart => "Sent with Heart",
+                ScreenEffect::Lasers => "Sent with Lasers",
+                ScreenEffect::ShootingStar => "Sent with Shooting Star",
+                ScreenEffect::Sparkles => "Sent with Sparkles",
+                ScreenEffect::Spotlight => "Sent with Spotlight",
+            },
+            Expressive::Bubble(effect) => match effect {
+                BubbleEffect::Slam => "Sent with Slam",
+                BubbleEffect::Loud => "Sent with Loud",
+                BubbleEffect::Gentle => "Sent with Gentle",
+                BubbleEffect::InvisibleInk => "Sent with Invisible Ink",
+            },
+            Expressive::Unknown(effect) => effect,
+            Expressive::None => "",
+        }
+    }
+
+    fn format_announcement(&self, msg: &'a Message) -> String {
+        let mut who = self
+            .config
+            .who(msg.handle_id, msg.is_from_me, &msg.destination_caller_id);
+        // Rename yourself so we render the proper grammar here
+        if who == ME {
+            who = self.config.options.custom_name.as_deref().unwrap_or(YOU);
+        }
+
+        let timestamp = self.get_time(msg, false);
+
+        match msg.get_announcement() {
+            Some(announcement) => match announcement {
+                Announcement::NameChange(name) => {
+                    format!("*{timestamp} {who} renamed the conversation to {name}*\n\n")
+                }
+                Announcement::PhotoChange => {
+                    format!("*{timestamp} {who} changed the group photo.*\n\n")
+                }
+                Announcement::Unknown(num) => {
+                    format!("*{timestamp} {who} performed unknown action {num}.*\n\n")
+                }
+            },
+            None => String::from("*Unable to format announcement!*\n\n"),
+        }
+    }
+
+    fn format_shareplay(&self) -> &str {
+        "*SharePlay Message Ended*"
+    }
+
+    fn format_edited(&self, msg: &'a Message, _: &str) -> Result<String, MessageError> {
+        if let Some(payload) = msg.message_summary_info(&self.config.db) {
+            // Parse the edited message
+            let mut edited_message =
+                EditedMessage::from_map(&payload).map_err(MessageError::PlistParseError)?;
+            if let Some(redactor) = &self.config.options.redactor {
+                redactor.redact_edits(&mut edited_message);
+            }
+
+            if edited_message.is_deleted() {
+                let who = if msg.is_from_me {
+                    self.config.options.custom_name.as_deref().unwrap_or(YOU)
+                } else {
+                    "They"
+                };
+                return Ok(format!("*{who} deleted a message.*"));
+            }
+
+            let mut lines: Vec<String> = Vec::with_capacity(edited_message.events.len());
+            let mut previous_timestamp: Option<&i64> = None;
+
+            for event in &edited_message.events {
+                match previous_timestamp {
+                    // Original message get an absolute timestamp
+                    None => {
+                        let parsed_timestamp = self
+                            .config
+                            .options
+                            .date_format
+                            .format(&get_local_time(&event.date, &self.config.offset));
+                        lines.push(format!("{parsed_timestamp} {}", event.text));
+                    }
+                    // Subsequent edits get a relative timestamp
+                    Some(prev_timestamp) => {
+                        let end = get_local_time(&event.date, &self.config.offset);
+                        let start = get_local_time(prev_timestamp, &self.config.offset);
+                        match readable_diff(start, end) {
+                            Some(diff) => {
+                                lines.push(format!("*Edited {diff} later:* {}", event.text))
+                            }
+                            None => lines.push(event.text.to_string()),
+                        }
+                    }
+                };
+
+                // Update the previous timestamp for the next loop
+                previous_timestamp = Some(&event.date);
+            }
+
+            return Ok(lines.join(LINE_BREAK));
+        }
+        Err(MessageError::PlistParseError(PlistParseError::NoPayload))
+    }
+}
+
+impl<'a> BalloonFormatter<&'a str> for Markdown<'a> {
+    fn format_url(&self, balloon: &URLMessage, _: &str) -> String {
+        let mut lines = vec![];
+
+        match (balloon.title, balloon.get_url()) {
+            (Some(title), Some(url)) => lines.push(format!("**{}**", link(title, url))),
+            (None, Some(url)) => lines.push(link(url, url)),
+            (Some(title), None) => lines.push(format!("**{title}**")),
+            (None, None) => {}
+        }
+
+        if let Some(summary) = balloon.summary {
+            lines.push(summary.to_string());
+        }
+
+        join_lines(&lines)
+    }
+
+    fn format_music(&self, balloon: &MusicMessage, _: &str) -> String {
+        let mut lines = vec![];
+
+        if let Some(track_name) = balloon.track_name {
+            lines.push(format!("**{track_name}**"));
+        }
+
+        if let Some(album) = balloon.album {
+            lines.push(album.to_string());
+        }
+
+        if let Some(artist) = balloon.artist {
+            lines.push(artist.to_string());
+        }
+
+        if let Some(url) = balloon.url {
+            lines.push(link(url, url));
+        }
+
+        join_lines(&lines)
+    }
+
+    fn format_collaboration(&self, balloon: &CollaborationMessage, _: &str) -> String {
+        let mut lines = vec![];
+
+        if let Some(name) = balloon.app_name.or(balloon.bundle_id) {
+            lines.push(format!("*{name} message:*"));
+        }
+
+        match (balloon.title, balloon.get_url()) {
+            (Some(title), Some(url)) => lines.push(link(title, url)),
+            (None, Some(url)) => lines.push(link(url, url)),
+            (Some(title), None) => lines.push(title.to_string()),
+            (None, None) => {}
+        }
+
+        join_lines(&lines)
+    }
+
+    fn format_app_store(&self, balloon: &AppStoreMessage, _: &'a str) -> String {
+        let mut lines = vec![];
+
+        if let Some(name) = balloon.app_name {
+            lines.push(format!("**{name}**"));
+        }
+
+        if let Some(description) = balloon.description {
+            lines.push(description.to_string());
+        }
+
+        if let Some(platform) = balloon.platform {
+            lines.push(platform.to_string());
+        }
+
+        if let Some(genre) = balloon.genre {
+            lines.push(genre.to_string());
+        }
+
+        if let Some(url) = balloon.url {
+            lines.push(link(url, url));
+        }
+
+        join_lines(&lines)
+    }
+
+    fn format_placemark(&self, balloon: &PlacemarkMessage, _: &'a str) -> String {
+        let mut lines = vec![];
+
+        match (balloon.place_name, balloon.get_url()) {
+            (Some(name), Some(url)) => lines.push(format!("**{}**", link(name, url))),
+            (None, Some(url)) => lines.push(link(url, url)),
+            (Some(name), None) => lines.push(format!("**{name}**")),
+            (None, None) => {}
+        }
+
+        [
+            balloon.placemark.name,
+            balloon.placemark.address,
+            balloon.placemark.state,
+            balloon.placemark.city,
+            balloon.placemark.iso_country_code,
+            balloon.placemark.postal_code,
+            balloon.placemark.country,
+            balloon.placemark.street,
+            balloon.placemark.sub_administrative_area,
+            balloon.placemark.sub_locality,
+        ]
+        .into_iter()
+        .flatten()
+        .for_each(|part| lines.push(part.to_string()));
+
+        join_lines(&lines)
+    }
+
+    fn format_handwriting(&self, _: &HandwrittenMessage, _: &str) -> String {
+        String::from("*Handwritten messages are not yet supported!*")
+    }
+
+    fn format_apple_pay(&self, balloon: &AppMessage, _: &str) -> String {
+        let mut out_s = String::new();
+        if let Some(caption) = balloon.caption {
+            out_s.push_str(caption);
+            out_s.push_str(" transaction: ");
+        }
+
+        if let Some(ldtext) = balloon.ldtext {
+            out_s.push_str(ldtext);
+        } else {
+            out_s.push_str("unknown amount");
+        }
+
+        out_s
+    }
+
+    fn format_fitness(&self, balloon: &AppMessage, _: &str) -> String {
+        let mut out_s = String::new();
+        if let Some(app_name) = balloon.app_name {
+            out_s.push_str(app_name);
+            out_s.push_str(" message: ");
+        }
+        if let Some(ldtext) = balloon.ldtext {
+            out_s.push_str(ldtext);
+        } else {
+            out_s.push_str("unknown workout");
+        }
+        out_s
+    }
+
+    fn format_slideshow(&self, balloon: &AppMessage, _: &str) -> String {
+        let mut lines = vec![];
+        if let Some(ldtext) = balloon.ldtext {
+            lines.push(format!("Photo album: {ldtext}"));
+        }
+
+        if let Some(url) = balloon.url {
+            lines.push(link(url, url));
+        }
+
+        join_lines(&lines)
+    }
+
+    fn format_find_my(&self, balloon: &AppMessage, _: &'a str) -> String {
+        let mut out_s = String::new();
+        if let Some(app_name) = balloon.app_name {
+            out_s.push_str(app_name);
+            out_s.push_str(": ");
+        }
+
+        if let Some(ldtext) = balloon.ldtext {
+            out_s.push_str(ldtext);
+        }
+
+        out_s
+    }
+
+    fn format_check_in(&self, balloon: &AppMessage, _: &'a str) -> String {
+        let mut lines = vec![balloon.caption.unwrap_or("Check In").to_string()];
+
+        let metadata: HashMap<&str, &str> = balloon.parse_query_string();
+
+        // Before manual check-in
+        if let Some(date_str) = metadata.get("estimatedEndTime") {
+            // Parse the estimated end time from the message's query string
+            let date_stamp = date_str.parse::<f64>().unwrap_or(0.) as i64 * TIMESTAMP_FACTOR;
+            let date_time = get_local_time(&date_stamp, &0);
+            lines.push(format!(
+                "Expected at {}",
+                self.config.options.date_format.format(&date_time)
+            ));
+        }
+        // Expired check-in
+        else if let Some(date_str) = metadata.get("triggerTime") {
+            // Parse the estimated end time from the message's query string
+            let date_stamp = date_str.parse::<f64>().unwrap_or(0.) as i64 * TIMESTAMP_FACTOR;
+            let date_time = get_local_time(&date_stamp, &0);
+            lines.push(format!(
+                "Was expected at {}",
+                self.config.options.date_format.format(&date_time)
+            ));
+        }
+        // Accepted check-in
+        else if let Some(date_str) = metadata.get("sendDate") {
+            // Parse the estimated end time from the message's query string
+            let date_stamp = date_str.parse::<f64>().unwrap_or(0.) as i64 * TIMESTAMP_FACTOR;
+            let date_time = get_local_time(&date_stamp, &0);
+            lines.push(format!(
+                "Checked in at {}",
+                self.config.options.date_format.format(&date_time)
+            ));
+        }
+
+        join_lines(&lines)
+    }
+
+    fn format_generic_app(
+        &self,
+        balloon: &AppMessage,
+        bundle_id: &str,
+        _: &mut Vec<Attachment>,
+        _: &str,
+    ) -> String {
+        let mut lines = vec![format!(
+            "*{} message:*",
+            balloon.app_name.unwrap_or(bundle_id)
+        )];
+
+        [
+            balloon.title,
+            balloon.subtitle,
+            balloon.caption,
+            balloon.subcaption,
+            balloon.trailing_caption,
+            balloon.trailing_subcaption,
+        ]
+        .into_iter()
+        .flatten()
+        .for_each(|part| lines.push(part.to_string()));
+
+        join_lines(&lines)
+    }
+}
+
+impl<'a> Markdown<'a> {
+    fn get_time(&self, message: &Message, full_date: bool) -> String {
+        let date = message.date(&self.config.offset);
+        let mut time = match &date {
+            Ok(date) if !full_date => self
+                .config
+                .options
+                .date_format
+                .in_zone(date)
+                .format(TIME_FORMAT)
+                .to_string(),
+            _ => self.config.options.date_format.format(&date),
+        };
+        let read_after = message.time_until_read(&self.config.offset);
+        if let Some(read_time) = read_after {
+            if !read_time.is_empty() {
+                let who = if message.is_from_me {
+                    "them"
+                } else {
+                    self.config.options.custom_name.as_deref().unwrap_or("you")
+                };
+                time.push_str(&format!(" (Read by {who} after {read_time})"));
+            }
+        }
+        time
+    }
+
+    /// Add a paragraph to a message, separated from the next one by a blank line
+    fn add_block(&self, string: &mut String, part: &str) {
+        let part = part.trim_end_matches('\n');
+        if !part.is_empty() {
+            string.push_str(part);
+            string.push_str("\n\n");
+        }
+    }
+
+    /// Build the front matter for a new file from the conversation a message belongs to
+    fn new_front_matter(&self, message: &Message) -> FrontMatter {
+        match self.config.conversation(message) {
+            Some((chatroom, _)) => FrontMatter {
+                title: chatroom
+                    .display_name()
+                    .map(String::from)
+                    .unwrap_or_else(|| self.config.filename(chatroom)),
+                participants: self
+                    .config
+                    .chatroom_participants
+                    .get(&chatroom.rowid)
+                    .map(|participants| {
+                        participants
+                            .iter()
+                            .map(|id| self.config.who(Some(*id), false, &None).to_string())
+                            .collect()
+                    })
+                    .unwrap_or_default(),
+                ..Default::default()
+            },
+            None => FrontMatter {
+                title: ORPHANED.to_string(),
+                ..Default::default()
+            },
+        }
+    }
+
+    /// Find the file a message is written to, partitioning it if the export is split by date
+    fn file_for(&mut self, message: &Message) -> &Path {
+        let path = match self.config.conversation(message) {
+            Some((chatroom, id)) => self.files.entry(*id).or_insert_with(|| {
+                let mut path = self.config.options.export_path.clone();
+                path.push(self.config.filename(chatroom));
+                path.set_extension("md");
+                path
+            }),
+            None => &mut self.orphaned,
+        };
+        if let Some(previous) = self.config.partition(path, message) {
+            self.config.close_output(&previous);
+        }
+        path
+    }
+
+    /// Build the front matter for every file the export writes to without writing any messages
+    fn collect_front_matter(&mut self) -> Result<(), RuntimeError> {
+        // Keep track of current message ROWID
+        let mut current_message_row = -1;
+
+        let mut statement =
+            Message::stream_rows(&self.config.db, &self.config.options.query_context)
+                .map_err(RuntimeError::DatabaseError)?;
+
+        let messages = statement
+            .query_map([], |row| Ok(Message::from_row(row)))
+            .map_err(|err| RuntimeError::DatabaseError(TableError::Messages(err)))?;
+
+        for message in messages {
+            let mut msg = Message::extract(message).map_err(RuntimeError::DatabaseError)?;
+
+            // Skip the same messages that `iter_messages()` skips
+            if msg.rowid == current_message_row || !self.config.is_selected(&mut msg) {
+                continue;
+            }
+            current_message_row = msg.rowid;
+
+            if msg.is_announcement() || !msg.is_reaction() {
+                self.track(&msg);
+            }
+        }
+        Ok(())
+    }
+
+    /// Add the sender and date of a message to the front matter of the file it is written to
+    fn track(&mut self, message: &Message) {
+        let path = self.file_for(message).to_path_buf();
+        if !self.front_matter.contains_key(&path) {
+            let front_matter = self.new_front_matter(message);
+            self.front_matter.insert(path.clone(), front_matter);
+        }
+
+        let sender = self
+            .config
+            .who(
+                message.handle_id,
+                message.is_from_me,
+                &message.destination_caller_id,
+            )
+            .to_string();
+        let date = message.date(&self.config.offset);
+
+        if let Some(front_matter) = self.front_matter.get_mut(&path) {
+            front_matter.participants.insert(sender);
+            if date.is_ok() {
+                let timestamp = self.config.options.date_format.format_iso(&date);
+                front_matter.start.get_or_insert_with(|| timestamp.clone());
+                front_matter.end = Some(timestamp);
+            }
+        }
+    }
+
+    /// Write the front matter to the top of a file before its first message
+    fn start_file(&mut self, path: &Path) {
+        let config = self.config;
+        let front_matter = self.front_matter.entry(path.to_path_buf()).or_default();
+
+        // Incremental exports continue the front matter of the file they add to
+        let previous = if config.options.incremental {
+            FrontMatter::reopen(config, path)
+        } else {
+            None
+        };
+
+        match previous {
+            Some((previous, body)) => {
+                front_matter.participants.extend(previous.participants);
+                front_matter.start = previous.start.or(front_matter.start.take());
+                front_matter.last_day = previous.last_day;
+
+                let mut out_s = front_matter.render();
+                out_s.push_str(&body);
+                if let Err(why) = config.replace_output(path, out_s.as_bytes()) {
+                    eprintln!("Unable to write to {path:?}: {why:?}");
+                }
+            }
+            None => config.write_to_file(path, &front_matter.render()),
+        }
+    }
+
+    /// Write a formatted message to its conversation, adding a day heading if it starts a new day
+    fn write_message(&mut self, message: &Message, text: &str) {
+        let path = self.get_or_create_file(message).to_path_buf();
+        if self.started.insert(path.clone()) {
+            self.start_file(&path);
+        }
+
+        let mut out_s = String::new();
+        if let (Some(front_matter), Ok(date)) = (
+            self.front_matter.get_mut(&path),
+            message.date(&self.config.offset),
+        ) {
+            let day = self
+                .config
+                .options
+                .date_format
+                .in_zone(&date)
+                .format(DAY_FORMAT)
+                .to_string();
+            if front_matter.last_day.as_ref() != Some(&day) {
+                out_s.push_str(&format!("## {day}\n\n"));
+                front_matter.last_day = Some(day);
+            }
+        }
+        out_s.push_str(text);
+
+        self.config.write_to_file(&path, &out_s);
+    }
+}
+
+/// Build a Markdown link, wrapping the destination in angle brackets so paths with spaces still resolve
+fn link(text: &str, url: &str) -> String {
+    format!("[{text}](<{url}>)")
+}
+
+/// Join lines into a single paragraph, keeping each on its own line
+fn join_lines(lines: &[String]) -> String {
+    lines.join(LINE_BREAK)
+}
+
+/// Keep line breaks from the original message instead of letting Markdown reflow them
+fn hard_breaks(text: &str) -> String {
+    text.replace('\n', LINE_BREAK)
+}
+
+/// Nest a formatted message in a blockquote
+fn blockquote(text: &str) -> String {
+    let mut out_s = String::with_capacity(text.len());
+    text.trim_end_matches('\n').lines().for_each(|line| {
+        if line.is_empty() {
+            out_s.push_str(">\n");
+        } else {
+            out_s.push_str("> ");
+            out_s.push_str(line);
+            out_s.push('\n');
+        }
+    });
+    out_s.push('\n');
+    out_s
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        env::{set_var, temp_dir},
+        fs::{create_dir_all, read_to_string, remove_dir_all, write},
+    };
+
+    use super::{blockquote, FrontMatter};
+    use crate::{exporters::exporter::Writer, Config, Exporter, Markdown, Options};
+    use imessage_database::tables::{attachment::Attachment, messages::Message};
+
+    fn blank() -> Message {
+        Message {
+            rowid: i32::default(),
+            guid: String::default(),
+            text: None,
+            service: Some("iMessage".to_string()),
+            handle_id: Some(i32::default()),
+            destination_caller_id: None,
+            subject: None,
+            date: i64::default(),
+            date_read: i64::default(),
+            date_delivered: i64::default(),
+            is_from_me: false,
+            is_read: false,
+            item_type: 0,
+            group_title: None,
+            group_action_type: 0,
+            associated_message_guid: None,
+            associated_message_type: Some(i32::default()),
+            balloon_bundle_id: None,
+            expressive_send_style_id: None,
+            thread_originator_guid: None,
+            thread_originator_part: None,
+            date_edited: 0,
+            chat_id: None,
+            num_attachments: 0,
+            deleted_from: None,
+            num_replies: 0,
+        }
+    }
+
+    pub fn fake_attachment() -> Attachment {
+        Attachment {
+            rowid: 0,
+            filename: Some("a/b/c/d.jpg".to_string()),
+            uti: Some("public.png".to_string()),
+            mime_type: Some("image/png".to_string()),
+            transfer_name: Some("d.jpg".to_string()),
+            total_bytes: 100,
+            is_sticker: false,
+            hide_attachment: 0,
+            copied_path: None,
+        }
+    }
+
+    #[test]
+    fn can_create() {
+        let options = Options::fake();
+        let config = Config::new(options).unwrap();
+        let exporter = Markdown::new(&config);
+        assert_eq!(exporter.files.len(), 0);
+        assert_eq!(exporter.front_matter.len(), 0);
+    }
+
+    #[test]
+    fn can_get_time_valid() {
+        // Set timezone to PST for consistent Local time
+        set_var("TZ", "PST");
+
+        // Create exporter
+        let options = Options::fake();
+        let config = Config::new(options).unwrap();
+        let exporter = Markdown::new(&config);
+
+        let mut message = blank();
+        // May 17, 2022  8:29:42 PM
+        message.date = 674526582885055488;
+
+        assert_eq!(exporter.get_time(&message, false), "5:29:42 PM");
+        assert_eq!(
+            exporter.get_time(&message, true),
+            "May 17, 2022  5:29:42 PM"
+        );
+    }
+
+    #[test]
+    fn can_format_md_from_me_normal() {
+        // Set timezone to PST for consistent Local time
+        set_var("TZ", "PST");
+
+        // Create exporter
+        let options = Options::fake();
+        let config = Config::new(options).unwrap();
+        let exporter = Markdown::new(&config);
+
+        let mut message = blank();
+        // May 17, 2022  8:29:42 PM
+        message.date = 674526582885055488;
+        message.text = Some("Hello world".to_string());
+        message.is_from_me = true;
+        message.chat_id = Some(0);
+
+        let actual = exporter.format_message(&message, 0).unwrap();
+        let expected = "**Me** · 5:29:42 PM\n\nHello world\n\n";
+
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn can_format_md_multiline_text() {
+        // Set timezone to PST for consistent Local time
+        set_var("TZ", "PST");
+
+        // Create exporter
+        let options = Options::fake();
+        let config = Config::new(options).unwrap();
+        let exporter = Markdown::new(&config);
+
+        let mut message = blank();
+        // May 17, 2022  8:29:42 PM
+        message.date = 674526582885055488;
+        message.text = Some("Hello\nworld".to_string());
+        message.is_from_me = true;
+
+        let actual = exporter.format_message(&message, 0).unwrap();
+        let expected = "**Me** · 5:29:42 PM\n\nHello  \nworld\n\n";
+
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn can_format_md_from_them_reply() {
+        // Set timezone to PST for consistent Local time
+        set_var("TZ", "PST");
+
+        // Create exporter
+        let options = Options::fake();
+        let mut config = Config::new(options).unwrap();
+        config
+            .participants
+            .insert(999999, "Sample Contact".to_string());
+        let exporter = Markdown::new(&config);
+
+        let mut message = blank();
+        // May 17, 2022  8:29:42 PM
+        message.date = 674526582885055488;
+        message.text = Some("Hello world".to_string());
+        message.handle_id = Some(999999);
+
+        let actual = exporter.format_message(&message, 1).unwrap();
+        let expected = "> **Sample Contact** · May 17, 2022  5:29:42 PM\n>\n> Hello world\n\n";
+
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn can_blockquote_nested_lines() {
+        assert_eq!(blockquote("a\n\nb  \nc\n\n"), "> a\n>\n> b  \n> c\n\n");
+    }
+
+    #[test]
+    fn can_format_md_shareplay() {
+        // Set timezone to PST for consistent Local time
+        set_var("TZ", "PST");
+
+        // Create exporter
+        let options = Options::fake();
+        let config = Config::new(options).unwrap();
+        let exporter = Markdown::new(&config);
+
+        let mut message = blank();
+        // May 17, 2022  8:29:42 PM
+        message.date = 674526582885055488;
+        message.item_type = 6;
+
+        let actual = exporter.format_message(&message, 0).unwrap();
+        let expected = "**Me** · 5:29:42 PM\n\n*SharePlay Message Ended*\n\n";
+
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn can_format_md_announcement() {
+        // Set timezone to PST for consistent Local time
+        set_var("TZ", "PST");
+
+        // Create exporter
+        let options = Options::fake();
+        let config = Config::new(options).unwrap();
+        let exporter = Markdown::new(&config);
+
+        let mut message = blank();
+        // May 17, 2022  8:29:42 PM
+        message.date = 674526582885055488;
+        message.group_title = Some("Hello world".to_string());
+
+        let actual = exporter.format_announcement(&message);
+        let expected = "*5:29:42 PM You renamed the conversation to Hello world*\n\n";
+
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn can_format_md_reaction_me() {
+        // Set timezone to PST for consistent Local time
+        set_var("TZ", "PST");
+
+        // Create exporter
+        let options = Options::fake();
+        let config = Config::new(options).unwrap();
+        let exporter = Markdown::new(&config);
+
+        let mut message = blank();
+        // May 17, 2022  8:29:42 PM
+        message.date = 674526582885055488;
+        message.associated_message_type = Some(2000);
+        message.associated_message_guid = Some("fake_guid".to_string());
+
+        let actual = exporter.format_reaction(&message).unwrap();
+        let expected = "Loved by Me";
+
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn can_format_md_attachment_image() {
+        // Create exporter
+        let options = Options::fake();
+        let config = Config::new(options).unwrap();
+        let exporter = Markdown::new(&config);
+
+        let message = blank();
+
+        let mut attachment = fake_attachment();
+
+        let actual = exporter
+            .format_attachment(&mut attachment, &message)
+            .unwrap();
+
+        assert_eq!(actual, "![d.jpg](<a/b/c/d.jpg>)");
+    }
+
+    #[test]
+    fn can_format_md_attachment_file() {
+        // Create exporter
+        let options = Options::fake();
+        let config = Config::new(options).unwrap();
+        let exporter = Markdown::new(&config);
+
+        let message = blank();
+
+        let mut attachment = fake_attachment();
+        attachment.filename = Some("a/b/c/My File.pdf".to_string());
+        attachment.transfer_name = Some("My File.pdf".to_string());
+        attachment.mime_type = Some("application/pdf".to_string());
+
+        let actual = exporter
+            .format_attachment(&mut attachment, &message)
+            .unwrap();
+
+        assert_eq!(actual, "[My File.pdf](<a/b/c/My File.pdf>) (100.00 B)");
+    }
+
+    #[test]
+    fn can_format_md_attachment_invalid() {
+        // Create exporter
+        let options = Options::fake();
+        let config = Config::new(options).unwrap();
+        let exporter = Markdown::new(&config);
+
+        let message = blank();
+
+        let mut attachment = fake_attachment();
+        attachment.filename = None;
+
+        let actual = exporter.format_attachment(&mut attachment, &message);
+
+        assert_eq!(actual, Err("d.jpg"));
+    }
+
+    #[test]
+    fn can_render_front_matter() {
+        let front_matter = FrontMatter {
+            title: "Group \"Chat\"".to_string(),
+            participants: ["Me".to_string(), "+15558675309".to_string()]
+                .into_iter()
+                .collect(),
+            start: Some("2022-05-17T17:29:42-07:00".to_string()),
+            end: Some("2022-05-18T09:00:00-07:00".to_string()),
+            last_day: None,
+        };
+
+        assert_eq!(
+            front_matter.render(),
+            "---\ntitle: \"Group \\\"Chat\\\"\"\nparticipants:\n  - \"+15558675309\"\n  - \"Me\"\nstart: 2022-05-17T17:29:42-07:00\nend: 2022-05-18T09:00:00-07:00\n---\n\n# Group \"Chat\"\n\n"
+        );
+    }
+
+    #[test]
+    fn can_reopen_front_matter() {
+        let export_path = temp_dir().join("imessage-exporter-markdown-reopen-test");
+        let _ = remove_dir_all(&export_path);
+        create_dir_all(&export_path).unwrap();
+        let path = export_path.join("Group.md");
+
+        let body = "## Tuesday, May 17, 2022\n\nfirst\n\n## Wednesday, May 18, 2022\n\nthird\n\n";
+        write(
+            &path,
+            format!("---\ntitle: \"Group \\\"Chat\\\"\"\nparticipants:\n  - \"+15558675309\"\n  - \"Me\"\nstart: 2022-05-17T17:29:42-07:00\nend: 2022-05-18T17:29:42-07:00\n---\n\n# Group \"Chat\"\n\n{body}"),
+        )
+        .unwrap();
+
+        let config = Config::new(Options::fake()).unwrap();
+        let (front_matter, actual) = FrontMatter::reopen(&config, &path).unwrap();
+        remove_dir_all(&export_path).unwrap();
+
+        assert_eq!(front_matter.title, "Group \"Chat\"");
+        assert_eq!(
+            front_matter.participants,
+            ["Me".to_string(), "+15558675309".to_string()]
+                .into_iter()
+                .collect()
+        );
+        assert_eq!(
+            front_matter.start.as_deref(),
+            Some("2022-05-17T17:29:42-07:00")
+        );
+        assert_eq!(
+            front_matter.end.as_deref(),
+            Some("2022-05-18T17:29:42-07:00")
+        );
+        assert_eq!(
+            front_matter.last_day.as_deref(),
+            Some("Wednesday, May 18, 2022")
+        );
+        assert_eq!(actual, body);
+    }
+
+    #[test]
+    fn can_write_day_headings_and_front_matter() {
+        // Set timezone to PST for consistent Local time
+        set_var("TZ", "PST");
+
+        let export_path = temp_dir().join("imessage-exporter-markdown-test");
+        let _ = remove_dir_all(&export_path);
+        create_dir_all(&export_path).unwrap();
+
+        // Create exporter
+        let mut options = Options::fake();
+        options.export_path = export_path.clone();
+        let config = Config::new(options).unwrap();
+        let mut exporter = Markdown::new(&config);
+
+        let mut first = blank();
+        // May 17, 2022  8:29:42 PM
+        first.date = 674526582885055488;
+        first.is_from_me = true;
+
+        let mut second = blank();
+        // May 17, 2022  9:30:31 PM
+        second.date = 674530231992568192;
+        second.is_from_me = true;
+
+        let mut third = blank();
+        // May 18, 2022  8:29:42 PM
+        third.date = 674612982885055488;
+        third.is_from_me = true;
+
+        for message in [&first, &second, &third] {
+            exporter.track(message);
+        }
+        exporter.write_message(&first, "first\n\n");
+        exporter.write_message(&second, "second\n\n");
+        exporter.write_message(&third, "third\n\n");
+
+        let actual = read_to_string(&exporter.orphaned).unwrap();
+        let expected = "---\ntitle: \"orphaned\"\nparticipants:\n  - \"Me\"\nstart: 2022-05-17T17:29:42-07:00\nend: 2022-05-18T17:29:42-07:00\n---\n\n# orphaned\n\n## Tuesday, May 17, 2022\n\nfirst\n\nsecond\n\n## Wednesday, May 18, 2022\n\nthird\n\n";
+
+        remove_dir_all(&export_path).unwrap();
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn can_continue_front_matter_incremental() {
+        // Set timezone to PST for consistent Local time
+        set_var("TZ", "PST");
+
+        let export_path = temp_dir().join("imessage-exporter-markdown-incremental-test");
+        let _ = remove_dir_all(&export_path);
+        create_dir_all(&export_path).unwrap();
+
+        // Create exporter
+        let mut options = Options::fake();
+        options.export_path = export_path.clone();
+        options.incremental = true;
+        let config = Config::new(options).unwrap();
+        let mut exporter = Markdown::new(&config);
+
+        write(
+            &exporter.orphaned,
+            "---\ntitle: \"orphaned\"\nparticipants:\n  - \"+15558675309\"\nstart: 2022-05-17T17:29:42-07:00\nend: 2022-05-17T17:29:42-07:00\n---\n\n# orphaned\n\n## Tuesday, May 17, 2022\n\nfirst\n\n",
+        )
+        .unwrap();
+
+        let mut second = blank();
+        // May 17, 2022  9:30:31 PM
+        second.date = 674530231992568192;
+        second.is_from_me = true;
+
+        let mut third = blank();
+        // May 18, 2022  8:29:42 PM
+        third.date = 674612982885055488;
+        third.is_from_me = true;
+
+        for message in [&second, &third] {
+            exporter.track(message);
+        }
+        exporter.write_message(&second, "second\n\n");
+        exporter.write_message(&third, "third\n\n");
+
+        let actual = read_to_string(&exporter.orphaned).unwrap();
+        let expected = "---\ntitle: \"orphaned\"\nparticipants:\n  - \"+15558675309\"\n  - \"Me\"\nstart: 2022-05-17T17:29:42-07:00\nend: 2022-05-18T17:29:42-07:00\n---\n\n# orphaned\n\n## Tuesday, May 17, 2022\n\nfirst\n\nsecond\n\n## Wednesday, May 18, 2022\n\nthird\n\n";
+
+        remove_dir_all(&export_path).unwrap();
+        assert_eq!(actual, expected);
+    }
+}
+
+#[cfg(test)]
+mod balloon_format_tests {
+    use crate::{exporters::exporter::BalloonFormatter, Config, Exporter, Markdown, Options};
+    use imessage_database::message_types::{app::AppMessage, music::MusicMessage, url::URLMessage};
+
+    #[test]
+    fn can_format_md_url() {
+        // Create exporter
+        let options = Options::fake();
+        let config = Config::new(options).unwrap();
+        let exporter = Markdown::new(&config);
+
+        let balloon = URLMessage {
+            title: Some("title"),
+            summary: Some("summary"),
+            url: Some("url"),
+            original_url: Some("original_url"),
+            item_type: Some("item_type"),
+            images: vec!["images"],
+            icons: vec!["icons"],
+            site_name: Some("site_name"),
+            placeholder: false,
+        };
+
+        let expected = exporter.format_url(&balloon, "");
+        let actual = "**[title](<url>)**  \nsummary";
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn can_format_md_music() {
+        // Create exporter
+        let options = Options::fake();
+        let config = Config::new(options).unwrap();
+        let exporter = Markdown::new(&config);
+
+        let balloon = MusicMessage {
+            url: Some("url"),
+            preview: Some("preview"),
+            artist: Some("artist"),
+            album: Some("album"),
+            track_name: Some("track_name"),
+        };
+
+        let expected = exporter.format_music(&balloon, "");
+        let actual = "**track_name**  \nalbum  \nartist  \n[url](<url>)";
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn can_format_md_generic_app() {
+        // Create exporter
+        let options = Options::fake();
+        let config = Config::new(options).unwrap();
+        let exporter = Markdown::new(&config);
+
+        let balloon = AppMessage {
+            image: Some("image"),
+            url: Some("url"),
+            title: Some("title"),
+            subtitle: Some("subtitle"),
+            caption: Some("caption"),
+            subcaption: Some("subcaption"),
+            trailing_caption: Some("trailing_caption"),
+            trailing_subcaption: Some("trailing_subcaption"),
+            app_name: Some("app_name"),
+            ldtext: Some("ldtext"),
+        };
+
+        let expected = exporter.format_generic_app(&balloon, "bundle_id", &mut vec![], "");
+        let actual = "*app_name message:*  \ntitle  \nsubtitle  \ncaption  \nsubcaption  \ntrailing_caption  \ntrailing_subcaption";
+
+        assert_eq!(expected, actual);
+    }
+}
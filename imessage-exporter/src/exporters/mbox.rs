@@ -0,0 +1,684 @@
+// File to export messages as emails in an mbox archive
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+
+use crate::{
+    app::{error::RuntimeError, progress::build_progress_bar_export, runtime::Config},
+    exporters::exporter::Exporter,
+};
+
+use imessage_database::{
+    error::table::TableError,
+    message_types::variants::Announcement,
+    tables::{
+        attachment::Attachment,
+        messages::{BubbleType, Message},
+        table::{Table, ME, ORPHANED, YOU},
+    },
+};
+
+/// Domain used to build email addresses for phone numbers and Message IDs, reserved by [RFC 2606](https://www.rfc-editor.org/rfc/rfc2606)
+const DOMAIN: &str = "imessage.invalid";
+/// Address used for messages sent from the database owner when their handle is unknown
+const ME_ADDRESS: &str = "me@imessage.invalid";
+/// Subject used for messages that are not in a conversation
+const DEFAULT_SUBJECT: &str = "iMessage";
+/// Format of the timestamp in the `From ` line that separates messages in an mbox file
+const MBOX_DATE_FORMAT: &str = "%a %b %e %H:%M:%S %Y";
+/// Maximum line length for base64 encoded MIME parts, per [RFC 2045](https://www.rfc-editor.org/rfc/rfc2045#section-6.8)
+const BASE64_LINE_LENGTH: usize = 76;
+/// Maximum number of bytes encoded in a single header encoded-word, keeping each word under 75 characters
+const ENCODED_WORD_BYTES: usize = 45;
+
+pub struct Mbox<'a> {
+    /// Data that is setup from the application's runtime
+    pub config: &'a Config,
+    /// Handles to files we want to write messages to
+    /// Map of internal unique chatroom ID to a filename
+    pub files: HashMap<i32, PathBuf>,
+    /// Path to file for orphaned messages
+    pub orphaned: PathBuf,
+}
+
+impl<'a> Exporter<'a> for Mbox<'a> {
+    fn new(config: &'a Config) -> Self {
+        let mut orphaned = config.options.export_path.clone();
+        orphaned.push(ORPHANED);
+        orphaned.set_extension("mbox");
+        Mbox {
+            config,
+            files: HashMap::new(),
+            orphaned,
+        }
+    }
+
+    fn iter_messages(&mut self) -> Result<(), RuntimeError> {
+        // Tell the user what we are doing
+        eprintln!(
+            "Exporting to {} as mbox...",
+            self.config.options.export_path.display()
+        );
+
+        // Keep track of current message ROWID
+        let mut current_message_row = -1;
+
+        // Set up progress bar
+        let mut current_message = 0;
+        let total_messages =
+            Message::get_count(&self.config.db, &self.config.options.query_context)
+                .map_err(RuntimeError::DatabaseError)?;
+        let pb = build_progress_bar_export(total_messages);
+
+        let mut statement =
+            Message::stream_rows(&self.config.db, &self.config.options.query_context)
+                .map_err(RuntimeError::DatabaseError)?;
+
+        let messages = statement
+            .query_map([], |row| Ok(Message::from_row(row)))
+            .map_err(|err| RuntimeError::DatabaseError(TableError::Messages(err)))?;
+
+        for message in messages {
+            let mut msg = Message::extract(message).map_err(RuntimeError::DatabaseError)?;
+
+            // Early escape if we try and render the same message GUID twice
+            // See https://github.com/ReagentX/imessage-exporter/issues/135 for rationale
+            if msg.rowid == current_message_row {
+                current_message += 1;
+                continue;
+            }
+            current_message_row = msg.rowid;
+
+            // Skip messages that are not near a match for the text filter
+            if !self.config.is_selected(&mut msg) {
+                current_message += 1;
+                continue;
+            }
+
+            // Reactions do not have any content of their own, so they do not become emails
+            if !msg.is_reaction() {
+                self.config.gen_text(&mut msg);
+                let email = self
+                    .format_email(&msg)
+                    .map_err(RuntimeError::DatabaseError)?;
+                self.config
+                    .write_to_file(self.get_or_create_file(&msg), &email);
+            }
+
+            current_message += 1;
+            if current_message % 99 == 0 {
+                pb.set_position(current_message);
+            }
+        }
+        pb.finish();
+        Ok(())
+    }
+
+    /// Create a file for the given chat, caching it so we don't need to build it later
+    fn get_or_create_file(&mut self, message: &Message) -> &Path {
+        let path = match self.config.conversation(message) {
+            Some((chatroom, id)) => self.files.entry(*id).or_insert_with(|| {
+                let mut path = self.config.options.export_path.clone();
+                path.push(self.config.filename(chatroom));
+                path.set_extension("mbox");
+                path
+            }),
+            None => &mut self.orphaned,
+        };
+        if let Some(previous) = self.config.partition(path, message) {
+            self.config.close_output(&previous);
+        }
+        self.config.record(message, path);
+        path
+    }
+}
+
+impl<'a> Mbox<'a> {
+    /// Build a complete mbox entry for a message, including the `From ` separator line and a trailing blank line
+    fn format_email(&self, message: &Message) -> Result<String, TableError> {
+        let date = message.date(&self.config.offset);
+        let sender_address = self.sender_address(message);
+
+        let mut headers = vec![
+            format!("From: {}", self.sender(message)),
+            format!("To: {}", self.recipients(message).join(", ")),
+        ];
+        if let Ok(date) = &date {
+            headers.push(format!("Date: {}", date.to_rfc2822()));
+        }
+        headers.push(format!(
+            "Subject: {}",
+            encode_header(&self.format_subject(message))
+        ));
+        headers.push(format!("Message-ID: {}", message_id(&message.guid)));
+        if let Some(thread_originator_guid) = &message.thread_originator_guid {
+            headers.push(format!(
+                "In-Reply-To: {}",
+                message_id(thread_originator_guid)
+            ));
+            headers.push(format!(
+                "References: {}",
+                message_id(thread_originator_guid)
+            ));
+        }
+        if let Some(service) = &message.service {
+            headers.push(format!("X-iMessage-Service: {service}"));
+        }
+        headers.push(String::from("MIME-Version: 1.0"));
+
+        let mut text = self.format_text(message);
+        let parts = self.format_attachments(message, &mut text)?;
+
+        let mut email = format!(
+            "From {sender_address} {}\n",
+            date.map(|date| date.format(MBOX_DATE_FORMAT).to_string())
+                .unwrap_or_else(|_| String::from("Thu Jan  1 00:00:00 1970"))
+        );
+        headers.iter().for_each(|header| {
+            email.push_str(header);
+            email.push('\n');
+        });
+
+        if parts.is_empty() {
+            email.push_str(&text_part_headers());
+            email.push('\n');
+            email.push_str(&escape_from_lines(&text));
+            email.push('\n');
+        } else {
+            let boundary = format!("imessage-exporter-{}", message.guid);
+            email.push_str(&format!(
+                "Content-Type: multipart/mixed; boundary=\"{boundary}\"\n\n"
+            ));
+            email.push_str(&format!("--{boundary}\n"));
+            email.push_str(&text_part_headers());
+            email.push('\n');
+            email.push_str(&escape_from_lines(&text));
+            email.push('\n');
+            parts.iter().for_each(|part| {
+                email.push_str(&format!("--{boundary}\n"));
+                email.push_str(part);
+            });
+            email.push_str(&format!("--{boundary}--\n"));
+        }
+
+        // mbox entries are separated by a blank line
+        email.push('\n');
+        Ok(email)
+    }
+
+    /// Get the text content of a message, without attachment or app placeholders
+    fn format_text(&self, message: &Message) -> String {
+        if message.is_announcement() {
+            return self.format_announcement(message);
+        }
+
+        message
+            .body()
+            .iter()
+            .filter_map(|part| match part {
+                BubbleType::Text(text) => Some(*text),
+                _ => None,
+            })
+            .collect::<Vec<&str>>()
+            .join("\n")
+    }
+
+    /// Describe a group announcement, i.e. a name or photo change
+    fn format_announcement(&self, message: &Message) -> String {
+        let mut who = self.config.who(
+            message.handle_id,
+            message.is_from_me,
+            &message.destination_caller_id,
+        );
+        // Rename yourself so we render the proper grammar here
+        if who == ME {
+            who = self.config.options.custom_name.as_deref().unwrap_or(YOU);
+        }
+
+        match message.get_announcement() {
+            Some(Announcement::NameChange(name)) => {
+                format!("{who} renamed the conversation to {name}")
+            }
+            Some(Announcement::PhotoChange) => format!("{who} changed the group photo."),
+            Some(Announcement::Unknown(num)) => format!("{who} performed unknown action {num}."),
+            None => String::from("Unable to format announcement!"),
+        }
+    }
+
+    /// Use the message's subject if it has one, otherwise the name of the chat it belongs to
+    fn format_subject(&self, message: &Message) -> String {
+        if let Some(subject) = &message.subject {
+            return subject.to_string();
+        }
+
+        match self.config.conversation(message) {
+            Some((chatroom, _)) => match chatroom.display_name() {
+                Some(name) => name.to_string(),
+                None => match self.config.chatroom_participants.get(&chatroom.rowid) {
+                    Some(participants) => self.config.filename_from_participants(participants),
+                    None => chatroom.chat_identifier.clone(),
+                },
+            },
+            None => DEFAULT_SUBJECT.to_string(),
+        }
+    }
+
+    /// Read a message's attachments from the disk into base64 encoded MIME parts
+    ///
+    /// Attachments that cannot be read are noted at the end of the message text instead
+    fn format_attachments(
+        &self,
+        message: &Message,
+        text: &mut String,
+    ) -> Result<Vec<String>, TableError> {
+        if !message.has_attachments() {
+            return Ok(vec![]);
+        }
+
+        let attachments = self.config.attachments(message)?;
+        let mut parts = Vec::with_capacity(attachments.len());
+        for attachment in &attachments {
+            match attachment.as_bytes(
+                &self.config.options.platform,
+                &self.config.options.db_path,
+                self.config.options.attachment_root.as_deref(),
+            ) {
+                Ok(Some(bytes)) => parts.push(attachment_part(attachment, &bytes)),
+                _ => {
+                    if !text.is_empty() {
+                        text.push('\n');
+                    }
+                    text.push_str(&format!("Attachment missing: {}", attachment.filename()));
+                }
+            }
+        }
+        Ok(parts)
+    }
+
+    /// Build the `From` mailbox for a message
+    fn sender(&self, message: &Message) -> String {
+        let name = if message.is_from_me {
+            Some(self.config.who(None, true, &message.destination_caller_id))
+        } else {
+            self.contact_name(message.handle_id)
+        };
+        mailbox(name, self.sender_handle(message))
+    }
+
+    /// Get the name of the contact a handle belongs to, if contacts were provided
+    fn contact_name(&self, handle_id: Option<i32>) -> Option<&str> {
+        handle_id
+            .and_then(|handle_id| self.config.names.get(&handle_id))
+            .map(String::as_str)
+    }
+
+    /// Build the `From ` line address for a message
+    fn sender_address(&self, message: &Message) -> String {
+        self.sender_handle(message)
+            .map(address)
+            .unwrap_or_else(|| ME_ADDRESS.to_string())
+    }
+
+    /// Get the phone number or email address of whoever sent a message, if it is known
+    fn sender_handle<'b>(&'b self, message: &'b Message) -> Option<&'b str> {
+        if message.is_from_me {
+            return message.destination_caller_id.as_deref();
+        }
+        message
+            .handle_id
+            .and_then(|handle_id| self.config.addresses.get(&handle_id))
+            .map(String::as_str)
+    }
+
+    /// Build the `To` mailboxes for a message, i.e. every member of the conversation except the sender
+    fn recipients(&self, message: &Message) -> Vec<String> {
+        let mut recipients = vec![];
+
+        if !message.is_from_me {
+            recipients.push(mailbox(
+                Some(self.config.who(None, true, &message.destination_caller_id)),
+                message.destination_caller_id.as_deref(),
+            ));
+        }
+
+        if let Some((chatroom, _)) = self.config.conversation(message) {
+            if let Some(participants) = self.config.chatroom_participants.get(&chatroom.rowid) {
+                participants
+                    .iter()
+                    .filter(|participant| {
+                        message.is_from_me || Some(**participant) != message.handle_id
+                    })
+                    .filter_map(|participant| {
+                        self.config.addresses.get(participant).map(|handle| {
+                            mailbox(self.contact_name(Some(*participant)), Some(handle))
+                        })
+                    })
+                    .for_each(|recipient| {
+                        if !recipients.contains(&recipient) {
+                            recipients.push(recipient);
+                        }
+                    });
+            }
+        }
+
+        recipients
+    }
+}
+
+/// Headers for the plain text part of a message
+fn text_part_headers() -> String {
+    String::from("Content-Type: text/plain; charset=utf-8\nContent-Transfer-Encoding: 8bit\n")
+}
+
+/// Build a base64 encoded MIME part for an attachment
+fn attachment_part(attachment: &Attachment, bytes: &[u8]) -> String {
+    let filename = encode_header(attachment.filename());
+    let mut part = format!(
+        "Content-Type: {}; name=\"{filename}\"\nContent-Disposition: attachment; filename=\"{filename}\"\nContent-Transfer-Encoding: base64\n\n",
+        attachment
+            .mime_type
+            .as_deref()
+            .unwrap_or("application/octet-stream"),
+    );
+
+    let encoded = STANDARD.encode(bytes);
+    encoded
+        .as_bytes()
+        .chunks(BASE64_LINE_LENGTH)
+        .for_each(|line| {
+            // base64 output is always ASCII
+            part.push_str(&String::from_utf8_lossy(line));
+            part.push('\n');
+        });
+    part
+}
+
+/// Build an email address for a handle, which is either an email address or a phone number
+fn address(handle: &str) -> String {
+    if handle.contains('@') {
+        return handle.to_string();
+    }
+    let local: String = handle
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '+')
+        .collect();
+    format!("{local}@{DOMAIN}")
+}
+
+/// Build an RFC 5322 mailbox, i.e. `"Name" <address>`, omitting the name if there is none or it is just the handle
+fn mailbox(name: Option<&str>, handle: Option<&str>) -> String {
+    let address = handle
+        .map(address)
+        .unwrap_or_else(|| ME_ADDRESS.to_string());
+    let Some(name) = name.filter(|name| !name.is_empty() && handle != Some(*name)) else {
+        return format!("<{address}>");
+    };
+    if name.is_ascii() {
+        return format!(
+            "\"{}\" <{address}>",
+            name.replace('\\', "\\\\").replace('"', "\\\"")
+        );
+    }
+    format!("{} <{address}>", encode_header(name))
+}
+
+/// Build a Message ID from a message GUID
+fn message_id(guid: &str) -> String {
+    format!("<{guid}@{DOMAIN}>")
+}
+
+/// Encode non-ASCII header values as [RFC 2047](https://www.rfc-editor.org/rfc/rfc2047) encoded-words
+fn encode_header(value: &str) -> String {
+    if value.is_ascii() && !value.contains(['\r', '\n']) {
+        return value.to_string();
+    }
+
+    let mut words = vec![];
+    let mut start = 0;
+    let mut end = 0;
+    for (idx, c) in value.char_indices() {
+        let next = idx + c.len_utf8();
+        if next - start > ENCODED_WORD_BYTES {
+            words.push(&value[start..end]);
+            start = end;
+        }
+        end = next;
+    }
+    words.push(&value[start..end]);
+
+    words
+        .iter()
+        .map(|word| format!("=?UTF-8?B?{}?=", STANDARD.encode(word)))
+        .collect::<Vec<String>>()
+        .join("\n ")
+}
+
+/// Quote lines that would otherwise be read as the start of a new message, per the `mboxrd` format
+fn escape_from_lines(text: &str) -> String {
+    text.lines()
+        .map(|line| {
+            if line.trim_start_matches('>').starts_with("From ") {
+                format!(">{line}")
+            } else {
+                line.to_string()
+            }
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeSet, env::set_var};
+
+    use super::{address, encode_header, escape_from_lines, mailbox};
+    use crate::{Config, Exporter, Mbox, Options};
+    use imessage_database::tables::{chat::Chat, messages::Message};
+
+    fn blank() -> Message {
+        Message {
+            rowid: i32::default(),
+            guid: String::default(),
+            text: None,
+            service: Some("iMessage".to_string()),
+            handle_id: Some(i32::default()),
+            destination_caller_id: None,
+            subject: None,
+            date: i64::default(),
+            date_read: i64::default(),
+            date_delivered: i64::default(),
+            is_from_me: false,
+            is_read: false,
+            item_type: 0,
+            group_title: None,
+            group_action_type: 0,
+            associated_message_guid: None,
+            associated_message_type: Some(i32::default()),
+            balloon_bundle_id: None,
+            expressive_send_style_id: None,
+            thread_originator_guid: None,
+            thread_originator_part: None,
+            date_edited: 0,
+            chat_id: None,
+            num_attachments: 0,
+            deleted_from: None,
+            num_replies: 0,
+        }
+    }
+
+    #[test]
+    fn can_create() {
+        let options = Options::fake();
+        let config = Config::new(options).unwrap();
+        let exporter = Mbox::new(&config);
+        assert_eq!(exporter.files.len(), 0);
+    }
+
+    #[test]
+    fn can_build_address_from_phone() {
+        assert_eq!(
+            address("+1 (555) 555-0100"),
+            "+15555550100@imessage.invalid"
+        );
+    }
+
+    #[test]
+    fn can_build_address_from_email() {
+        assert_eq!(address("jane@example.com"), "jane@example.com");
+    }
+
+    #[test]
+    fn can_build_mailbox() {
+        assert_eq!(
+            mailbox(Some("jane@example.com"), Some("jane@example.com")),
+            "<jane@example.com>"
+        );
+        assert_eq!(
+            mailbox(None, Some("jane@example.com")),
+            "<jane@example.com>"
+        );
+        assert_eq!(
+            mailbox(Some("Jane \"JD\" Doe"), Some("jane@example.com")),
+            "\"Jane \\\"JD\\\" Doe\" <jane@example.com>"
+        );
+        assert_eq!(mailbox(Some("Me"), None), "\"Me\" <me@imessage.invalid>");
+        assert_eq!(
+            mailbox(Some("Zoë"), Some("zoe@example.com")),
+            "=?UTF-8?B?Wm/Dqw==?= <zoe@example.com>"
+        );
+    }
+
+    #[test]
+    fn can_encode_header() {
+        assert_eq!(encode_header("Book Club"), "Book Club");
+        assert_eq!(encode_header("Café"), "=?UTF-8?B?Q2Fmw6k=?=");
+    }
+
+    #[test]
+    fn can_encode_long_header() {
+        let encoded = encode_header(&"é".repeat(30));
+        let words: Vec<&str> = encoded.split("\n ").collect();
+        assert_eq!(words.len(), 2);
+        assert!(words.iter().all(|word| word.len() <= 75));
+    }
+
+    #[test]
+    fn can_escape_from_lines() {
+        assert_eq!(
+            escape_from_lines("From here\n>From there\nNot From"),
+            ">From here\n>>From there\nNot From"
+        );
+    }
+
+    #[test]
+    fn can_format_email_from_them() {
+        // Set timezone to PST for consistent Local time
+        set_var("TZ", "PST");
+
+        // Create exporter
+        let options = Options::fake();
+        let mut config = Config::new(options).unwrap();
+        config
+            .participants
+            .insert(999999, "+15555550100".to_string());
+        config.addresses.insert(999999, "+15555550100".to_string());
+        let exporter = Mbox::new(&config);
+
+        let mut message = blank();
+        // May 17, 2022  8:29:42 PM
+        message.date = 674526582885055488;
+        message.guid = "ABCD".to_string();
+        message.text = Some("Hello world\nFrom me".to_string());
+        message.handle_id = Some(999999);
+        message.thread_originator_guid = Some("EFGH".to_string());
+
+        let actual = exporter.format_email(&message).unwrap();
+        let expected = "From +15555550100@imessage.invalid Tue May 17 17:29:42 2022\n\
+            From: <+15555550100@imessage.invalid>\n\
+            To: \"Me\" <me@imessage.invalid>\n\
+            Date: Tue, 17 May 2022 17:29:42 -0700\n\
+            Subject: iMessage\n\
+            Message-ID: <ABCD@imessage.invalid>\n\
+            In-Reply-To: <EFGH@imessage.invalid>\n\
+            References: <EFGH@imessage.invalid>\n\
+            X-iMessage-Service: iMessage\n\
+            MIME-Version: 1.0\n\
+            Content-Type: text/plain; charset=utf-8\n\
+            Content-Transfer-Encoding: 8bit\n\
+            \n\
+            Hello world\n\
+            >From me\n\
+            \n";
+
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn can_build_addresses_from_each_handle() {
+        // Create exporter
+        let options = Options::fake();
+        let mut config = Config::new(options).unwrap();
+        config.chatrooms.insert(
+            1,
+            Chat {
+                rowid: 1,
+                chat_identifier: "chat0".to_string(),
+                service_name: Some("iMessage".to_string()),
+                display_name: None,
+            },
+        );
+        config.real_chatrooms.insert(1, 1);
+        config
+            .chatroom_participants
+            .insert(1, BTreeSet::from([10, 11, 12]));
+        // Handles 10 and 11 share a `person_centric_id` and belong to a contact
+        for (id, participant, address) in [
+            (10, "+15555550100 jane@example.com", "+15555550100"),
+            (11, "+15555550100 jane@example.com", "jane@example.com"),
+            (12, "+15555550101", "+15555550101"),
+        ] {
+            config.participants.insert(id, participant.to_string());
+            config.addresses.insert(id, address.to_string());
+        }
+        config.names.insert(10, "Jane Doe".to_string());
+        config.names.insert(11, "Jane Doe".to_string());
+        let exporter = Mbox::new(&config);
+
+        let mut message = blank();
+        message.chat_id = Some(1);
+        message.handle_id = Some(10);
+
+        assert_eq!(
+            exporter.sender(&message),
+            "\"Jane Doe\" <+15555550100@imessage.invalid>"
+        );
+        assert_eq!(
+            exporter.sender_address(&message),
+            "+15555550100@imessage.invalid"
+        );
+        assert_eq!(
+            exporter.recipients(&message),
+            vec![
+                "\"Me\" <me@imessage.invalid>",
+                "\"Jane Doe\" <jane@example.com>",
+                "<+15555550101@imessage.invalid>",
+            ]
+        );
+    }
+
+    #[test]
+    fn can_format_email_subject() {
+        // Create exporter
+        let options = Options::fake();
+        let config = Config::new(options).unwrap();
+        let exporter = Mbox::new(&config);
+
+        let mut message = blank();
+        message.subject = Some("Plans".to_string());
+
+        assert_eq!(exporter.format_subject(&message), "Plans");
+    }
+}
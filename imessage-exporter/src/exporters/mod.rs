@@ -1,4 +1,11 @@
+pub mod csv;
 pub mod exporter;
 pub mod html;
+pub mod jsonl;
+pub mod markdown;
+pub mod mbox;
+pub mod parquet;
+pub mod sqlite;
+pub mod template;
 pub mod txt;
-pub mod jsonl;
\ No newline at end of file
+pub mod xml;
@@ -0,0 +1,832 @@
+/*!
+ Exports messages, attachments, and reactions as [Apache Parquet](https://parquet.apache.org/) files.
+
+ Rows are buffered and written as Arrow record batches of [`BATCH_SIZE`] rows, so memory use
+ stays bounded no matter how large the source database is. Column names match the tables
+ documented in the [`SQLite`](crate::exporters::sqlite::SCHEMA) export.
+
+ All dates are stored as microsecond-precision UTC timestamps.
+*/
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use arrow_array::{
+    ArrayRef, BooleanArray, Int32Array, RecordBatch, StringArray, TimestampMicrosecondArray,
+    UInt64Array,
+};
+use arrow_schema::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use parquet::{
+    arrow::ArrowWriter, basic::Compression, errors::ParquetError,
+    file::properties::WriterProperties,
+};
+
+use crate::{
+    app::{
+        encryption::{Encryption, OutputFile},
+        error::RuntimeError,
+        progress::build_progress_bar_export,
+        runtime::Config,
+    },
+    exporters::exporter::Exporter,
+    SQLite,
+};
+
+use imessage_database::{
+    error::table::TableError,
+    message_types::variants::Variant,
+    tables::{chat::Chat, messages::Message, table::Table},
+};
+
+/// Number of rows buffered before they are written as a record batch
+pub const BATCH_SIZE: usize = 8192;
+/// Maximum number of rows in a Parquet row group, which the writer holds in memory until it is flushed
+const ROW_GROUP_SIZE: usize = 65536;
+/// Time zone of every timestamp column
+const TIME_ZONE: &str = "UTC";
+/// Name of the exported messages file
+const MESSAGES: &str = "messages";
+/// Name of the exported attachments file
+const ATTACHMENTS: &str = "attachments";
+/// Name of the exported reactions file
+const REACTIONS: &str = "reactions";
+
+pub struct Parquet<'a> {
+    /// Data that is setup from the application's runtime
+    pub config: &'a Config,
+    /// Path to the exported messages file
+    pub path: PathBuf,
+    /// Cache of conversation names, keyed by deduplicated conversation ID
+    names: HashMap<i32, String>,
+}
+
+impl<'a> Exporter<'a> for Parquet<'a> {
+    fn new(config: &'a Config) -> Self {
+        Parquet {
+            config,
+            path: Self::output_path(config, MESSAGES),
+            names: HashMap::new(),
+        }
+    }
+
+    fn iter_messages(&mut self) -> Result<(), RuntimeError> {
+        // Tell the user what we are doing
+        eprintln!(
+            "Exporting to {} as parquet...",
+            self.config.options.export_path.display()
+        );
+
+        let encryption = self.config.options.encryption.as_ref();
+        let mut output = Output {
+            messages: Batches::create(&self.path, encryption)?,
+            attachments: Batches::create(&Self::output_path(self.config, ATTACHMENTS), encryption)?,
+            reactions: Batches::create(&Self::output_path(self.config, REACTIONS), encryption)?,
+        };
+
+        // Keep track of current message ROWID
+        let mut current_message_row = -1;
+
+        // Set up progress bar
+        let mut current_message = 0;
+        let total_messages =
+            Message::get_count(&self.config.db, &self.config.options.query_context)
+                .map_err(RuntimeError::DatabaseError)?;
+        let pb = build_progress_bar_export(total_messages);
+
+        let mut statement =
+            Message::stream_rows(&self.config.db, &self.config.options.query_context)
+                .map_err(RuntimeError::DatabaseError)?;
+
+        let messages = statement
+            .query_map([], |row| Ok(Message::from_row(row)))
+            .map_err(|err| RuntimeError::DatabaseError(TableError::Messages(err)))?;
+
+        for message in messages {
+            let mut msg = Message::extract(message).map_err(RuntimeError::DatabaseError)?;
+
+            // Early escape if we try and render the same message GUID twice
+            // See https://github.com/ReagentX/imessage-exporter/issues/135 for rationale
+            if msg.rowid == current_message_row {
+                current_message += 1;
+                continue;
+            }
+            current_message_row = msg.rowid;
+
+            // Skip messages that are not near a match for the text filter
+            if !self.config.is_selected(&mut msg) {
+                current_message += 1;
+                continue;
+            }
+
+            // Reactions are written alongside the message they target
+            if !msg.is_reaction() {
+                self.config.gen_text(&mut msg);
+                self.write_message(&mut output, &msg)?;
+            }
+
+            current_message += 1;
+            if current_message % 99 == 0 {
+                pb.set_position(current_message);
+            }
+        }
+        pb.finish();
+
+        output.messages.close()?;
+        output.attachments.close()?;
+        output.reactions.close()?;
+
+        Ok(())
+    }
+
+    /// All messages are written to the same file
+    fn get_or_create_file(&mut self, _: &Message) -> &Path {
+        &self.path
+    }
+}
+
+impl<'a> Parquet<'a> {
+    /// Get the path to one of the exported files
+    fn output_path(config: &Config, name: &str) -> PathBuf {
+        let mut path = config.options.export_path.clone();
+        path.push(name);
+        path.set_extension("parquet");
+        path
+    }
+
+    /// Write a message along with its attachments and reactions
+    fn write_message(
+        &mut self,
+        output: &mut Output,
+        message: &Message,
+    ) -> Result<(), RuntimeError> {
+        let row = self.message_row(message);
+        output.messages.push(row)?;
+        self.write_attachments(output, message)?;
+        self.write_reactions(output, message)?;
+        Ok(())
+    }
+
+    /// Build the row for a message
+    fn message_row(&mut self, message: &Message) -> MessageRow {
+        let (variant, app) = SQLite::variant_columns(message);
+        let (conversation_id, conversation) = match self.config.conversation(message) {
+            Some((chatroom, id)) => (Some(*id), Some(self.conversation_name(chatroom, *id))),
+            None => (None, None),
+        };
+
+        MessageRow {
+            id: message.rowid,
+            guid: message.guid.clone(),
+            conversation_id,
+            conversation,
+            sender_id: self.sender_id(message),
+            sender: self
+                .config
+                .who(
+                    message.handle_id,
+                    message.is_from_me,
+                    &message.destination_caller_id,
+                )
+                .to_string(),
+            is_from_me: message.is_from_me,
+            date: timestamp(&message.date, &self.config.offset),
+            date_read: timestamp(&message.date_read, &self.config.offset),
+            date_delivered: timestamp(&message.date_delivered, &self.config.offset),
+            date_edited: timestamp(&message.date_edited, &self.config.offset),
+            service: message.service.clone(),
+            subject: message.subject.clone(),
+            text: message.text.clone(),
+            variant,
+            app,
+            expressive: SQLite::expressive_column(&message.get_expressive()),
+            reply_to_guid: message.thread_originator_guid.clone(),
+            is_edited: message.is_edited(),
+            is_deleted: message.is_deleted(),
+        }
+    }
+
+    /// Write the attachments of a message, copying the files if requested
+    fn write_attachments(
+        &self,
+        output: &mut Output,
+        message: &Message,
+    ) -> Result<(), RuntimeError> {
+        if !message.has_attachments() {
+            return Ok(());
+        }
+
+        let mut attachments = self
+            .config
+            .attachments(message)
+            .map_err(RuntimeError::DatabaseError)?;
+
+        for attachment in attachments.iter_mut() {
+            let _ = self.config.options.attachment_manager.handle_attachment(
+                message,
+                attachment,
+                self.config,
+            );
+            output.attachments.push(AttachmentRow {
+                id: attachment.rowid,
+                message_id: message.rowid,
+                filename: attachment.filename().to_string(),
+                mime_type: attachment.mime_type.clone(),
+                uti: attachment.uti.clone(),
+                total_bytes: attachment.total_bytes,
+                is_sticker: attachment.is_sticker,
+                path: self.config.message_attachment_path(attachment),
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Write the tapbacks and stickers placed on a message from the reactions cache
+    ///
+    /// Removed tapbacks are not written.
+    fn write_reactions(&self, output: &mut Output, message: &Message) -> Result<(), RuntimeError> {
+        let reactions_map = match self.config.reactions.get(&message.guid) {
+            Some(reactions_map) => reactions_map,
+            None => return Ok(()),
+        };
+
+        for (part, reactions) in reactions_map {
+            for reaction in reactions {
+                let (kind, path) = match reaction.variant() {
+                    Variant::Reaction(_, true, tapback) => (format!("{tapback:?}"), None),
+                    Variant::Sticker(_) => {
+                        let mut stickers = self
+                            .config
+                            .attachments(reaction)
+                            .map_err(RuntimeError::DatabaseError)?;
+                        // Sticker messages have only one attachment, the sticker image
+                        let path = stickers.get_mut(0).map(|sticker| {
+                            let _ = self.config.options.attachment_manager.handle_attachment(
+                                reaction,
+                                sticker,
+                                self.config,
+                            );
+                            self.config.message_attachment_path(sticker)
+                        });
+                        ("Sticker".to_string(), path)
+                    }
+                    _ => continue,
+                };
+
+                output.reactions.push(ReactionRow {
+                    id: reaction.rowid,
+                    guid: reaction.guid.clone(),
+                    message_id: message.rowid,
+                    part: *part as u64,
+                    sender_id: self.sender_id(reaction),
+                    sender: self
+                        .config
+                        .who(
+                            reaction.handle_id,
+                            reaction.is_from_me,
+                            &reaction.destination_caller_id,
+                        )
+                        .to_string(),
+                    kind,
+                    date: timestamp(&reaction.date, &self.config.offset),
+                    path,
+                })?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Get the deduplicated participant ID of the sender of a message
+    fn sender_id(&self, message: &Message) -> Option<i32> {
+        // Messages from the database owner are stored with handle 0, which the cache maps to `Me`
+        let handle_id = if message.is_from_me {
+            Some(0)
+        } else {
+            message.handle_id
+        };
+        handle_id.and_then(|handle_id| self.config.real_participants.get(&handle_id).copied())
+    }
+
+    /// Get the name of a deduplicated conversation, possibly using cached data
+    fn conversation_name(&mut self, chatroom: &Chat, conversation_id: i32) -> String {
+        let config = self.config;
+        self.names
+            .entry(conversation_id)
+            .or_insert_with(|| config.conversation_name(chatroom))
+            .clone()
+    }
+}
+
+/// Convert a date from the iMessage table to microseconds since the unix epoch, using `NULL` for dates that were never set
+fn timestamp(date: &i64, offset: &i64) -> Option<i64> {
+    if *date == 0 {
+        return None;
+    }
+    Some(date / 1000 + offset * 1_000_000)
+}
+
+/// Build the type of a timestamp column
+fn timestamp_type() -> DataType {
+    DataType::Timestamp(TimeUnit::Microsecond, Some(TIME_ZONE.into()))
+}
+
+/// Build a timestamp column
+fn timestamp_column(values: impl Iterator<Item = Option<i64>>) -> ArrayRef {
+    Arc::new(TimestampMicrosecondArray::from_iter(values).with_timezone(TIME_ZONE))
+}
+
+/// The files written during an export
+struct Output {
+    messages: Batches<MessageRow>,
+    attachments: Batches<AttachmentRow>,
+    reactions: Batches<ReactionRow>,
+}
+
+/// A row that can be written to a Parquet file
+trait Record: Sized {
+    /// The Arrow schema of the file
+    fn schema() -> Schema;
+    /// Convert a set of rows to columns in the same order as the [`schema()`](Record::schema) fields
+    fn columns(rows: &[Self]) -> Vec<ArrayRef>;
+}
+
+/// Buffers rows and writes them to a Parquet file one record batch at a time
+struct Batches<T: Record> {
+    writer: ArrowWriter<OutputFile>,
+    schema: SchemaRef,
+    rows: Vec<T>,
+}
+
+impl<T: Record> Batches<T> {
+    /// Create the file at `path`, replacing it if it exists
+    fn create(path: &Path, encryption: Option<&Encryption>) -> Result<Self, RuntimeError> {
+        let file = OutputFile::create(path, encryption).map_err(RuntimeError::DiskError)?;
+        let schema = Arc::new(T::schema());
+        let properties = WriterProperties::builder()
+            .set_compression(Compression::SNAPPY)
+            .set_max_row_group_size(ROW_GROUP_SIZE)
+            .build();
+        let writer = ArrowWriter::try_new(file, schema.clone(), Some(properties))
+            .map_err(RuntimeError::ExportParquetError)?;
+        Ok(Batches {
+            writer,
+            schema,
+            rows: Vec::with_capacity(BATCH_SIZE),
+        })
+    }
+
+    /// Add a row, writing the buffered rows once there are enough for a batch
+    fn push(&mut self, row: T) -> Result<(), RuntimeError> {
+        self.rows.push(row);
+        if self.rows.len() >= BATCH_SIZE {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Write the buffered rows as a record batch
+    fn flush(&mut self) -> Result<(), RuntimeError> {
+        if self.rows.is_empty() {
+            return Ok(());
+        }
+        let batch = RecordBatch::try_new(self.schema.clone(), T::columns(&self.rows))
+            .map_err(|why| RuntimeError::ExportParquetError(ParquetError::from(why)))?;
+        self.writer
+            .write(&batch)
+            .map_err(RuntimeError::ExportParquetError)?;
+        self.rows.clear();
+        Ok(())
+    }
+
+    /// Write any remaining rows and the file footer
+    fn close(mut self) -> Result<(), RuntimeError> {
+        self.flush()?;
+        // `into_inner` writes the file footer before handing back the file
+        self.writer
+            .into_inner()
+            .map_err(RuntimeError::ExportParquetError)?
+            .finish()
+            .map_err(RuntimeError::DiskError)
+    }
+}
+
+/// One row per message; reactions are stored in the reactions file instead
+struct MessageRow {
+    id: i32,
+    guid: String,
+    conversation_id: Option<i32>,
+    conversation: Option<String>,
+    sender_id: Option<i32>,
+    sender: String,
+    is_from_me: bool,
+    date: Option<i64>,
+    date_read: Option<i64>,
+    date_delivered: Option<i64>,
+    date_edited: Option<i64>,
+    service: Option<String>,
+    subject: Option<String>,
+    text: Option<String>,
+    variant: &'static str,
+    app: Option<String>,
+    expressive: Option<String>,
+    reply_to_guid: Option<String>,
+    is_edited: bool,
+    is_deleted: bool,
+}
+
+impl Record for MessageRow {
+    fn schema() -> Schema {
+        Schema::new(vec![
+            Field::new("id", DataType::Int32, false),
+            Field::new("guid", DataType::Utf8, false),
+            Field::new("conversation_id", DataType::Int32, true),
+            Field::new("conversation", DataType::Utf8, true),
+            Field::new("sender_id", DataType::Int32, true),
+            Field::new("sender", DataType::Utf8, false),
+            Field::new("is_from_me", DataType::Boolean, false),
+            Field::new("date", timestamp_type(), true),
+            Field::new("date_read", timestamp_type(), true),
+            Field::new("date_delivered", timestamp_type(), true),
+            Field::new("date_edited", timestamp_type(), true),
+            Field::new("service", DataType::Utf8, true),
+            Field::new("subject", DataType::Utf8, true),
+            Field::new("text", DataType::Utf8, true),
+            Field::new("variant", DataType::Utf8, false),
+            Field::new("app", DataType::Utf8, true),
+            Field::new("expressive", DataType::Utf8, true),
+            Field::new("reply_to_guid", DataType::Utf8, true),
+            Field::new("is_edited", DataType::Boolean, false),
+            Field::new("is_deleted", DataType::Boolean, false),
+        ])
+    }
+
+    fn columns(rows: &[Self]) -> Vec<ArrayRef> {
+        vec![
+            Arc::new(Int32Array::from_iter_values(rows.iter().map(|r| r.id))),
+            Arc::new(StringArray::from_iter_values(rows.iter().map(|r| &r.guid))),
+            Arc::new(Int32Array::from_iter(
+                rows.iter().map(|r| r.conversation_id),
+            )),
+            Arc::new(StringArray::from_iter(
+                rows.iter().map(|r| r.conversation.as_deref()),
+            )),
+            Arc::new(Int32Array::from_iter(rows.iter().map(|r| r.sender_id))),
+            Arc::new(StringArray::from_iter_values(
+                rows.iter().map(|r| &r.sender),
+            )),
+            Arc::new(BooleanArray::from_iter(
+                rows.iter().map(|r| Some(r.is_from_me)),
+            )),
+            timestamp_column(rows.iter().map(|r| r.date)),
+            timestamp_column(rows.iter().map(|r| r.date_read)),
+            timestamp_column(rows.iter().map(|r| r.date_delivered)),
+            timestamp_column(rows.iter().map(|r| r.date_edited)),
+            Arc::new(StringArray::from_iter(
+                rows.iter().map(|r| r.service.as_deref()),
+            )),
+            Arc::new(StringArray::from_iter(
+                rows.iter().map(|r| r.subject.as_deref()),
+            )),
+            Arc::new(StringArray::from_iter(
+                rows.iter().map(|r| r.text.as_deref()),
+            )),
+            Arc::new(StringArray::from_iter_values(
+                rows.iter().map(|r| r.variant),
+            )),
+            Arc::new(StringArray::from_iter(
+                rows.iter().map(|r| r.app.as_deref()),
+            )),
+            Arc::new(StringArray::from_iter(
+                rows.iter().map(|r| r.expressive.as_deref()),
+            )),
+            Arc::new(StringArray::from_iter(
+                rows.iter().map(|r| r.reply_to_guid.as_deref()),
+            )),
+            Arc::new(BooleanArray::from_iter(
+                rows.iter().map(|r| Some(r.is_edited)),
+            )),
+            Arc::new(BooleanArray::from_iter(
+                rows.iter().map(|r| Some(r.is_deleted)),
+            )),
+        ]
+    }
+}
+
+/// Files attached to messages
+struct AttachmentRow {
+    id: i32,
+    message_id: i32,
+    filename: String,
+    mime_type: Option<String>,
+    uti: Option<String>,
+    total_bytes: u64,
+    is_sticker: bool,
+    path: String,
+}
+
+impl Record for AttachmentRow {
+    fn schema() -> Schema {
+        Schema::new(vec![
+            Field::new("id", DataType::Int32, false),
+            Field::new("message_id", DataType::Int32, false),
+            Field::new("filename", DataType::Utf8, false),
+            Field::new("mime_type", DataType::Utf8, true),
+            Field::new("uti", DataType::Utf8, true),
+            Field::new("total_bytes", DataType::UInt64, false),
+            Field::new("is_sticker", DataType::Boolean, false),
+            Field::new("path", DataType::Utf8, false),
+        ])
+    }
+
+    fn columns(rows: &[Self]) -> Vec<ArrayRef> {
+        vec![
+            Arc::new(Int32Array::from_iter_values(rows.iter().map(|r| r.id))),
+            Arc::new(Int32Array::from_iter_values(
+                rows.iter().map(|r| r.message_id),
+            )),
+            Arc::new(StringArray::from_iter_values(
+                rows.iter().map(|r| &r.filename),
+            )),
+            Arc::new(StringArray::from_iter(
+                rows.iter().map(|r| r.mime_type.as_deref()),
+            )),
+            Arc::new(StringArray::from_iter(
+                rows.iter().map(|r| r.uti.as_deref()),
+            )),
+            Arc::new(UInt64Array::from_iter_values(
+                rows.iter().map(|r| r.total_bytes),
+            )),
+            Arc::new(BooleanArray::from_iter(
+                rows.iter().map(|r| Some(r.is_sticker)),
+            )),
+            Arc::new(StringArray::from_iter_values(rows.iter().map(|r| &r.path))),
+        ]
+    }
+}
+
+/// Tapbacks and stickers placed on messages
+struct ReactionRow {
+    id: i32,
+    guid: String,
+    message_id: i32,
+    part: u64,
+    sender_id: Option<i32>,
+    sender: String,
+    kind: String,
+    date: Option<i64>,
+    path: Option<String>,
+}
+
+impl Record for ReactionRow {
+    fn schema() -> Schema {
+        Schema::new(vec![
+            Field::new("id", DataType::Int32, false),
+            Field::new("guid", DataType::Utf8, false),
+            Field::new("message_id", DataType::Int32, false),
+            Field::new("part", DataType::UInt64, false),
+            Field::new("sender_id", DataType::Int32, true),
+            Field::new("sender", DataType::Utf8, false),
+            Field::new("kind", DataType::Utf8, false),
+            Field::new("date", timestamp_type(), true),
+            Field::new("path", DataType::Utf8, true),
+        ])
+    }
+
+    fn columns(rows: &[Self]) -> Vec<ArrayRef> {
+        vec![
+            Arc::new(Int32Array::from_iter_values(rows.iter().map(|r| r.id))),
+            Arc::new(StringArray::from_iter_values(rows.iter().map(|r| &r.guid))),
+            Arc::new(Int32Array::from_iter_values(
+                rows.iter().map(|r| r.message_id),
+            )),
+            Arc::new(UInt64Array::from_iter_values(rows.iter().map(|r| r.part))),
+            Arc::new(Int32Array::from_iter(rows.iter().map(|r| r.sender_id))),
+            Arc::new(StringArray::from_iter_values(
+                rows.iter().map(|r| &r.sender),
+            )),
+            Arc::new(StringArray::from_iter_values(rows.iter().map(|r| &r.kind))),
+            timestamp_column(rows.iter().map(|r| r.date)),
+            Arc::new(StringArray::from_iter(
+                rows.iter().map(|r| r.path.as_deref()),
+            )),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeSet, env::temp_dir, fs::File, path::PathBuf};
+
+    use arrow_array::{Array, Int32Array, StringArray, TimestampMicrosecondArray};
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+    use crate::{
+        exporters::parquet::{timestamp, AttachmentRow, Batches, MessageRow, BATCH_SIZE},
+        Config, Exporter, Options, Parquet,
+    };
+    use imessage_database::tables::{chat::Chat, messages::Message};
+
+    pub fn blank() -> Message {
+        Message {
+            rowid: i32::default(),
+            guid: String::default(),
+            text: None,
+            service: Some("iMessage".to_string()),
+            handle_id: Some(i32::default()),
+            destination_caller_id: None,
+            subject: None,
+            date: i64::default(),
+            date_read: i64::default(),
+            date_delivered: i64::default(),
+            is_from_me: false,
+            is_read: false,
+            item_type: 0,
+            group_title: None,
+            group_action_type: 0,
+            associated_message_guid: None,
+            associated_message_type: Some(i32::default()),
+            balloon_bundle_id: None,
+            expressive_send_style_id: None,
+            thread_originator_guid: None,
+            thread_originator_part: None,
+            date_edited: 0,
+            chat_id: None,
+            num_attachments: 0,
+            deleted_from: None,
+            num_replies: 0,
+        }
+    }
+
+    fn fake_attachment(id: i32) -> AttachmentRow {
+        AttachmentRow {
+            id,
+            message_id: 1,
+            filename: format!("{id}.jpg"),
+            mime_type: Some("image/jpeg".to_string()),
+            uti: None,
+            total_bytes: 100,
+            is_sticker: false,
+            path: format!("attachments/{id}.jpg"),
+        }
+    }
+
+    #[test]
+    fn can_create() {
+        let options = Options::fake();
+        let config = Config::new(options).unwrap();
+        let exporter = Parquet::new(&config);
+        assert_eq!(exporter.path, PathBuf::from("messages.parquet"));
+    }
+
+    #[test]
+    fn can_get_timestamp() {
+        // May 17, 2022  5:29:42.885 PM PST
+        assert_eq!(
+            timestamp(&674526582885055488, &978307200),
+            Some(1652833782885055)
+        );
+    }
+
+    #[test]
+    fn cant_get_unset_timestamp() {
+        assert_eq!(timestamp(&0, &978307200), None);
+    }
+
+    #[test]
+    fn can_get_message_row() {
+        // Create exporter
+        let options = Options::fake();
+        let mut config = Config::new(options).unwrap();
+        config
+            .participants
+            .insert(999999, "Sample Contact".to_string());
+        config.real_participants.insert(999999, 7);
+        config.chatrooms.insert(
+            3,
+            Chat {
+                rowid: 3,
+                chat_identifier: "chat3".to_string(),
+                service_name: Some("iMessage".to_string()),
+                display_name: None,
+            },
+        );
+        config.real_chatrooms.insert(3, 5);
+        config
+            .chatroom_participants
+            .insert(3, BTreeSet::from([999999]));
+        let mut exporter = Parquet::new(&config);
+
+        // Create fake message
+        let mut message = blank();
+        message.rowid = 1;
+        message.guid = "guid".to_string();
+        message.handle_id = Some(999999);
+        message.chat_id = Some(3);
+        message.text = Some("Hello world".to_string());
+        message.date = 674526582885055488;
+        message.expressive_send_style_id =
+            Some("com.apple.MobileSMS.expressivesend.impact".to_string());
+
+        let row = exporter.message_row(&message);
+        assert_eq!(row.conversation_id, Some(5));
+        assert_eq!(row.conversation.as_deref(), Some("Sample Contact"));
+        assert_eq!(row.sender_id, Some(7));
+        assert_eq!(row.sender, "Sample Contact");
+        assert_eq!(row.date, Some(1652833782885055));
+        assert_eq!(row.date_read, None);
+        assert_eq!(row.variant, "normal");
+        assert_eq!(row.expressive.as_deref(), Some("Slam"));
+    }
+
+    #[test]
+    fn can_write_message_batches() {
+        let path = temp_dir().join("imessage-exporter-test-messages.parquet");
+
+        // Write more rows than fit in a single batch
+        let mut batches = Batches::<MessageRow>::create(&path, None).unwrap();
+        for id in 0..BATCH_SIZE as i32 + 1 {
+            batches
+                .push(MessageRow {
+                    id,
+                    guid: format!("guid-{id}"),
+                    conversation_id: None,
+                    conversation: None,
+                    sender_id: None,
+                    sender: "Me".to_string(),
+                    is_from_me: true,
+                    date: Some(1652833782885055),
+                    date_read: None,
+                    date_delivered: None,
+                    date_edited: None,
+                    service: Some("iMessage".to_string()),
+                    subject: None,
+                    text: Some("Hello world".to_string()),
+                    variant: "normal",
+                    app: None,
+                    expressive: None,
+                    reply_to_guid: None,
+                    is_edited: false,
+                    is_deleted: false,
+                })
+                .unwrap();
+            // The buffer is flushed once it is full
+            assert!(batches.rows.len() < BATCH_SIZE);
+        }
+        batches.close().unwrap();
+
+        let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(&path).unwrap())
+            .unwrap()
+            .build()
+            .unwrap();
+        let batches: Vec<_> = reader.map(Result::unwrap).collect();
+        let rows: usize = batches.iter().map(|batch| batch.num_rows()).sum();
+        assert_eq!(rows, BATCH_SIZE + 1);
+
+        let batch = &batches[0];
+        let guids = batch
+            .column_by_name("guid")
+            .unwrap()
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        assert_eq!(guids.value(1), "guid-1");
+        let dates = batch
+            .column_by_name("date")
+            .unwrap()
+            .as_any()
+            .downcast_ref::<TimestampMicrosecondArray>()
+            .unwrap();
+        assert_eq!(dates.value(0), 1652833782885055);
+        assert!(batch.column_by_name("date_read").unwrap().is_null(0));
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn can_write_attachments() {
+        let path = temp_dir().join("imessage-exporter-test-attachments.parquet");
+
+        let mut batches = Batches::<AttachmentRow>::create(&path, None).unwrap();
+        batches.push(fake_attachment(1)).unwrap();
+        batches.push(fake_attachment(2)).unwrap();
+        batches.close().unwrap();
+
+        let mut reader = ParquetRecordBatchReaderBuilder::try_new(File::open(&path).unwrap())
+            .unwrap()
+            .build()
+            .unwrap();
+        let batch = reader.next().unwrap().unwrap();
+        let ids = batch
+            .column_by_name("id")
+            .unwrap()
+            .as_any()
+            .downcast_ref::<Int32Array>()
+            .unwrap();
+        assert_eq!(ids.values(), &[1, 2]);
+        assert!(batch.column_by_name("uti").unwrap().is_null(0));
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,27 @@
+// Sort the conversation table when a column header is clicked
+document.querySelectorAll(".index th").forEach((header, column) => {
+	header.addEventListener("click", () => {
+		const body = document.querySelector(".index tbody");
+		const ascending = header.dataset.order !== "ascending";
+		const numeric = header.dataset.type === "number";
+		document.querySelectorAll(".index th").forEach((other) => delete other.dataset.order);
+		header.dataset.order = ascending ? "ascending" : "descending";
+
+		const value = (row) => row.cells[column].dataset.sort ?? row.cells[column].textContent;
+		const rows = Array.from(body.rows).sort((a, b) => {
+			const order = numeric
+				? Number(value(a)) - Number(value(b))
+				: value(a).localeCompare(value(b));
+			return ascending ? order : -order;
+		});
+		body.append(...rows);
+	});
+});
+
+// Hide conversations that do not match the filter text
+document.querySelector(".index_filter").addEventListener("input", (event) => {
+	const filter = event.target.value.toLowerCase();
+	document.querySelectorAll(".index tbody tr").forEach((row) => {
+		row.hidden = !row.textContent.toLowerCase().includes(filter);
+	});
+});
@@ -0,0 +1,69 @@
+// Called by the search index written next to the conversation once the page has loaded
+function searchIndex(messages, senders, pages) {
+	const panel = document.querySelector(".search");
+	const query = panel.querySelector(".search_query");
+	const sender = panel.querySelector(".search_sender");
+	const attachments = panel.querySelector(".search_attachments");
+	const date = panel.querySelector(".search_date");
+	const results = panel.querySelector(".search_results");
+
+	// Most matches are on other pages of large conversations, so only list the first few
+	const limit = 100;
+	const page = decodeURIComponent(location.pathname.split("/").pop());
+	const entries = messages.map(([id, from, sent, hasAttachments, on, text]) => ({
+		id,
+		from,
+		sent,
+		hasAttachments: hasAttachments === 1,
+		page: pages[on],
+		text,
+		search: text.toLowerCase(),
+	}));
+	const link = (entry) =>
+		(entry.page === page ? "" : encodeURIComponent(entry.page)) + "#m-" + entry.id;
+
+	senders.forEach((name, index) => sender.add(new Option(name, index)));
+
+	// Hide messages on this page that do not match, and list matches on other pages
+	const filter = () => {
+		const text = query.value.trim().toLowerCase();
+		const from = sender.value === "" ? null : Number(sender.value);
+		const active = text !== "" || from !== null || attachments.checked;
+		const matches = entries.filter(
+			(entry) =>
+				entry.search.includes(text) &&
+				(from === null || entry.from === from) &&
+				(!attachments.checked || entry.hasAttachments)
+		);
+
+		const shown = new Set(matches.filter((entry) => entry.page === page).map((entry) => "m-" + entry.id));
+		document.querySelectorAll(".searchable").forEach((message) => {
+			message.hidden = active && !shown.has(message.id);
+		});
+
+		results.replaceChildren();
+		if (!active) {
+			return;
+		}
+		const elsewhere = matches.filter((entry) => entry.page !== page);
+		const count = document.createElement("p");
+		count.textContent = `${matches.length} matching messages, ${elsewhere.length} on other pages`;
+		results.append(count);
+		elsewhere.slice(0, limit).forEach((entry) => {
+			const result = document.createElement("a");
+			result.href = link(entry);
+			result.textContent = `${new Date(entry.sent * 1000).toLocaleString()} ${senders[entry.from]}: ${entry.text.slice(0, 100)}`;
+			results.append(result);
+		});
+	};
+	[query, sender, attachments].forEach((input) => input.addEventListener("input", filter));
+
+	// Jump to the first message sent on or after the chosen date
+	date.addEventListener("change", () => {
+		const start = new Date(date.value + "T00:00").getTime() / 1000;
+		const entry = entries.find((entry) => entry.sent >= start) ?? entries[entries.length - 1];
+		if (entry) {
+			location.href = link(entry);
+		}
+	});
+}
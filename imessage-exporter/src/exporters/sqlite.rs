@@ -0,0 +1,799 @@
+/*!
+ Exports the database into a normalized `SQLite` database with a stable schema.
+
+ Handles and chats are deduplicated using the same logic as the other exporters,
+ so each row in `participants` and `conversations` represents a single person or thread.
+ See [`SCHEMA`] for the documented table definitions.
+*/
+
+use std::path::{Path, PathBuf};
+
+use rusqlite::{params, Connection, DatabaseName, Error};
+
+use crate::{
+    app::{error::RuntimeError, progress::build_progress_bar_export, runtime::Config},
+    exporters::exporter::Exporter,
+};
+
+use imessage_database::{
+    error::{message::MessageError, table::TableError},
+    message_types::{
+        edited::EditedMessage,
+        expressives::Expressive,
+        variants::{BalloonProvider, CustomBalloon, Variant},
+    },
+    tables::{messages::Message, table::Table},
+    util::dates::get_local_time,
+};
+
+/// Name of the exported database file
+const OUTPUT: &str = "messages";
+
+/// Table definitions for the exported database
+///
+/// All dates are [RFC 3339](https://www.rfc-editor.org/rfc/rfc3339) strings in the time zone set with `--time-zone`,
+/// or the local time zone if none is set.
+/// The comments are stored in `sqlite_master`, so they remain visible to anyone inspecting the schema.
+pub const SCHEMA: &str = "
+-- One row per conversation; chats with the same participants are merged
+CREATE TABLE conversations (
+    id INTEGER PRIMARY KEY, -- Deduplicated conversation ID
+    name TEXT NOT NULL      -- Chat display name, or a list of its participants
+);
+
+-- One row per chat in the source database, mapped to its deduplicated conversation
+CREATE TABLE chats (
+    id INTEGER PRIMARY KEY,                                   -- ROWID of the chat in chat.db
+    conversation_id INTEGER NOT NULL REFERENCES conversations (id),
+    identifier TEXT NOT NULL,                                 -- Phone number, email, or group identifier
+    service TEXT,                                             -- iMessage, SMS, etc.
+    display_name TEXT                                         -- Name assigned to the chat, if any
+);
+
+-- One row per person; handles that resolve to the same contact are merged
+CREATE TABLE participants (
+    id INTEGER PRIMARY KEY, -- Deduplicated participant ID
+    name TEXT NOT NULL      -- Resolved contact name or handle
+);
+
+-- Members of each conversation
+CREATE TABLE conversation_participants (
+    conversation_id INTEGER NOT NULL REFERENCES conversations (id),
+    participant_id INTEGER NOT NULL REFERENCES participants (id),
+    PRIMARY KEY (conversation_id, participant_id)
+) WITHOUT ROWID;
+
+-- One row per message; reactions are stored in the reactions table instead
+CREATE TABLE messages (
+    id INTEGER PRIMARY KEY,                              -- ROWID of the message in chat.db
+    guid TEXT NOT NULL UNIQUE,
+    conversation_id INTEGER REFERENCES conversations (id), -- NULL if the message is orphaned
+    sender_id INTEGER REFERENCES participants (id),      -- NULL if the sender is unknown
+    sender TEXT NOT NULL,                                -- Resolved sender name
+    is_from_me INTEGER NOT NULL,
+    date TEXT,
+    date_read TEXT,                                      -- NULL if never read
+    date_delivered TEXT,                                 -- NULL if never delivered
+    service TEXT,
+    subject TEXT,
+    text TEXT,                                           -- Latest text; U+FFFC marks attachment positions
+    variant TEXT NOT NULL,                               -- normal, app, announcement, edited, sticker, shareplay, unknown
+    app TEXT,                                            -- Balloon type or bundle ID for app messages
+    expressive TEXT,                                     -- Bubble or screen effect name
+    reply_to_guid TEXT,                                  -- GUID of the message that started the thread
+    is_edited INTEGER NOT NULL,
+    is_unsent INTEGER NOT NULL,
+    is_deleted INTEGER NOT NULL                          -- Deleted from the conversation, but still recoverable
+);
+
+-- Files attached to messages
+CREATE TABLE attachments (
+    id INTEGER PRIMARY KEY,                               -- ROWID of the attachment in chat.db
+    message_id INTEGER NOT NULL REFERENCES messages (id),
+    filename TEXT NOT NULL,                               -- Name of the file when sent or received
+    mime_type TEXT,
+    uti TEXT,                                             -- Uniform Type Identifier
+    total_bytes INTEGER NOT NULL,
+    is_sticker INTEGER NOT NULL,
+    path TEXT NOT NULL                                    -- Copied path if attachments were copied, else the source path
+);
+
+-- Tapbacks and stickers placed on messages
+CREATE TABLE reactions (
+    id INTEGER PRIMARY KEY,                               -- ROWID of the reaction in chat.db
+    guid TEXT NOT NULL UNIQUE,
+    message_id INTEGER NOT NULL REFERENCES messages (id), -- Message that was reacted to
+    part INTEGER NOT NULL,                                -- Index of the message body part that was reacted to
+    sender_id INTEGER REFERENCES participants (id),
+    sender TEXT NOT NULL,
+    kind TEXT NOT NULL,                                   -- Loved, Liked, Disliked, Laughed, Emphasized, Questioned, or Sticker
+    date TEXT,
+    path TEXT                                             -- Path to the sticker image, for stickers
+);
+
+-- Edit history of edited messages, starting with the original text
+CREATE TABLE edits (
+    message_id INTEGER NOT NULL REFERENCES messages (id),
+    position INTEGER NOT NULL, -- 0 is the original message
+    date TEXT,
+    text TEXT NOT NULL,
+    PRIMARY KEY (message_id, position)
+) WITHOUT ROWID;
+
+CREATE INDEX messages_conversation ON messages (conversation_id, date);
+CREATE INDEX attachments_message ON attachments (message_id);
+CREATE INDEX reactions_message ON reactions (message_id);
+";
+
+pub struct SQLite<'a> {
+    /// Data that is setup from the application's runtime
+    pub config: &'a Config,
+    /// Path to the exported database
+    pub path: PathBuf,
+}
+
+impl<'a> Exporter<'a> for SQLite<'a> {
+    fn new(config: &'a Config) -> Self {
+        let mut path = config.options.export_path.clone();
+        path.push(OUTPUT);
+        path.set_extension("sqlite");
+        SQLite { config, path }
+    }
+
+    fn iter_messages(&mut self) -> Result<(), RuntimeError> {
+        // Tell the user what we are doing
+        eprintln!(
+            "Exporting to {} as sqlite...",
+            self.config.options.export_path.display()
+        );
+
+        // Encrypted exports build the database in memory, so it is only written to disk once it is encrypted
+        let output = match &self.config.options.encryption {
+            Some(_) => Connection::open_in_memory(),
+            None => Connection::open(&self.path),
+        }
+        .map_err(RuntimeError::ExportDatabaseError)?;
+        output
+            .execute_batch(SCHEMA)
+            .map_err(RuntimeError::ExportDatabaseError)?;
+
+        // Write everything in a single transaction; this is orders of magnitude faster than autocommit
+        output
+            .execute_batch("BEGIN TRANSACTION;")
+            .map_err(RuntimeError::ExportDatabaseError)?;
+
+        eprintln!("Writing conversations and participants...");
+        self.write_participants(&output)
+            .map_err(RuntimeError::ExportDatabaseError)?;
+        self.write_conversations(&output)
+            .map_err(RuntimeError::ExportDatabaseError)?;
+
+        // Keep track of current message ROWID
+        let mut current_message_row = -1;
+
+        // Set up progress bar
+        let mut current_message = 0;
+        let total_messages =
+            Message::get_count(&self.config.db, &self.config.options.query_context)
+                .map_err(RuntimeError::DatabaseError)?;
+        let pb = build_progress_bar_export(total_messages);
+
+        let mut statement =
+            Message::stream_rows(&self.config.db, &self.config.options.query_context)
+                .map_err(RuntimeError::DatabaseError)?;
+
+        let messages = statement
+            .query_map([], |row| Ok(Message::from_row(row)))
+            .map_err(|err| RuntimeError::DatabaseError(TableError::Messages(err)))?;
+
+        for message in messages {
+            let mut msg = Message::extract(message).map_err(RuntimeError::DatabaseError)?;
+
+            // Early escape if we try and render the same message GUID twice
+            // See https://github.com/ReagentX/imessage-exporter/issues/135 for rationale
+            if msg.rowid == current_message_row {
+                current_message += 1;
+                continue;
+            }
+            current_message_row = msg.rowid;
+
+            // Skip messages that are not near a match for the text filter
+            if !self.config.is_selected(&mut msg) {
+                current_message += 1;
+                continue;
+            }
+
+            // Reactions are written alongside the message they target
+            if !msg.is_reaction() {
+                self.config.gen_text(&mut msg);
+                self.write_message(&output, &msg)?;
+            }
+
+            current_message += 1;
+            if current_message % 99 == 0 {
+                pb.set_position(current_message);
+            }
+        }
+        pb.finish();
+
+        output
+            .execute_batch("COMMIT;")
+            .map_err(RuntimeError::ExportDatabaseError)?;
+
+        if self.config.options.encryption.is_some() {
+            let database = output
+                .serialize(DatabaseName::Main)
+                .map_err(RuntimeError::ExportDatabaseError)?;
+            self.config
+                .replace_output(&self.path, &database)
+                .map_err(RuntimeError::DiskError)?;
+        }
+
+        Ok(())
+    }
+
+    /// All messages are written to the same database
+    fn get_or_create_file(&mut self, _: &Message) -> &Path {
+        &self.path
+    }
+}
+
+impl<'a> SQLite<'a> {
+    /// Write the deduplicated participants
+    fn write_participants(&self, output: &Connection) -> Result<(), Error> {
+        let mut statement =
+            output.prepare("INSERT OR IGNORE INTO participants (id, name) VALUES (?1, ?2)")?;
+        for (handle_id, participant_id) in &self.config.real_participants {
+            statement.execute(params![
+                participant_id,
+                self.config.who(Some(*handle_id), *handle_id == 0, &None)
+            ])?;
+        }
+        Ok(())
+    }
+
+    /// Write the deduplicated conversations, the chats they contain, and their members
+    fn write_conversations(&self, output: &Connection) -> Result<(), Error> {
+        let mut conversation_statement =
+            output.prepare("INSERT OR IGNORE INTO conversations (id, name) VALUES (?1, ?2)")?;
+        let mut chat_statement = output.prepare(
+            "INSERT INTO chats (id, conversation_id, identifier, service, display_name) VALUES (?1, ?2, ?3, ?4, ?5)",
+        )?;
+        let mut member_statement = output.prepare(
+            "INSERT OR IGNORE INTO conversation_participants (conversation_id, participant_id) VALUES (?1, ?2)",
+        )?;
+
+        for (chat_id, conversation_id) in &self.config.real_chatrooms {
+            if let Some(chatroom) = self.config.chatrooms.get(chat_id) {
+                let participants = self.config.chatroom_participants.get(chat_id);
+
+                conversation_statement.execute(params![
+                    conversation_id,
+                    self.config.conversation_name(chatroom)
+                ])?;
+                chat_statement.execute(params![
+                    chat_id,
+                    conversation_id,
+                    chatroom.chat_identifier,
+                    chatroom.service_name,
+                    chatroom.display_name()
+                ])?;
+
+                for handle_id in participants.into_iter().flatten() {
+                    if let Some(participant_id) = self.config.real_participants.get(handle_id) {
+                        member_statement.execute(params![conversation_id, participant_id])?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Write a message along with its attachments, reactions, and edit history
+    fn write_message(&self, output: &Connection, message: &Message) -> Result<(), RuntimeError> {
+        let (variant, app) = Self::variant_columns(message);
+
+        // Parse the edit history first, since unsent messages are flagged on the message row
+        let payload = if message.is_edited() {
+            message.message_summary_info(&self.config.db)
+        } else {
+            None
+        };
+        let mut edited =
+            payload
+                .as_ref()
+                .and_then(|payload| match EditedMessage::from_map(payload) {
+                    Ok(edited) => Some(edited),
+                    Err(why) => {
+                        eprintln!(
+                            "Unable to parse edit history for {}: {}",
+                            message.guid,
+                            MessageError::PlistParseError(why)
+                        );
+                        None
+                    }
+                });
+        let is_unsent = edited.as_ref().is_some_and(EditedMessage::is_deleted);
+
+        output
+            .prepare_cached(
+                "INSERT OR IGNORE INTO messages (
+                    id, guid, conversation_id, sender_id, sender, is_from_me, date, date_read, date_delivered,
+                    service, subject, text, variant, app, expressive, reply_to_guid, is_edited, is_unsent, is_deleted
+                ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19)",
+            )
+            .and_then(|mut statement| {
+                statement.execute(params![
+                    message.rowid,
+                    message.guid,
+                    self.config.conversation(message).map(|(_, id)| id),
+                    self.sender_id(message),
+                    self.config.who(
+                        message.handle_id,
+                        message.is_from_me,
+                        &message.destination_caller_id
+                    ),
+                    message.is_from_me,
+                    self.date_column(&message.date),
+                    self.date_column(&message.date_read),
+                    self.date_column(&message.date_delivered),
+                    message.service,
+                    message.subject,
+                    message.text,
+                    variant,
+                    app,
+                    Self::expressive_column(&message.get_expressive()),
+                    message.thread_originator_guid,
+                    message.is_edited(),
+                    is_unsent,
+                    message.is_deleted(),
+                ])
+            })
+            .map_err(RuntimeError::ExportDatabaseError)?;
+
+        if let Some(edited) = &mut edited {
+            self.write_edits(output, message, edited)
+                .map_err(RuntimeError::ExportDatabaseError)?;
+        }
+        self.write_attachments(output, message)?;
+        self.write_reactions(output, message)?;
+
+        Ok(())
+    }
+
+    /// Write the attachments of a message, copying the files if requested
+    fn write_attachments(
+        &self,
+        output: &Connection,
+        message: &Message,
+    ) -> Result<(), RuntimeError> {
+        if !message.has_attachments() {
+            return Ok(());
+        }
+
+        let mut attachments = self
+            .config
+            .attachments(message)
+            .map_err(RuntimeError::DatabaseError)?;
+        let mut statement = output
+            .prepare_cached(
+                "INSERT OR IGNORE INTO attachments (
+                    id, message_id, filename, mime_type, uti, total_bytes, is_sticker, path
+                ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            )
+            .map_err(RuntimeError::ExportDatabaseError)?;
+
+        for attachment in attachments.iter_mut() {
+            let _ = self.config.options.attachment_manager.handle_attachment(
+                message,
+                attachment,
+                self.config,
+            );
+            statement
+                .execute(params![
+                    attachment.rowid,
+                    message.rowid,
+                    attachment.filename(),
+                    attachment.mime_type,
+                    attachment.uti,
+                    attachment.total_bytes,
+                    attachment.is_sticker,
+                    self.config.message_attachment_path(attachment),
+                ])
+                .map_err(RuntimeError::ExportDatabaseError)?;
+        }
+        Ok(())
+    }
+
+    /// Write the tapbacks and stickers placed on a message from the reactions cache
+    ///
+    /// Removed tapbacks are not written.
+    fn write_reactions(&self, output: &Connection, message: &Message) -> Result<(), RuntimeError> {
+        let reactions_map = match self.config.reactions.get(&message.guid) {
+            Some(reactions_map) => reactions_map,
+            None => return Ok(()),
+        };
+
+        let mut statement = output
+            .prepare_cached(
+                "INSERT OR IGNORE INTO reactions (
+                    id, guid, message_id, part, sender_id, sender, kind, date, path
+                ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            )
+            .map_err(RuntimeError::ExportDatabaseError)?;
+
+        for (part, reactions) in reactions_map {
+            for reaction in reactions {
+                let (kind, path) = match reaction.variant() {
+                    Variant::Reaction(_, true, tapback) => (format!("{tapback:?}"), None),
+                    Variant::Sticker(_) => {
+                        let mut stickers = self
+                            .config
+                            .attachments(reaction)
+                            .map_err(RuntimeError::DatabaseError)?;
+                        // Sticker messages have only one attachment, the sticker image
+                        let path = stickers.get_mut(0).map(|sticker| {
+                            let _ = self.config.options.attachment_manager.handle_attachment(
+                                reaction,
+                                sticker,
+                                self.config,
+                            );
+                            self.config.message_attachment_path(sticker)
+                        });
+                        ("Sticker".to_string(), path)
+                    }
+                    _ => continue,
+                };
+
+                statement
+                    .execute(params![
+                        reaction.rowid,
+                        reaction.guid,
+                        message.rowid,
+                        part,
+                        self.sender_id(reaction),
+                        self.config.who(
+                            reaction.handle_id,
+                            reaction.is_from_me,
+                            &reaction.destination_caller_id
+                        ),
+                        kind,
+                        self.date_column(&reaction.date),
+                        path,
+                    ])
+                    .map_err(RuntimeError::ExportDatabaseError)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Write the edit history of a message, redacting it if requested
+    fn write_edits(
+        &self,
+        output: &Connection,
+        message: &Message,
+        edited: &mut EditedMessage,
+    ) -> Result<(), Error> {
+        if let Some(redactor) = &self.config.options.redactor {
+            redactor.redact_edits(edited);
+        }
+
+        let mut statement = output.prepare_cached(
+            "INSERT OR IGNORE INTO edits (message_id, position, date, text) VALUES (?1, ?2, ?3, ?4)",
+        )?;
+        for (position, event) in edited.events.iter().enumerate() {
+            statement.execute(params![
+                message.rowid,
+                position,
+                self.date_column(&event.date),
+                event.text,
+            ])?;
+        }
+        Ok(())
+    }
+
+    /// Get the deduplicated participant ID of the sender of a message
+    fn sender_id(&self, message: &Message) -> Option<&i32> {
+        // Messages from the database owner are stored with handle 0, which the cache maps to `Me`
+        let handle_id = if message.is_from_me {
+            Some(0)
+        } else {
+            message.handle_id
+        };
+        handle_id.and_then(|handle_id| self.config.real_participants.get(&handle_id))
+    }
+
+    /// Format a date column, using `NULL` for dates that were never set
+    fn date_column(&self, date: &i64) -> Option<String> {
+        if *date == 0 {
+            return None;
+        }
+        Some(
+            self.config
+                .options
+                .date_format
+                .format_iso(&get_local_time(date, &self.config.offset)),
+        )
+    }
+
+    /// Get the `variant` and `app` columns for a message
+    pub(crate) fn variant_columns(message: &Message) -> (&'static str, Option<String>) {
+        if message.is_announcement() {
+            return ("announcement", None);
+        }
+        match message.variant() {
+            Variant::Normal => ("normal", None),
+            Variant::Edited => ("edited", None),
+            Variant::SharePlay => ("shareplay", None),
+            Variant::Sticker(_) => ("sticker", None),
+            Variant::Reaction(..) => ("reaction", None),
+            Variant::Unknown(code) => ("unknown", Some(code.to_string())),
+            Variant::App(balloon) => match balloon {
+                CustomBalloon::Application(bundle_id) => ("app", Some(bundle_id.to_string())),
+                _ => ("app", Some(format!("{balloon:?}"))),
+            },
+        }
+    }
+
+    /// Get the `expressive` column for a message
+    pub(crate) fn expressive_column(expressive: &Expressive) -> Option<String> {
+        match expressive {
+            Expressive::Screen(effect) => Some(format!("{effect:?}")),
+            Expressive::Bubble(effect) => Some(format!("{effect:?}")),
+            Expressive::Unknown(effect) => Some(effect.to_string()),
+            Expressive::None => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeSet, env::set_var, path::PathBuf};
+
+    use rusqlite::Connection;
+
+    use crate::{
+        app::redactor::Redactor, exporters::sqlite::SCHEMA, Config, Exporter, Options, SQLite,
+    };
+    use imessage_database::{
+        message_types::edited::{EditedEvent, EditedMessage},
+        tables::{chat::Chat, messages::Message},
+    };
+
+    pub fn blank() -> Message {
+        Message {
+            rowid: i32::default(),
+            guid: String::default(),
+            text: None,
+            service: Some("iMessage".to_string()),
+            handle_id: Some(i32::default()),
+            destination_caller_id: None,
+            subject: None,
+            date: i64::default(),
+            date_read: i64::default(),
+            date_delivered: i64::default(),
+            is_from_me: false,
+            is_read: false,
+            item_type: 0,
+            group_title: None,
+            group_action_type: 0,
+            associated_message_guid: None,
+            associated_message_type: Some(i32::default()),
+            balloon_bundle_id: None,
+            expressive_send_style_id: None,
+            thread_originator_guid: None,
+            thread_originator_part: None,
+            date_edited: 0,
+            chat_id: None,
+            num_attachments: 0,
+            deleted_from: None,
+            num_replies: 0,
+        }
+    }
+
+    fn fake_output() -> Connection {
+        let output = Connection::open_in_memory().unwrap();
+        output.execute_batch(SCHEMA).unwrap();
+        output
+    }
+
+    fn fake_chat(rowid: i32, display_name: Option<&str>) -> Chat {
+        Chat {
+            rowid,
+            chat_identifier: format!("chat{rowid}"),
+            service_name: Some("iMessage".to_string()),
+            display_name: display_name.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn can_create() {
+        let options = Options::fake();
+        let config = Config::new(options).unwrap();
+        let exporter = SQLite::new(&config);
+        assert_eq!(exporter.path, PathBuf::from("messages.sqlite"));
+    }
+
+    #[test]
+    fn can_write_deduplicated_conversations() {
+        // Create exporter
+        let options = Options::fake();
+        let mut config = Config::new(options).unwrap();
+
+        // Two handles for the same contact
+        config.participants.insert(1, "Contact".to_string());
+        config.participants.insert(2, "Contact".to_string());
+        config.real_participants.insert(1, 10);
+        config.real_participants.insert(2, 10);
+
+        // Two chats that were deduplicated into the same conversation
+        config.chatrooms.insert(1, fake_chat(1, None));
+        config.chatrooms.insert(2, fake_chat(2, Some("Group")));
+        config.real_chatrooms.insert(1, 5);
+        config.real_chatrooms.insert(2, 5);
+        config.chatroom_participants.insert(1, BTreeSet::from([1]));
+        config.chatroom_participants.insert(2, BTreeSet::from([2]));
+
+        let exporter = SQLite::new(&config);
+        let output = fake_output();
+        exporter.write_participants(&output).unwrap();
+        exporter.write_conversations(&output).unwrap();
+
+        let participants: i32 = output
+            .query_row(
+                "SELECT COUNT(*) FROM participants WHERE id = 10",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        let conversations: i32 = output
+            .query_row("SELECT COUNT(*) FROM conversations", [], |row| row.get(0))
+            .unwrap();
+        let chats: i32 = output
+            .query_row(
+                "SELECT COUNT(*) FROM chats WHERE conversation_id = 5",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        let members: i32 = output
+            .query_row(
+                "SELECT COUNT(*) FROM conversation_participants",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+
+        assert_eq!(participants, 1);
+        assert_eq!(conversations, 1);
+        assert_eq!(chats, 2);
+        assert_eq!(members, 1);
+    }
+
+    #[test]
+    fn can_write_message() {
+        // Set timezone to PST for consistent Local time
+        set_var("TZ", "PST");
+
+        // Create exporter
+        let options = Options::fake();
+        let mut config = Config::new(options).unwrap();
+        config
+            .participants
+            .insert(999999, "Sample Contact".to_string());
+        config.real_participants.insert(999999, 7);
+        let exporter = SQLite::new(&config);
+        let output = fake_output();
+        exporter.write_participants(&output).unwrap();
+
+        // Create fake message
+        let mut message = blank();
+        message.rowid = 1;
+        message.guid = "guid".to_string();
+        message.handle_id = Some(999999);
+        message.text = Some("Hello world".to_string());
+        // May 17, 2022  8:29:42 PM
+        message.date = 674526582885055488;
+        message.expressive_send_style_id =
+            Some("com.apple.MobileSMS.expressivesend.impact".to_string());
+
+        exporter.write_message(&output, &message).unwrap();
+
+        let row: (i32, String, String, Option<String>, String, Option<String>) = output
+            .query_row(
+                "SELECT sender_id, sender, date, date_read, variant, expressive FROM messages WHERE id = 1",
+                [],
+                |row| {
+                    Ok((
+                        row.get(0)?,
+                        row.get(1)?,
+                        row.get(2)?,
+                        row.get(3)?,
+                        row.get(4)?,
+                        row.get(5)?,
+                    ))
+                },
+            )
+            .unwrap();
+
+        assert_eq!(
+            row,
+            (
+                7,
+                "Sample Contact".to_string(),
+                "2022-05-17T17:29:42-07:00".to_string(),
+                None,
+                "normal".to_string(),
+                Some("Slam".to_string())
+            )
+        );
+    }
+
+    #[test]
+    fn can_write_redacted_edits() {
+        // Create exporter
+        let mut options = Options::fake();
+        options.redactor = Some(Redactor::new(&[]).unwrap());
+        let config = Config::new(options).unwrap();
+        let exporter = SQLite::new(&config);
+        let output = fake_output();
+
+        let mut message = blank();
+        message.rowid = 1;
+        let mut edited = EditedMessage {
+            events: vec![
+                EditedEvent {
+                    date: 0,
+                    text: "Call me at 555-867-5309".to_string(),
+                    guid: None,
+                },
+                EditedEvent {
+                    date: 0,
+                    text: "Email jane@example.com instead".to_string(),
+                    guid: None,
+                },
+            ],
+        };
+
+        exporter.write_participants(&output).unwrap();
+        exporter.write_message(&output, &message).unwrap();
+        exporter
+            .write_edits(&output, &message, &mut edited)
+            .unwrap();
+
+        let mut statement = output
+            .prepare("SELECT text FROM edits WHERE message_id = 1 ORDER BY position")
+            .unwrap();
+        let texts: Vec<String> = statement
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+
+        assert_eq!(texts, vec!["Call me at [phone]", "Email [email] instead"]);
+    }
+
+    #[test]
+    fn can_get_variant_columns() {
+        let mut message = blank();
+        assert_eq!(SQLite::variant_columns(&message), ("normal", None));
+
+        message.balloon_bundle_id = Some("com.example.app".to_string());
+        assert_eq!(
+            SQLite::variant_columns(&message),
+            ("app", Some("com.example.app".to_string()))
+        );
+
+        message.balloon_bundle_id = None;
+        message.group_title = Some("New Name".to_string());
+        assert_eq!(SQLite::variant_columns(&message), ("announcement", None));
+    }
+
+    #[test]
+    fn cant_get_unset_date_column() {
+        let options = Options::fake();
+        let config = Config::new(options).unwrap();
+        let exporter = SQLite::new(&config);
+        assert_eq!(exporter.date_column(&0), None);
+    }
+}
@@ -0,0 +1,335 @@
+/*!
+ Contains a small template engine used to customize the markup of HTML exports.
+
+ Templates are plain text with tags wrapped in double braces:
+
+ - `{{ key }}` is replaced with the value of `key`, or nothing if there is no such key
+ - `{{#key}} ... {{/key}}` is rendered only if `key` has a non-empty value
+ - `{{^key}} ... {{/key}}` is rendered only if `key` is empty or missing
+
+ Values are inserted as-is. The exporter escapes message content before it is placed in a context,
+ so values are already safe to use as HTML.
+*/
+
+use std::{
+    fs::read_to_string,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+/// Template for the page that wraps every exported file
+pub const PAGE_TEMPLATE: &str = "page.html";
+/// Template for a single message
+pub const MESSAGE_TEMPLATE: &str = "message.html";
+/// Template for a tapback reaction
+pub const REACTION_TEMPLATE: &str = "reaction.html";
+/// Template for an embedded attachment
+pub const ATTACHMENT_TEMPLATE: &str = "attachment.html";
+/// Template for an app message balloon
+pub const BALLOON_TEMPLATE: &str = "balloon.html";
+/// Stylesheet inlined into every page
+pub const STYLESHEET: &str = "style.css";
+
+const DEFAULT_PAGE: &str = include_str!("resources/templates/page.html");
+const DEFAULT_MESSAGE: &str = include_str!("resources/templates/message.html");
+const DEFAULT_REACTION: &str = include_str!("resources/templates/reaction.html");
+const DEFAULT_ATTACHMENT: &str = include_str!("resources/templates/attachment.html");
+const DEFAULT_BALLOON: &str = include_str!("resources/templates/balloon.html");
+const DEFAULT_STYLE: &str = include_str!("resources/style.css");
+
+/// The tag in the page template that is replaced by the exported messages
+const CONTENT: &str = "content";
+
+/// A template that can be rendered with a set of key-value pairs
+#[derive(Debug, PartialEq, Eq)]
+pub struct Template {
+    source: String,
+}
+
+impl Template {
+    pub fn new(source: &str) -> Self {
+        Template {
+            source: source.to_string(),
+        }
+    }
+
+    /// Render the template, replacing tags with values from `context`
+    pub fn render(&self, context: &[(&str, &str)]) -> String {
+        let mut out_s = String::with_capacity(self.source.len());
+        render_into(&self.source, context, &mut out_s);
+        out_s
+    }
+
+    /// Split the template into the parts before and after the first `{{ key }}` tag
+    pub fn split(&self, key: &str) -> Option<(Template, Template)> {
+        let mut offset = 0;
+        while let Some((start, end, tag)) = next_tag(&self.source[offset..]) {
+            if tag == key {
+                return Some((
+                    Template::new(&self.source[..offset + start]),
+                    Template::new(&self.source[offset + end..]),
+                ));
+            }
+            offset += end;
+        }
+        None
+    }
+}
+
+/// Find the next tag in `source`, returning its start, end, and trimmed contents
+fn next_tag(source: &str) -> Option<(usize, usize, &str)> {
+    let start = source.find("{{")?;
+    let end = start + 2 + source[start + 2..].find("}}")?;
+    Some((start, end + 2, source[start + 2..end].trim()))
+}
+
+/// Get the value of `key` from the context, or an empty string if it is missing
+fn lookup<'a>(context: &[(&str, &'a str)], key: &str) -> &'a str {
+    context
+        .iter()
+        .find(|(name, _)| *name == key)
+        .map(|(_, value)| *value)
+        .unwrap_or_default()
+}
+
+/// Split `source` at the tag that closes the `key` section, returning the section body and what follows it
+fn section<'a>(source: &'a str, key: &str) -> (&'a str, &'a str) {
+    let mut depth = 0;
+    let mut offset = 0;
+    while let Some((start, end, tag)) = next_tag(&source[offset..]) {
+        match tag.split_at_checked(1) {
+            Some(("#" | "^", name)) if name.trim() == key => depth += 1,
+            Some(("/", name)) if name.trim() == key => {
+                if depth == 0 {
+                    return (&source[..offset + start], &source[offset + end..]);
+                }
+                depth -= 1;
+            }
+            _ => {}
+        }
+        offset += end;
+    }
+    // Unclosed sections run to the end of the template
+    (source, "")
+}
+
+fn render_into(source: &str, context: &[(&str, &str)], out_s: &mut String) {
+    let mut rest = source;
+    while let Some((start, end, tag)) = next_tag(rest) {
+        out_s.push_str(&rest[..start]);
+        rest = &rest[end..];
+        match tag.split_at_checked(1) {
+            Some((kind @ ("#" | "^"), key)) => {
+                let (body, remainder) = section(rest, key.trim());
+                let has_value = !lookup(context, key.trim()).is_empty();
+                if has_value == (kind == "#") {
+                    render_into(body, context, out_s);
+                }
+                rest = remainder;
+            }
+            // Stray closing tags render nothing
+            Some(("/", _)) => {}
+            _ => out_s.push_str(lookup(context, tag)),
+        }
+    }
+    out_s.push_str(rest);
+}
+
+/// The set of templates used to build an HTML export
+#[derive(Debug, PartialEq, Eq)]
+pub struct Templates {
+    /// CSS inlined into the `{{ style }}` tag of each page
+    pub style: String,
+    /// Part of the page template before `{{ content }}`
+    pub header: Template,
+    /// Part of the page template after `{{ content }}`
+    pub footer: String,
+    /// Template for each message
+    pub message: Template,
+    /// Template for each tapback reaction
+    pub reaction: Template,
+    /// Template for each attachment
+    pub attachment: Template,
+    /// Template for each app message balloon
+    pub balloon: Template,
+}
+
+impl Templates {
+    /// Load templates from a directory, using the default for any file the directory does not contain
+    pub fn load(dir: Option<&Path>) -> Self {
+        let mut templates = Templates::default();
+        let Some(dir) = dir else {
+            return templates;
+        };
+
+        if let Some(style) = read_template(dir, STYLESHEET) {
+            templates.style = style;
+        }
+        if let Some(page) = read_template(dir, PAGE_TEMPLATE) {
+            match Template::new(&page).split(CONTENT) {
+                Some((header, footer)) => {
+                    templates.header = header;
+                    templates.footer = footer.render(&[]);
+                }
+                None => eprintln!(
+                    "{PAGE_TEMPLATE} does not contain a {{{{ {CONTENT} }}}} tag, using the default page instead!"
+                ),
+            }
+        }
+        if let Some(message) = read_template(dir, MESSAGE_TEMPLATE) {
+            templates.message = Template::new(&message);
+        }
+        if let Some(reaction) = read_template(dir, REACTION_TEMPLATE) {
+            templates.reaction = Template::new(&reaction);
+        }
+        if let Some(attachment) = read_template(dir, ATTACHMENT_TEMPLATE) {
+            templates.attachment = Template::new(&attachment);
+        }
+        if let Some(balloon) = read_template(dir, BALLOON_TEMPLATE) {
+            templates.balloon = Template::new(&balloon);
+        }
+        templates
+    }
+
+    /// Render the start of a page, up to where messages are written
+    pub fn header(&self, title: &str) -> String {
+        self.header
+            .render(&[("title", title), ("style", &self.style)])
+    }
+}
+
+impl Default for Templates {
+    fn default() -> Self {
+        // The default page always contains the content tag
+        let (header, footer) = Template::new(DEFAULT_PAGE)
+            .split(CONTENT)
+            .unwrap_or((Template::new(DEFAULT_PAGE), Template::new("")));
+        Templates {
+            style: DEFAULT_STYLE.to_string(),
+            header,
+            footer: footer.render(&[]),
+            message: Template::new(DEFAULT_MESSAGE),
+            reaction: Template::new(DEFAULT_REACTION),
+            attachment: Template::new(DEFAULT_ATTACHMENT),
+            balloon: Template::new(DEFAULT_BALLOON),
+        }
+    }
+}
+
+/// Read a template file, returning `None` if it does not exist or cannot be read
+fn read_template(dir: &Path, name: &str) -> Option<String> {
+    let path: PathBuf = dir.join(name);
+    match read_to_string(&path) {
+        Ok(template) => Some(template),
+        Err(why) if why.kind() == ErrorKind::NotFound => None,
+        Err(why) => {
+            eprintln!("Unable to read template {path:?}, using the default instead: {why}");
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        env::temp_dir,
+        fs::{create_dir_all, remove_dir_all, write},
+    };
+
+    use crate::exporters::template::{Template, Templates};
+
+    #[test]
+    fn can_render_values() {
+        let template = Template::new("<b>{{ name }}</b> said {{text}}{{missing}}");
+        assert_eq!(
+            template.render(&[("name", "Sample"), ("text", "hi")]),
+            "<b>Sample</b> said hi"
+        );
+    }
+
+    #[test]
+    fn can_render_sections() {
+        let template =
+            Template::new("{{#url}}<a href=\"{{url}}\">{{/url}}link{{^url}} (none){{/url}}");
+        assert_eq!(
+            template.render(&[("url", "https://example.com")]),
+            "<a href=\"https://example.com\">link"
+        );
+        assert_eq!(template.render(&[("url", "")]), "link (none)");
+        assert_eq!(template.render(&[]), "link (none)");
+    }
+
+    #[test]
+    fn can_render_nested_sections() {
+        let template = Template::new("{{#a}}[{{#b}}{{b}}{{/b}}{{#a}}{{a}}{{/a}}]{{/a}}");
+        assert_eq!(template.render(&[("a", "1"), ("b", "2")]), "[21]");
+        assert_eq!(template.render(&[("a", "1")]), "[1]");
+        assert_eq!(template.render(&[("b", "2")]), "");
+    }
+
+    #[test]
+    fn can_render_unclosed() {
+        assert_eq!(Template::new("{{#a}}x").render(&[("a", "1")]), "x");
+        assert_eq!(Template::new("a {{ b").render(&[("b", "1")]), "a {{ b");
+        assert_eq!(Template::new("a {{/b}}c").render(&[]), "a c");
+    }
+
+    #[test]
+    fn can_split() {
+        let (header, footer) = Template::new("<body>{{ content }}</body>")
+            .split("content")
+            .unwrap();
+        assert_eq!(header, Template::new("<body>"));
+        assert_eq!(footer, Template::new("</body>"));
+        assert!(Template::new("<body></body>").split("content").is_none());
+    }
+
+    #[test]
+    fn can_load_partial_directory() {
+        let dir = temp_dir().join("imessage-exporter-template-test");
+        create_dir_all(&dir).unwrap();
+        write(dir.join("style.css"), "body { color: red; }").unwrap();
+        write(
+            dir.join("page.html"),
+            "<title>{{title}}</title><style>{{style}}</style>{{content}}<footer>Firm</footer>",
+        )
+        .unwrap();
+
+        let templates = Templates::load(Some(&dir));
+        remove_dir_all(&dir).unwrap();
+
+        let defaults = Templates::default();
+        assert_eq!(
+            templates.header("Case"),
+            "<title>Case</title><style>body { color: red; }</style>"
+        );
+        assert_eq!(templates.footer, "<footer>Firm</footer>");
+        assert_eq!(templates.message, defaults.message);
+        assert_eq!(templates.balloon, defaults.balloon);
+    }
+
+    #[test]
+    fn can_load_without_content_tag() {
+        let dir = temp_dir().join("imessage-exporter-template-no-content-test");
+        create_dir_all(&dir).unwrap();
+        write(dir.join("page.html"), "<html></html>").unwrap();
+
+        let templates = Templates::load(Some(&dir));
+        remove_dir_all(&dir).unwrap();
+
+        assert_eq!(templates, Templates::default());
+    }
+
+    #[test]
+    fn can_render_default_page() {
+        let templates = Templates::default();
+        assert!(templates
+            .header("")
+            .ends_with("</style>\n</head>\n<body>\n"));
+        assert!(!templates.header("").contains("<title>"));
+        assert!(templates
+            .header("Conversations")
+            .contains("\n<title>Conversations</title>\n<style>\n"));
+        assert_eq!(templates.footer, "</body></html>");
+    }
+}
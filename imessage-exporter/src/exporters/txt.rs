@@ -1,7 +1,5 @@
 use std::{
     collections::HashMap,
-    fs::File,
-    io::Write,
     path::{Path, PathBuf},
 };
 
@@ -30,7 +28,7 @@ use imessage_database::{
         table::{Table, FITNESS_RECEIVER, ME, ORPHANED, YOU},
     },
     util::{
-        dates::{format, get_local_time, readable_diff, TIMESTAMP_FACTOR},
+        dates::{get_local_time, readable_diff, TIMESTAMP_FACTOR},
         plist::parse_plist,
     },
 };
@@ -93,18 +91,26 @@ impl<'a> Exporter<'a> for TXT<'a> {
             }
             current_message_row = msg.rowid;
 
+            // Skip messages that are not near a match for the text filter
+            if !self.config.is_selected(&mut msg) {
+                current_message += 1;
+                continue;
+            }
+
             // Render the announcement in-line
             if msg.is_announcement() {
                 let announcement = self.format_announcement(&msg);
-                TXT::write_to_file(self.get_or_create_file(&msg), &announcement);
+                self.config
+                    .write_to_file(self.get_or_create_file(&msg), &announcement);
             }
             // Message replies and reactions are rendered in context, so no need to render them separately
             else if !msg.is_reaction() {
-                let _ = msg.gen_text(&self.config.db);
+                self.config.gen_text(&mut msg);
                 let message = self
                     .format_message(&msg, 0)
                     .map_err(RuntimeError::DatabaseError)?;
-                TXT::write_to_file(self.get_or_create_file(&msg), &message);
+                self.config
+                    .write_to_file(self.get_or_create_file(&msg), &message);
             }
             current_message += 1;
             if current_message % 99 == 0 {
@@ -117,15 +123,20 @@ impl<'a> Exporter<'a> for TXT<'a> {
 
     /// Create a file for the given chat, caching it so we don't need to build it later
     fn get_or_create_file(&mut self, message: &Message) -> &Path {
-        match self.config.conversation(message) {
+        let path = match self.config.conversation(message) {
             Some((chatroom, id)) => self.files.entry(*id).or_insert_with(|| {
                 let mut path = self.config.options.export_path.clone();
                 path.push(self.config.filename(chatroom));
                 path.set_extension("txt");
                 path
             }),
-            None => &self.orphaned,
+            None => &mut self.orphaned,
+        };
+        if let Some(previous) = self.config.partition(path, message) {
+            self.config.close_output(&previous);
         }
+        self.config.record(message, path);
+        path
     }
 }
 
@@ -160,7 +171,7 @@ impl<'a> Writer<'a> for TXT<'a> {
 
         // Useful message metadata
         let message_parts = message.body();
-        let mut attachments = Attachment::from_message(&self.config.db, message)?;
+        let mut attachments = self.config.attachments(message)?;
         let mut replies = message.get_replies(&self.config.db)?;
 
         // Index of where we are in the attachment Vector
@@ -279,7 +290,7 @@ impl<'a> Writer<'a> for TXT<'a> {
                 replies
                     .iter_mut()
                     .try_for_each(|reply| -> Result<(), TableError> {
-                        let _ = reply.gen_text(&self.config.db);
+                        self.config.gen_text(reply);
                         if !reply.is_reaction() {
                             self.add_line(
                                 &mut formatted_message,
@@ -365,7 +376,10 @@ impl<'a> Writer<'a> for TXT<'a> {
                 // Handle URL messages separately since they are a special case
                 let res = if message.is_url() {
                     let parsed = parse_plist(&payload)?;
-                    let bubble = URLMessage::get_url_message_override(&parsed)?;
+                    let mut bubble = URLMessage::get_url_message_override(&parsed)?;
+                    if let Some(redactor) = &self.config.options.redactor {
+                        bubble = redactor.strip_link(bubble);
+                    }
                     match bubble {
                         URLOverride::Normal(balloon) => self.format_url(&balloon, indent),
                         URLOverride::AppleMusic(balloon) => self.format_music(&balloon, indent),
@@ -427,7 +441,7 @@ impl<'a> Writer<'a> for TXT<'a> {
                 ))
             }
             Variant::Sticker(_) => {
-                let mut paths = Attachment::from_message(&self.config.db, msg)?;
+                let mut paths = self.config.attachments(msg)?;
                 let who =
                     self.config
                         .who(msg.handle_id, msg.is_from_me, &msg.destination_caller_id);
@@ -475,9 +489,13 @@ impl<'a> Writer<'a> for TXT<'a> {
             who = self.config.options.custom_name.as_deref().unwrap_or(YOU);
         }
 
-        let timestamp = format(&msg.date(&self.config.offset));
+        let timestamp = self
+            .config
+            .options
+            .date_format
+            .format(&msg.date(&self.config.offset));
 
-        return match msg.get_announcement() {
+        match msg.get_announcement() {
             Some(announcement) => match announcement {
                 Announcement::NameChange(name) => {
                     format!("{timestamp} {who} renamed the conversation to {name}\n\n")
@@ -490,7 +508,7 @@ impl<'a> Writer<'a> for TXT<'a> {
                 }
             },
             None => String::from("Unable to format announcement!\n\n"),
-        };
+        }
     }
 
     fn format_shareplay(&self) -> &str {
@@ -500,8 +518,11 @@ impl<'a> Writer<'a> for TXT<'a> {
     fn format_edited(&self, msg: &'a Message, indent: &str) -> Result<String, MessageError> {
         if let Some(payload) = msg.message_summary_info(&self.config.db) {
             // Parse the edited message
-            let edited_message =
+            let mut edited_message =
                 EditedMessage::from_map(&payload).map_err(MessageError::PlistParseError)?;
+            if let Some(redactor) = &self.config.options.redactor {
+                redactor.redact_edits(&mut edited_message);
+            }
 
             let mut out_s = String::new();
             let mut previous_timestamp: Option<&i64> = None;
@@ -519,8 +540,11 @@ impl<'a> Writer<'a> for TXT<'a> {
                     match previous_timestamp {
                         // Original message get an absolute timestamp
                         None => {
-                            let parsed_timestamp =
-                                format(&get_local_time(&event.date, &self.config.offset));
+                            let parsed_timestamp = self
+                                .config
+                                .options
+                                .date_format
+                                .format(&get_local_time(&event.date, &self.config.offset));
                             out_s.push_str(&parsed_timestamp);
                             out_s.push(' ');
                         }
@@ -549,15 +573,6 @@ impl<'a> Writer<'a> for TXT<'a> {
         }
         Err(MessageError::PlistParseError(PlistParseError::NoPayload))
     }
-
-    fn write_to_file(file: &Path, text: &str) {
-        match File::options().append(true).create(true).open(file) {
-            Ok(mut file) => {
-                let _ = file.write_all(text.as_bytes());
-            }
-            Err(why) => eprintln!("Unable to write to {file:?}: {why:?}"),
-        };
-    }
 }
 
 impl<'a> BalloonFormatter<&'a str> for TXT<'a> {
@@ -785,7 +800,7 @@ impl<'a> BalloonFormatter<&'a str> for TXT<'a> {
             // Parse the estimated end time from the message's query string
             let date_stamp = date_str.parse::<f64>().unwrap_or(0.) as i64 * TIMESTAMP_FACTOR;
             let date_time = get_local_time(&date_stamp, &0);
-            let date_string = format(&date_time);
+            let date_string = self.config.options.date_format.format(&date_time);
 
             out_s.push_str("\nExpected at ");
             out_s.push_str(&date_string);
@@ -795,7 +810,7 @@ impl<'a> BalloonFormatter<&'a str> for TXT<'a> {
             // Parse the estimated end time from the message's query string
             let date_stamp = date_str.parse::<f64>().unwrap_or(0.) as i64 * TIMESTAMP_FACTOR;
             let date_time = get_local_time(&date_stamp, &0);
-            let date_string = format(&date_time);
+            let date_string = self.config.options.date_format.format(&date_time);
 
             out_s.push_str("\nWas expected at ");
             out_s.push_str(&date_string);
@@ -805,7 +820,7 @@ impl<'a> BalloonFormatter<&'a str> for TXT<'a> {
             // Parse the estimated end time from the message's query string
             let date_stamp = date_str.parse::<f64>().unwrap_or(0.) as i64 * TIMESTAMP_FACTOR;
             let date_time = get_local_time(&date_stamp, &0);
-            let date_string = format(&date_time);
+            let date_string = self.config.options.date_format.format(&date_time);
 
             out_s.push_str("\nChecked in at ");
             out_s.push_str(&date_string);
@@ -864,7 +879,11 @@ impl<'a> BalloonFormatter<&'a str> for TXT<'a> {
 
 impl<'a> TXT<'a> {
     fn get_time(&self, message: &Message) -> String {
-        let mut date = format(&message.date(&self.config.offset));
+        let mut date = self
+            .config
+            .options
+            .date_format
+            .format(&message.date(&self.config.offset));
         let read_after = message.time_until_read(&self.config.offset);
         if let Some(time) = read_after {
             if !time.is_empty() {
@@ -896,12 +915,11 @@ mod tests {
     };
 
     use crate::{
-        app::attachment_manager::AttachmentManager, exporters::exporter::Writer, Config, Exporter,
-        Options, TXT,
+        app::redactor::Redactor, exporters::exporter::Writer, Config, Exporter, Options, TXT,
     };
     use imessage_database::{
         tables::{attachment::Attachment, messages::Message},
-        util::{dirs::default_db_path, platform::Platform, query_context::QueryContext},
+        util::platform::Platform,
     };
 
     fn blank() -> Message {
@@ -935,23 +953,6 @@ mod tests {
         }
     }
 
-    pub fn fake_options() -> Options {
-        Options {
-            db_path: default_db_path(),
-            attachment_root: None,
-            attachment_manager: AttachmentManager::Disabled,
-            diagnostic: false,
-            export_type: None,
-            export_path: PathBuf::new(),
-            query_context: QueryContext::default(),
-            no_lazy: false,
-            custom_name: None,
-            use_caller_id: false,
-            platform: Platform::macOS,
-            ignore_disk_space: false,
-        }
-    }
-
     pub fn fake_attachment() -> Attachment {
         Attachment {
             rowid: 0,
@@ -968,7 +969,7 @@ mod tests {
 
     #[test]
     fn can_create() {
-        let options = fake_options();
+        let options = Options::fake();
         let config = Config::new(options).unwrap();
         let exporter = TXT::new(&config);
         assert_eq!(exporter.files.len(), 0);
@@ -980,7 +981,7 @@ mod tests {
         set_var("TZ", "PST");
 
         // Create exporter
-        let options = fake_options();
+        let options = Options::fake();
         let config = Config::new(options).unwrap();
         let exporter = TXT::new(&config);
 
@@ -999,13 +1000,40 @@ mod tests {
         );
     }
 
+    #[test]
+    fn can_get_time_in_time_zone() {
+        // Set timezone to PST for consistent Local time
+        set_var("TZ", "PST");
+
+        // Create exporter
+        let mut options = Options::fake();
+        options.date_format.time_zone = "Asia/Tokyo".parse().ok();
+        options.date_format.format = "%Y-%m-%d %H:%M %Z".to_string();
+        let config = Config::new(options).unwrap();
+        let exporter = TXT::new(&config);
+
+        // Create fake message
+        let mut message = blank();
+        // May 17, 2022  8:29:42 PM
+        message.date = 674526582885055488;
+        // May 17, 2022  8:29:42 PM
+        message.date_delivered = 674526582885055488;
+        // May 17, 2022  9:30:31 PM
+        message.date_read = 674530231992568192;
+
+        assert_eq!(
+            exporter.get_time(&message),
+            "2022-05-18 09:29 JST (Read by you after 1 hour, 49 seconds)"
+        );
+    }
+
     #[test]
     fn can_get_time_invalid() {
         // Set timezone to PST for consistent Local time
         set_var("TZ", "PST");
 
         // Create exporter
-        let options = fake_options();
+        let options = Options::fake();
         let config = Config::new(options).unwrap();
         let exporter = TXT::new(&config);
 
@@ -1023,7 +1051,7 @@ mod tests {
     #[test]
     fn can_add_line_no_indent() {
         // Create exporter
-        let options = fake_options();
+        let options = Options::fake();
         let config = Config::new(options).unwrap();
         let exporter = TXT::new(&config);
 
@@ -1037,7 +1065,7 @@ mod tests {
     #[test]
     fn can_add_line_indent() {
         // Create exporter
-        let options = fake_options();
+        let options = Options::fake();
         let config = Config::new(options).unwrap();
         let exporter = TXT::new(&config);
 
@@ -1054,7 +1082,7 @@ mod tests {
         set_var("TZ", "PST");
 
         // Create exporter
-        let options = fake_options();
+        let options = Options::fake();
         let config = Config::new(options).unwrap();
         let exporter = TXT::new(&config);
 
@@ -1071,13 +1099,38 @@ mod tests {
         assert_eq!(actual, expected);
     }
 
+    #[test]
+    fn can_format_txt_redacted() {
+        // Set timezone to PST for consistent Local time
+        set_var("TZ", "PST");
+
+        // Create exporter
+        let mut options = Options::fake();
+        options.redactor = Some(Redactor::new(&[]).unwrap());
+        let config = Config::new(options).unwrap();
+        let exporter = TXT::new(&config);
+
+        let mut message = blank();
+        // May 17, 2022  8:29:42 PM
+        message.date = 674526582885055488;
+        message.text = Some("Text me at 555-867-5309 or jane@example.com".to_string());
+        message.is_from_me = true;
+        message.chat_id = Some(0);
+        config.gen_text(&mut message);
+
+        let actual = exporter.format_message(&message, 0).unwrap();
+        let expected = "May 17, 2022  5:29:42 PM\nMe\nText me at [phone] or [email]\n\n";
+
+        assert_eq!(actual, expected);
+    }
+
     #[test]
     fn can_format_txt_from_me_normal_deleted() {
         // Set timezone to PST for consistent Local time
         set_var("TZ", "PST");
 
         // Create exporter
-        let options = fake_options();
+        let options = Options::fake();
         let config = Config::new(options).unwrap();
         let exporter = TXT::new(&config);
 
@@ -1100,7 +1153,7 @@ mod tests {
         set_var("TZ", "PST");
 
         // Create exporter
-        let options = fake_options();
+        let options = Options::fake();
         let config = Config::new(options).unwrap();
         let exporter = TXT::new(&config);
 
@@ -1125,7 +1178,7 @@ mod tests {
         set_var("TZ", "PST");
 
         // Create exporter
-        let options = fake_options();
+        let options = Options::fake();
         let mut config = Config::new(options).unwrap();
         config
             .participants
@@ -1150,7 +1203,7 @@ mod tests {
         set_var("TZ", "PST");
 
         // Create exporter
-        let options = fake_options();
+        let options = Options::fake();
         let mut config = Config::new(options).unwrap();
         config
             .participants
@@ -1180,7 +1233,7 @@ mod tests {
         set_var("TZ", "PST");
 
         // Create exporter
-        let mut options = fake_options();
+        let mut options = Options::fake();
         options.custom_name = Some("Name".to_string());
         let mut config = Config::new(options).unwrap();
         config
@@ -1211,7 +1264,7 @@ mod tests {
         set_var("TZ", "PST");
 
         // Create exporter
-        let options = fake_options();
+        let options = Options::fake();
         let config = Config::new(options).unwrap();
         let exporter = TXT::new(&config);
 
@@ -1232,7 +1285,7 @@ mod tests {
         set_var("TZ", "PST");
 
         // Create exporter
-        let options = fake_options();
+        let options = Options::fake();
         let config = Config::new(options).unwrap();
         let exporter = TXT::new(&config);
 
@@ -1253,7 +1306,7 @@ mod tests {
         set_var("TZ", "PST");
 
         // Create exporter
-        let mut options = fake_options();
+        let mut options = Options::fake();
         options.custom_name = Some("Name".to_string());
         let config = Config::new(options).unwrap();
         let exporter = TXT::new(&config);
@@ -1275,7 +1328,7 @@ mod tests {
         set_var("TZ", "PST");
 
         // Create exporter
-        let options = fake_options();
+        let options = Options::fake();
         let config = Config::new(options).unwrap();
         let exporter = TXT::new(&config);
 
@@ -1297,7 +1350,7 @@ mod tests {
         set_var("TZ", "PST");
 
         // Create exporter
-        let options = fake_options();
+        let options = Options::fake();
         let mut config = Config::new(options).unwrap();
         config
             .participants
@@ -1320,7 +1373,7 @@ mod tests {
     #[test]
     fn can_format_txt_attachment_macos() {
         // Create exporter
-        let options = fake_options();
+        let options = Options::fake();
         let config = Config::new(options).unwrap();
         let exporter = TXT::new(&config);
 
@@ -1338,7 +1391,7 @@ mod tests {
     #[test]
     fn can_format_txt_attachment_macos_invalid() {
         // Create exporter
-        let options = fake_options();
+        let options = Options::fake();
         let config = Config::new(options).unwrap();
         let exporter = TXT::new(&config);
 
@@ -1355,7 +1408,7 @@ mod tests {
     #[test]
     fn can_format_txt_attachment_ios() {
         // Create exporter
-        let options = fake_options();
+        let options = Options::fake();
         let mut config = Config::new(options).unwrap();
         config.options.platform = Platform::iOS;
         let exporter = TXT::new(&config);
@@ -1374,7 +1427,7 @@ mod tests {
     #[test]
     fn can_format_txt_attachment_ios_invalid() {
         // Create exporter
-        let options = fake_options();
+        let options = Options::fake();
         let mut config = Config::new(options).unwrap();
         // Modify this
         config.options.platform = Platform::iOS;
@@ -1393,7 +1446,7 @@ mod tests {
     #[test]
     fn can_format_txt_attachment_sticker() {
         // Create exporter
-        let mut options = fake_options();
+        let mut options = Options::fake();
         options.export_path = current_dir().unwrap().parent().unwrap().to_path_buf();
 
         let config = Config::new(options).unwrap();
@@ -1426,8 +1479,7 @@ mod tests {
 mod balloon_format_tests {
     use std::env::set_var;
 
-    use super::tests::fake_options;
-    use crate::{exporters::exporter::BalloonFormatter, Config, Exporter, TXT};
+    use crate::{exporters::exporter::BalloonFormatter, Config, Exporter, Options, TXT};
     use imessage_database::message_types::{
         app::AppMessage,
         app_store::AppStoreMessage,
@@ -1440,7 +1492,7 @@ mod balloon_format_tests {
     #[test]
     fn can_format_txt_url() {
         // Create exporter
-        let options = fake_options();
+        let options = Options::fake();
         let config = Config::new(options).unwrap();
         let exporter = TXT::new(&config);
 
@@ -1465,7 +1517,7 @@ mod balloon_format_tests {
     #[test]
     fn can_format_txt_music() {
         // Create exporter
-        let options = fake_options();
+        let options = Options::fake();
         let config = Config::new(options).unwrap();
         let exporter = TXT::new(&config);
 
@@ -1486,7 +1538,7 @@ mod balloon_format_tests {
     #[test]
     fn can_format_txt_collaboration() {
         // Create exporter
-        let options = fake_options();
+        let options = Options::fake();
         let config = Config::new(options).unwrap();
         let exporter = TXT::new(&config);
 
@@ -1508,7 +1560,7 @@ mod balloon_format_tests {
     #[test]
     fn can_format_txt_apple_pay() {
         // Create exporter
-        let options = fake_options();
+        let options = Options::fake();
         let config = Config::new(options).unwrap();
         let exporter = TXT::new(&config);
 
@@ -1534,7 +1586,7 @@ mod balloon_format_tests {
     #[test]
     fn can_format_txt_fitness() {
         // Create exporter
-        let options = fake_options();
+        let options = Options::fake();
         let config = Config::new(options).unwrap();
         let exporter = TXT::new(&config);
 
@@ -1560,7 +1612,7 @@ mod balloon_format_tests {
     #[test]
     fn can_format_txt_slideshow() {
         // Create exporter
-        let options = fake_options();
+        let options = Options::fake();
         let config = Config::new(options).unwrap();
         let exporter = TXT::new(&config);
 
@@ -1586,7 +1638,7 @@ mod balloon_format_tests {
     #[test]
     fn can_format_txt_find_my() {
         // Create exporter
-        let options = fake_options();
+        let options = Options::fake();
         let config = Config::new(options).unwrap();
         let exporter = TXT::new(&config);
 
@@ -1615,7 +1667,7 @@ mod balloon_format_tests {
         set_var("TZ", "PST");
 
         // Create exporter
-        let options = fake_options();
+        let options = Options::fake();
         let config = Config::new(options).unwrap();
         let exporter = TXT::new(&config);
 
@@ -1644,7 +1696,7 @@ mod balloon_format_tests {
         set_var("TZ", "PST");
 
         // Create exporter
-        let options = fake_options();
+        let options = Options::fake();
         let config = Config::new(options).unwrap();
         let exporter = TXT::new(&config);
 
@@ -1673,7 +1725,7 @@ mod balloon_format_tests {
         set_var("TZ", "PST");
 
         // Create exporter
-        let options = fake_options();
+        let options = Options::fake();
         let config = Config::new(options).unwrap();
         let exporter = TXT::new(&config);
 
@@ -1699,7 +1751,7 @@ mod balloon_format_tests {
     #[test]
     fn can_format_txt_app_store() {
         // Create exporter
-        let options = fake_options();
+        let options = Options::fake();
         let config = Config::new(options).unwrap();
         let exporter = TXT::new(&config);
 
@@ -1721,7 +1773,7 @@ mod balloon_format_tests {
     #[test]
     fn can_format_txt_placemark() {
         // Create exporter
-        let options = fake_options();
+        let options = Options::fake();
         let config = Config::new(options).unwrap();
         let exporter = TXT::new(&config);
 
@@ -1752,7 +1804,7 @@ mod balloon_format_tests {
     #[test]
     fn can_format_txt_generic_app() {
         // Create exporter
-        let options = fake_options();
+        let options = Options::fake();
         let config = Config::new(options).unwrap();
         let exporter = TXT::new(&config);
 
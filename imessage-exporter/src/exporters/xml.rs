@@ -0,0 +1,657 @@
+// File to export messages as an Android "SMS Backup & Restore" XML backup
+use std::{
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use uuid::Uuid;
+
+use crate::{
+    app::{
+        error::RuntimeError, progress::build_progress_bar_export, runtime::Config,
+        sanitizers::sanitize_xml,
+    },
+    exporters::exporter::Exporter,
+};
+
+use imessage_database::{
+    error::table::TableError,
+    tables::{
+        messages::{BubbleType, Message},
+        table::Table,
+    },
+};
+
+/// Name of the backup file, which restores every conversation at once
+const BACKUP: &str = "sms";
+/// Extension of the file message elements are written to before they are wrapped in the root element
+const PARTIAL: &str = "part";
+/// Address Android uses to refer to the device owner in MMS messages
+const ME_ADDRESS: &str = "insert-address-token";
+/// Contact name used when a handle has no name
+const UNKNOWN_CONTACT: &str = "(Unknown)";
+/// Value used for fields that have no data
+const NULL: &str = "null";
+/// `addr` type for the sender of an MMS message
+const ADDRESS_FROM: u8 = 137;
+/// `addr` type for a recipient of an MMS message
+const ADDRESS_TO: u8 = 151;
+
+pub struct XML<'a> {
+    /// Data that is setup from the application's runtime
+    pub config: &'a Config,
+    /// Path to the backup file
+    pub path: PathBuf,
+    /// Path to the file message elements are written to while iterating
+    pub body: PathBuf,
+    /// Number of messages written to the backup
+    pub count: usize,
+}
+
+impl<'a> Exporter<'a> for XML<'a> {
+    fn new(config: &'a Config) -> Self {
+        let mut path = config.options.export_path.clone();
+        path.push(BACKUP);
+        path.set_extension("xml");
+
+        let mut body = path.clone();
+        body.set_extension(PARTIAL);
+
+        XML {
+            config,
+            path,
+            body,
+            count: 0,
+        }
+    }
+
+    fn iter_messages(&mut self) -> Result<(), RuntimeError> {
+        // Tell the user what we are doing
+        eprintln!(
+            "Exporting to {} as xml...",
+            self.config.options.export_path.display()
+        );
+
+        // Keep track of current message ROWID
+        let mut current_message_row = -1;
+
+        // Set up progress bar
+        let mut current_message = 0;
+        let total_messages =
+            Message::get_count(&self.config.db, &self.config.options.query_context)
+                .map_err(RuntimeError::DatabaseError)?;
+        let pb = build_progress_bar_export(total_messages);
+
+        let mut statement =
+            Message::stream_rows(&self.config.db, &self.config.options.query_context)
+                .map_err(RuntimeError::DatabaseError)?;
+
+        let messages = statement
+            .query_map([], |row| Ok(Message::from_row(row)))
+            .map_err(|err| RuntimeError::DatabaseError(TableError::Messages(err)))?;
+
+        for message in messages {
+            let mut msg = Message::extract(message).map_err(RuntimeError::DatabaseError)?;
+
+            // Early escape if we try and render the same message GUID twice
+            // See https://github.com/ReagentX/imessage-exporter/issues/135 for rationale
+            if msg.rowid == current_message_row {
+                current_message += 1;
+                continue;
+            }
+            current_message_row = msg.rowid;
+
+            // Skip messages that are not near a match for the text filter
+            if !self.config.is_selected(&mut msg) {
+                current_message += 1;
+                continue;
+            }
+
+            // Android has no equivalent for reactions or group announcements
+            if !msg.is_reaction() && !msg.is_announcement() {
+                self.config.gen_text(&mut msg);
+                if let Some(element) = self
+                    .format_message(&msg)
+                    .map_err(RuntimeError::DatabaseError)?
+                {
+                    self.config
+                        .write_to_file(self.get_or_create_file(&msg), &element);
+                    self.count += 1;
+                }
+            }
+
+            current_message += 1;
+            if current_message % 99 == 0 {
+                pb.set_position(current_message);
+            }
+        }
+        pb.finish();
+
+        // The root element includes the message count, so it is written once every message is known
+        self.write_backup()
+    }
+
+    /// Every message is written to the same backup file
+    fn get_or_create_file(&mut self, _: &Message) -> &Path {
+        &self.body
+    }
+}
+
+impl<'a> XML<'a> {
+    /// Build the `<sms>` or `<mms>` element for a message
+    ///
+    /// Returns `None` if there is nobody to address the message to
+    fn format_message(&self, message: &Message) -> Result<Option<String>, TableError> {
+        let addresses = self.addresses(message);
+        if addresses.is_empty() {
+            return Ok(None);
+        }
+
+        if addresses.len() == 1 && !message.has_attachments() {
+            return Ok(Some(self.format_sms(message, addresses[0])));
+        }
+        self.format_mms(message, &addresses).map(Some)
+    }
+
+    /// Build an `<sms>` element for a text-only message in a conversation with a single other person
+    fn format_sms(&self, message: &Message, address: (i32, &str)) -> String {
+        let date = message.date(&self.config.offset);
+        let timestamp = date
+            .as_ref()
+            .map(|date| date.timestamp_millis())
+            .unwrap_or(0);
+
+        format!(
+            "  <sms protocol=\"0\" address=\"{}\" date=\"{timestamp}\" type=\"{}\" subject=\"{}\" body=\"{}\" toa=\"null\" sc_toa=\"null\" service_center=\"null\" read=\"{}\" status=\"-1\" locked=\"0\" date_sent=\"{timestamp}\" sub_id=\"-1\" readable_date=\"{}\" contact_name=\"{}\" />\n",
+            sanitize_xml(address.1),
+            if message.is_from_me { 2 } else { 1 },
+            sanitize_xml(message.subject.as_deref().unwrap_or(NULL)),
+            sanitize_xml(&self.format_text(message)),
+            u8::from(message.is_from_me || message.is_read),
+            sanitize_xml(&self.config.options.date_format.format(&date)),
+            sanitize_xml(&self.contact_name(&[address])),
+        )
+    }
+
+    /// Build an `<mms>` element for a group message or a message with attachments
+    fn format_mms(
+        &self,
+        message: &Message,
+        addresses: &[(i32, &str)],
+    ) -> Result<String, TableError> {
+        let date = message.date(&self.config.offset);
+        let timestamp = date
+            .as_ref()
+            .map(|date| date.timestamp_millis())
+            .unwrap_or(0);
+
+        let mut text = self.format_text(message);
+        let attachments = self.format_attachments(message, &mut text)?;
+
+        let mut parts = vec![];
+        if !text.is_empty() {
+            parts.push(format!(
+                "      <part seq=\"0\" ct=\"text/plain\" name=\"null\" chset=\"106\" cd=\"null\" fn=\"null\" cid=\"&lt;text0&gt;\" cl=\"text0.txt\" ctt_s=\"null\" ctt_t=\"null\" text=\"{}\" />\n",
+                sanitize_xml(&text)
+            ));
+        }
+        parts.extend(attachments);
+
+        // The sender is listed first, followed by everyone else in the conversation
+        let sender = if message.is_from_me {
+            ME_ADDRESS
+        } else {
+            self.sender_address(message).unwrap_or(ME_ADDRESS)
+        };
+        let mut addrs = vec![format_addr(sender, ADDRESS_FROM)];
+        addresses
+            .iter()
+            .filter(|(_, address)| *address != sender)
+            .for_each(|(_, address)| addrs.push(format_addr(address, ADDRESS_TO)));
+        if !message.is_from_me {
+            addrs.push(format_addr(
+                message
+                    .destination_caller_id
+                    .as_deref()
+                    .unwrap_or(ME_ADDRESS),
+                ADDRESS_TO,
+            ));
+        }
+
+        let mut element = format!(
+            "  <mms date=\"{timestamp}\" date_sent=\"{}\" rr=\"null\" sub=\"{}\" ct_t=\"application/vnd.wap.multipart.related\" read_status=\"null\" seen=\"1\" msg_box=\"{}\" address=\"{}\" sub_cs=\"null\" resp_st=\"null\" retr_st=\"null\" d_tm=\"null\" text_only=\"{}\" exp=\"null\" locked=\"0\" m_id=\"null\" st=\"null\" retr_txt_cs=\"null\" retr_txt=\"null\" creator=\"null\" m_size=\"null\" rpt_a=\"null\" ct_cls=\"null\" pri=\"129\" sub_id=\"-1\" tr_id=\"null\" read=\"{}\" m_cls=\"personal\" d_rpt=\"129\" v=\"18\" ct_l=\"null\" m_type=\"{}\" readable_date=\"{}\" contact_name=\"{}\">\n",
+            timestamp / 1000,
+            sanitize_xml(message.subject.as_deref().unwrap_or(NULL)),
+            if message.is_from_me { 2 } else { 1 },
+            sanitize_xml(
+                &addresses
+                    .iter()
+                    .map(|(_, address)| *address)
+                    .collect::<Vec<&str>>()
+                    .join("~")
+            ),
+            u8::from(!message.has_attachments()),
+            u8::from(message.is_from_me || message.is_read),
+            if message.is_from_me { 128 } else { 132 },
+            sanitize_xml(&self.config.options.date_format.format(&date)),
+            sanitize_xml(&self.contact_name(addresses)),
+        );
+        element.push_str("    <parts>\n");
+        parts.iter().for_each(|part| element.push_str(part));
+        element.push_str("    </parts>\n");
+        element.push_str("    <addrs>\n");
+        addrs.iter().for_each(|addr| element.push_str(addr));
+        element.push_str("    </addrs>\n");
+        element.push_str("  </mms>\n");
+
+        Ok(element)
+    }
+
+    /// Get the text content of a message, without attachment or app placeholders
+    fn format_text(&self, message: &Message) -> String {
+        message
+            .body()
+            .iter()
+            .filter_map(|part| match part {
+                BubbleType::Text(text) => Some(*text),
+                _ => None,
+            })
+            .collect::<Vec<&str>>()
+            .join("\n")
+    }
+
+    /// Read a message's attachments from the disk into base64 encoded `<part>` elements
+    ///
+    /// Attachments that cannot be read are noted at the end of the message text instead
+    fn format_attachments(
+        &self,
+        message: &Message,
+        text: &mut String,
+    ) -> Result<Vec<String>, TableError> {
+        if !message.has_attachments() {
+            return Ok(vec![]);
+        }
+
+        let attachments = self.config.attachments(message)?;
+        let mut parts = Vec::with_capacity(attachments.len());
+        for attachment in &attachments {
+            match attachment.as_bytes(
+                &self.config.options.platform,
+                &self.config.options.db_path,
+                self.config.options.attachment_root.as_deref(),
+            ) {
+                Ok(Some(bytes)) => {
+                    let name = sanitize_xml(attachment.filename());
+                    parts.push(format!(
+                        "      <part seq=\"0\" ct=\"{}\" name=\"{name}\" chset=\"null\" cd=\"null\" fn=\"null\" cid=\"&lt;{name}&gt;\" cl=\"{name}\" ctt_s=\"null\" ctt_t=\"null\" text=\"null\" data=\"{}\" />\n",
+                        sanitize_xml(
+                            attachment
+                                .mime_type
+                                .as_deref()
+                                .unwrap_or("application/octet-stream")
+                        ),
+                        STANDARD.encode(bytes),
+                    ));
+                }
+                _ => {
+                    if !text.is_empty() {
+                        text.push('\n');
+                    }
+                    text.push_str(&format!("Attachment missing: {}", attachment.filename()));
+                }
+            }
+        }
+        Ok(parts)
+    }
+
+    /// Get the handle ID and phone number or email address of everyone in a message's conversation, except the device owner
+    ///
+    /// If the message is not part of a known conversation, fall back to the sender's handle
+    fn addresses(&self, message: &Message) -> Vec<(i32, &str)> {
+        let mut addresses: Vec<(i32, &str)> = vec![];
+
+        if let Some((chatroom, _)) = self.config.conversation(message) {
+            if let Some(participants) = self.config.chatroom_participants.get(&chatroom.rowid) {
+                participants
+                    .iter()
+                    .filter_map(|participant| {
+                        self.config
+                            .addresses
+                            .get(participant)
+                            .map(|address| (*participant, address.as_str()))
+                    })
+                    .for_each(|(participant, address)| {
+                        if !addresses.iter().any(|(_, added)| *added == address) {
+                            addresses.push((participant, address));
+                        }
+                    });
+            }
+        }
+
+        if addresses.is_empty() {
+            if let (Some(handle_id), Some(sender)) =
+                (message.handle_id, self.sender_address(message))
+            {
+                addresses.push((handle_id, sender));
+            }
+        }
+
+        addresses
+    }
+
+    /// Get the phone number or email address of whoever sent a message, if it was not the device owner
+    fn sender_address(&self, message: &Message) -> Option<&str> {
+        if message.is_from_me {
+            return None;
+        }
+        message
+            .handle_id
+            .and_then(|handle_id| self.config.addresses.get(&handle_id))
+            .map(String::as_str)
+    }
+
+    /// Build the `contact_name` for a conversation, using the contact names of its members
+    fn contact_name(&self, addresses: &[(i32, &str)]) -> String {
+        let names: Vec<&str> = addresses
+            .iter()
+            .map(|(handle_id, _)| {
+                self.config
+                    .names
+                    .get(handle_id)
+                    .map_or(UNKNOWN_CONTACT, String::as_str)
+            })
+            .collect();
+        names.join(", ")
+    }
+
+    /// Wrap the message elements in the root element to build the final backup file
+    fn write_backup(&self) -> Result<(), RuntimeError> {
+        let backup_date = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_millis())
+            .unwrap_or(0);
+
+        self.config
+            .replace_output(
+                &self.path,
+                format!(
+                    "<?xml version='1.0' encoding='UTF-8' standalone='yes' ?>\n<smses count=\"{}\" backup_set=\"{}\" backup_date=\"{backup_date}\" type=\"full\">\n",
+                    self.count,
+                    Uuid::new_v4()
+                )
+                .as_bytes(),
+            )
+            .map_err(RuntimeError::DiskError)?;
+
+        if self.config.output_exists(&self.body) {
+            self.config
+                .append_output(&self.body, &self.path)
+                .map_err(RuntimeError::DiskError)?;
+        }
+
+        self.config.write_to_file(&self.path, "</smses>\n");
+        self.config.close_output(&self.path);
+        Ok(())
+    }
+}
+
+/// Build an `<addr>` element for an MMS message
+fn format_addr(address: &str, addr_type: u8) -> String {
+    format!(
+        "      <addr address=\"{}\" type=\"{addr_type}\" charset=\"106\" />\n",
+        sanitize_xml(address)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::BTreeSet,
+        env::{set_var, temp_dir},
+        fs::{create_dir_all, read_to_string, remove_dir_all},
+    };
+
+    use crate::{Config, Exporter, Options, XML};
+    use imessage_database::tables::{chat::Chat, messages::Message};
+
+    fn blank() -> Message {
+        Message {
+            rowid: i32::default(),
+            guid: String::default(),
+            text: None,
+            service: Some("iMessage".to_string()),
+            handle_id: Some(i32::default()),
+            destination_caller_id: None,
+            subject: None,
+            date: i64::default(),
+            date_read: i64::default(),
+            date_delivered: i64::default(),
+            is_from_me: false,
+            is_read: false,
+            item_type: 0,
+            group_title: None,
+            group_action_type: 0,
+            associated_message_guid: None,
+            associated_message_type: Some(i32::default()),
+            balloon_bundle_id: None,
+            expressive_send_style_id: None,
+            thread_originator_guid: None,
+            thread_originator_part: None,
+            date_edited: 0,
+            chat_id: None,
+            num_attachments: 0,
+            deleted_from: None,
+            num_replies: 0,
+        }
+    }
+
+    /// Add a chat with ROWID `1` and the given participants to the config
+    fn add_chat(config: &mut Config, participants: &[(i32, &str)]) {
+        config.chatrooms.insert(
+            1,
+            Chat {
+                rowid: 1,
+                chat_identifier: "chat0".to_string(),
+                service_name: Some("iMessage".to_string()),
+                display_name: None,
+            },
+        );
+        config.real_chatrooms.insert(1, 1);
+        config.chatroom_participants.insert(
+            1,
+            participants
+                .iter()
+                .map(|(id, _)| *id)
+                .collect::<BTreeSet<i32>>(),
+        );
+        participants.iter().for_each(|(id, handle)| {
+            config.participants.insert(*id, handle.to_string());
+            config.addresses.insert(*id, handle.to_string());
+        });
+    }
+
+    #[test]
+    fn can_create() {
+        let options = Options::fake();
+        let config = Config::new(options).unwrap();
+        let exporter = XML::new(&config);
+        assert_eq!(exporter.count, 0);
+        assert!(exporter.path.ends_with("sms.xml"));
+    }
+
+    #[test]
+    fn can_format_sms_received() {
+        // Set timezone to PST for consistent Local time
+        set_var("TZ", "PST");
+
+        // Create exporter
+        let options = Options::fake();
+        let mut config = Config::new(options).unwrap();
+        config
+            .participants
+            .insert(999999, "+15555550100".to_string());
+        config.addresses.insert(999999, "+15555550100".to_string());
+        let exporter = XML::new(&config);
+
+        let mut message = blank();
+        // May 17, 2022  8:29:42 PM
+        message.date = 674526582885055488;
+        message.text = Some("Hello \"world\"\nBye".to_string());
+        message.handle_id = Some(999999);
+
+        let actual = exporter.format_message(&message).unwrap().unwrap();
+        let expected = "  <sms protocol=\"0\" address=\"+15555550100\" date=\"1652833782000\" type=\"1\" subject=\"null\" body=\"Hello &quot;world&quot;&#10;Bye\" toa=\"null\" sc_toa=\"null\" service_center=\"null\" read=\"0\" status=\"-1\" locked=\"0\" date_sent=\"1652833782000\" sub_id=\"-1\" readable_date=\"May 17, 2022  5:29:42 PM\" contact_name=\"(Unknown)\" />\n";
+
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn can_format_sms_sent() {
+        // Set timezone to PST for consistent Local time
+        set_var("TZ", "PST");
+
+        // Create exporter
+        let options = Options::fake();
+        let mut config = Config::new(options).unwrap();
+        add_chat(&mut config, &[(10, "+15555550100")]);
+        let exporter = XML::new(&config);
+
+        let mut message = blank();
+        // May 17, 2022  8:29:42 PM
+        message.date = 674526582885055488;
+        message.text = Some("Hi".to_string());
+        message.is_from_me = true;
+        message.chat_id = Some(1);
+
+        let actual = exporter.format_message(&message).unwrap().unwrap();
+
+        assert!(actual.starts_with("  <sms protocol=\"0\" address=\"+15555550100\""));
+        assert!(actual.contains(" type=\"2\" "));
+        assert!(actual.contains(" read=\"1\" "));
+    }
+
+    #[test]
+    fn can_format_mms_group() {
+        // Set timezone to PST for consistent Local time
+        set_var("TZ", "PST");
+
+        // Create exporter
+        let options = Options::fake();
+        let mut config = Config::new(options).unwrap();
+        add_chat(
+            &mut config,
+            &[(10, "+15555550100"), (11, "jane@example.com")],
+        );
+        let exporter = XML::new(&config);
+
+        let mut message = blank();
+        // May 17, 2022  8:29:42 PM
+        message.date = 674526582885055488;
+        message.text = Some("Group msg".to_string());
+        message.handle_id = Some(11);
+        message.chat_id = Some(1);
+
+        let actual = exporter.format_message(&message).unwrap().unwrap();
+
+        assert!(actual.starts_with("  <mms date=\"1652833782000\" date_sent=\"1652833782\""));
+        assert!(actual.contains(" msg_box=\"1\" address=\"+15555550100~jane@example.com\" "));
+        assert!(actual.contains(" text_only=\"1\" "));
+        assert!(actual.contains(" m_type=\"132\" "));
+        assert!(actual.contains("text=\"Group msg\""));
+        assert!(actual.ends_with(
+            "    <addrs>\n      <addr address=\"jane@example.com\" type=\"137\" charset=\"106\" />\n      <addr address=\"+15555550100\" type=\"151\" charset=\"106\" />\n      <addr address=\"insert-address-token\" type=\"151\" charset=\"106\" />\n    </addrs>\n  </mms>\n"
+        ));
+    }
+
+    #[test]
+    fn can_format_sms_contact_name() {
+        // Create exporter
+        let options = Options::fake();
+        let mut config = Config::new(options).unwrap();
+        add_chat(&mut config, &[(10, "+15555550100")]);
+        config.names.insert(10, "Jane Doe".to_string());
+        let exporter = XML::new(&config);
+
+        let mut message = blank();
+        message.text = Some("Hi".to_string());
+        message.handle_id = Some(10);
+        message.chat_id = Some(1);
+
+        let actual = exporter.format_message(&message).unwrap().unwrap();
+
+        assert!(actual.starts_with("  <sms protocol=\"0\" address=\"+15555550100\""));
+        assert!(actual.ends_with(" contact_name=\"Jane Doe\" />\n"));
+    }
+
+    #[test]
+    fn can_format_mms_merged_handles() {
+        // Create exporter
+        let options = Options::fake();
+        let mut config = Config::new(options).unwrap();
+        add_chat(
+            &mut config,
+            &[(10, "+15555550100"), (11, "jane@example.com")],
+        );
+        // Both handles share a `person_centric_id` and belong to a contact
+        for id in [10, 11] {
+            config
+                .participants
+                .insert(id, "+15555550100 jane@example.com".to_string());
+            config.names.insert(id, "Jane Doe".to_string());
+        }
+        let exporter = XML::new(&config);
+
+        let mut message = blank();
+        message.text = Some("Hi".to_string());
+        message.handle_id = Some(11);
+        message.chat_id = Some(1);
+
+        let actual = exporter.format_message(&message).unwrap().unwrap();
+
+        assert!(actual.contains(" address=\"+15555550100~jane@example.com\" "));
+        assert!(actual.contains(" contact_name=\"Jane Doe, Jane Doe\">"));
+        assert!(actual.contains("<addr address=\"jane@example.com\" type=\"137\""));
+    }
+
+    #[test]
+    fn cant_format_without_address() {
+        // Create exporter
+        let options = Options::fake();
+        let config = Config::new(options).unwrap();
+        let exporter = XML::new(&config);
+
+        let mut message = blank();
+        message.text = Some("Hello".to_string());
+        message.is_from_me = true;
+
+        assert!(exporter.format_message(&message).unwrap().is_none());
+    }
+
+    #[test]
+    fn can_write_backup() {
+        let export_path = temp_dir().join("imessage-exporter-xml-test");
+        let _ = remove_dir_all(&export_path);
+        create_dir_all(&export_path).unwrap();
+
+        // Create exporter
+        let mut options = Options::fake();
+        options.export_path = export_path.clone();
+        let config = Config::new(options).unwrap();
+        let mut exporter = XML::new(&config);
+
+        exporter.config.write_to_file(&exporter.body, "  <sms />\n");
+        exporter.count = 1;
+        exporter.write_backup().unwrap();
+
+        let actual = read_to_string(&exporter.path).unwrap();
+        let body_exists = exporter.body.exists();
+        remove_dir_all(&export_path).unwrap();
+
+        assert!(actual.starts_with(
+            "<?xml version='1.0' encoding='UTF-8' standalone='yes' ?>\n<smses count=\"1\" "
+        ));
+        assert!(actual.ends_with(" type=\"full\">\n  <sms />\n</smses>\n"));
+        assert!(!body_exists);
+    }
+}
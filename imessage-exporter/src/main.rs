@@ -3,7 +3,10 @@
 mod app;
 mod exporters;
 
-pub use exporters::{exporter::Exporter, html::HTML, txt::TXT, jsonl::JSONL};
+pub use exporters::{
+    csv::CSV, exporter::Exporter, html::HTML, jsonl::JSONL, markdown::Markdown, mbox::Mbox,
+    parquet::Parquet, sqlite::SQLite, txt::TXT, xml::XML,
+};
 
 use app::{
     options::{from_command_line, Options},